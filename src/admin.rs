@@ -0,0 +1,356 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+};
+use serde::Deserialize;
+use std::time::Duration;
+
+use crate::{canary::CanarySplit, file_cache, health::HEALTH_CHECK_TIMEOUT_SECS, proxy::ProxyState, rate_limiter};
+
+/// Header que deben enviar los clientes de los endpoints de administración
+pub const ADMIN_SECRET_HEADER: &str = "x-vk-secret";
+
+/// Verifies the request carries the configured `vk_secret`.
+/// Admin endpoints are disabled entirely when no secret is configured.
+pub fn require_admin(state: &ProxyState, headers: &HeaderMap) -> Result<(), StatusCode> {
+    let Some(ref secret) = state.config.vk_secret else {
+        tracing::warn!("Admin endpoint called but VK_SECRET is not configured");
+        return Err(StatusCode::FORBIDDEN);
+    };
+
+    let provided = headers
+        .get(ADMIN_SECRET_HEADER)
+        .and_then(|v| v.to_str().ok());
+
+    match provided {
+        Some(provided) if constant_time_eq(provided.as_bytes(), secret.as_bytes()) => Ok(()),
+        _ => {
+            tracing::warn!("Rejected admin request with missing or invalid secret");
+            Err(StatusCode::UNAUTHORIZED)
+        }
+    }
+}
+
+/// Compares two byte strings without short-circuiting on the first mismatch
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Handler que expone la configuración efectiva del gateway (sin secretos)
+pub async fn gateway_config(
+    State(state): State<ProxyState>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, StatusCode> {
+    require_admin(&state, &headers)?;
+
+    Ok((StatusCode::OK, axum::Json(effective_settings(&state))))
+}
+
+/// Effective configuration plus the values resolved by the constructed
+/// components, with every secret redacted. Also logged once at startup.
+pub fn effective_settings(state: &ProxyState) -> serde_json::Value {
+    let cors_mode = if state.config.cors_allowed_origins.is_some() {
+        "allowlist"
+    } else {
+        "permissive"
+    };
+
+    serde_json::json!({
+        "config": state.config.redacted(),
+        "derived": {
+            "load_balancer": state.load_balancer.name(),
+            "load_balancer_routes": state.route_balancers.iter().map(|(route, balancer)| serde_json::json!({
+                "prefix": route.prefix,
+                "load_balancer": balancer.name(),
+            })).collect::<Vec<_>>(),
+            "health_check_timeout_secs": HEALTH_CHECK_TIMEOUT_SECS,
+            "backends": state.backends.len(),
+            "cors_mode": cors_mode,
+        },
+    })
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OverrideParams {
+    /// Segundos tras los que el override vence; sin valor dura hasta limpiarse
+    pub ttl_secs: Option<u64>,
+}
+
+async fn force_health(
+    state: &ProxyState,
+    headers: &HeaderMap,
+    server_id: &str,
+    healthy: bool,
+    params: OverrideParams,
+) -> Result<axum::Json<serde_json::Value>, StatusCode> {
+    require_admin(state, headers)?;
+
+    if state.backends.find(server_id).is_none() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let ttl = params.ttl_secs.map(Duration::from_secs);
+    state.health_checker.set_override(server_id, healthy, ttl).await;
+
+    Ok(axum::Json(serde_json::json!({
+        "server_id": server_id,
+        "forced_healthy": healthy,
+        "ttl_secs": params.ttl_secs,
+    })))
+}
+
+/// Handler que fuerza un backend como no saludable, excluyéndolo de inmediato
+pub async fn force_unhealthy(
+    State(state): State<ProxyState>,
+    Path(server_id): Path<String>,
+    Query(params): Query<OverrideParams>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, StatusCode> {
+    force_health(&state, &headers, &server_id, false, params).await
+}
+
+/// Handler que fuerza un backend como saludable, ignorando los health checks
+pub async fn force_healthy(
+    State(state): State<ProxyState>,
+    Path(server_id): Path<String>,
+    Query(params): Query<OverrideParams>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, StatusCode> {
+    force_health(&state, &headers, &server_id, true, params).await
+}
+
+/// Handler que elimina el override y vuelve al estado calculado por los health checks
+pub async fn clear_health_override(
+    State(state): State<ProxyState>,
+    Path(server_id): Path<String>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, StatusCode> {
+    require_admin(&state, &headers)?;
+
+    if state.health_checker.clear_override(&server_id).await {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(StatusCode::NOT_FOUND)
+    }
+}
+
+/// Peso máximo aceptado, para acotar la lista ponderada del balanceador
+const MAX_BACKEND_WEIGHT: usize = 100;
+
+#[derive(Debug, Deserialize)]
+pub struct WeightRequest {
+    pub weight: usize,
+}
+
+/// Applies (or clears, with `None`) a weight override on every load balancer
+/// that uses weights, global or per route. False if none of them does.
+fn set_weight_override(state: &ProxyState, server_id: &str, weight: Option<usize>) -> bool {
+    // Sin cortocircuito: todos los balanceadores ponderados reciben el peso
+    state
+        .balancers()
+        .fold(false, |applied, balancer| balancer.set_weight_override(server_id, weight) | applied)
+}
+
+/// Handler que fija un peso temporal para un backend (balanceadores ponderados)
+pub async fn set_backend_weight(
+    State(state): State<ProxyState>,
+    Path(server_id): Path<String>,
+    headers: HeaderMap,
+    axum::Json(body): axum::Json<WeightRequest>,
+) -> Result<impl IntoResponse, StatusCode> {
+    require_admin(&state, &headers)?;
+
+    if state.backends.find(&server_id).is_none() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    if body.weight > MAX_BACKEND_WEIGHT {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    if !set_weight_override(&state, &server_id, Some(body.weight)) {
+        tracing::warn!("Weight override rejected: no load balancer uses weights");
+        return Err(StatusCode::CONFLICT);
+    }
+
+    tracing::warn!("Backend {} weight set to {} by operator", server_id, body.weight);
+    Ok(axum::Json(serde_json::json!({
+        "server_id": server_id,
+        "weight": body.weight,
+    })))
+}
+
+/// Handler que elimina el peso temporal, volviendo al peso base del backend
+pub async fn clear_backend_weight(
+    State(state): State<ProxyState>,
+    Path(server_id): Path<String>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, StatusCode> {
+    require_admin(&state, &headers)?;
+
+    if !set_weight_override(&state, &server_id, None) {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    tracing::info!("Weight override cleared for backend {}", server_id);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Handler que fija el backend canary y el porcentaje del tráfico balanceado que recibe
+pub async fn set_canary(
+    State(state): State<ProxyState>,
+    headers: HeaderMap,
+    axum::Json(body): axum::Json<CanarySplit>,
+) -> Result<impl IntoResponse, StatusCode> {
+    require_admin(&state, &headers)?;
+
+    if state.backends.find(&body.server_id).is_none() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    if !CanarySplit::is_valid_percent(body.percent) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    tracing::warn!("Canary set to backend {} at {}% by operator", body.server_id, body.percent);
+    state.canary.set(Some(body.clone()));
+    Ok(axum::Json(body))
+}
+
+/// Handler que elimina el canary, devolviendo todo el tráfico al pool estable
+pub async fn clear_canary(
+    State(state): State<ProxyState>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, StatusCode> {
+    require_admin(&state, &headers)?;
+
+    state.canary.set(None);
+    tracing::info!("Canary cleared by operator");
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Handler que deja de enrutar a todos los backends de un provider
+pub async fn disable_provider(
+    State(state): State<ProxyState>,
+    Path(provider): Path<String>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, StatusCode> {
+    require_admin(&state, &headers)?;
+
+    let backends = state
+        .backends
+        .all()
+        .into_iter()
+        .filter(|b| b.provider.eq_ignore_ascii_case(&provider))
+        .count();
+    if backends == 0 {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    if state.provider_switch.disable(&provider) {
+        tracing::warn!("Provider {} ({} backends) disabled by operator", provider, backends);
+    }
+    Ok(axum::Json(serde_json::json!({
+        "provider": provider,
+        "disabled": true,
+        "backends": backends,
+    })))
+}
+
+/// Handler que vuelve a enrutar a los backends de un provider deshabilitado
+pub async fn enable_provider(
+    State(state): State<ProxyState>,
+    Path(provider): Path<String>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, StatusCode> {
+    require_admin(&state, &headers)?;
+
+    if state.provider_switch.enable(&provider) {
+        tracing::info!("Provider {} re-enabled by operator", provider);
+    }
+    Ok(axum::Json(serde_json::json!({
+        "provider": provider,
+        "disabled": false,
+    })))
+}
+
+/// Handler que elimina del caché de búsquedas el dueño de un archivo
+pub async fn evict_file_lookup(
+    State(state): State<ProxyState>,
+    Path(file_id): Path<String>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, StatusCode> {
+    require_admin(&state, &headers)?;
+
+    let removed = file_cache::evict(&state.redis, &file_id).await.map_err(|e| {
+        tracing::error!("Failed to evict cached owner of file {}: {}", file_id, e);
+        StatusCode::SERVICE_UNAVAILABLE
+    })?;
+
+    tracing::info!("Cached owner of file {} evicted by operator ({} removed)", file_id, removed);
+    Ok(axum::Json(serde_json::json!({
+        "file_id": file_id,
+        "removed": removed,
+    })))
+}
+
+/// Handler que vacía el caché de búsquedas de archivos completo
+pub async fn flush_file_lookups(
+    State(state): State<ProxyState>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, StatusCode> {
+    require_admin(&state, &headers)?;
+
+    let removed = file_cache::flush(&state.redis).await.map_err(|e| {
+        tracing::error!("Failed to flush the file lookup cache: {}", e);
+        StatusCode::SERVICE_UNAVAILABLE
+    })?;
+
+    tracing::warn!("File lookup cache flushed by operator ({} removed)", removed);
+    Ok(axum::Json(serde_json::json!({ "removed": removed })))
+}
+
+/// Tokens devueltos por defecto y como máximo en cada página del listado
+const DEFAULT_BLOCKED_PAGE_SIZE: usize = 100;
+const MAX_BLOCKED_PAGE_SIZE: usize = 1000;
+
+#[derive(Debug, Deserialize)]
+pub struct BlockedParams {
+    /// Cursor devuelto por la página anterior (0 o sin valor = desde el inicio)
+    pub cursor: Option<u64>,
+    pub limit: Option<usize>,
+}
+
+/// Handler que lista los tokens bloqueados por el rate limiter y su TTL restante
+pub async fn list_blocked_tokens(
+    State(state): State<ProxyState>,
+    Query(params): Query<BlockedParams>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, StatusCode> {
+    require_admin(&state, &headers)?;
+
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_BLOCKED_PAGE_SIZE)
+        .clamp(1, MAX_BLOCKED_PAGE_SIZE);
+    let groups: Vec<String> = state
+        .config
+        .rate_limit_routes
+        .iter()
+        .map(|route| route.name.clone())
+        .collect();
+
+    let (blocked, next_cursor) =
+        rate_limiter::list_blocked_tokens(&state.redis, &groups, params.cursor.unwrap_or(0), limit)
+            .await
+            .map_err(|e| {
+                tracing::error!("Failed to list blocked tokens: {}", e);
+                StatusCode::SERVICE_UNAVAILABLE
+            })?;
+
+    Ok(axum::Json(serde_json::json!({
+        "blocked": blocked,
+        "next_cursor": next_cursor,
+    })))
+}
@@ -0,0 +1,219 @@
+use axum::body::{Body, Bytes};
+use http_body::{Frame, SizeHint};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+use tokio::time::{Instant, Sleep};
+
+/// Crédito máximo acumulado mientras el backend no envía datos: tras una pausa,
+/// la ráfaga siguiente no supera un segundo de transferencia
+const MAX_BURST: Duration = Duration::from_secs(1);
+
+/// Tamaño máximo de los frames entregados, para repartir los bytes de un frame
+/// grande en vez de enviarlos de golpe tras una sola espera
+const MAX_CHUNK_BYTES: u64 = 64 * 1024;
+
+/// Wraps a response body so it is delivered at most at `bytes_per_sec`. Frames
+/// pass through as they arrive (nothing is buffered beyond the current one),
+/// only delayed until the bytes sent so far fit in the rate.
+pub fn throttle(body: Body, bytes_per_sec: u64) -> Body {
+    Body::new(ThrottledBody {
+        inner: body,
+        rate: bytes_per_sec.max(1),
+        chunk_size: (bytes_per_sec / 10).clamp(1, MAX_CHUNK_BYTES) as usize,
+        pending: Bytes::new(),
+        ready: None,
+        delay: None,
+        window_start: Instant::now(),
+        window_bytes: 0,
+    })
+}
+
+struct ThrottledBody {
+    inner: Body,
+    rate: u64,
+    chunk_size: usize,
+    /// Resto del frame de datos en curso, aún sin entregar
+    pending: Bytes,
+    /// Trozo listo para entregar cuando termine `delay`
+    ready: Option<Bytes>,
+    delay: Option<Pin<Box<Sleep>>>,
+    /// Inicio de la ventana y bytes entregados desde entonces
+    window_start: Instant,
+    window_bytes: u64,
+}
+
+impl ThrottledBody {
+    /// Instant at which `len` more bytes can go out without exceeding the rate
+    fn due(&mut self, len: usize) -> Instant {
+        let now = Instant::now();
+        let due = self.window_start + Duration::from_secs_f64(self.window_bytes as f64 / self.rate as f64);
+        if due + MAX_BURST < now {
+            self.window_start = now.checked_sub(MAX_BURST).unwrap_or(now);
+            self.window_bytes = 0;
+        }
+        self.window_bytes += len as u64;
+        self.window_start + Duration::from_secs_f64(self.window_bytes as f64 / self.rate as f64)
+    }
+}
+
+impl http_body::Body for ThrottledBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = &mut *self;
+        loop {
+            if let Some(delay) = this.delay.as_mut() {
+                ready!(delay.as_mut().poll(cx));
+                this.delay = None;
+            }
+            if let Some(chunk) = this.ready.take() {
+                return Poll::Ready(Some(Ok(Frame::data(chunk))));
+            }
+
+            if !this.pending.is_empty() {
+                let chunk = this.pending.split_to(this.pending.len().min(this.chunk_size));
+                let due = this.due(chunk.len());
+                if due > Instant::now() {
+                    this.delay = Some(Box::pin(tokio::time::sleep_until(due)));
+                }
+                this.ready = Some(chunk);
+                continue;
+            }
+
+            match ready!(Pin::new(&mut this.inner).poll_frame(cx)) {
+                Some(Ok(frame)) => match frame.into_data() {
+                    Ok(data) => this.pending = data,
+                    // Los trailers no cuentan para el límite
+                    Err(frame) => return Poll::Ready(Some(Ok(frame))),
+                },
+                other => return Poll::Ready(other),
+            }
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.ready.is_none() && self.pending.is_empty() && self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        let buffered = (self.pending.len() + self.ready.as_ref().map_or(0, Bytes::len)) as u64;
+        let inner = self.inner.size_hint();
+        let mut hint = SizeHint::new();
+        if let Some(upper) = inner.upper() {
+            hint.set_upper(upper + buffered);
+        }
+        hint.set_lower(inner.lower() + buffered);
+        hint
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::{BodyExt, StreamBody};
+
+    fn throttled(rate: u64) -> ThrottledBody {
+        ThrottledBody {
+            inner: Body::empty(),
+            rate,
+            chunk_size: (rate / 10).clamp(1, MAX_CHUNK_BYTES) as usize,
+            pending: Bytes::new(),
+            ready: None,
+            delay: None,
+            window_start: Instant::now(),
+            window_bytes: 0,
+        }
+    }
+
+    fn chunked_body(frames: Vec<Frame<Bytes>>) -> Body {
+        let frames = futures::stream::iter(frames.into_iter().map(Ok::<_, std::io::Error>));
+        Body::new(StreamBody::new(frames))
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn due_spaces_bytes_at_the_rate() {
+        let mut body = throttled(1000);
+        let start = body.window_start;
+
+        assert_eq!(body.due(500), start + Duration::from_millis(500));
+        assert_eq!(body.due(500), start + Duration::from_secs(1));
+        assert_eq!(body.due(250), start + Duration::from_millis(1250));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn due_caps_the_burst_after_an_idle_period() {
+        let mut body = throttled(1000);
+        body.due(1000);
+
+        tokio::time::advance(Duration::from_secs(10)).await;
+        let now = Instant::now();
+        // Un segundo de crédito sale sin esperar, el resto al ritmo del límite
+        assert_eq!(body.due(1000), now);
+        assert_eq!(body.due(500), now + Duration::from_millis(500));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn due_keeps_credit_within_the_burst() {
+        let mut body = throttled(1000);
+        body.due(1000);
+
+        tokio::time::advance(Duration::from_millis(1500)).await;
+        assert_eq!(body.due(500), body.window_start + Duration::from_millis(1500));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn response_is_not_delivered_faster_than_the_rate() {
+        let frames = (0..5).map(|_| Frame::data(Bytes::from(vec![7u8; 1000]))).collect();
+        let mut body = throttle(chunked_body(frames), 1000);
+        let start = Instant::now();
+
+        let mut delivered = 0;
+        while let Some(frame) = body.frame().await {
+            let data = frame.unwrap().into_data().unwrap();
+            assert!(data.len() <= 100, "frames are split into tenths of a second");
+            assert!(data.iter().all(|b| *b == 7));
+            delivered += data.len();
+
+            let allowed = start.elapsed().as_secs_f64() * 1000.0;
+            assert!(delivered as f64 <= allowed + 1e-6, "{} bytes after {:?}", delivered, start.elapsed());
+        }
+
+        assert_eq!(delivered, 5000);
+        assert!(start.elapsed() >= Duration::from_secs(5));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn trailers_follow_the_throttled_data() {
+        let mut trailers = axum::http::HeaderMap::new();
+        trailers.insert("x-checksum", "abc".parse().unwrap());
+        let frames = vec![Frame::data(Bytes::from(vec![1u8; 300])), Frame::trailers(trailers.clone())];
+        let mut body = throttle(chunked_body(frames), 1000);
+
+        let mut data = 0;
+        let mut received = None;
+        while let Some(frame) = body.frame().await {
+            match frame.unwrap().into_data() {
+                Ok(bytes) => {
+                    assert!(received.is_none(), "data after the trailers");
+                    data += bytes.len();
+                }
+                Err(frame) => received = frame.into_trailers().ok(),
+            }
+        }
+
+        assert_eq!(data, 300);
+        assert_eq!(received, Some(trailers));
+    }
+
+    #[test]
+    fn size_hint_is_preserved() {
+        let body = throttle(Body::from(vec![0u8; 4096]), 1000);
+        assert_eq!(http_body::Body::size_hint(&body).exact(), Some(4096));
+    }
+}
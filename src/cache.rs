@@ -1,35 +1,333 @@
 use redis::aio::ConnectionManager;
-use redis::AsyncCommands;
-use std::time::Duration;
+use redis::{AsyncCommands, RedisError};
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
 
-pub async fn create_redis_client(redis_url: &str) -> Result<ConnectionManager, redis::RedisError> {
+/// Intentos por operación mientras Redis está saludable
+const MAX_ATTEMPTS: u32 = 3;
+/// Backoff inicial entre reintentos (se duplica en cada intento)
+const BASE_BACKOFF_MS: u64 = 50;
+/// Fallos consecutivos tras los cuales Redis se marca no saludable y se recrea la conexión
+const RECREATE_AFTER_FAILURES: u32 = 5;
+/// Tiempo mínimo entre dos recreaciones del ConnectionManager
+const MIN_RECREATE_INTERVAL: Duration = Duration::from_secs(10);
+/// Tiempo máximo para establecer una nueva conexión
+const RECONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// Tiempo máximo de cada operación: ConnectionManager no tiene timeout de
+/// respuesta, y un socket medio abierto la dejaría esperando para siempre
+const REDIS_OP_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Redis client that retries transient errors with exponential backoff and
+/// rebuilds its `ConnectionManager` when it looks wedged after repeated failures.
+#[derive(Clone)]
+pub struct RedisClient {
+    inner: Arc<RedisInner>,
+}
+
+struct RedisInner {
+    client: redis::Client,
+    manager: RwLock<ConnectionManager>,
+    healthy: AtomicBool,
+    consecutive_failures: AtomicU32,
+    /// Una sola recreación en curso a la vez, en segundo plano
+    reconnecting: AtomicBool,
+    last_recreate: std::sync::Mutex<Option<Instant>>,
+}
+
+pub async fn create_redis_client(redis_url: &str) -> Result<RedisClient, RedisError> {
     let client = redis::Client::open(redis_url)?;
-    ConnectionManager::new(client).await
+    let manager = ConnectionManager::new(client.clone()).await?;
+
+    Ok(RedisClient {
+        inner: Arc::new(RedisInner {
+            client,
+            manager: RwLock::new(manager),
+            healthy: AtomicBool::new(true),
+            consecutive_failures: AtomicU32::new(0),
+            reconnecting: AtomicBool::new(false),
+            last_recreate: std::sync::Mutex::new(None),
+        }),
+    })
 }
 
-/// Cache functions for future use - currently unused but kept for planned features
-#[allow(dead_code)]
+/// Errors worth retrying: the command never reached Redis or the connection broke
+fn is_transient(e: &RedisError) -> bool {
+    e.is_io_error() || e.is_connection_dropped() || e.is_connection_refusal() || e.is_timeout()
+}
+
+/// Bounds an operation by `REDIS_OP_TIMEOUT`, reporting the timeout as an I/O error
+async fn with_timeout<T>(op: impl Future<Output = Result<T, RedisError>>) -> Result<T, RedisError> {
+    match tokio::time::timeout(REDIS_OP_TIMEOUT, op).await {
+        Ok(result) => result,
+        Err(_) => Err(RedisError::from(std::io::Error::new(
+            std::io::ErrorKind::TimedOut,
+            format!("Redis operation timed out after {:?}", REDIS_OP_TIMEOUT),
+        ))),
+    }
+}
+
+impl RedisClient {
+    /// Whether the last Redis operations succeeded
+    pub fn is_healthy(&self) -> bool {
+        self.inner.healthy.load(Ordering::Relaxed)
+    }
+
+    /// Runs a Redis operation, retrying transient errors with exponential backoff.
+    /// While Redis is marked unhealthy a single attempt is made so callers that
+    /// fail open (e.g. the rate limiter) don't pay the backoff on every request.
+    /// Each attempt is bounded by `REDIS_OP_TIMEOUT`; a timeout counts as a
+    /// transient error, so `op` must be safe to repeat.
+    pub async fn run<T, F, Fut>(&self, mut op: F) -> Result<T, RedisError>
+    where
+        F: FnMut(ConnectionManager) -> Fut,
+        Fut: Future<Output = Result<T, RedisError>>,
+    {
+        let max_attempts = if self.is_healthy() { MAX_ATTEMPTS } else { 1 };
+        let mut attempt = 0;
+
+        loop {
+            let conn = self.inner.manager.read().await.clone();
+
+            match with_timeout(op(conn)).await {
+                Ok(value) => {
+                    self.record_success();
+                    return Ok(value);
+                }
+                Err(e) if is_transient(&e) => {
+                    attempt += 1;
+                    self.record_failure(&e);
+
+                    if attempt >= max_attempts {
+                        return Err(e);
+                    }
+
+                    let backoff = Duration::from_millis(BASE_BACKOFF_MS << (attempt - 1));
+                    tracing::debug!(
+                        "Transient Redis error (attempt {}/{}), retrying in {:?}: {}",
+                        attempt,
+                        max_attempts,
+                        backoff,
+                        e
+                    );
+                    tokio::time::sleep(backoff).await;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Runs a non-idempotent Redis operation a single time: if its reply is
+    /// lost the command may already have run, and a retry would apply it twice.
+    /// Failures still count towards the health tracking and reconnection.
+    pub async fn run_once<T, F, Fut>(&self, op: F) -> Result<T, RedisError>
+    where
+        F: FnOnce(ConnectionManager) -> Fut,
+        Fut: Future<Output = Result<T, RedisError>>,
+    {
+        let conn = self.inner.manager.read().await.clone();
+        let result = with_timeout(op(conn)).await;
+        match &result {
+            Ok(_) => self.record_success(),
+            Err(e) if is_transient(e) => self.record_failure(e),
+            Err(_) => {}
+        }
+        result
+    }
+
+    fn record_success(&self) {
+        self.inner.consecutive_failures.store(0, Ordering::Relaxed);
+
+        if !self.inner.healthy.swap(true, Ordering::Relaxed) {
+            tracing::info!("Redis connection recovered, marked as healthy");
+        }
+    }
+
+    fn record_failure(&self, e: &RedisError) {
+        let failures = self.inner.consecutive_failures.fetch_add(1, Ordering::Relaxed) + 1;
+
+        if failures < RECREATE_AFTER_FAILURES {
+            return;
+        }
+
+        if self.inner.healthy.swap(false, Ordering::Relaxed) {
+            tracing::error!(
+                "Redis marked as unhealthy after {} consecutive failures: {}",
+                failures,
+                e
+            );
+        }
+
+        self.spawn_recreate_connection();
+    }
+
+    /// Rebuilds the ConnectionManager in a background task, at most once per
+    /// `MIN_RECREATE_INTERVAL` and never twice concurrently, so requests don't
+    /// wait for the reconnection
+    fn spawn_recreate_connection(&self) {
+        {
+            let mut last_recreate = self.inner.last_recreate.lock().unwrap();
+            if last_recreate.is_some_and(|at| at.elapsed() < MIN_RECREATE_INTERVAL) {
+                return;
+            }
+            if self.inner.reconnecting.swap(true, Ordering::AcqRel) {
+                return;
+            }
+            *last_recreate = Some(Instant::now());
+        }
+
+        let client = self.clone();
+        tokio::spawn(async move {
+            client.recreate_connection().await;
+            client.inner.reconnecting.store(false, Ordering::Release);
+        });
+    }
+
+    async fn recreate_connection(&self) {
+        tracing::warn!("Recreating Redis connection manager");
+
+        match tokio::time::timeout(
+            RECONNECT_TIMEOUT,
+            ConnectionManager::new(self.inner.client.clone()),
+        )
+        .await
+        {
+            Ok(Ok(manager)) => {
+                *self.inner.manager.write().await = manager;
+                tracing::info!("Redis connection manager recreated");
+            }
+            Ok(Err(e)) => tracing::warn!("Failed to recreate Redis connection: {}", e),
+            Err(_) => tracing::warn!(
+                "Timed out recreating Redis connection after {:?}",
+                RECONNECT_TIMEOUT
+            ),
+        }
+    }
+}
+
+/// Stores a value with an expiration
 pub async fn cache_set(
-    conn: &mut ConnectionManager,
+    redis: &RedisClient,
     key: &str,
     value: &str,
     ttl: Duration,
-) -> Result<(), redis::RedisError> {
-    conn.set_ex(key, value, ttl.as_secs() as u64).await
+) -> Result<(), RedisError> {
+    redis
+        .run(|mut conn| async move { conn.set_ex(key, value, ttl.as_secs()).await })
+        .await
 }
 
-#[allow(dead_code)]
-pub async fn cache_get(
-    conn: &mut ConnectionManager,
-    key: &str,
-) -> Result<Option<String>, redis::RedisError> {
-    conn.get(key).await
+/// Reads a value, `None` if missing or expired
+pub async fn cache_get(redis: &RedisClient, key: &str) -> Result<Option<String>, RedisError> {
+    redis
+        .run(|mut conn| async move { conn.get(key).await })
+        .await
 }
 
+/// Cache function for future use - currently unused but kept for planned features
 #[allow(dead_code)]
-pub async fn cache_delete(
-    conn: &mut ConnectionManager,
-    key: &str,
-) -> Result<(), redis::RedisError> {
-    conn.del(key).await
+pub async fn cache_delete(redis: &RedisClient, key: &str) -> Result<(), RedisError> {
+    redis
+        .run(|mut conn| async move { conn.del(key).await })
+        .await
+}
+
+/// Client connected to a fake server that only acknowledges the `CLIENT SETINFO`
+/// pipeline sent on connect and never answers a command, for tests of
+/// components that hold a `RedisClient` without using it
+#[cfg(test)]
+pub async fn unresponsive_test_client() -> RedisClient {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((mut socket, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let mut buf = [0u8; 1024];
+                if matches!(socket.read(&mut buf).await, Ok(n) if n > 0) {
+                    let _ = socket.write_all(b"+OK\r\n+OK\r\n").await;
+                }
+                while matches!(socket.read(&mut buf).await, Ok(n) if n > 0) {}
+            });
+        }
+    });
+    create_redis_client(&format!("redis://{}", addr)).await.unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fake_redis::{FakeRedis, Fault};
+
+    async fn incr(redis: &RedisClient) -> Result<i64, RedisError> {
+        redis.run(|mut conn| async move { conn.incr("counter", 1).await }).await
+    }
+
+    #[tokio::test]
+    async fn timed_out_operation_is_retried() {
+        let server = FakeRedis::start().await;
+        let redis = server.client().await;
+        server.fail_next(Fault::Silent);
+
+        assert_eq!(incr(&redis).await.unwrap(), 1);
+        assert_eq!(server.command_names(), ["INCRBY", "INCRBY"]);
+        assert_eq!(redis.inner.consecutive_failures.load(Ordering::Relaxed), 0);
+        assert!(redis.is_healthy());
+    }
+
+    #[tokio::test]
+    async fn redis_errors_are_not_retried() {
+        let server = FakeRedis::start().await;
+        let redis = server.client().await;
+        server.fail_next(Fault::Error("ERR value is not an integer"));
+
+        let err = incr(&redis).await.unwrap_err();
+        assert!(!is_transient(&err));
+        assert_eq!(server.command_names(), ["INCRBY"]);
+        assert_eq!(redis.inner.consecutive_failures.load(Ordering::Relaxed), 0);
+    }
+
+    #[tokio::test]
+    async fn run_once_never_retries() {
+        let server = FakeRedis::start().await;
+        let redis = server.client().await;
+        server.fail_next(Fault::Close);
+
+        let result: Result<i64, _> = redis
+            .run_once(|mut conn| async move { conn.incr("counter", 1).await })
+            .await;
+        assert!(is_transient(&result.unwrap_err()));
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(server.command_names(), ["INCRBY"]);
+        assert_eq!(redis.inner.consecutive_failures.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn marked_unhealthy_after_consecutive_failures() {
+        let server = FakeRedis::start().await;
+        let redis = server.client().await;
+        for _ in 0..2 * MAX_ATTEMPTS {
+            server.fail_next(Fault::Close);
+        }
+
+        // Dos operaciones de 3 intentos superan RECREATE_AFTER_FAILURES
+        assert!(incr(&redis).await.is_err());
+        assert!(redis.is_healthy());
+        assert!(incr(&redis).await.is_err());
+        assert!(!redis.is_healthy());
+
+        // Unhealthy: un único intento, sin backoff
+        let names_before = server.command_names().len();
+        server.fail_next(Fault::Close);
+        assert!(incr(&redis).await.is_err());
+        assert_eq!(server.command_names().len(), names_before + 1);
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(incr(&redis).await.unwrap(), 1);
+        assert!(redis.is_healthy());
+        assert_eq!(redis.inner.consecutive_failures.load(Ordering::Relaxed), 0);
+    }
 }
@@ -0,0 +1,160 @@
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+
+use crate::db::Backend;
+use crate::load_balancer::strategies::RandomBalancer;
+
+/// Backend canary y porcentaje del tráfico balanceado que recibe
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CanarySplit {
+    pub server_id: String,
+    /// Porcentaje entre 0 y 100, admite decimales (`0.5`)
+    pub percent: f64,
+}
+
+impl CanarySplit {
+    pub fn is_valid_percent(percent: f64) -> bool {
+        (0.0..=100.0).contains(&percent)
+    }
+}
+
+/// Reparto de tráfico hacia un backend canary, ajustable en tiempo de ejecución
+pub struct Canary {
+    split: RwLock<Option<CanarySplit>>,
+    rng: RandomBalancer,
+    /// Peticiones enviadas al canary desde el arranque
+    routed: AtomicU64,
+}
+
+impl Canary {
+    pub fn new(split: Option<CanarySplit>, seed: Option<u64>) -> Self {
+        Self {
+            split: RwLock::new(split),
+            rng: RandomBalancer::new(seed),
+            routed: AtomicU64::new(0),
+        }
+    }
+
+    pub fn get(&self) -> Option<CanarySplit> {
+        self.split.read().unwrap().clone()
+    }
+
+    /// Replaces (`Some`) or removes (`None`) the canary split
+    pub fn set(&self, split: Option<CanarySplit>) {
+        *self.split.write().unwrap() = split;
+    }
+
+    /// Takes the canary out of `healthy`, leaving the stable pool, and returns
+    /// it when this request falls within its percentage. The canary also gets
+    /// the request when it is the only healthy backend.
+    pub fn pick(&self, healthy: &mut Vec<Backend>) -> Option<Backend> {
+        let split = self.get()?;
+        let position = healthy.iter().position(|b| b.server_id == split.server_id)?;
+        let canary = healthy.remove(position);
+
+        // Resolución de centésimas de punto porcentual
+        let roll = (self.rng.next_u64() % 10_000) as f64 / 100.0;
+        if roll < split.percent || healthy.is_empty() {
+            self.routed.fetch_add(1, Ordering::Relaxed);
+            return Some(canary);
+        }
+        None
+    }
+
+    /// Current split and counter for the stats endpoint
+    pub fn snapshot(&self) -> serde_json::Value {
+        match self.get() {
+            Some(split) => serde_json::json!({
+                "server_id": split.server_id,
+                "percent": split.percent,
+                "routed": self.routed.load(Ordering::Relaxed),
+            }),
+            None => serde_json::Value::Null,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_backend;
+
+    fn canary(percent: f64) -> Canary {
+        let split = CanarySplit {
+            server_id: "canary".to_string(),
+            percent,
+        };
+        Canary::new(Some(split), Some(7))
+    }
+
+    fn pool() -> Vec<Backend> {
+        ["stable-a", "canary", "stable-b"].into_iter().map(test_backend).collect()
+    }
+
+    /// Requests routed to the canary out of `requests`
+    fn routed(canary: &Canary, requests: usize) -> usize {
+        (0..requests)
+            .filter(|_| {
+                let mut healthy = pool();
+                let picked = canary.pick(&mut healthy);
+                assert!(healthy.iter().all(|b| b.server_id != "canary"));
+                picked.is_some()
+            })
+            .count()
+    }
+
+    #[test]
+    fn zero_percent_never_routes_to_the_canary() {
+        let canary = canary(0.0);
+        assert_eq!(routed(&canary, 1000), 0);
+        assert_eq!(canary.snapshot()["routed"], 0);
+    }
+
+    #[test]
+    fn hundred_percent_always_routes_to_the_canary() {
+        let canary = canary(100.0);
+        assert_eq!(routed(&canary, 1000), 1000);
+        assert_eq!(canary.snapshot()["routed"], 1000);
+    }
+
+    #[test]
+    fn seeded_split_is_close_to_its_percentage() {
+        let routed_first = routed(&canary(25.0), 4000);
+        assert!((800..1200).contains(&routed_first), "{}", routed_first);
+        // La misma semilla repite exactamente el reparto
+        assert_eq!(routed(&canary(25.0), 4000), routed_first);
+    }
+
+    #[test]
+    fn canary_gets_the_request_when_it_is_the_only_healthy_backend() {
+        let canary = canary(0.0);
+        let mut healthy = vec![test_backend("canary")];
+
+        assert_eq!(canary.pick(&mut healthy).unwrap().server_id, "canary");
+        assert!(healthy.is_empty());
+    }
+
+    #[test]
+    fn unhealthy_or_missing_canary_leaves_the_pool_untouched() {
+        let mut healthy = vec![test_backend("stable-a"), test_backend("stable-b")];
+        assert!(canary(100.0).pick(&mut healthy).is_none());
+        assert_eq!(healthy.len(), 2);
+
+        let disabled = Canary::new(None, Some(7));
+        let mut healthy = pool();
+        assert!(disabled.pick(&mut healthy).is_none());
+        assert_eq!(healthy.len(), 3);
+        assert!(disabled.snapshot().is_null());
+    }
+
+    #[test]
+    fn percent_must_be_between_0_and_100() {
+        for percent in [0.0, 0.5, 100.0] {
+            assert!(CanarySplit::is_valid_percent(percent));
+        }
+        for percent in [-0.1, 100.1, f64::NAN] {
+            assert!(!CanarySplit::is_valid_percent(percent));
+        }
+    }
+}
@@ -0,0 +1,515 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::Request,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use http_body::{Frame, SizeHint};
+use serde::{Deserialize, Serialize};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+
+use crate::{cache::RedisClient, config::REDACTED};
+
+/// Lista de Redis con las peticiones capturadas cuando no hay `CAPTURE_FILE`
+pub const CAPTURE_REDIS_KEY: &str = "capture:requests";
+
+/// Capturas pendientes de escribir; si el writer no da abasto se descartan
+const CAPTURE_QUEUE_CAPACITY: usize = 1024;
+
+/// Headers ocultados siempre en las capturas, además de los de `DEBUG_HEADER_REDACT`
+const CAPTURE_REDACTED_HEADERS: [&str; 3] = ["cookie", "proxy-authorization", "x-forwarded-for"];
+
+/// Parámetros de query cuyo valor se oculta en las capturas
+const CAPTURE_REDACTED_PARAMS: [&str; 6] = ["token", "access_token", "signature", "sig", "key", "secret"];
+
+/// Captura de una muestra de las peticiones entrantes para reproducir bugs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CaptureConfig {
+    pub enabled: bool,
+    /// Fracción de peticiones capturadas (0.0-1.0)
+    pub sample_rate: f64,
+    /// Archivo JSON Lines donde se añaden las capturas; sin él se guardan en Redis
+    pub file: Option<String>,
+    /// Bytes del body guardados como máximo (0 = sin body)
+    pub max_body_bytes: usize,
+    /// Capturas conservadas en la lista de Redis
+    pub redis_max_entries: usize,
+}
+
+impl Default for CaptureConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            sample_rate: 0.01,
+            file: None,
+            max_body_bytes: 0,
+            redis_max_entries: 1000,
+        }
+    }
+}
+
+/// Records a sample of incoming requests, with sensitive headers and query
+/// parameters redacted, to a file or a Redis list for later replay
+pub struct RequestCapture {
+    config: CaptureConfig,
+    /// Headers ocultados, en minúsculas
+    redact: Vec<String>,
+    sender: Option<mpsc::Sender<serde_json::Value>>,
+    counters: Arc<CaptureCounters>,
+}
+
+#[derive(Debug, Default)]
+struct CaptureCounters {
+    captured: AtomicU64,
+    /// Capturas descartadas con la cola llena
+    dropped: AtomicU64,
+}
+
+impl CaptureCounters {
+    fn send(&self, sender: &mpsc::Sender<serde_json::Value>, record: serde_json::Value) {
+        match sender.try_send(record) {
+            Ok(()) => self.captured.fetch_add(1, Ordering::Relaxed),
+            Err(_) => self.dropped.fetch_add(1, Ordering::Relaxed),
+        };
+    }
+}
+
+impl RequestCapture {
+    /// Starts the background writer when capture is enabled
+    pub fn new(config: CaptureConfig, redact_headers: &[String], redis: RedisClient) -> Self {
+        let mut redact = redact_headers.to_vec();
+        redact.extend(CAPTURE_REDACTED_HEADERS.iter().map(|h| h.to_string()));
+
+        let sender = config.enabled.then(|| {
+            let (sender, receiver) = mpsc::channel(CAPTURE_QUEUE_CAPACITY);
+            match config.file.clone() {
+                Some(path) => {
+                    tracing::info!("Capturing {:.1}% of requests to {}", config.sample_rate * 100.0, path);
+                    tokio::spawn(write_to_file(path, receiver));
+                }
+                None => {
+                    tracing::info!(
+                        "Capturing {:.1}% of requests to Redis list {}",
+                        config.sample_rate * 100.0,
+                        CAPTURE_REDIS_KEY
+                    );
+                    tokio::spawn(write_to_redis(redis, config.redis_max_entries, receiver));
+                }
+            }
+            sender
+        });
+
+        Self {
+            config,
+            redact,
+            sender,
+            counters: Arc::new(CaptureCounters::default()),
+        }
+    }
+
+    /// Captures `req` if it is sampled. Without a body cap the record is
+    /// queued right away; otherwise the body is copied as it streams to the
+    /// backend and the record is queued once it ends (or is dropped).
+    pub fn capture(&self, req: &mut Request) {
+        let Some(sender) = self.sender.as_ref() else {
+            return;
+        };
+        if !sampled(self.config.sample_rate) {
+            return;
+        }
+
+        let record = self.record(req);
+        if self.config.max_body_bytes == 0 || http_body::Body::size_hint(req.body()).exact() == Some(0) {
+            self.counters.send(sender, record);
+            return;
+        }
+
+        let inner = std::mem::take(req.body_mut());
+        *req.body_mut() = Body::new(CapturingBody {
+            inner,
+            record: Some(record),
+            body: Vec::new(),
+            body_bytes: 0,
+            max_body_bytes: self.config.max_body_bytes,
+            sender: sender.clone(),
+            counters: self.counters.clone(),
+        });
+    }
+
+    /// Method, URI and headers of the request, redacted
+    fn record(&self, req: &Request) -> serde_json::Value {
+        let headers: Vec<_> = req
+            .headers()
+            .iter()
+            .map(|(name, value)| {
+                let value = if self.redact.iter().any(|r| r == name.as_str()) {
+                    REDACTED
+                } else {
+                    value.to_str().unwrap_or("<binary>")
+                };
+                [name.as_str(), value]
+            })
+            .collect();
+
+        let mut uri = req.uri().path().to_string();
+        if let Some(query) = req.uri().query() {
+            uri.push('?');
+            uri.push_str(&redact_query(query));
+        }
+
+        serde_json::json!({
+            "timestamp": std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or(0),
+            "method": req.method().as_str(),
+            "uri": uri,
+            "version": format!("{:?}", req.version()),
+            "headers": headers,
+        })
+    }
+
+    /// Counters for the stats endpoint, `null` when capture is disabled
+    pub fn snapshot(&self) -> serde_json::Value {
+        if self.sender.is_none() {
+            return serde_json::Value::Null;
+        }
+        serde_json::json!({
+            "sample_rate": self.config.sample_rate,
+            "captured": self.counters.captured.load(Ordering::Relaxed),
+            "dropped": self.counters.dropped.load(Ordering::Relaxed),
+        })
+    }
+}
+
+/// Decides whether the current request is sampled
+fn sampled(rate: f64) -> bool {
+    if rate <= 0.0 {
+        return false;
+    }
+    if rate >= 1.0 {
+        return true;
+    }
+
+    use std::collections::hash_map::RandomState;
+    use std::hash::BuildHasher;
+
+    let hash = RandomState::new().hash_one(std::time::SystemTime::now());
+    (hash as f64 / u64::MAX as f64) < rate
+}
+
+/// Query string with the values of sensitive parameters replaced by `***`
+fn redact_query(query: &str) -> String {
+    query
+        .split('&')
+        .map(|pair| match pair.split_once('=') {
+            Some((name, _)) if CAPTURE_REDACTED_PARAMS.iter().any(|p| name.eq_ignore_ascii_case(p)) => {
+                format!("{}={}", name, REDACTED)
+            }
+            _ => pair.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+async fn write_to_file(path: String, mut receiver: mpsc::Receiver<serde_json::Value>) {
+    let file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .await;
+    let mut file = match file {
+        Ok(file) => file,
+        Err(e) => {
+            tracing::error!("Failed to open capture file {}, capture disabled: {}", path, e);
+            return;
+        }
+    };
+
+    while let Some(record) = receiver.recv().await {
+        let mut line = record.to_string();
+        line.push('\n');
+        if let Err(e) = file.write_all(line.as_bytes()).await {
+            tracing::warn!("Failed to write request capture to {}: {}", path, e);
+        }
+    }
+}
+
+async fn write_to_redis(redis: RedisClient, max_entries: usize, mut receiver: mpsc::Receiver<serde_json::Value>) {
+    let last = max_entries.max(1) as isize - 1;
+    while let Some(record) = receiver.recv().await {
+        let record = record.to_string();
+        // LPUSH no es idempotente: un reintento tras perder la respuesta la duplicaría
+        let result: Result<(), _> = redis
+            .run_once(|mut conn| async move {
+                redis::pipe()
+                    .lpush(CAPTURE_REDIS_KEY, record)
+                    .ignore()
+                    .ltrim(CAPTURE_REDIS_KEY, 0, last)
+                    .ignore()
+                    .query_async(&mut conn)
+                    .await
+            })
+            .await;
+        if let Err(e) = result {
+            tracing::warn!("Failed to store request capture in Redis: {}", e);
+        }
+    }
+}
+
+/// Body que copia hasta `max_body_bytes` mientras se transmite y encola la
+/// captura al terminar, o al descartarse si la subida se corta
+struct CapturingBody {
+    inner: Body,
+    record: Option<serde_json::Value>,
+    body: Vec<u8>,
+    body_bytes: u64,
+    max_body_bytes: usize,
+    sender: mpsc::Sender<serde_json::Value>,
+    counters: Arc<CaptureCounters>,
+}
+
+impl http_body::Body for CapturingBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let frame = ready!(Pin::new(&mut self.inner).poll_frame(cx));
+        if let Some(data) = frame.as_ref().and_then(|f| f.as_ref().ok()).and_then(Frame::data_ref) {
+            let room = self.max_body_bytes.saturating_sub(self.body.len());
+            self.body.extend_from_slice(&data[..room.min(data.len())]);
+            self.body_bytes += data.len() as u64;
+        }
+        Poll::Ready(frame)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl Drop for CapturingBody {
+    fn drop(&mut self) {
+        let Some(mut record) = self.record.take() else {
+            return;
+        };
+        record["body_base64"] = STANDARD.encode(&self.body).into();
+        record["body_bytes"] = self.body_bytes.into();
+        record["body_truncated"] = (self.body_bytes > self.body.len() as u64).into();
+        self.counters.send(&self.sender, record);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fake_redis::{FakeRedis, Fault};
+    use http_body_util::BodyExt;
+    use std::time::Duration;
+
+    fn capture_config(max_body_bytes: usize) -> CaptureConfig {
+        CaptureConfig {
+            enabled: true,
+            sample_rate: 1.0,
+            file: None,
+            max_body_bytes,
+            redis_max_entries: 2,
+        }
+    }
+
+    fn request(body: &'static str) -> Request {
+        Request::builder()
+            .method("POST")
+            .uri("/upload/abc?token=t0p&page=2&Signature=xyz")
+            .header("authorization", "Bearer secret")
+            .header("cookie", "session=s3cret")
+            .header("content-type", "text/plain")
+            .header("content-length", body.len())
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    async fn captured(redis: &RedisClient, count: usize) -> Vec<serde_json::Value> {
+        for _ in 0..100 {
+            let entries: Vec<String> = redis
+                .run(|mut conn| async move {
+                    redis::cmd("LRANGE")
+                        .arg(CAPTURE_REDIS_KEY)
+                        .arg(0)
+                        .arg(-1)
+                        .query_async(&mut conn)
+                        .await
+                })
+                .await
+                .unwrap();
+            if entries.len() >= count {
+                return entries.iter().map(|e| serde_json::from_str(e).unwrap()).collect();
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("expected {} captured requests", count);
+    }
+
+    #[tokio::test]
+    async fn captured_request_is_redacted() {
+        let server = FakeRedis::start().await;
+        let redis = server.client().await;
+        let capture = RequestCapture::new(capture_config(0), &["authorization".to_string()], redis.clone());
+
+        let mut req = request("hello");
+        capture.capture(&mut req);
+
+        let record = &captured(&redis, 1).await[0];
+        assert_eq!(record["method"], "POST");
+        assert_eq!(record["uri"], "/upload/abc?token=***&page=2&Signature=***");
+        let headers = record["headers"].as_array().unwrap();
+        let header = |name: &str| {
+            headers
+                .iter()
+                .find(|h| h[0] == name)
+                .map(|h| h[1].as_str().unwrap().to_string())
+        };
+        assert_eq!(header("authorization").as_deref(), Some(REDACTED));
+        assert_eq!(header("cookie").as_deref(), Some(REDACTED));
+        assert_eq!(header("content-type").as_deref(), Some("text/plain"));
+        assert!(record.get("body_base64").is_none());
+        assert!(!record.to_string().contains("s3cret"));
+        assert!(!record.to_string().contains("t0p"));
+        assert_eq!(capture.snapshot()["captured"], 1);
+    }
+
+    #[tokio::test]
+    async fn captured_body_is_cut_at_the_cap_without_altering_the_upload() {
+        let server = FakeRedis::start().await;
+        let redis = server.client().await;
+        let capture = RequestCapture::new(capture_config(5), &[], redis.clone());
+
+        let mut req = request("hello world");
+        capture.capture(&mut req);
+        let forwarded = req.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(&forwarded[..], b"hello world");
+
+        let record = &captured(&redis, 1).await[0];
+        assert_eq!(record["body_base64"], STANDARD.encode("hello"));
+        assert_eq!(record["body_bytes"], 11);
+        assert_eq!(record["body_truncated"], true);
+    }
+
+    #[tokio::test]
+    async fn redis_list_keeps_the_latest_captures() {
+        let server = FakeRedis::start().await;
+        let redis = server.client().await;
+        let capture = RequestCapture::new(capture_config(0), &[], redis.clone());
+
+        for path in ["/a", "/b", "/c"] {
+            let mut req = Request::builder().uri(path).body(Body::empty()).unwrap();
+            capture.capture(&mut req);
+        }
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let records = captured(&redis, 2).await;
+        let uris: Vec<_> = records.iter().map(|r| r["uri"].as_str().unwrap()).collect();
+        assert_eq!(uris, ["/c", "/b"]);
+    }
+
+    #[tokio::test]
+    async fn capture_whose_reply_was_lost_is_not_pushed_twice() {
+        let server = FakeRedis::start().await;
+        let redis = server.client().await;
+        let config = CaptureConfig {
+            redis_max_entries: 10,
+            ..capture_config(0)
+        };
+        let capture = RequestCapture::new(config, &[], redis.clone());
+
+        // El LPUSH se aplica pero la conexión se cierra antes de responder
+        server.fail_next(Fault::Lost);
+        let mut req = Request::builder().uri("/lost").body(Body::empty()).unwrap();
+        capture.capture(&mut req);
+        for _ in 0..100 {
+            if server.command_names().contains(&"LPUSH".to_string()) {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        captured(&redis, 1).await;
+
+        let mut req = Request::builder().uri("/next").body(Body::empty()).unwrap();
+        capture.capture(&mut req);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let records = captured(&redis, 2).await;
+        let uris: Vec<_> = records.iter().map(|r| r["uri"].as_str().unwrap()).collect();
+        assert_eq!(uris, ["/next", "/lost"]);
+    }
+
+    #[tokio::test]
+    async fn disabled_or_unsampled_capture_records_nothing() {
+        let server = FakeRedis::start().await;
+        let redis = server.client().await;
+
+        let disabled = RequestCapture::new(CaptureConfig::default(), &[], redis.clone());
+        assert!(disabled.snapshot().is_null());
+
+        let unsampled = RequestCapture::new(
+            CaptureConfig {
+                sample_rate: 0.0,
+                ..capture_config(0)
+            },
+            &[],
+            redis.clone(),
+        );
+        let mut req = request("hello");
+        disabled.capture(&mut req);
+        unsampled.capture(&mut req);
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(unsampled.snapshot()["captured"], 0);
+        assert!(!server.command_names().contains(&"LPUSH".to_string()));
+    }
+
+    #[tokio::test]
+    async fn captures_are_appended_to_the_file() {
+        let path = std::env::temp_dir().join(format!("vk-gateway-capture-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let server = FakeRedis::start().await;
+        let config = CaptureConfig {
+            file: Some(path.to_str().unwrap().to_string()),
+            ..capture_config(0)
+        };
+        let capture = RequestCapture::new(config, &[], server.client().await);
+
+        let mut req = request("hello");
+        capture.capture(&mut req);
+
+        let mut contents = String::new();
+        for _ in 0..100 {
+            contents = std::fs::read_to_string(&path).unwrap_or_default();
+            if contents.ends_with('\n') {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        std::fs::remove_file(&path).unwrap();
+
+        let record: serde_json::Value = serde_json::from_str(contents.trim_end()).unwrap();
+        assert_eq!(record["uri"], "/upload/abc?token=***&page=2&Signature=***");
+        assert!(!contents.contains("s3cret"));
+    }
+
+    #[test]
+    fn query_redaction_keeps_other_params() {
+        assert_eq!(redact_query("a=1&key=k&flag&SECRET=s"), "a=1&key=***&flag&SECRET=***");
+        assert_eq!(redact_query("tokens=1"), "tokens=1");
+    }
+}
@@ -0,0 +1,266 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::Request,
+    http::{HeaderMap, StatusCode},
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use http_body::{Frame, SizeHint};
+use md5::Md5;
+use sha2::{Digest, Sha256};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+
+/// MD5 del body en base64 (RFC 1864)
+const CONTENT_MD5: &str = "content-md5";
+
+/// SHA-256 del body en hexadecimal o base64
+const CHECKSUM_SHA256: &str = "x-checksum-sha256";
+
+/// Whether the body of a verified request turned out not to match its checksum
+#[derive(Debug, Clone, Default)]
+pub struct ChecksumOutcome(Arc<AtomicBool>);
+
+impl ChecksumOutcome {
+    pub fn mismatched(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Digests announced by the client, decoded to raw bytes
+struct Expected {
+    md5: Option<Vec<u8>>,
+    sha256: Option<Vec<u8>>,
+}
+
+fn decode_digest(value: &str, len: usize) -> Option<Vec<u8>> {
+    let value = value.trim();
+    let bytes = if value.len() == len * 2 {
+        hex::decode(value).ok()?
+    } else {
+        STANDARD.decode(value).ok()?
+    };
+    (bytes.len() == len).then_some(bytes)
+}
+
+fn expected(headers: &HeaderMap) -> Result<Option<Expected>, StatusCode> {
+    let header = |name: &str, len: usize| match headers.get(name) {
+        None => Ok(None),
+        Some(value) => value
+            .to_str()
+            .ok()
+            .and_then(|v| decode_digest(v, len))
+            .map(Some)
+            .ok_or_else(|| {
+                tracing::warn!("Rejected request with a malformed {} header", name);
+                StatusCode::BAD_REQUEST
+            }),
+    };
+
+    let md5 = header(CONTENT_MD5, 16)?;
+    let sha256 = header(CHECKSUM_SHA256, 32)?;
+    if md5.is_none() && sha256.is_none() {
+        return Ok(None);
+    }
+    Ok(Some(Expected { md5, sha256 }))
+}
+
+/// Verifies the request body against `Content-MD5` and/or `X-Checksum-Sha256`.
+///
+/// The body keeps streaming to the backend while the digests are computed, but
+/// its last frame is held back until they are checked: on a mismatch the body
+/// ends with an error instead, so the backend sees an aborted upload and never
+/// a complete corrupted one. The returned outcome tells the caller to answer
+/// `400` in that case. Malformed checksum headers are rejected right away.
+pub fn verify_body(req: &mut Request) -> Result<ChecksumOutcome, StatusCode> {
+    let outcome = ChecksumOutcome::default();
+    let Some(expected) = expected(req.headers())? else {
+        return Ok(outcome);
+    };
+
+    let inner = std::mem::take(req.body_mut());
+    *req.body_mut() = Body::new(VerifyingBody {
+        inner,
+        md5: expected.md5.as_ref().map(|_| Md5::new()),
+        sha256: expected.sha256.as_ref().map(|_| Sha256::new()),
+        expected,
+        outcome: outcome.clone(),
+        held: None,
+        done: false,
+    });
+    Ok(outcome)
+}
+
+/// Body que calcula los digests mientras se transmite y los compara al final
+struct VerifyingBody {
+    inner: Body,
+    expected: Expected,
+    md5: Option<Md5>,
+    sha256: Option<Sha256>,
+    outcome: ChecksumOutcome,
+    /// Último frame leído, retenido hasta saber si hay otro o si el body terminó.
+    /// Con `Content-Length` hyper deja de leer tras el último byte, así que el
+    /// final del body no puede esperar a que se pida un frame más.
+    held: Option<Frame<Bytes>>,
+    done: bool,
+}
+
+impl VerifyingBody {
+    fn matches(&mut self) -> bool {
+        let md5_ok = match (self.md5.take(), &self.expected.md5) {
+            (Some(hasher), Some(expected)) => hasher.finalize().as_slice() == expected.as_slice(),
+            _ => true,
+        };
+        let sha256_ok = match (self.sha256.take(), &self.expected.sha256) {
+            (Some(hasher), Some(expected)) => hasher.finalize().as_slice() == expected.as_slice(),
+            _ => true,
+        };
+        md5_ok && sha256_ok
+    }
+}
+
+impl http_body::Body for VerifyingBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        loop {
+            if self.done {
+                return Poll::Ready(self.held.take().map(Ok));
+            }
+
+            match ready!(Pin::new(&mut self.inner).poll_frame(cx)) {
+                Some(Ok(frame)) => {
+                    if let Some(data) = frame.data_ref() {
+                        if let Some(hasher) = self.md5.as_mut() {
+                            hasher.update(data);
+                        }
+                        if let Some(hasher) = self.sha256.as_mut() {
+                            hasher.update(data);
+                        }
+                    }
+                    if let Some(previous) = self.held.replace(frame) {
+                        return Poll::Ready(Some(Ok(previous)));
+                    }
+                }
+                Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+                None => {
+                    self.done = true;
+                    if !self.matches() {
+                        self.held = None;
+                        self.outcome.0.store(true, Ordering::Relaxed);
+                        tracing::warn!("Request body does not match its checksum, aborting upload");
+                        return Poll::Ready(Some(Err(axum::Error::new("request body checksum mismatch"))));
+                    }
+                }
+            }
+        }
+    }
+
+    // Nunca se anuncia el final antes de haber comprobado los digests
+    fn is_end_stream(&self) -> bool {
+        self.done && self.held.is_none()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::{BodyExt, StreamBody};
+    use std::convert::Infallible;
+
+    const MD5_BASE64: &str = "XrY7u+Ae7tCTyyK7j1rNww==";
+    const MD5_HEX: &str = "5eb63bbbe01eeed093cb22bb8f5acdc3";
+    const SHA256_HEX: &str = "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9";
+    const SHA256_BASE64: &str = "uU0nuZNNPgilLlLX2n2r+sSE7+N6U4DukIj3rOLvzek=";
+
+    /// Request whose body is "hello world", split in two frames
+    fn upload(headers: &[(&str, &str)]) -> Request {
+        let frames = ["hello ", "world"].map(|chunk| Ok::<_, Infallible>(Frame::data(Bytes::from_static(chunk.as_bytes()))));
+        let mut builder = Request::builder().method("PUT").uri("/files/a");
+        for (name, value) in headers {
+            builder = builder.header(*name, *value);
+        }
+        builder.body(Body::new(StreamBody::new(futures::stream::iter(frames)))).unwrap()
+    }
+
+    /// Verifies and reads the body; `Err` when it was aborted
+    async fn read(headers: &[(&str, &str)]) -> (Result<Bytes, axum::Error>, bool) {
+        let mut req = upload(headers);
+        let outcome = verify_body(&mut req).unwrap();
+        let body = req.into_body().collect().await.map(|b| b.to_bytes());
+        (body, outcome.mismatched())
+    }
+
+    #[tokio::test]
+    async fn matching_digests_pass_the_body_through() {
+        for headers in [
+            [(CONTENT_MD5, MD5_BASE64)].as_slice(),
+            &[(CONTENT_MD5, MD5_HEX)],
+            &[(CHECKSUM_SHA256, SHA256_HEX)],
+            &[(CHECKSUM_SHA256, SHA256_BASE64)],
+            &[(CONTENT_MD5, MD5_BASE64), (CHECKSUM_SHA256, SHA256_HEX)],
+        ] {
+            let (body, mismatched) = read(headers).await;
+            assert_eq!(body.unwrap(), "hello world", "{:?}", headers);
+            assert!(!mismatched);
+        }
+    }
+
+    #[tokio::test]
+    async fn mismatched_digest_aborts_the_body() {
+        let wrong_sha256 = SHA256_HEX.replace('b', "c");
+        for headers in [
+            [(CONTENT_MD5, "AAAAAAAAAAAAAAAAAAAAAA==")].as_slice(),
+            &[(CHECKSUM_SHA256, wrong_sha256.as_str())],
+            // Basta con que falle uno de los dos
+            &[(CONTENT_MD5, MD5_BASE64), (CHECKSUM_SHA256, wrong_sha256.as_str())],
+        ] {
+            let (body, mismatched) = read(headers).await;
+            assert!(body.is_err(), "{:?}", headers);
+            assert!(mismatched);
+        }
+    }
+
+    #[tokio::test]
+    async fn last_frame_is_held_until_the_digest_is_checked() {
+        let mut req = upload(&[(CHECKSUM_SHA256, SHA256_HEX)]);
+        verify_body(&mut req).unwrap();
+        let mut body = req.into_body();
+
+        // El primer frame sale en cuanto llega el segundo; el último espera al final
+        let first = body.frame().await.unwrap().unwrap().into_data().unwrap();
+        assert_eq!(first, "hello ");
+        assert!(!http_body::Body::is_end_stream(&body));
+        let last = body.frame().await.unwrap().unwrap().into_data().unwrap();
+        assert_eq!(last, "world");
+        assert!(body.frame().await.is_none());
+    }
+
+    #[test]
+    fn malformed_checksum_headers_are_400() {
+        for headers in [
+            [(CONTENT_MD5, "not base64!")].as_slice(),
+            &[(CONTENT_MD5, SHA256_HEX)],
+            &[(CHECKSUM_SHA256, MD5_HEX)],
+            &[(CHECKSUM_SHA256, "zz")],
+        ] {
+            assert_eq!(verify_body(&mut upload(headers)).unwrap_err(), StatusCode::BAD_REQUEST, "{:?}", headers);
+        }
+    }
+
+    #[test]
+    fn requests_without_checksum_are_untouched() {
+        let mut req = upload(&[]);
+        let outcome = verify_body(&mut req).unwrap();
+        assert!(!outcome.mismatched());
+    }
+}
@@ -0,0 +1,571 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::Request,
+    http::{header, HeaderMap, HeaderValue},
+    response::Response,
+};
+use flate2::{
+    write::{GzDecoder, GzEncoder, ZlibDecoder},
+    Compression,
+};
+use http_body::{Frame, SizeHint};
+use std::io::Write;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+
+use crate::db::Backend;
+
+/// Tipos que ya van comprimidos: volver a comprimirlos solo gasta CPU
+const INCOMPRESSIBLE_TYPES: [&str; 10] = [
+    "image/",
+    "video/",
+    "audio/",
+    "application/zip",
+    "application/gzip",
+    "application/x-gzip",
+    "application/zstd",
+    "application/x-7z-compressed",
+    "application/x-rar-compressed",
+    "application/grpc",
+];
+
+/// Headers de checksum calculados sobre el body original, que dejaría de coincidir
+const CHECKSUM_HEADERS: [&str; 2] = ["content-md5", "x-checksum-sha256"];
+
+#[derive(Debug, Default)]
+struct CompressionCounters {
+    requests: AtomicU64,
+    bytes_in: AtomicU64,
+    bytes_out: AtomicU64,
+}
+
+/// Compresión gzip de los bodies de subida hacia los backends que la aceptan
+/// (`compress_requests`), a cambio de CPU ahorra ancho de banda de salida
+#[derive(Debug)]
+pub struct RequestCompression {
+    /// Bodies con un tamaño conocido menor que este se envían sin comprimir
+    min_bytes: u64,
+    counters: Arc<CompressionCounters>,
+}
+
+impl RequestCompression {
+    pub fn new(min_bytes: u64) -> Self {
+        Self {
+            min_bytes,
+            counters: Arc::new(CompressionCounters::default()),
+        }
+    }
+
+    /// Gzips the body of `req` as it streams when `backend` opted in and the
+    /// body is worth compressing. The client's encoding is never replaced, so
+    /// an already encoded body is not compressed twice.
+    pub fn compress_body(&self, backend: &Backend, req: &mut Request) {
+        if !backend.compresses_requests() || !self.should_compress(req) {
+            return;
+        }
+
+        self.counters.requests.fetch_add(1, Ordering::Relaxed);
+
+        let headers = req.headers_mut();
+        headers.remove(header::CONTENT_ENCODING);
+        // La longitud comprimida no se conoce hasta el final: se envía chunked
+        headers.remove(header::CONTENT_LENGTH);
+        headers.insert(header::CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+
+        let inner = std::mem::take(req.body_mut());
+        *req.body_mut() = Body::new(GzipBody {
+            inner,
+            encoder: Some(GzEncoder::new(Vec::new(), Compression::default())),
+            trailers: None,
+            counters: self.counters.clone(),
+        });
+    }
+
+    fn should_compress(&self, req: &Request) -> bool {
+        let headers = req.headers();
+        if !identity_encoded(headers) || CHECKSUM_HEADERS.iter().any(|h| headers.contains_key(*h)) {
+            return false;
+        }
+
+        let content_type = headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("")
+            .to_ascii_lowercase();
+        if INCOMPRESSIBLE_TYPES.iter().any(|t| content_type.starts_with(t)) {
+            return false;
+        }
+
+        // Con tamaño desconocido (chunked) se comprime; sin body nunca
+        let body = req.body();
+        if http_body::Body::is_end_stream(body) {
+            return false;
+        }
+        http_body::Body::size_hint(body)
+            .exact()
+            .is_none_or(|len| len >= self.min_bytes.max(1))
+    }
+
+    /// Counters for the stats endpoint
+    pub fn snapshot(&self) -> serde_json::Value {
+        let bytes_in = self.counters.bytes_in.load(Ordering::Relaxed);
+        let bytes_out = self.counters.bytes_out.load(Ordering::Relaxed);
+        serde_json::json!({
+            "requests": self.counters.requests.load(Ordering::Relaxed),
+            "bytes_in": bytes_in,
+            "bytes_out": bytes_out,
+            "ratio": (bytes_in > 0).then(|| bytes_out as f64 / bytes_in as f64),
+        })
+    }
+}
+
+/// Whether the body carries no content coding, explicitly or by omission
+fn identity_encoded(headers: &HeaderMap) -> bool {
+    headers.get_all(header::CONTENT_ENCODING).iter().all(|value| {
+        value
+            .to_str()
+            .is_ok_and(|v| v.split(',').all(|coding| coding.trim().eq_ignore_ascii_case("identity")))
+    })
+}
+
+/// Body que comprime con gzip los frames de datos a medida que llegan; los
+/// trailers se envían después del final del stream comprimido
+struct GzipBody {
+    inner: Body,
+    /// `None` una vez cerrado el stream gzip
+    encoder: Option<GzEncoder<Vec<u8>>>,
+    trailers: Option<HeaderMap>,
+    counters: Arc<CompressionCounters>,
+}
+
+impl GzipBody {
+    /// Bytes comprimidos pendientes de enviar
+    fn take_output(&mut self) -> Bytes {
+        let output = self.encoder.as_mut().map(|e| std::mem::take(e.get_mut())).unwrap_or_default();
+        self.counters.bytes_out.fetch_add(output.len() as u64, Ordering::Relaxed);
+        Bytes::from(output)
+    }
+
+    /// Closes the gzip stream and returns its last bytes
+    fn finish(&mut self) -> Result<Bytes, axum::Error> {
+        let Some(encoder) = self.encoder.take() else {
+            return Ok(Bytes::new());
+        };
+        let output = encoder.finish().map_err(axum::Error::new)?;
+        self.counters.bytes_out.fetch_add(output.len() as u64, Ordering::Relaxed);
+        Ok(Bytes::from(output))
+    }
+}
+
+impl http_body::Body for GzipBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        loop {
+            if self.encoder.is_none() {
+                return Poll::Ready(self.trailers.take().map(|t| Ok(Frame::trailers(t))));
+            }
+
+            match ready!(Pin::new(&mut self.inner).poll_frame(cx)) {
+                Some(Ok(frame)) => match frame.into_data() {
+                    Ok(data) => {
+                        self.counters.bytes_in.fetch_add(data.len() as u64, Ordering::Relaxed);
+                        if let Some(encoder) = self.encoder.as_mut() {
+                            encoder.write_all(&data).map_err(axum::Error::new)?;
+                        }
+                        // El encoder acumula hasta tener un bloque completo
+                        let output = self.take_output();
+                        if !output.is_empty() {
+                            return Poll::Ready(Some(Ok(Frame::data(output))));
+                        }
+                    }
+                    Err(frame) => {
+                        self.trailers = frame.into_trailers().ok();
+                        let output = self.finish()?;
+                        return Poll::Ready(Some(Ok(Frame::data(output))));
+                    }
+                },
+                Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+                None => {
+                    let output = self.finish()?;
+                    return Poll::Ready(Some(Ok(Frame::data(output))));
+                }
+            }
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.encoder.is_none() && self.trailers.is_none()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        SizeHint::default()
+    }
+}
+
+/// Decompresses, as it streams, a gzip or deflate response whose coding the
+/// client did not accept (`IDENTITY_ENCODING_FALLBACK`). `accept_encoding` is
+/// the client's header: without one only `identity` is assumed safe. Other
+/// codings cannot be decoded here and go through unchanged.
+pub fn decode_unaccepted(accept_encoding: Option<&HeaderValue>, response: Response) -> Response {
+    let Some(coding) = response
+        .headers()
+        .get(header::CONTENT_ENCODING)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.trim().to_ascii_lowercase())
+    else {
+        return response;
+    };
+    let coding = if coding == "x-gzip" { "gzip".to_string() } else { coding };
+    if coding == "identity" || accept_encoding.is_some_and(|accepted| accepts(accepted, &coding)) {
+        return response;
+    }
+
+    let decoder = match coding.as_str() {
+        "gzip" => Decoder::Gzip(GzDecoder::new(Vec::new())),
+        // El "deflate" de HTTP es el formato zlib (RFC 9110)
+        "deflate" => Decoder::Deflate(ZlibDecoder::new(Vec::new())),
+        _ => {
+            tracing::warn!("Cannot decode a {:?} response the client did not accept", coding);
+            return response;
+        }
+    };
+
+    let (mut parts, inner) = response.into_parts();
+    parts.headers.remove(header::CONTENT_ENCODING);
+    // La longitud descomprimida no se conoce hasta el final: se envía chunked
+    parts.headers.remove(header::CONTENT_LENGTH);
+    let body = DecodingBody {
+        inner,
+        decoder: Some(decoder),
+        written: false,
+        trailers: None,
+    };
+    Response::from_parts(parts, Body::new(body))
+}
+
+/// Whether an `Accept-Encoding` value allows `coding`, by name or through `*`
+/// (`q=0` rejects it)
+fn accepts(accept_encoding: &HeaderValue, coding: &str) -> bool {
+    let Ok(accept_encoding) = accept_encoding.to_str() else {
+        return false;
+    };
+    let mut wildcard = false;
+    for entry in accept_encoding.split(',') {
+        let mut params = entry.split(';');
+        let name = params.next().unwrap_or("").trim();
+        let quality = params
+            .find_map(|p| p.trim().strip_prefix("q="))
+            .and_then(|q| q.trim().parse::<f32>().ok())
+            .unwrap_or(1.0);
+        if name.eq_ignore_ascii_case(coding) || (coding == "gzip" && name.eq_ignore_ascii_case("x-gzip")) {
+            return quality > 0.0;
+        }
+        if name == "*" {
+            wildcard = quality > 0.0;
+        }
+    }
+    wildcard
+}
+
+/// Decodificadores de las codificaciones que el gateway sabe deshacer
+enum Decoder {
+    Gzip(GzDecoder<Vec<u8>>),
+    Deflate(ZlibDecoder<Vec<u8>>),
+}
+
+impl Decoder {
+    fn write_all(&mut self, data: &[u8]) -> std::io::Result<()> {
+        match self {
+            Decoder::Gzip(d) => d.write_all(data),
+            Decoder::Deflate(d) => d.write_all(data),
+        }
+    }
+
+    /// Bytes descomprimidos pendientes de enviar
+    fn take_output(&mut self) -> Bytes {
+        let output = match self {
+            Decoder::Gzip(d) => d.get_mut(),
+            Decoder::Deflate(d) => d.get_mut(),
+        };
+        Bytes::from(std::mem::take(output))
+    }
+
+    fn finish(self) -> std::io::Result<Vec<u8>> {
+        match self {
+            Decoder::Gzip(d) => d.finish(),
+            Decoder::Deflate(d) => d.finish(),
+        }
+    }
+}
+
+/// Body que descomprime los frames de datos a medida que llegan; los trailers
+/// se envían después del último bloque descomprimido
+struct DecodingBody {
+    inner: Body,
+    /// `None` una vez terminado el stream comprimido
+    decoder: Option<Decoder>,
+    /// Un body vacío (HEAD, 304) no es un stream comprimido truncado
+    written: bool,
+    trailers: Option<HeaderMap>,
+}
+
+impl DecodingBody {
+    /// Closes the compressed stream and returns its last decoded bytes
+    fn finish(&mut self) -> Result<Bytes, axum::Error> {
+        let Some(decoder) = self.decoder.take() else {
+            return Ok(Bytes::new());
+        };
+        if !self.written {
+            return Ok(Bytes::new());
+        }
+        decoder.finish().map(Bytes::from).map_err(axum::Error::new)
+    }
+}
+
+impl http_body::Body for DecodingBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        loop {
+            if self.decoder.is_none() {
+                return Poll::Ready(self.trailers.take().map(|t| Ok(Frame::trailers(t))));
+            }
+
+            match ready!(Pin::new(&mut self.inner).poll_frame(cx)) {
+                Some(Ok(frame)) => match frame.into_data() {
+                    Ok(data) => {
+                        self.written |= !data.is_empty();
+                        let Some(decoder) = self.decoder.as_mut() else {
+                            continue;
+                        };
+                        decoder.write_all(&data).map_err(axum::Error::new)?;
+                        let output = decoder.take_output();
+                        if !output.is_empty() {
+                            return Poll::Ready(Some(Ok(Frame::data(output))));
+                        }
+                    }
+                    Err(frame) => {
+                        self.trailers = frame.into_trailers().ok();
+                        let output = self.finish()?;
+                        return Poll::Ready(Some(Ok(Frame::data(output))));
+                    }
+                },
+                Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+                None => {
+                    let output = self.finish()?;
+                    return Poll::Ready(Some(Ok(Frame::data(output))));
+                }
+            }
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.decoder.is_none() && self.trailers.is_none()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        SizeHint::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_backend;
+    use flate2::read::GzDecoder;
+    use http_body_util::{BodyExt, StreamBody};
+    use std::io::Read;
+
+    fn compressing_backend() -> Backend {
+        Backend {
+            compress_requests: Some(true),
+            ..test_backend("b")
+        }
+    }
+
+    fn upload(content_type: &str, body: &'static str) -> Request {
+        Request::builder()
+            .method("POST")
+            .uri("/upload")
+            .header(header::CONTENT_TYPE, content_type)
+            .header(header::CONTENT_LENGTH, body.len())
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    fn gunzip(bytes: &[u8]) -> String {
+        let mut text = String::new();
+        GzDecoder::new(bytes).read_to_string(&mut text).unwrap();
+        text
+    }
+
+    #[tokio::test]
+    async fn body_is_gzipped_and_decompresses_to_the_original() {
+        let compression = RequestCompression::new(1);
+        let original = "hello hello hello hello hello hello hello hello";
+        let mut req = upload("text/plain", original);
+
+        compression.compress_body(&compressing_backend(), &mut req);
+        assert_eq!(req.headers()[header::CONTENT_ENCODING], "gzip");
+        assert!(!req.headers().contains_key(header::CONTENT_LENGTH));
+
+        let compressed = req.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(gunzip(&compressed), original);
+
+        let stats = compression.snapshot();
+        assert_eq!(stats["requests"], 1);
+        assert_eq!(stats["bytes_in"], original.len());
+        assert_eq!(stats["bytes_out"], compressed.len());
+    }
+
+    #[tokio::test]
+    async fn bodies_that_should_not_be_compressed_are_left_alone() {
+        let compression = RequestCompression::new(16);
+        let skipped = [
+            // El backend no lo pidió
+            (test_backend("b"), upload("text/plain", "a long enough plain text body")),
+            // Ya comprimidos
+            (compressing_backend(), upload("image/png", "a long enough plain text body")),
+            (compressing_backend(), upload("Application/Zip", "a long enough plain text body")),
+            // Por debajo del mínimo
+            (compressing_backend(), upload("text/plain", "short")),
+        ];
+        for (backend, mut req) in skipped {
+            compression.compress_body(&backend, &mut req);
+            assert!(!req.headers().contains_key(header::CONTENT_ENCODING));
+            assert!(req.headers().contains_key(header::CONTENT_LENGTH));
+        }
+
+        // Sin body no hay nada que comprimir
+        let mut req = Request::builder().method("POST").body(Body::empty()).unwrap();
+        compression.compress_body(&compressing_backend(), &mut req);
+        assert!(!req.headers().contains_key(header::CONTENT_ENCODING));
+        assert_eq!(compression.snapshot()["requests"], 0);
+    }
+
+    #[tokio::test]
+    async fn checksummed_or_encoded_bodies_are_not_compressed_again() {
+        let compression = RequestCompression::new(1);
+        for (name, value) in [
+            ("content-md5", "XUFAKrxLKna5cZ2REBfFkg=="),
+            ("x-checksum-sha256", "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"),
+            ("content-encoding", "br"),
+        ] {
+            let mut req = upload("text/plain", "hello hello hello hello");
+            req.headers_mut().insert(name, HeaderValue::from_static(value));
+
+            compression.compress_body(&compressing_backend(), &mut req);
+            assert_eq!(
+                req.into_body().collect().await.unwrap().to_bytes(),
+                "hello hello hello hello",
+                "{}",
+                name
+            );
+        }
+
+        // `identity` no es una codificación real
+        let mut req = upload("text/plain", "hello hello hello hello");
+        req.headers_mut()
+            .insert(header::CONTENT_ENCODING, HeaderValue::from_static("identity"));
+        compression.compress_body(&compressing_backend(), &mut req);
+        assert_eq!(req.headers()[header::CONTENT_ENCODING], "gzip");
+    }
+
+    #[tokio::test]
+    async fn chunked_bodies_keep_their_trailers_after_the_gzip_stream() {
+        let mut trailers = HeaderMap::new();
+        trailers.insert("x-checksum", HeaderValue::from_static("abc"));
+        let frames: Vec<Result<Frame<Bytes>, axum::Error>> = vec![
+            Ok(Frame::data(Bytes::from("first chunk, "))),
+            Ok(Frame::data(Bytes::from("second chunk"))),
+            Ok(Frame::trailers(trailers)),
+        ];
+        let mut req = Request::builder()
+            .method("POST")
+            .body(Body::new(StreamBody::new(futures::stream::iter(frames))))
+            .unwrap();
+
+        RequestCompression::new(1024).compress_body(&compressing_backend(), &mut req);
+        assert_eq!(req.headers()[header::CONTENT_ENCODING], "gzip");
+
+        let collected = req.into_body().collect().await.unwrap();
+        assert_eq!(collected.trailers().unwrap()["x-checksum"], "abc");
+        assert_eq!(gunzip(&collected.to_bytes()), "first chunk, second chunk");
+    }
+
+    fn gzip(text: &str) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(text.as_bytes()).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn accept_encoding_covers_codings_by_name_or_wildcard() {
+        let accepts = |value: &'static str, coding| accepts(&HeaderValue::from_static(value), coding);
+        assert!(accepts("gzip, br", "gzip"));
+        assert!(accepts("x-gzip", "gzip"));
+        assert!(accepts("*", "deflate"));
+        assert!(!accepts("br", "gzip"));
+        assert!(!accepts("gzip;q=0, *", "gzip"));
+        assert!(!accepts("*;q=0", "gzip"));
+        assert!(!accepts("identity", "gzip"));
+    }
+
+    #[tokio::test]
+    async fn unaccepted_responses_are_decoded_across_frames() {
+        let encoded = gzip("streamed report body");
+        let (first, second) = encoded.split_at(encoded.len() / 2);
+        let response = || {
+            let frames: Vec<Result<Frame<Bytes>, axum::Error>> = vec![
+                Ok(Frame::data(Bytes::copy_from_slice(first))),
+                Ok(Frame::data(Bytes::copy_from_slice(second))),
+            ];
+            Response::builder()
+                .header(header::CONTENT_ENCODING, "gzip")
+                .header(header::CONTENT_LENGTH, encoded.len())
+                .body(Body::new(StreamBody::new(futures::stream::iter(frames))))
+                .unwrap()
+        };
+
+        for accept_encoding in [None, Some(HeaderValue::from_static("br"))] {
+            let decoded = decode_unaccepted(accept_encoding.as_ref(), response());
+            assert!(decoded.headers().get(header::CONTENT_ENCODING).is_none());
+            assert!(decoded.headers().get(header::CONTENT_LENGTH).is_none());
+            let body = decoded.into_body().collect().await.unwrap().to_bytes();
+            assert_eq!(body.as_ref(), b"streamed report body");
+        }
+
+        let accepted = decode_unaccepted(Some(&HeaderValue::from_static("gzip")), response());
+        assert_eq!(accepted.headers()[header::CONTENT_ENCODING], "gzip");
+        assert_eq!(accepted.into_body().collect().await.unwrap().to_bytes().as_ref(), encoded.as_slice());
+    }
+
+    #[tokio::test]
+    async fn undecodable_or_empty_responses_pass_through() {
+        let response = Response::builder()
+            .header(header::CONTENT_ENCODING, "br")
+            .body(Body::from("brotli bytes"))
+            .unwrap();
+        let response = decode_unaccepted(None, response);
+        assert_eq!(response.headers()[header::CONTENT_ENCODING], "br");
+
+        // Respuesta a un HEAD: sin body que descomprimir
+        let response = Response::builder()
+            .header(header::CONTENT_ENCODING, "gzip")
+            .body(Body::empty())
+            .unwrap();
+        let body = decode_unaccepted(None, response).into_body().collect().await.unwrap().to_bytes();
+        assert!(body.is_empty());
+    }
+}
@@ -1,5 +1,26 @@
+use async_trait::async_trait;
 use sqlx::{PgPool, postgres::PgPoolOptions};
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Columnas opcionales de `config.local` y su tipo, añadidas después de la tabla
+/// original. Las que falten se leen como NULL para no romper tablas existentes.
+const OPTIONAL_BACKEND_COLUMNS: &[(&str, &str)] = &[
+    ("health_check_interval", "INTEGER"),
+    ("http_version", "TEXT"),
+    ("tags", "TEXT"),
+    ("priority", "INTEGER"),
+    ("compress_requests", "BOOLEAN"),
+    ("max_rps", "DOUBLE PRECISION"),
+    ("follow_redirects", "BOOLEAN"),
+    ("role", "TEXT"),
+    ("health_check_type", "TEXT"),
+    ("bandwidth_limit", "BIGINT"),
+];
+
+/// Las columnas ausentes se avisan una sola vez, no en cada refresco de backends
+static MISSING_COLUMNS_LOGGED: AtomicBool = AtomicBool::new(false);
 
 #[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
 pub struct Backend {
@@ -7,6 +28,163 @@ pub struct Backend {
     pub provider: String,
     pub server_name: String,
     pub server_url: String,
+    /// Intervalo de health check propio del backend (segundos); NULL usa el global
+    #[serde(default)]
+    pub health_check_interval: Option<i32>,
+    /// Versión HTTP hacia el backend: `1.0`, `1.1` o `2`; NULL usa HTTP/1.1
+    #[serde(default)]
+    pub http_version: Option<String>,
+    /// Etiquetas `clave=valor` separadas por comas (`tier=hot,region=us`)
+    #[serde(default)]
+    pub tags: Option<String>,
+    /// Tier de failover: solo se usa el tier más bajo con backends saludables.
+    /// NULL equivale a 0 (primario).
+    #[serde(default)]
+    pub priority: Option<i32>,
+    /// Comprime con gzip los bodies de subida enviados al backend. NULL equivale a false.
+    #[serde(default)]
+    pub compress_requests: Option<bool>,
+    /// Peticiones por segundo máximas enviadas al backend. NULL o <= 0 = sin límite.
+    #[serde(default)]
+    pub max_rps: Option<f64>,
+    /// Sigue en el gateway las redirecciones del backend. NULL usa `REDIRECT_MODE`.
+    #[serde(default)]
+    pub follow_redirects: Option<bool>,
+    /// Peticiones balanceadas que recibe: `read` (GET y HEAD), `write` (el resto)
+    /// o `both`. NULL equivale a both.
+    #[serde(default)]
+    pub role: Option<String>,
+    /// Tipo de health check: `http` o `grpc` (protocolo `grpc.health.v1`). NULL equivale a http.
+    #[serde(default)]
+    pub health_check_type: Option<String>,
+    /// Bytes por segundo máximos del body de cada respuesta. NULL usa el límite
+    /// del provider o el global; 0 = sin límite.
+    #[serde(default)]
+    pub bandwidth_limit: Option<i64>,
+}
+
+/// Rol de un backend en un despliegue con réplicas de lectura
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackendRole {
+    Read,
+    Write,
+    Both,
+}
+
+impl Backend {
+    /// Health check interval for this backend, falling back to `default_secs`
+    pub fn health_check_interval(&self, default_secs: u64) -> std::time::Duration {
+        let secs = self
+            .health_check_interval
+            .filter(|secs| *secs > 0)
+            .map(|secs| secs as u64)
+            .unwrap_or(default_secs);
+        std::time::Duration::from_secs(secs.max(1))
+    }
+
+    /// Whether this backend is probed with the gRPC health checking protocol
+    pub fn uses_grpc_health_check(&self) -> bool {
+        self.health_check_type
+            .as_deref()
+            .is_some_and(|kind| kind.trim().eq_ignore_ascii_case("grpc"))
+    }
+
+    /// HTTP version used towards this backend, HTTP/1.1 when unset or unrecognized
+    pub fn http_version(&self) -> axum::http::Version {
+        match self.http_version.as_deref().map(str::trim) {
+            Some("1.0") => axum::http::Version::HTTP_10,
+            Some("2") | Some("2.0") => axum::http::Version::HTTP_2,
+            _ => axum::http::Version::HTTP_11,
+        }
+    }
+
+    /// Failover tier of this backend, 0 when unset
+    pub fn priority(&self) -> i32 {
+        self.priority.unwrap_or(0)
+    }
+
+    /// Whether upload bodies are gzipped before being sent to this backend
+    pub fn compresses_requests(&self) -> bool {
+        self.compress_requests.unwrap_or(false)
+    }
+
+    /// Outbound request rate cap for this backend, if any
+    pub fn max_rps(&self) -> Option<f64> {
+        self.max_rps.filter(|rps| *rps > 0.0)
+    }
+
+    /// Whether the gateway follows this backend's redirects, `default` when unset
+    pub fn follows_redirects(&self, default: bool) -> bool {
+        self.follow_redirects.unwrap_or(default)
+    }
+
+    /// Role of this backend, both when unset or unrecognized
+    pub fn role(&self) -> BackendRole {
+        match self.role.as_deref().map(|role| role.trim().to_lowercase()).as_deref() {
+            Some("read") => BackendRole::Read,
+            Some("write") => BackendRole::Write,
+            _ => BackendRole::Both,
+        }
+    }
+
+    /// Whether load-balanced `method` requests may go to this backend: reads
+    /// (GET, HEAD) to read-capable backends, everything else to write-capable ones
+    pub fn serves(&self, method: &axum::http::Method) -> bool {
+        let is_read = matches!(*method, axum::http::Method::GET | axum::http::Method::HEAD);
+        match self.role() {
+            BackendRole::Both => true,
+            BackendRole::Read => is_read,
+            BackendRole::Write => !is_read,
+        }
+    }
+
+    /// Parsed tags. Items without `=` become tags with an empty value; keys are
+    /// lowercased and the last occurrence of a repeated key wins.
+    pub fn tags(&self) -> BTreeMap<String, String> {
+        self.tags
+            .as_deref()
+            .unwrap_or("")
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(|item| match item.split_once('=') {
+                Some((key, value)) => (key.trim().to_lowercase(), value.trim().to_string()),
+                None => (item.to_lowercase(), String::new()),
+            })
+            .collect()
+    }
+
+    /// Whether the backend has tag `key`, with `value` when given
+    pub fn has_tag(&self, key: &str, value: Option<&str>) -> bool {
+        match (self.tags().get(&key.to_lowercase()), value) {
+            (Some(tag), Some(value)) => tag == value,
+            (Some(_), None) => true,
+            (None, _) => false,
+        }
+    }
+}
+
+/// Keeps the backends whose role serves `method`. When none does they are all
+/// kept and false is returned, so the caller can fall back instead of failing.
+pub fn retain_serving(backends: &mut Vec<Backend>, method: &axum::http::Method) -> bool {
+    if backends.iter().any(|b| b.serves(method)) {
+        backends.retain(|b| b.serves(method));
+        true
+    } else {
+        backends.is_empty()
+    }
+}
+
+/// Backend with only the required fields set, for unit tests
+#[cfg(test)]
+pub fn test_backend(server_id: &str) -> Backend {
+    serde_json::from_value(serde_json::json!({
+        "server_id": server_id,
+        "provider": "test",
+        "server_name": server_id,
+        "server_url": format!("http://{}.internal", server_id),
+    }))
+    .expect("valid test backend")
 }
 
 pub async fn create_pool(database_url: &str) -> Result<PgPool, sqlx::Error> {
@@ -16,24 +194,62 @@ pub async fn create_pool(database_url: &str) -> Result<PgPool, sqlx::Error> {
         .await
 }
 
-pub async fn get_all_backends(pool: &PgPool) -> Result<Vec<Backend>, sqlx::Error> {
-    sqlx::query_as::<_, Backend>(
-        "SELECT server_id, provider, server_name, server_url FROM config.local"
+/// Select list for `config.local`: optional columns missing from `existing`
+/// are read as typed NULLs
+fn backend_columns(existing: &HashSet<String>) -> String {
+    let mut columns = vec!["server_id".to_string(), "provider".to_string(), "server_name".to_string(), "server_url".to_string()];
+    columns.extend(OPTIONAL_BACKEND_COLUMNS.iter().map(|(name, sql_type)| {
+        if existing.contains(*name) {
+            name.to_string()
+        } else {
+            format!("NULL::{} AS {}", sql_type, name)
+        }
+    }));
+    columns.join(", ")
+}
+
+/// `SELECT ... FROM config.local` with the columns the table actually has, so
+/// a table created before the optional columns existed keeps loading
+async fn select_backends(pool: &PgPool) -> Result<String, sqlx::Error> {
+    let existing: HashSet<String> = sqlx::query_scalar::<_, String>(
+        "SELECT column_name::text FROM information_schema.columns WHERE table_schema = 'config' AND table_name = 'local'"
     )
     .fetch_all(pool)
-    .await
+    .await?
+    .into_iter()
+    .collect();
+
+    let missing: Vec<&str> = OPTIONAL_BACKEND_COLUMNS
+        .iter()
+        .map(|(name, _)| *name)
+        .filter(|name| !existing.contains(*name))
+        .collect();
+    if !missing.is_empty() && !MISSING_COLUMNS_LOGGED.swap(true, Ordering::Relaxed) {
+        tracing::warn!(
+            "config.local is missing the optional columns {:?}; they are read as NULL until added (see the README)",
+            missing
+        );
+    }
+
+    Ok(format!("SELECT {} FROM config.local", backend_columns(&existing)))
+}
+
+pub async fn get_all_backends(pool: &PgPool) -> Result<Vec<Backend>, sqlx::Error> {
+    let query = select_backends(pool).await?;
+    sqlx::query_as::<_, Backend>(&query)
+        .fetch_all(pool)
+        .await
 }
 
 /// Get a specific backend by ID from the database
 /// Available for direct backend lookups when needed
 #[allow(dead_code)]
 pub async fn get_backend_by_id(pool: &PgPool, server_id: &str) -> Result<Option<Backend>, sqlx::Error> {
-    sqlx::query_as::<_, Backend>(
-        "SELECT server_id, provider, server_name, server_url FROM config.local WHERE server_id = $1"
-    )
-    .bind(server_id)
-    .fetch_optional(pool)
-    .await
+    let query = format!("{} WHERE server_id = $1", select_backends(pool).await?);
+    sqlx::query_as::<_, Backend>(&query)
+        .bind(server_id)
+        .fetch_optional(pool)
+        .await
 }
 
 /// Get the server_id for a file from metadata table
@@ -49,22 +265,117 @@ pub async fn get_file_backend(pool: &PgPool, file_id: &str) -> Result<Option<Str
     Ok(result)
 }
 
-#[derive(Debug, sqlx::FromRow)]
+/// Checks that `application.metadata` has the columns file routing queries.
+/// Runs a query that returns no rows, so it is cheap on any table size.
+pub async fn check_metadata_schema(pool: &PgPool) -> Result<(), sqlx::Error> {
+    sqlx::query("SELECT file_id, server_id FROM application.metadata WHERE false")
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Whether the error means a table or column does not exist, as opposed to a
+/// connection problem that may go away on its own
+pub fn is_schema_error(error: &sqlx::Error) -> bool {
+    // 42P01 undefined_table, 42703 undefined_column, 3F000 invalid_schema_name
+    error
+        .as_database_error()
+        .and_then(|e| e.code())
+        .is_some_and(|code| matches!(code.as_ref(), "42P01" | "42703" | "3F000"))
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
 pub struct ExpiredFile {
     pub file_id: String,
     pub server_id: String,
 }
 
-/// Get all files that have expired (delete_at <= NOW())
-pub async fn get_expired_files(pool: &PgPool) -> Result<Vec<ExpiredFile>, sqlx::Error> {
+/// Get all files that have expired (delete_at <= NOW()). With `max_attempts`
+/// greater than 0, files whose deletion already failed that many times are
+/// left in the dead-letter table instead of being retried.
+pub async fn get_expired_files(pool: &PgPool, max_attempts: i32) -> Result<Vec<ExpiredFile>, sqlx::Error> {
+    if max_attempts <= 0 {
+        return sqlx::query_as::<_, ExpiredFile>(
+            "SELECT file_id, server_id FROM application.metadata
+             WHERE delete_at IS NOT NULL AND delete_at <= NOW()"
+        )
+        .fetch_all(pool)
+        .await;
+    }
+
     sqlx::query_as::<_, ExpiredFile>(
-        "SELECT file_id, server_id FROM application.metadata
-         WHERE delete_at IS NOT NULL AND delete_at <= NOW()"
+        "SELECT m.file_id, m.server_id FROM application.metadata m
+         LEFT JOIN application.expiry_dead_letter d ON d.file_id = m.file_id
+         WHERE m.delete_at IS NOT NULL AND m.delete_at <= NOW()
+           AND (d.attempts IS NULL OR d.attempts < $1)"
     )
+    .bind(max_attempts)
     .fetch_all(pool)
     .await
 }
 
+/// Creates the table where failed expiry deletions are recorded
+pub async fn ensure_expiry_dead_letter_table(pool: &PgPool) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS application.expiry_dead_letter (
+            file_id TEXT PRIMARY KEY,
+            server_id TEXT NOT NULL,
+            error TEXT NOT NULL,
+            attempts INTEGER NOT NULL DEFAULT 1,
+            first_failed_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+            last_failed_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+        )"
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Records a failed deletion of an expired file. Returns the number of
+/// attempts made so far.
+pub async fn record_expiry_failure(
+    pool: &PgPool,
+    file_id: &str,
+    server_id: &str,
+    error: &str,
+) -> Result<i32, sqlx::Error> {
+    sqlx::query_scalar::<_, i32>(
+        "INSERT INTO application.expiry_dead_letter (file_id, server_id, error)
+         VALUES ($1, $2, $3)
+         ON CONFLICT (file_id) DO UPDATE SET
+            server_id = EXCLUDED.server_id,
+            error = EXCLUDED.error,
+            attempts = application.expiry_dead_letter.attempts + 1,
+            last_failed_at = NOW()
+         RETURNING attempts"
+    )
+    .bind(file_id)
+    .bind(server_id)
+    .bind(error)
+    .fetch_one(pool)
+    .await
+}
+
+/// Removes a file from the dead-letter table once its deletion succeeded
+pub async fn clear_expiry_failure(pool: &PgPool, file_id: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM application.expiry_dead_letter WHERE file_id = $1")
+        .bind(file_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Number of expired files no longer retried after `max_attempts` failures
+pub async fn count_dead_lettered(pool: &PgPool, max_attempts: i32) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM application.expiry_dead_letter WHERE attempts >= $1")
+        .bind(max_attempts)
+        .fetch_one(pool)
+        .await
+}
+
 /// Delete a file from metadata table
 pub async fn delete_file_metadata(pool: &PgPool, file_id: &str) -> Result<(), sqlx::Error> {
     sqlx::query("DELETE FROM application.metadata WHERE file_id = $1")
@@ -74,3 +385,154 @@ pub async fn delete_file_metadata(pool: &PgPool, file_id: &str) -> Result<(), sq
 
     Ok(())
 }
+
+/// Metadata y dead-letter que usa el barrido de archivos caducados. Postgres en
+/// producción; los tests usan un almacén en memoria.
+#[async_trait]
+pub trait ExpiryStore: Send + Sync {
+    async fn expired_files(&self, max_attempts: i32) -> Result<Vec<ExpiredFile>, sqlx::Error>;
+
+    async fn dead_lettered(&self, max_attempts: i32) -> Result<i64, sqlx::Error>;
+
+    async fn record_failure(&self, file: &ExpiredFile, error: &str) -> Result<i32, sqlx::Error>;
+
+    async fn clear_failure(&self, file_id: &str) -> Result<(), sqlx::Error>;
+
+    async fn delete_metadata(&self, file_id: &str) -> Result<(), sqlx::Error>;
+}
+
+#[async_trait]
+impl ExpiryStore for PgPool {
+    async fn expired_files(&self, max_attempts: i32) -> Result<Vec<ExpiredFile>, sqlx::Error> {
+        get_expired_files(self, max_attempts).await
+    }
+
+    async fn dead_lettered(&self, max_attempts: i32) -> Result<i64, sqlx::Error> {
+        count_dead_lettered(self, max_attempts).await
+    }
+
+    async fn record_failure(&self, file: &ExpiredFile, error: &str) -> Result<i32, sqlx::Error> {
+        record_expiry_failure(self, &file.file_id, &file.server_id, error).await
+    }
+
+    async fn clear_failure(&self, file_id: &str) -> Result<(), sqlx::Error> {
+        clear_expiry_failure(self, file_id).await
+    }
+
+    async fn delete_metadata(&self, file_id: &str) -> Result<(), sqlx::Error> {
+        delete_file_metadata(self, file_id).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::Method;
+
+    fn with_role(server_id: &str, role: Option<&str>) -> Backend {
+        Backend {
+            role: role.map(str::to_string),
+            ..test_backend(server_id)
+        }
+    }
+
+    #[test]
+    fn role_parsing_defaults_to_both() {
+        assert_eq!(with_role("a", Some("read")).role(), BackendRole::Read);
+        assert_eq!(with_role("a", Some(" WRITE ")).role(), BackendRole::Write);
+        assert_eq!(with_role("a", Some("both")).role(), BackendRole::Both);
+        assert_eq!(with_role("a", None).role(), BackendRole::Both);
+        assert_eq!(with_role("a", Some("primary")).role(), BackendRole::Both);
+    }
+
+    #[test]
+    fn reads_go_to_read_capable_backends() {
+        let (read, write, both) = (with_role("r", Some("read")), with_role("w", Some("write")), with_role("b", None));
+
+        for method in [Method::GET, Method::HEAD] {
+            assert!(read.serves(&method));
+            assert!(!write.serves(&method));
+            assert!(both.serves(&method));
+        }
+        for method in [Method::POST, Method::PUT, Method::PATCH, Method::DELETE, Method::OPTIONS] {
+            assert!(!read.serves(&method));
+            assert!(write.serves(&method));
+            assert!(both.serves(&method));
+        }
+    }
+
+    #[test]
+    fn retain_serving_filters_by_role() {
+        let mut backends = vec![with_role("r", Some("read")), with_role("w", Some("write")), with_role("b", None)];
+
+        assert!(retain_serving(&mut backends, &Method::POST));
+        let ids: Vec<_> = backends.iter().map(|b| b.server_id.as_str()).collect();
+        assert_eq!(ids, ["w", "b"]);
+    }
+
+    #[test]
+    fn retain_serving_falls_back_to_every_backend() {
+        let mut backends = vec![with_role("r1", Some("read")), with_role("r2", Some("read"))];
+
+        assert!(!retain_serving(&mut backends, &Method::PUT));
+        assert_eq!(backends.len(), 2);
+        assert!(retain_serving(&mut Vec::new(), &Method::GET));
+    }
+
+    #[test]
+    fn backend_columns_read_missing_optional_columns_as_null() {
+        let existing: HashSet<String> = ["server_id", "provider", "server_name", "server_url", "priority"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        let columns = backend_columns(&existing);
+
+        assert!(columns.starts_with("server_id, provider, server_name, server_url, NULL::INTEGER AS health_check_interval, "));
+        assert!(columns.contains(", priority, "));
+        assert!(columns.ends_with("NULL::BIGINT AS bandwidth_limit"));
+    }
+
+    #[test]
+    fn backend_columns_select_every_existing_column() {
+        let existing: HashSet<String> = OPTIONAL_BACKEND_COLUMNS.iter().map(|(name, _)| name.to_string()).collect();
+
+        assert_eq!(
+            backend_columns(&existing),
+            "server_id, provider, server_name, server_url, health_check_interval, http_version, tags, priority, compress_requests, max_rps, follow_redirects, role, health_check_type, bandwidth_limit"
+        );
+    }
+
+    fn with_tags(tags: &str) -> Backend {
+        Backend {
+            tags: Some(tags.to_string()),
+            ..test_backend("tagged")
+        }
+    }
+
+    #[test]
+    fn tags_are_parsed_with_lowercase_keys() {
+        let backend = with_tags(" Tier=hot , region = us,canary,,tier=cold");
+        let tags: Vec<_> = backend.tags().into_iter().collect();
+
+        assert_eq!(
+            tags,
+            [
+                ("canary".to_string(), String::new()),
+                ("region".to_string(), "us".to_string()),
+                ("tier".to_string(), "cold".to_string()),
+            ]
+        );
+        assert!(test_backend("untagged").tags().is_empty());
+    }
+
+    #[test]
+    fn has_tag_matches_key_and_optional_value() {
+        let backend = with_tags("tier=hot,canary");
+
+        assert!(backend.has_tag("tier", None));
+        assert!(backend.has_tag("TIER", Some("hot")));
+        assert!(!backend.has_tag("tier", Some("HOT")));
+        assert!(backend.has_tag("canary", Some("")));
+        assert!(!backend.has_tag("region", None));
+    }
+}
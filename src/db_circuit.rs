@@ -0,0 +1,227 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Circuit breaker de las consultas de enrutamiento de archivos a Postgres.
+/// Tras varios fallos seguidos deja de consultar la base de datos durante un
+/// tiempo y las peticiones van directamente al balanceador.
+#[derive(Debug)]
+pub struct DbCircuit {
+    /// Fallos consecutivos que abren el circuito (0 = deshabilitado)
+    threshold: u32,
+    /// Ventana en la que deben ocurrir esos fallos
+    window: Duration,
+    /// Tiempo que el circuito permanece abierto antes de volver a probar
+    cooldown: Duration,
+    state: Mutex<CircuitState>,
+    /// Consultas omitidas con el circuito abierto
+    skipped: AtomicU64,
+    /// Veces que se ha abierto el circuito
+    trips: AtomicU64,
+}
+
+#[derive(Debug, Default)]
+struct CircuitState {
+    failures: u32,
+    first_failure: Option<Instant>,
+    open_until: Option<Instant>,
+    /// Hay una consulta de prueba en curso tras el cooldown
+    probing: bool,
+}
+
+impl DbCircuit {
+    pub fn new(threshold: u32, window: Duration, cooldown: Duration) -> Self {
+        Self {
+            threshold,
+            window,
+            cooldown,
+            state: Mutex::new(CircuitState::default()),
+            skipped: AtomicU64::new(0),
+            trips: AtomicU64::new(0),
+        }
+    }
+
+    /// Whether a lookup may hit the database. Once the cooldown is over a
+    /// single probe is let through; the rest keep skipping until it finishes.
+    pub fn allow(&self) -> bool {
+        if self.threshold == 0 {
+            return true;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        let allowed = match state.open_until {
+            None => true,
+            Some(until) if Instant::now() < until || state.probing => false,
+            Some(_) => {
+                state.probing = true;
+                true
+            }
+        };
+        if !allowed {
+            self.skipped.fetch_add(1, Ordering::Relaxed);
+        }
+        allowed
+    }
+
+    pub fn record_success(&self) {
+        if self.threshold == 0 {
+            return;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        if state.open_until.is_some() {
+            tracing::info!("Database lookups recovered, closing the DB circuit");
+        }
+        *state = CircuitState::default();
+    }
+
+    pub fn record_failure(&self) {
+        if self.threshold == 0 {
+            return;
+        }
+
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+
+        // La prueba tras el cooldown ha fallado: otro cooldown completo
+        if state.probing {
+            state.probing = false;
+            state.open_until = Some(now + self.cooldown);
+            tracing::warn!("DB circuit probe failed, skipping lookups for {:?}", self.cooldown);
+            return;
+        }
+
+        let in_window = state
+            .first_failure
+            .is_some_and(|first| now.duration_since(first) <= self.window);
+        if !in_window {
+            state.first_failure = Some(now);
+            state.failures = 0;
+        }
+        state.failures += 1;
+
+        if state.failures >= self.threshold && state.open_until.is_none() {
+            state.open_until = Some(now + self.cooldown);
+            self.trips.fetch_add(1, Ordering::Relaxed);
+            tracing::warn!(
+                "{} consecutive database errors, skipping file lookups for {:?}",
+                state.failures,
+                self.cooldown
+            );
+        }
+    }
+
+    /// Current state and counters for the stats endpoint
+    pub fn snapshot(&self) -> serde_json::Value {
+        let state = self.state.lock().unwrap();
+        let now = Instant::now();
+        let status = match state.open_until {
+            _ if self.threshold == 0 => "disabled",
+            None => "closed",
+            Some(until) if now < until => "open",
+            Some(_) => "half_open",
+        };
+
+        serde_json::json!({
+            "state": status,
+            "consecutive_failures": state.failures,
+            "open_for_secs": state.open_until.map(|until| until.saturating_duration_since(now).as_secs()),
+            "skipped_lookups": self.skipped.load(Ordering::Relaxed),
+            "trips": self.trips.load(Ordering::Relaxed),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COOLDOWN: Duration = Duration::from_millis(50);
+
+    fn tripped() -> DbCircuit {
+        let circuit = DbCircuit::new(3, Duration::from_secs(60), COOLDOWN);
+        for _ in 0..3 {
+            assert!(circuit.allow());
+            circuit.record_failure();
+        }
+        circuit
+    }
+
+    #[test]
+    fn consecutive_failures_open_the_circuit() {
+        let circuit = tripped();
+
+        assert!(!circuit.allow());
+        assert!(!circuit.allow());
+        let snapshot = circuit.snapshot();
+        assert_eq!(snapshot["state"], "open");
+        assert_eq!(snapshot["trips"], 1);
+        assert_eq!(snapshot["skipped_lookups"], 2);
+    }
+
+    #[test]
+    fn a_success_resets_the_failure_count() {
+        let circuit = DbCircuit::new(3, Duration::from_secs(60), COOLDOWN);
+        circuit.record_failure();
+        circuit.record_failure();
+        circuit.record_success();
+        circuit.record_failure();
+
+        assert!(circuit.allow());
+        assert_eq!(circuit.snapshot()["state"], "closed");
+    }
+
+    #[test]
+    fn failures_outside_the_window_do_not_add_up() {
+        let circuit = DbCircuit::new(2, Duration::from_millis(20), COOLDOWN);
+        circuit.record_failure();
+        std::thread::sleep(Duration::from_millis(40));
+        circuit.record_failure();
+
+        assert!(circuit.allow());
+        assert_eq!(circuit.snapshot()["consecutive_failures"], 1);
+    }
+
+    #[test]
+    fn only_one_probe_passes_after_the_cooldown() {
+        let circuit = tripped();
+        std::thread::sleep(COOLDOWN + Duration::from_millis(10));
+        assert_eq!(circuit.snapshot()["state"], "half_open");
+
+        assert!(circuit.allow());
+        assert!(!circuit.allow());
+        assert!(!circuit.allow());
+
+        circuit.record_success();
+        assert!(circuit.allow());
+        assert!(circuit.allow());
+        assert_eq!(circuit.snapshot()["state"], "closed");
+    }
+
+    #[test]
+    fn failed_probe_reopens_for_a_full_cooldown() {
+        let circuit = tripped();
+        std::thread::sleep(COOLDOWN + Duration::from_millis(10));
+
+        assert!(circuit.allow());
+        circuit.record_failure();
+        assert!(!circuit.allow());
+        assert_eq!(circuit.snapshot()["state"], "open");
+        // No cuenta como un nuevo disparo
+        assert_eq!(circuit.snapshot()["trips"], 1);
+
+        std::thread::sleep(COOLDOWN + Duration::from_millis(10));
+        assert!(circuit.allow());
+    }
+
+    #[test]
+    fn zero_threshold_disables_the_circuit() {
+        let circuit = DbCircuit::new(0, Duration::from_secs(60), COOLDOWN);
+        for _ in 0..10 {
+            circuit.record_failure();
+        }
+
+        assert!(circuit.allow());
+        assert_eq!(circuit.snapshot()["state"], "disabled");
+    }
+}
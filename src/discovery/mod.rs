@@ -0,0 +1,91 @@
+pub mod sources;
+
+use crate::{config::Config, db::Backend};
+use async_trait::async_trait;
+use sqlx::PgPool;
+use std::sync::Arc;
+
+/// Trait que define una fuente de backends (base de datos, archivo, DNS...).
+/// Implementa este trait para descubrir backends desde otro origen.
+#[async_trait]
+pub trait BackendSource: Send + Sync {
+    /// Retorna la lista actual de backends.
+    ///
+    /// # Errors
+    /// Si la fuente no está disponible; el llamador conserva la lista anterior.
+    async fn list_backends(&self) -> anyhow::Result<Vec<Backend>>;
+
+    /// Retorna el nombre de la fuente
+    fn name(&self) -> &str;
+}
+
+/// Factory para crear la fuente de backends configurada en `BACKEND_SOURCE`
+pub fn create_backend_source(
+    config: &Config,
+    db_pool: Option<&PgPool>,
+) -> anyhow::Result<Arc<dyn BackendSource>> {
+    match config.backend_source.to_lowercase().as_str() {
+        "postgres" | "postgresql" | "db" => {
+            let db_pool = db_pool
+                .ok_or_else(|| anyhow::anyhow!("DATABASE_URL must be set when BACKEND_SOURCE=postgres"))?;
+            Ok(Arc::new(sources::PostgresBackendSource::new(db_pool.clone())))
+        }
+        "env" => {
+            let spec = config
+                .backends_env
+                .as_deref()
+                .ok_or_else(|| anyhow::anyhow!("BACKENDS must be set when BACKEND_SOURCE=env"))?;
+            Ok(Arc::new(sources::EnvBackendSource::new(spec)?))
+        }
+        "file" => {
+            let path = config
+                .backend_file
+                .clone()
+                .ok_or_else(|| anyhow::anyhow!("BACKEND_FILE must be set when BACKEND_SOURCE=file"))?;
+            Ok(Arc::new(sources::FileBackendSource::new(path)))
+        }
+        "dns" | "dns-srv" => {
+            let srv_name = config
+                .dns_srv_name
+                .clone()
+                .ok_or_else(|| anyhow::anyhow!("DNS_SRV_NAME must be set when BACKEND_SOURCE=dns"))?;
+            Ok(Arc::new(sources::DnsSrvBackendSource::new(
+                srv_name,
+                config.dns_srv_scheme.clone(),
+                config.dns_srv_provider.clone(),
+                Box::new(sources::SystemSrvResolver::new()?),
+            )))
+        }
+        other => Err(anyhow::anyhow!("Unknown backend source '{}'", other)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy::test_config;
+
+    #[tokio::test]
+    async fn env_source_needs_no_database() {
+        let mut config = test_config();
+        config.backend_source = "env".to_string();
+        config.backends_env = Some("local=http://127.0.0.1:8081".to_string());
+
+        let source = create_backend_source(&config, None).unwrap();
+        assert_eq!(source.name(), "Env");
+        assert_eq!(source.list_backends().await.unwrap()[0].server_id, "local");
+    }
+
+    #[test]
+    fn sources_without_their_settings_are_errors() {
+        let mut config = test_config();
+        config.backend_source = "postgres".to_string();
+        let error = create_backend_source(&config, None).err().unwrap().to_string();
+        assert!(error.contains("DATABASE_URL"), "{}", error);
+
+        config.backend_source = "env".to_string();
+        config.backends_env = None;
+        let error = create_backend_source(&config, None).err().unwrap().to_string();
+        assert!(error.contains("BACKENDS"), "{}", error);
+    }
+}
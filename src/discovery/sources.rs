@@ -0,0 +1,405 @@
+use super::BackendSource;
+use crate::db::Backend;
+use async_trait::async_trait;
+use hickory_resolver::TokioAsyncResolver;
+use serde::Deserialize;
+use sqlx::PgPool;
+use std::path::PathBuf;
+use std::time::Instant;
+use tokio::sync::Mutex;
+
+/// Fuente Postgres - lee los backends de la tabla `config.local`
+pub struct PostgresBackendSource {
+    pool: PgPool,
+}
+
+impl PostgresBackendSource {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl BackendSource for PostgresBackendSource {
+    async fn list_backends(&self) -> anyhow::Result<Vec<Backend>> {
+        Ok(crate::db::get_all_backends(&self.pool).await?)
+    }
+
+    fn name(&self) -> &str {
+        "Postgres"
+    }
+}
+
+/// Formato del archivo de backends (JSON o TOML)
+#[derive(Deserialize)]
+struct BackendFile {
+    backends: Vec<Backend>,
+}
+
+/// Fuente de archivo - lee los backends de un archivo JSON o TOML.
+/// El formato se elige por la extensión (`.toml`, cualquier otra se trata como JSON).
+/// El archivo se relee en cada refresco, así que puede editarse en caliente.
+pub struct FileBackendSource {
+    path: PathBuf,
+}
+
+impl FileBackendSource {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+#[async_trait]
+impl BackendSource for FileBackendSource {
+    async fn list_backends(&self) -> anyhow::Result<Vec<Backend>> {
+        let contents = tokio::fs::read_to_string(&self.path).await.map_err(|e| {
+            anyhow::anyhow!("Failed to read backend file {}: {}", self.path.display(), e)
+        })?;
+
+        let is_toml = self.path.extension().is_some_and(|ext| ext == "toml");
+        let file: BackendFile = if is_toml {
+            toml::from_str(&contents)?
+        } else {
+            serde_json::from_str(&contents)?
+        };
+
+        Ok(file.backends)
+    }
+
+    fn name(&self) -> &str {
+        "File"
+    }
+}
+
+/// Fuente de entorno - backends fijos definidos en `BACKENDS`, para despliegues
+/// sin base de datos. Acepta un array JSON con el formato de `BACKEND_FILE` o
+/// una lista `server_id=url` separada por comas. Se parsea al arrancar.
+pub struct EnvBackendSource {
+    backends: Vec<Backend>,
+}
+
+impl EnvBackendSource {
+    pub fn new(spec: &str) -> anyhow::Result<Self> {
+        Ok(Self {
+            backends: parse_backend_spec(spec)?,
+        })
+    }
+}
+
+#[async_trait]
+impl BackendSource for EnvBackendSource {
+    async fn list_backends(&self) -> anyhow::Result<Vec<Backend>> {
+        Ok(self.backends.clone())
+    }
+
+    fn name(&self) -> &str {
+        "Env"
+    }
+}
+
+/// Parses `BACKENDS`: a JSON array of backends, or `server_id=url` entries
+/// separated by commas, which get provider `env` and their ID as name
+pub fn parse_backend_spec(spec: &str) -> anyhow::Result<Vec<Backend>> {
+    let spec = spec.trim();
+    if spec.starts_with('[') {
+        return serde_json::from_str(spec).map_err(|e| anyhow::anyhow!("BACKENDS is not a valid JSON array: {}", e));
+    }
+
+    spec.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (server_id, server_url) = entry
+                .split_once('=')
+                .map(|(id, url)| (id.trim(), url.trim()))
+                .filter(|(id, url)| !id.is_empty() && !url.is_empty())
+                .ok_or_else(|| anyhow::anyhow!("BACKENDS entry '{}' must be server_id=url", entry))?;
+            Ok(Backend {
+                server_id: server_id.to_string(),
+                provider: "env".to_string(),
+                server_name: server_id.to_string(),
+                server_url: server_url.to_string(),
+                health_check_interval: None,
+                http_version: None,
+                tags: None,
+                priority: None,
+                compress_requests: None,
+                max_rps: None,
+                follow_redirects: None,
+                role: None,
+                health_check_type: None,
+                bandwidth_limit: None,
+            })
+        })
+        .collect()
+}
+
+/// Destino de un registro SRV
+#[derive(Debug, Clone)]
+pub struct SrvTarget {
+    pub host: String,
+    pub port: u16,
+}
+
+/// Resolución de registros SRV, separada para poder sustituir el resolver del sistema
+#[async_trait]
+pub trait SrvResolver: Send + Sync {
+    /// Resolves `name`, returning its targets and the instant the answer expires (record TTL)
+    async fn resolve(&self, name: &str) -> anyhow::Result<(Vec<SrvTarget>, Instant)>;
+}
+
+/// Resolver SRV basado en la configuración DNS del sistema (`/etc/resolv.conf`)
+pub struct SystemSrvResolver {
+    resolver: TokioAsyncResolver,
+}
+
+impl SystemSrvResolver {
+    pub fn new() -> anyhow::Result<Self> {
+        Ok(Self {
+            resolver: TokioAsyncResolver::tokio_from_system_conf()?,
+        })
+    }
+}
+
+#[async_trait]
+impl SrvResolver for SystemSrvResolver {
+    async fn resolve(&self, name: &str) -> anyhow::Result<(Vec<SrvTarget>, Instant)> {
+        let lookup = self.resolver.srv_lookup(name).await?;
+
+        let targets = lookup
+            .iter()
+            .map(|srv| SrvTarget {
+                host: srv.target().to_utf8().trim_end_matches('.').to_string(),
+                port: srv.port(),
+            })
+            .collect();
+
+        Ok((targets, lookup.as_lookup().valid_until()))
+    }
+}
+
+/// Fuente DNS SRV - resuelve un nombre SRV (p. ej. un headless service de
+/// Kubernetes o Consul DNS) en backends `scheme://host:port`. El resultado se
+/// reutiliza hasta que vence el TTL del registro; si la resolución falla se
+/// conserva el último conjunto conocido.
+pub struct DnsSrvBackendSource {
+    srv_name: String,
+    scheme: String,
+    provider: String,
+    resolver: Box<dyn SrvResolver>,
+    last_known: Mutex<Option<(Vec<Backend>, Instant)>>,
+}
+
+impl DnsSrvBackendSource {
+    pub fn new(
+        srv_name: impl Into<String>,
+        scheme: impl Into<String>,
+        provider: impl Into<String>,
+        resolver: Box<dyn SrvResolver>,
+    ) -> Self {
+        Self {
+            srv_name: srv_name.into(),
+            scheme: scheme.into(),
+            provider: provider.into(),
+            resolver,
+            last_known: Mutex::new(None),
+        }
+    }
+
+    /// Maps SRV targets to backends. The server_id is the target hostname, or
+    /// `host:port` when the same host is published on several ports.
+    fn to_backends(&self, targets: &[SrvTarget]) -> Vec<Backend> {
+        targets
+            .iter()
+            .map(|target| {
+                let shared_host = targets.iter().filter(|t| t.host == target.host).count() > 1;
+                let address = format!("{}:{}", target.host, target.port);
+                Backend {
+                    server_id: if shared_host { address.clone() } else { target.host.clone() },
+                    provider: self.provider.clone(),
+                    server_name: address.clone(),
+                    server_url: format!("{}://{}", self.scheme, address),
+                    health_check_interval: None,
+                    http_version: None,
+                    tags: None,
+                    priority: None,
+                    compress_requests: None,
+                    max_rps: None,
+                    follow_redirects: None,
+                    role: None,
+                    health_check_type: None,
+                    bandwidth_limit: None,
+                }
+            })
+            .collect()
+    }
+}
+
+#[async_trait]
+impl BackendSource for DnsSrvBackendSource {
+    async fn list_backends(&self) -> anyhow::Result<Vec<Backend>> {
+        let mut last_known = self.last_known.lock().await;
+
+        if let Some((backends, valid_until)) = last_known.as_ref() {
+            if Instant::now() < *valid_until {
+                return Ok(backends.clone());
+            }
+        }
+
+        match self.resolver.resolve(&self.srv_name).await {
+            Ok((targets, valid_until)) => {
+                let backends = self.to_backends(&targets);
+                *last_known = Some((backends.clone(), valid_until));
+                Ok(backends)
+            }
+            Err(e) => match last_known.as_ref() {
+                Some((backends, _)) => {
+                    tracing::warn!(
+                        "Failed to resolve SRV record {}, keeping {} last known backends: {}",
+                        self.srv_name,
+                        backends.len(),
+                        e
+                    );
+                    Ok(backends.clone())
+                }
+                None => Err(e),
+            },
+        }
+    }
+
+    fn name(&self) -> &str {
+        "DnsSrv"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    type SrvAnswer = anyhow::Result<(Vec<SrvTarget>, Instant)>;
+
+    /// Resolver que devuelve las respuestas encoladas, en orden
+    struct FakeResolver {
+        answers: std::sync::Mutex<Vec<SrvAnswer>>,
+        lookups: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl SrvResolver for FakeResolver {
+        async fn resolve(&self, _name: &str) -> SrvAnswer {
+            self.lookups.fetch_add(1, Ordering::Relaxed);
+            self.answers.lock().unwrap().remove(0)
+        }
+    }
+
+    fn target(host: &str, port: u16) -> SrvTarget {
+        SrvTarget { host: host.to_string(), port }
+    }
+
+    fn srv_source(answers: Vec<SrvAnswer>) -> (DnsSrvBackendSource, Arc<AtomicUsize>) {
+        let lookups = Arc::new(AtomicUsize::new(0));
+        let resolver = FakeResolver { answers: std::sync::Mutex::new(answers), lookups: lookups.clone() };
+        (DnsSrvBackendSource::new("_http._tcp.storage", "https", "k8s", Box::new(resolver)), lookups)
+    }
+
+    fn expired() -> Instant {
+        Instant::now() - Duration::from_secs(1)
+    }
+
+    #[tokio::test]
+    async fn srv_targets_become_backends() {
+        let targets = vec![target("node-a.storage", 8443), target("node-b.storage", 8443), target("node-b.storage", 9443)];
+        let (source, _) = srv_source(vec![Ok((targets, expired()))]);
+
+        let backends = source.list_backends().await.unwrap();
+        let ids: Vec<&str> = backends.iter().map(|b| b.server_id.as_str()).collect();
+        assert_eq!(ids, ["node-a.storage", "node-b.storage:8443", "node-b.storage:9443"]);
+        assert_eq!(backends[0].server_url, "https://node-a.storage:8443");
+        assert_eq!(backends[0].server_name, "node-a.storage:8443");
+        assert!(backends.iter().all(|b| b.provider == "k8s"));
+    }
+
+    #[tokio::test]
+    async fn answer_is_reused_until_its_ttl_expires() {
+        let fresh = Instant::now() + Duration::from_secs(60);
+        let (source, lookups) = srv_source(vec![Ok((vec![target("node-a", 80)], fresh))]);
+
+        source.list_backends().await.unwrap();
+        let backends = source.list_backends().await.unwrap();
+        assert_eq!(backends[0].server_id, "node-a");
+        assert_eq!(lookups.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn failed_resolution_keeps_the_last_known_backends() {
+        let (source, lookups) = srv_source(vec![
+            Ok((vec![target("node-a", 80)], expired())),
+            Err(anyhow::anyhow!("SERVFAIL")),
+            Ok((vec![target("node-b", 80)], expired())),
+        ]);
+
+        assert_eq!(source.list_backends().await.unwrap()[0].server_id, "node-a");
+        assert_eq!(source.list_backends().await.unwrap()[0].server_id, "node-a");
+        assert_eq!(source.list_backends().await.unwrap()[0].server_id, "node-b");
+        assert_eq!(lookups.load(Ordering::Relaxed), 3);
+    }
+
+    #[tokio::test]
+    async fn first_failed_resolution_is_an_error() {
+        let (source, _) = srv_source(vec![Err(anyhow::anyhow!("NXDOMAIN"))]);
+        assert!(source.list_backends().await.is_err());
+    }
+
+    fn ids_and_urls(backends: &[Backend]) -> Vec<(&str, &str)> {
+        backends.iter().map(|b| (b.server_id.as_str(), b.server_url.as_str())).collect()
+    }
+
+    #[test]
+    fn comma_list_entries_become_env_backends() {
+        let spec = " local = http://127.0.0.1:8081 ,, replica=https://user:pw@replica:9000/api ";
+        let backends = parse_backend_spec(spec).unwrap();
+
+        assert_eq!(
+            ids_and_urls(&backends),
+            [("local", "http://127.0.0.1:8081"), ("replica", "https://user:pw@replica:9000/api")]
+        );
+        assert_eq!(backends[0].provider, "env");
+        assert_eq!(backends[0].server_name, "local");
+        assert_eq!(backends[0].priority, None);
+        assert!(parse_backend_spec("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn json_array_keeps_every_backend_field() {
+        let spec = r#"[
+            {"server_id": "a", "provider": "s3", "server_name": "A", "server_url": "http://a", "priority": 1, "role": "read"},
+            {"server_id": "b", "provider": "s3", "server_name": "B", "server_url": "http://b"}
+        ]"#;
+        let backends = parse_backend_spec(spec).unwrap();
+
+        assert_eq!(ids_and_urls(&backends), [("a", "http://a"), ("b", "http://b")]);
+        assert_eq!(backends[0].provider, "s3");
+        assert_eq!(backends[0].priority, Some(1));
+        assert_eq!(backends[0].role.as_deref(), Some("read"));
+        assert_eq!(backends[1].role, None);
+    }
+
+    #[test]
+    fn malformed_specs_are_errors() {
+        for spec in ["local", "=http://a", "local=", "a=http://a,b", r#"[{"server_id": "a"}]"#] {
+            assert!(parse_backend_spec(spec).is_err(), "{} was accepted", spec);
+        }
+        assert!(EnvBackendSource::new("a=http://a,broken").is_err());
+    }
+
+    #[tokio::test]
+    async fn env_source_lists_the_parsed_backends() {
+        let source = EnvBackendSource::new("a=http://a,b=http://b").unwrap();
+        assert_eq!(source.name(), "Env");
+        let backends = source.list_backends().await.unwrap();
+        assert_eq!(ids_and_urls(&backends), [("a", "http://a"), ("b", "http://b")]);
+    }
+}
@@ -0,0 +1,122 @@
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{header, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::config::StaticResponse;
+
+/// Marca las respuestas cuyo body no se sustituye por una página de error: las
+/// de los backends y las que ya llevan un body configurado por el operador
+#[derive(Debug, Clone, Copy)]
+pub struct PreserveBody;
+
+/// Middleware that replaces the body of the error responses generated by the
+/// gateway itself with the page configured for their status (`ERROR_PAGE_{status}`).
+/// The status and the other headers (`Retry-After`, `Set-Cookie`...) are kept.
+pub async fn error_page_middleware(
+    State(pages): State<Arc<HashMap<u16, StaticResponse>>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let response = next.run(req).await;
+    if response.extensions().get::<PreserveBody>().is_some() {
+        return response;
+    }
+    let Some(page) = pages.get(&response.status().as_u16()) else {
+        return response;
+    };
+
+    let (mut parts, _) = response.into_parts();
+    parts.headers.remove(header::CONTENT_LENGTH);
+    parts.headers.remove(header::CONTENT_ENCODING);
+    if let Ok(content_type) = HeaderValue::from_str(&page.content_type) {
+        parts.headers.insert(header::CONTENT_TYPE, content_type);
+    }
+    // Evita que un CDN guarde la página de error como respuesta del origen
+    parts.headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    Response::from_parts(parts, Body::from(page.body.clone()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{http::StatusCode, middleware, response::IntoResponse, routing::get, Router};
+    use tower::ServiceExt;
+
+    fn page(status: u16, content_type: &str, body: &str) -> (u16, StaticResponse) {
+        let page = StaticResponse {
+            status,
+            content_type: content_type.to_string(),
+            file: format!("{}.html", status),
+            body: body.as_bytes().to_vec(),
+        };
+        (status, page)
+    }
+
+    fn app() -> Router {
+        let pages = HashMap::from([
+            page(429, "application/json", r#"{"error":"slow down"}"#),
+            page(502, "text/html", "<h1>Bad gateway</h1>"),
+        ]);
+        Router::new()
+            .route(
+                "/limited",
+                get(|| async { (StatusCode::TOO_MANY_REQUESTS, [(header::RETRY_AFTER, "30")], "Too many requests") }),
+            )
+            .route("/upstream", get(|| async { (StatusCode::BAD_GATEWAY, "backend body") }))
+            .route(
+                "/preserved",
+                get(|| async {
+                    let mut response = (StatusCode::BAD_GATEWAY, "backend body").into_response();
+                    response.extensions_mut().insert(PreserveBody);
+                    response
+                }),
+            )
+            .route("/unavailable", get(|| async { (StatusCode::SERVICE_UNAVAILABLE, "bare") }))
+            .layer(middleware::from_fn_with_state(Arc::new(pages), error_page_middleware))
+    }
+
+    async fn send(uri: &str) -> (StatusCode, axum::http::HeaderMap, String) {
+        let response = app()
+            .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let (parts, body) = response.into_parts();
+        let body = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+        (parts.status, parts.headers, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn configured_page_replaces_the_body_and_keeps_status_and_headers() {
+        let (status, headers, body) = send("/limited").await;
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(headers[header::CONTENT_TYPE], "application/json");
+        assert_eq!(headers[header::CACHE_CONTROL], "no-store");
+        assert_eq!(headers[header::RETRY_AFTER], "30");
+        // Nunca la longitud del body original
+        assert!(headers.get(header::CONTENT_LENGTH).is_none_or(|len| len == "21"));
+        assert_eq!(body, r#"{"error":"slow down"}"#);
+
+        let (status, headers, body) = send("/upstream").await;
+        assert_eq!(status, StatusCode::BAD_GATEWAY);
+        assert_eq!(headers[header::CONTENT_TYPE], "text/html");
+        assert_eq!(body, "<h1>Bad gateway</h1>");
+    }
+
+    #[tokio::test]
+    async fn preserved_and_unconfigured_responses_are_untouched() {
+        let (status, headers, body) = send("/preserved").await;
+        assert_eq!(status, StatusCode::BAD_GATEWAY);
+        assert!(headers.get(header::CACHE_CONTROL).is_none());
+        assert_eq!(body, "backend body");
+
+        let (status, _, body) = send("/unavailable").await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body, "bare");
+    }
+}
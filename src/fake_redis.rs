@@ -0,0 +1,433 @@
+//! Servidor Redis en memoria para los tests: implementa los comandos que usa el
+//! gateway (strings con TTL, listas, `SCAN`, scripts emulados en Rust) y permite inyectar fallos en los
+//! próximos comandos para simular timeouts, desconexiones y errores.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use crate::cache::{create_redis_client, RedisClient};
+
+/// Fallo inyectado en el próximo comando recibido
+#[derive(Debug, Clone)]
+pub enum Fault {
+    /// No responde: la respuesta se envía justo antes de la del siguiente comando
+    Silent,
+    /// Cierra la conexión sin responder
+    Close,
+    /// Ejecuta el comando y cierra la conexión sin responder: la respuesta se pierde
+    Lost,
+    /// Responde con un error de Redis (`-ERR ...`)
+    Error(&'static str),
+}
+
+enum Entry {
+    Str(Vec<u8>),
+    List(VecDeque<Vec<u8>>),
+}
+
+/// Emulación de un script Lua: recibe KEYS y ARGV y devuelve un entero
+type ScriptHandler = Arc<dyn Fn(&mut ScriptContext<'_>, &[String], &[String]) -> i64 + Send + Sync>;
+
+/// Acceso a los datos desde un script emulado, como `redis.call`
+pub struct ScriptContext<'a> {
+    state: &'a mut State,
+}
+
+impl ScriptContext<'_> {
+    /// Runs a command and returns its integer reply (0 for any other reply)
+    pub fn call(&mut self, args: &[&str]) -> i64 {
+        let mut args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        args[0] = args[0].to_uppercase();
+        let reply = execute(self.state, &args);
+        std::str::from_utf8(&reply)
+            .ok()
+            .and_then(|reply| reply.strip_prefix(':'))
+            .and_then(|reply| reply.trim_end().parse().ok())
+            .unwrap_or(0)
+    }
+
+    /// Value of a string key, like `redis.call("GET", key)`
+    pub fn get(&mut self, key: &str) -> Option<String> {
+        self.state.purge_expired();
+        match self.state.data.get(key) {
+            Some((Entry::Str(value), _)) => Some(String::from_utf8_lossy(value).into_owned()),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Default)]
+struct State {
+    data: BTreeMap<String, (Entry, Option<Instant>)>,
+    /// Scripts emulados, por su SHA1 (`EVALSHA`)
+    scripts: HashMap<String, ScriptHandler>,
+    faults: VecDeque<Fault>,
+    /// Comandos recibidos (sin los `CLIENT` de cada conexión nueva)
+    commands: Vec<Vec<String>>,
+    /// Última clave devuelta por cada cursor de `SCAN` en curso
+    scan_cursors: HashMap<usize, String>,
+}
+
+impl State {
+    fn purge_expired(&mut self) {
+        let now = Instant::now();
+        self.data.retain(|_, (_, expires)| expires.is_none_or(|at| at > now));
+    }
+}
+
+pub struct FakeRedis {
+    addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+}
+
+impl FakeRedis {
+    pub async fn start() -> Self {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let state = Arc::new(Mutex::new(State::default()));
+        let shared = state.clone();
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                tokio::spawn(serve(socket, shared.clone()));
+            }
+        });
+        Self { addr, state }
+    }
+
+    pub fn url(&self) -> String {
+        format!("redis://{}", self.addr)
+    }
+
+    pub async fn client(&self) -> RedisClient {
+        create_redis_client(&self.url()).await.unwrap()
+    }
+
+    /// Makes the next command that reaches the server fail with `fault`
+    pub fn fail_next(&self, fault: Fault) {
+        self.state.lock().unwrap().faults.push_back(fault);
+    }
+
+    /// Answers `EVALSHA` of `script` by running `handler` instead of its Lua source
+    pub fn register_script(
+        &self,
+        script: &redis::Script,
+        handler: impl Fn(&mut ScriptContext<'_>, &[String], &[String]) -> i64 + Send + Sync + 'static,
+    ) {
+        self.state
+            .lock()
+            .unwrap()
+            .scripts
+            .insert(script.get_hash().to_string(), Arc::new(handler));
+    }
+
+    /// Names of the commands received so far
+    pub fn command_names(&self) -> Vec<String> {
+        self.state.lock().unwrap().commands.iter().map(|c| c[0].clone()).collect()
+    }
+}
+
+async fn serve(mut socket: TcpStream, state: Arc<Mutex<State>>) {
+    let mut buf = Vec::new();
+    let mut chunk = [0u8; 4096];
+    // Respuestas retenidas por `Fault::Silent`
+    let mut withheld = 0;
+
+    loop {
+        let n = match socket.read(&mut chunk).await {
+            Ok(0) | Err(_) => return,
+            Ok(n) => n,
+        };
+        buf.extend_from_slice(&chunk[..n]);
+
+        let mut out = Vec::new();
+        while let Some((args, used)) = parse_command(&buf) {
+            buf.drain(..used);
+            if args.is_empty() {
+                continue;
+            }
+            if args[0].eq_ignore_ascii_case(b"CLIENT") {
+                out.extend_from_slice(b"+OK\r\n");
+                continue;
+            }
+
+            let args: Vec<String> = args.iter().map(|a| String::from_utf8_lossy(a).into_owned()).collect();
+            let mut args = args;
+            args[0] = args[0].to_uppercase();
+            let fault = {
+                let mut state = state.lock().unwrap();
+                state.commands.push(args.clone());
+                state.faults.pop_front()
+            };
+            match fault {
+                Some(Fault::Silent) => {
+                    withheld += 1;
+                    continue;
+                }
+                Some(Fault::Close) => {
+                    let _ = socket.write_all(&out).await;
+                    return;
+                }
+                Some(Fault::Lost) => {
+                    execute(&mut state.lock().unwrap(), &args);
+                    let _ = socket.write_all(&out).await;
+                    return;
+                }
+                Some(Fault::Error(message)) => {
+                    flush_withheld(&mut out, &mut withheld);
+                    out.extend_from_slice(format!("-{}\r\n", message).as_bytes());
+                }
+                None => {
+                    flush_withheld(&mut out, &mut withheld);
+                    let reply = execute(&mut state.lock().unwrap(), &args);
+                    out.extend_from_slice(&reply);
+                }
+            }
+        }
+        if !out.is_empty() && socket.write_all(&out).await.is_err() {
+            return;
+        }
+    }
+}
+
+fn flush_withheld(out: &mut Vec<u8>, withheld: &mut usize) {
+    for _ in 0..*withheld {
+        out.extend_from_slice(b"+OK\r\n");
+    }
+    *withheld = 0;
+}
+
+/// Parses one RESP array of bulk strings, returning it and the bytes used
+fn parse_command(buf: &[u8]) -> Option<(Vec<Vec<u8>>, usize)> {
+    fn line(buf: &[u8], from: usize) -> Option<(&[u8], usize)> {
+        let end = buf[from..].windows(2).position(|w| w == b"\r\n")? + from;
+        Some((&buf[from..end], end + 2))
+    }
+
+    let (header, mut pos) = line(buf, 0)?;
+    let count: usize = std::str::from_utf8(header.strip_prefix(b"*")?).ok()?.parse().ok()?;
+    let mut args = Vec::with_capacity(count);
+    for _ in 0..count {
+        let (len, next) = line(buf, pos)?;
+        let len: usize = std::str::from_utf8(len.strip_prefix(b"$")?).ok()?.parse().ok()?;
+        if buf.len() < next + len + 2 {
+            return None;
+        }
+        args.push(buf[next..next + len].to_vec());
+        pos = next + len + 2;
+    }
+    Some((args, pos))
+}
+
+fn bulk(value: &[u8]) -> Vec<u8> {
+    let mut out = format!("${}\r\n", value.len()).into_bytes();
+    out.extend_from_slice(value);
+    out.extend_from_slice(b"\r\n");
+    out
+}
+
+fn array(items: Vec<Vec<u8>>) -> Vec<u8> {
+    let mut out = format!("*{}\r\n", items.len()).into_bytes();
+    for item in items {
+        out.extend_from_slice(&item);
+    }
+    out
+}
+
+fn integer(value: i64) -> Vec<u8> {
+    format!(":{}\r\n", value).into_bytes()
+}
+
+const NIL: &[u8] = b"$-1\r\n";
+const OK: &[u8] = b"+OK\r\n";
+const WRONG_TYPE: &[u8] = b"-WRONGTYPE Operation against a key holding the wrong kind of value\r\n";
+
+/// Glob de Redis reducido a `*` y `?`, suficiente para los patrones del gateway
+fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some((b'*', rest)) => (0..=text.len()).any(|i| glob_match(rest, &text[i..])),
+        Some((b'?', rest)) => !text.is_empty() && glob_match(rest, &text[1..]),
+        Some((c, rest)) => text.first() == Some(c) && glob_match(rest, &text[1..]),
+    }
+}
+
+fn execute(state: &mut State, args: &[String]) -> Vec<u8> {
+    state.purge_expired();
+    let arg = |i: usize| args.get(i).map(String::as_str).unwrap_or("");
+    let int_arg = |i: usize| arg(i).parse::<i64>().unwrap_or(0);
+
+    match args[0].as_str() {
+        "PING" => b"+PONG\r\n".to_vec(),
+        "GET" => match state.data.get(arg(1)) {
+            Some((Entry::Str(value), _)) => bulk(value),
+            Some(_) => WRONG_TYPE.to_vec(),
+            None => NIL.to_vec(),
+        },
+        "SET" => {
+            let mut expires = None;
+            let mut nx = false;
+            let mut i = 3;
+            while i < args.len() {
+                match arg(i).to_uppercase().as_str() {
+                    "NX" => nx = true,
+                    "EX" => {
+                        expires = Some(Instant::now() + Duration::from_secs(int_arg(i + 1) as u64));
+                        i += 1;
+                    }
+                    "PX" => {
+                        expires = Some(Instant::now() + Duration::from_millis(int_arg(i + 1) as u64));
+                        i += 1;
+                    }
+                    _ => {}
+                }
+                i += 1;
+            }
+            if nx && state.data.contains_key(arg(1)) {
+                return NIL.to_vec();
+            }
+            state
+                .data
+                .insert(arg(1).to_string(), (Entry::Str(arg(2).as_bytes().to_vec()), expires));
+            OK.to_vec()
+        }
+        "SETEX" => {
+            let expires = Some(Instant::now() + Duration::from_secs(int_arg(2) as u64));
+            state
+                .data
+                .insert(arg(1).to_string(), (Entry::Str(arg(3).as_bytes().to_vec()), expires));
+            OK.to_vec()
+        }
+        "DEL" => integer(args[1..].iter().filter(|key| state.data.remove(key.as_str()).is_some()).count() as i64),
+        "EXISTS" => integer(args[1..].iter().filter(|key| state.data.contains_key(key.as_str())).count() as i64),
+        "INCR" | "INCRBY" => {
+            let by = if args[0] == "INCR" { 1 } else { int_arg(2) };
+            let (entry, _) = state
+                .data
+                .entry(arg(1).to_string())
+                .or_insert((Entry::Str(b"0".to_vec()), None));
+            let Entry::Str(value) = entry else {
+                return WRONG_TYPE.to_vec();
+            };
+            let next = String::from_utf8_lossy(value).parse::<i64>().unwrap_or(0) + by;
+            *value = next.to_string().into_bytes();
+            integer(next)
+        }
+        "EXPIRE" => match state.data.get_mut(arg(1)) {
+            Some((_, expires)) => {
+                *expires = Some(Instant::now() + Duration::from_secs(int_arg(2) as u64));
+                integer(1)
+            }
+            None => integer(0),
+        },
+        "TTL" => match state.data.get(arg(1)) {
+            Some((_, Some(at))) => integer(at.saturating_duration_since(Instant::now()).as_secs_f64().ceil() as i64),
+            Some((_, None)) => integer(-1),
+            None => integer(-2),
+        },
+        "LPUSH" | "RPUSH" => {
+            let (entry, _) = state
+                .data
+                .entry(arg(1).to_string())
+                .or_insert((Entry::List(VecDeque::new()), None));
+            let Entry::List(items) = entry else {
+                return WRONG_TYPE.to_vec();
+            };
+            for value in &args[2..] {
+                if args[0] == "LPUSH" {
+                    items.push_front(value.as_bytes().to_vec());
+                } else {
+                    items.push_back(value.as_bytes().to_vec());
+                }
+            }
+            integer(items.len() as i64)
+        }
+        "LTRIM" => {
+            if let Some((Entry::List(items), _)) = state.data.get_mut(arg(1)) {
+                let (start, stop) = list_range(items.len(), int_arg(2), int_arg(3));
+                *items = items.iter().skip(start).take(stop.saturating_sub(start)).cloned().collect();
+            }
+            OK.to_vec()
+        }
+        "LRANGE" => match state.data.get(arg(1)) {
+            Some((Entry::List(items), _)) => {
+                let (start, stop) = list_range(items.len(), int_arg(2), int_arg(3));
+                array(items.iter().skip(start).take(stop.saturating_sub(start)).map(|i| bulk(i)).collect())
+            }
+            _ => array(Vec::new()),
+        },
+        "LLEN" => match state.data.get(arg(1)) {
+            Some((Entry::List(items), _)) => integer(items.len() as i64),
+            _ => integer(0),
+        },
+        "SCAN" => {
+            let cursor = int_arg(1).max(0) as usize;
+            let mut pattern = "*".to_string();
+            let mut count = 10;
+            let mut i = 2;
+            while i + 1 < args.len() {
+                match arg(i).to_uppercase().as_str() {
+                    "MATCH" => pattern = arg(i + 1).to_string(),
+                    "COUNT" => count = int_arg(i + 1).max(1) as usize,
+                    _ => {}
+                }
+                i += 2;
+            }
+            // El cursor recuerda la última clave recorrida, así que borrar claves
+            // durante el SCAN no hace saltarse otras (como garantiza Redis)
+            let after = state.scan_cursors.remove(&cursor);
+            let from = match &after {
+                Some(key) => std::ops::Bound::Excluded(key.clone()),
+                None => std::ops::Bound::Unbounded,
+            };
+            // Como Redis, COUNT acota las claves recorridas, no las devueltas
+            let visited: Vec<String> = state
+                .data
+                .range((from, std::ops::Bound::Unbounded))
+                .take(count)
+                .map(|(key, _)| key.clone())
+                .collect();
+            let last = visited.last().filter(|last| {
+                let rest = (std::ops::Bound::Excluded(last.to_string()), std::ops::Bound::Unbounded);
+                state.data.range(rest).next().is_some()
+            });
+            // Cada SCAN ya está registrado en `commands`, así que su número es un cursor único
+            let next = match last {
+                Some(last) => {
+                    state.scan_cursors.insert(state.commands.len(), last.clone());
+                    state.commands.len()
+                }
+                None => 0,
+            };
+            let matched = visited
+                .iter()
+                .filter(|key| glob_match(pattern.as_bytes(), key.as_bytes()))
+                .map(|key| bulk(key.as_bytes()))
+                .collect();
+            array(vec![bulk(next.to_string().as_bytes()), array(matched)])
+        }
+        "EVALSHA" => {
+            let Some(handler) = state.scripts.get(arg(1)).cloned() else {
+                return b"-NOSCRIPT No matching script. Please use EVAL.\r\n".to_vec();
+            };
+            let key_count = int_arg(2).max(0) as usize;
+            if args.len() < 3 + key_count {
+                return b"-ERR Number of keys can't be greater than number of args\r\n".to_vec();
+            }
+            let (keys, argv) = args[3..].split_at(key_count);
+            integer(handler(&mut ScriptContext { state }, keys, argv))
+        }
+        other => format!("-ERR unknown command '{}'\r\n", other).into_bytes(),
+    }
+}
+
+/// Inclusive Redis list indexes (negative from the end) as a `start..stop` range
+fn list_range(len: usize, start: i64, stop: i64) -> (usize, usize) {
+    let resolve = |index: i64| if index < 0 { (len as i64 + index).max(0) } else { index };
+    let start = resolve(start).min(len as i64) as usize;
+    let stop = (resolve(stop) + 1).min(len as i64).max(0) as usize;
+    (start, stop)
+}
@@ -0,0 +1,153 @@
+use redis::{AsyncCommands, RedisError};
+use std::time::Duration;
+
+use crate::cache::RedisClient;
+
+/// Prefijo de las claves del caché de `file_id` -> `server_id`
+const KEY_PREFIX: &str = "file_owner:";
+
+/// Claves pedidas a Redis en cada iteración de `SCAN` al vaciar el caché
+const SCAN_BATCH: usize = 500;
+
+/// Redis key holding the backend that owns `file_id`
+pub fn key(file_id: &str) -> String {
+    format!("{}{}", KEY_PREFIX, file_id)
+}
+
+/// `SCAN` pattern matching every file-lookup key. The prefix has no glob
+/// characters, so no other namespace can match it.
+fn pattern() -> String {
+    format!("{}*", KEY_PREFIX)
+}
+
+/// Cached owner of `file_id`. Redis errors count as a miss, so the lookup
+/// falls back to the database instead of failing the request.
+pub async fn get(redis: &RedisClient, file_id: &str) -> Option<String> {
+    match crate::cache::cache_get(redis, &key(file_id)).await {
+        Ok(server_id) => server_id,
+        Err(e) => {
+            tracing::warn!("Failed to read cached owner of file {}: {}", file_id, e);
+            None
+        }
+    }
+}
+
+/// Remembers the owner of `file_id` for `ttl`
+pub async fn set(redis: &RedisClient, file_id: &str, server_id: &str, ttl: Duration) {
+    if let Err(e) = crate::cache::cache_set(redis, &key(file_id), server_id, ttl).await {
+        tracing::warn!("Failed to cache owner of file {}: {}", file_id, e);
+    }
+}
+
+/// Evicts the cached owner of one file. Returns the number of keys removed (0 or 1).
+pub async fn evict(redis: &RedisClient, file_id: &str) -> Result<u64, RedisError> {
+    let key = key(file_id);
+    let key = &key;
+    redis
+        .run(|mut conn| async move { conn.del(key).await })
+        .await
+}
+
+/// Evicts every cached file owner with `SCAN` + `DEL`, a batch at a time so
+/// Redis is never blocked by a single large command. Returns the number of
+/// keys removed.
+pub async fn flush(redis: &RedisClient) -> Result<u64, RedisError> {
+    let pattern = pattern();
+    let pattern = &pattern;
+    let mut cursor = 0u64;
+    let mut removed = 0;
+
+    loop {
+        let (next, keys): (u64, Vec<String>) = redis
+            .run(|mut conn| async move {
+                redis::cmd("SCAN")
+                    .arg(cursor)
+                    .arg("MATCH")
+                    .arg(pattern)
+                    .arg("COUNT")
+                    .arg(SCAN_BATCH)
+                    .query_async(&mut conn)
+                    .await
+            })
+            .await?;
+
+        if !keys.is_empty() {
+            let keys = &keys;
+            // Las claves que expiran entre SCAN y DEL no cuentan como eliminadas
+            let deleted: u64 = redis
+                .run(|mut conn| async move { conn.del(keys).await })
+                .await?;
+            removed += deleted;
+        }
+
+        cursor = next;
+        if cursor == 0 {
+            return Ok(removed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fake_redis::{FakeRedis, Fault};
+
+    #[test]
+    fn keys_live_in_their_own_namespace() {
+        assert_eq!(key("abc-123"), "file_owner:abc-123");
+        assert_eq!(pattern(), "file_owner:*");
+        assert!(key("abc-123").starts_with(pattern().trim_end_matches('*')));
+        assert!(!"file_lock:abc-123".starts_with(pattern().trim_end_matches('*')));
+    }
+
+    #[tokio::test]
+    async fn evict_removes_a_single_entry() {
+        let server = FakeRedis::start().await;
+        let redis = server.client().await;
+        let ttl = Duration::from_secs(60);
+        set(&redis, "evict-one", "server-1", ttl).await;
+        set(&redis, "evict-other", "server-2", ttl).await;
+
+        assert_eq!(evict(&redis, "evict-one").await.unwrap(), 1);
+        assert_eq!(evict(&redis, "evict-one").await.unwrap(), 0);
+        assert_eq!(get(&redis, "evict-one").await, None);
+        assert_eq!(get(&redis, "evict-other").await.as_deref(), Some("server-2"));
+    }
+
+    #[tokio::test]
+    async fn flush_removes_only_file_lookups() {
+        let server = FakeRedis::start().await;
+        let redis = server.client().await;
+        let ttl = Duration::from_secs(60);
+        // Más claves que un lote de SCAN, para recorrer varios cursores
+        for i in 0..1200 {
+            set(&redis, &format!("flush-{}", i), "server-1", ttl).await;
+        }
+        crate::cache::cache_set(&redis, "file_lock:flush-0", "token", ttl).await.unwrap();
+
+        assert_eq!(flush(&redis).await.unwrap(), 1200);
+        assert_eq!(flush(&redis).await.unwrap(), 0);
+        assert_eq!(get(&redis, "flush-0").await, None);
+        assert_eq!(
+            crate::cache::cache_get(&redis, "file_lock:flush-0").await.unwrap().as_deref(),
+            Some("token")
+        );
+        assert!(server.command_names().iter().filter(|name| name.as_str() == "SCAN").count() > 2);
+    }
+
+    #[tokio::test]
+    async fn redis_errors_are_a_miss_on_lookup_and_an_error_on_eviction() {
+        let server = FakeRedis::start().await;
+        let redis = server.client().await;
+        set(&redis, "file-1", "server-1", Duration::from_secs(60)).await;
+
+        server.fail_next(Fault::Error("ERR boom"));
+        assert_eq!(get(&redis, "file-1").await, None);
+        server.fail_next(Fault::Error("ERR boom"));
+        assert!(evict(&redis, "file-1").await.is_err());
+        server.fail_next(Fault::Error("ERR boom"));
+        assert!(flush(&redis).await.is_err());
+
+        assert_eq!(get(&redis, "file-1").await.as_deref(), Some("server-1"));
+    }
+}
@@ -0,0 +1,372 @@
+use axum::http::Method;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::cache::RedisClient;
+
+/// Intervalo entre intentos mientras se espera un lock ocupado
+const LOCK_RETRY_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Libera el lock solo si sigue siendo nuestro: tras expirar el TTL otra
+/// petición pudo adquirirlo y no hay que borrárselo
+const RELEASE_SCRIPT: &str = r#"
+if redis.call("GET", KEYS[1]) == ARGV[1] then
+    return redis.call("DEL", KEYS[1])
+end
+return 0
+"#;
+
+/// Serialización de las escrituras a un mismo archivo con un lock en Redis
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileLockConfig {
+    /// Prefijos de ruta donde se aplica (vacío = desactivado)
+    pub paths: Vec<String>,
+    /// Vida máxima de un lock, por si el gateway que lo tiene muere
+    pub ttl_secs: u64,
+    /// Tiempo que una petición espera a que el lock se libere antes del 409 (0 = no espera)
+    pub wait_ms: u64,
+}
+
+impl Default for FileLockConfig {
+    fn default() -> Self {
+        Self {
+            paths: Vec::new(),
+            ttl_secs: 300,
+            wait_ms: 0,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct FileLockCounters {
+    acquired: AtomicU64,
+    /// Peticiones que esperaron a que otra liberara el lock
+    waited: AtomicU64,
+    /// Peticiones rechazadas con 409
+    conflicts: AtomicU64,
+    redis_errors: AtomicU64,
+}
+
+/// Per-file advisory locks held in Redis (`SET NX` with a TTL), so that
+/// concurrent uploads or deletes of the same file reach the backend one at a time
+pub struct FileLocks {
+    config: FileLockConfig,
+    redis: RedisClient,
+    counters: Arc<FileLockCounters>,
+}
+
+/// Result of trying to lock a file
+pub enum LockOutcome {
+    /// The request doesn't need a lock
+    NotRequired,
+    Acquired(FileLockGuard),
+    /// Another request holds the lock
+    Conflict,
+}
+
+impl FileLocks {
+    pub fn new(config: FileLockConfig, redis: RedisClient) -> Self {
+        if !config.paths.is_empty() {
+            tracing::info!(
+                "Locking file writes under {:?} (TTL {}s, wait {}ms)",
+                config.paths,
+                config.ttl_secs,
+                config.wait_ms
+            );
+        }
+        Self {
+            config,
+            redis,
+            counters: Arc::new(FileLockCounters::default()),
+        }
+    }
+
+    /// Whether a request modifies a file on a locked route
+    fn applies(&self, method: &Method, path: &str) -> bool {
+        matches!(*method, Method::POST | Method::PUT | Method::PATCH | Method::DELETE)
+            && self.config.paths.iter().any(|prefix| path.starts_with(prefix.as_str()))
+    }
+
+    /// Locks `file_id` for a mutating request on a locked route, waiting up to
+    /// `wait_ms` for the current holder. Redis errors fail open: the request
+    /// goes through unlocked rather than failing.
+    pub async fn lock(&self, method: &Method, path: &str, file_id: &str) -> LockOutcome {
+        if !self.applies(method, path) {
+            return LockOutcome::NotRequired;
+        }
+
+        let key = format!("file_lock:{}", file_id);
+        let token = uuid::Uuid::new_v4().to_string();
+        let ttl_secs = self.config.ttl_secs.max(1);
+        let deadline = tokio::time::Instant::now() + Duration::from_millis(self.config.wait_ms);
+        let mut waited = false;
+
+        loop {
+            let result: Result<bool, _> = self
+                .redis
+                .run(|mut conn| {
+                    let (key, token) = (key.clone(), token.clone());
+                    async move {
+                        let reply: Option<String> = redis::cmd("SET")
+                            .arg(&key)
+                            .arg(&token)
+                            .arg("NX")
+                            .arg("EX")
+                            .arg(ttl_secs)
+                            .query_async(&mut conn)
+                            .await?;
+                        if reply.is_some() {
+                            return Ok(true);
+                        }
+                        // Un reintento tras perder la respuesta encuentra nuestro propio lock
+                        let holder: Option<String> = redis::cmd("GET").arg(&key).query_async(&mut conn).await?;
+                        Ok(holder.as_deref() == Some(token.as_str()))
+                    }
+                })
+                .await;
+
+            match result {
+                Ok(true) => {
+                    self.counters.acquired.fetch_add(1, Ordering::Relaxed);
+                    if waited {
+                        self.counters.waited.fetch_add(1, Ordering::Relaxed);
+                    }
+                    tracing::debug!("Locked file {} for {} {}", file_id, method, path);
+                    return LockOutcome::Acquired(FileLockGuard {
+                        key,
+                        token,
+                        redis: self.redis.clone(),
+                    });
+                }
+                Ok(false) if tokio::time::Instant::now() + LOCK_RETRY_INTERVAL <= deadline => {
+                    waited = true;
+                    tokio::time::sleep(LOCK_RETRY_INTERVAL).await;
+                }
+                Ok(false) => {
+                    self.counters.conflicts.fetch_add(1, Ordering::Relaxed);
+                    tracing::info!("File {} is locked by another request, rejecting {} {}", file_id, method, path);
+                    return LockOutcome::Conflict;
+                }
+                Err(e) => {
+                    self.counters.redis_errors.fetch_add(1, Ordering::Relaxed);
+                    tracing::warn!("Failed to lock file {}, proceeding without a lock: {}", file_id, e);
+                    return LockOutcome::NotRequired;
+                }
+            }
+        }
+    }
+
+    /// Counters for the stats endpoint, `null` when locking is disabled
+    pub fn snapshot(&self) -> serde_json::Value {
+        if self.config.paths.is_empty() {
+            return serde_json::Value::Null;
+        }
+        serde_json::json!({
+            "acquired": self.counters.acquired.load(Ordering::Relaxed),
+            "waited": self.counters.waited.load(Ordering::Relaxed),
+            "conflicts": self.counters.conflicts.load(Ordering::Relaxed),
+            "redis_errors": self.counters.redis_errors.load(Ordering::Relaxed),
+        })
+    }
+}
+
+/// Lock held while the request is forwarded. It is released when dropped,
+/// also when the client disconnects mid-request.
+pub struct FileLockGuard {
+    key: String,
+    token: String,
+    redis: RedisClient,
+}
+
+impl Drop for FileLockGuard {
+    fn drop(&mut self) {
+        let key = std::mem::take(&mut self.key);
+        let token = std::mem::take(&mut self.token);
+        let redis = self.redis.clone();
+
+        tokio::spawn(async move {
+            let result: Result<i32, _> = redis
+                .run(|mut conn| {
+                    let (key, token) = (key.clone(), token.clone());
+                    async move {
+                        redis::Script::new(RELEASE_SCRIPT)
+                            .key(key)
+                            .arg(token)
+                            .invoke_async(&mut conn)
+                            .await
+                    }
+                })
+                .await;
+            // Si no se libera, expira con el TTL
+            if let Err(e) = result {
+                tracing::warn!("Failed to release file lock {}: {}", key, e);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fake_redis::{FakeRedis, Fault};
+
+    const UPLOAD: &str = "/api/v1/files/upload";
+
+    async fn file_locks(wait_ms: u64) -> (FakeRedis, FileLocks) {
+        let server = FakeRedis::start().await;
+        server.register_script(&redis::Script::new(RELEASE_SCRIPT), |redis, keys, argv| {
+            if redis.get(&keys[0]).as_deref() == Some(argv[0].as_str()) {
+                redis.call(&["DEL", &keys[0]])
+            } else {
+                0
+            }
+        });
+        let config = FileLockConfig {
+            paths: vec!["/api/v1/files".to_string()],
+            ttl_secs: 30,
+            wait_ms,
+        };
+        let locks = FileLocks::new(config, server.client().await);
+        (server, locks)
+    }
+
+    async fn lock_value(locks: &FileLocks, file_id: &str) -> Option<String> {
+        let key = format!("file_lock:{}", file_id);
+        locks
+            .redis
+            .run(|mut conn| {
+                let key = key.clone();
+                async move { redis::cmd("GET").arg(key).query_async(&mut conn).await }
+            })
+            .await
+            .unwrap()
+    }
+
+    /// Waits for the guard released in the background to delete its key
+    async fn wait_released(locks: &FileLocks, file_id: &str) {
+        for _ in 0..100 {
+            if lock_value(locks, file_id).await.is_none() {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("lock on {} was not released", file_id);
+    }
+
+    fn acquired(outcome: LockOutcome) -> FileLockGuard {
+        match outcome {
+            LockOutcome::Acquired(guard) => guard,
+            LockOutcome::NotRequired => panic!("expected a lock, got NotRequired"),
+            LockOutcome::Conflict => panic!("expected a lock, got Conflict"),
+        }
+    }
+
+    #[tokio::test]
+    async fn only_writes_under_the_configured_paths_are_locked() {
+        let (server, locks) = file_locks(0).await;
+
+        for method in [Method::POST, Method::PUT, Method::PATCH, Method::DELETE] {
+            assert!(locks.applies(&method, UPLOAD), "{}", method);
+        }
+        assert!(!locks.applies(&Method::GET, UPLOAD));
+        assert!(!locks.applies(&Method::HEAD, UPLOAD));
+        assert!(!locks.applies(&Method::POST, "/api/v1/other"));
+
+        assert!(matches!(locks.lock(&Method::GET, UPLOAD, "f1").await, LockOutcome::NotRequired));
+        assert!(server.command_names().is_empty());
+
+        let disabled = FileLocks::new(FileLockConfig::default(), server.client().await);
+        assert!(matches!(disabled.lock(&Method::POST, UPLOAD, "f1").await, LockOutcome::NotRequired));
+        assert!(disabled.snapshot().is_null());
+    }
+
+    #[tokio::test]
+    async fn concurrent_writes_to_the_same_file_conflict() {
+        let (_server, locks) = file_locks(0).await;
+
+        let guard = acquired(locks.lock(&Method::POST, UPLOAD, "f1").await);
+        assert!(matches!(locks.lock(&Method::PUT, UPLOAD, "f1").await, LockOutcome::Conflict));
+        // Otro archivo no se ve afectado
+        let _other = acquired(locks.lock(&Method::POST, UPLOAD, "f2").await);
+
+        drop(guard);
+        wait_released(&locks, "f1").await;
+        let _again = acquired(locks.lock(&Method::PUT, UPLOAD, "f1").await);
+
+        let stats = locks.snapshot();
+        assert_eq!(stats["acquired"], 3);
+        assert_eq!(stats["conflicts"], 1);
+        assert_eq!(stats["waited"], 0);
+    }
+
+    #[tokio::test]
+    async fn lock_taken_by_an_attempt_whose_reply_was_lost_is_ours() {
+        let (server, locks) = file_locks(0).await;
+
+        // El primer SET se aplica pero la respuesta no llega: el reintento ve la clave
+        server.fail_next(Fault::Lost);
+        let guard = acquired(locks.lock(&Method::POST, UPLOAD, "f1").await);
+        assert_eq!(lock_value(&locks, "f1").await.as_deref(), Some(guard.token.as_str()));
+        assert!(matches!(locks.lock(&Method::PUT, UPLOAD, "f1").await, LockOutcome::Conflict));
+
+        drop(guard);
+        wait_released(&locks, "f1").await;
+    }
+
+    #[tokio::test]
+    async fn writes_wait_for_the_holder_to_release_the_lock() {
+        let (_server, locks) = file_locks(1000).await;
+
+        let guard = acquired(locks.lock(&Method::POST, UPLOAD, "f1").await);
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(150)).await;
+            drop(guard);
+        });
+
+        let started = std::time::Instant::now();
+        let _second = acquired(locks.lock(&Method::POST, UPLOAD, "f1").await);
+        assert!(started.elapsed() >= Duration::from_millis(100));
+        assert_eq!(locks.snapshot()["waited"], 1);
+    }
+
+    #[tokio::test]
+    async fn waiting_gives_up_with_a_conflict_after_wait_ms() {
+        let (_server, locks) = file_locks(200).await;
+
+        let _guard = acquired(locks.lock(&Method::POST, UPLOAD, "f1").await);
+        let started = std::time::Instant::now();
+        assert!(matches!(locks.lock(&Method::POST, UPLOAD, "f1").await, LockOutcome::Conflict));
+        assert!(started.elapsed() >= Duration::from_millis(100));
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn expired_lock_taken_by_another_request_is_not_released() {
+        let (_server, locks) = file_locks(0).await;
+
+        let guard = acquired(locks.lock(&Method::POST, UPLOAD, "f1").await);
+        // El TTL venció y otra petición tomó el lock con su propio token
+        locks
+            .redis
+            .run(|mut conn| async move {
+                redis::cmd("SET").arg("file_lock:f1").arg("other-holder").query_async::<_, ()>(&mut conn).await
+            })
+            .await
+            .unwrap();
+
+        drop(guard);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(lock_value(&locks, "f1").await.as_deref(), Some("other-holder"));
+    }
+
+    #[tokio::test]
+    async fn redis_errors_let_the_request_through_unlocked() {
+        let (server, locks) = file_locks(0).await;
+        server.fail_next(Fault::Error("ERR boom"));
+
+        assert!(matches!(locks.lock(&Method::POST, UPLOAD, "f1").await, LockOutcome::NotRequired));
+        assert_eq!(locks.snapshot()["redis_errors"], 1);
+    }
+}
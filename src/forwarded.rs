@@ -0,0 +1,309 @@
+use axum::{
+    extract::{ConnectInfo, Request},
+    http::HeaderMap,
+    middleware::Next,
+    response::Response,
+};
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, SocketAddr};
+
+/// Lectura del cliente original desde los headers de un proxy de confianza
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ForwardedConfig {
+    /// Confía en `Forwarded` / `X-Forwarded-*` (solo si el gateway no es accesible directamente)
+    pub trust: bool,
+    /// Proxies de confianza delante del gateway: el cliente es la entrada que
+    /// añadió el más externo, contando desde la derecha
+    pub trusted_hops: usize,
+}
+
+impl Default for ForwardedConfig {
+    fn default() -> Self {
+        Self {
+            trust: false,
+            trusted_hops: 1,
+        }
+    }
+}
+
+/// Cliente original según el proxy de confianza, guardado como extensión de la petición
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ForwardedClient {
+    /// `None` si el proxy lo ocultó (`unknown` o un identificador `_ofuscado`)
+    pub ip: Option<IpAddr>,
+    pub proto: Option<String>,
+    pub host: Option<String>,
+}
+
+/// Middleware that resolves the original client from the forwarding headers
+/// of the trusted proxies. The headers themselves reach the backend unchanged.
+pub async fn resolve_client(config: ForwardedConfig, mut req: Request, next: Next) -> Response {
+    if config.trust {
+        if let Some(client) = forwarded_client(req.headers(), config.trusted_hops) {
+            tracing::debug!("Client from forwarding headers: {:?}", client);
+            req.extensions_mut().insert(client);
+        }
+    }
+    next.run(req).await
+}
+
+/// Client IP for logs and limits: the one from the trusted forwarding headers,
+/// or the connection peer (the one from the PROXY header, if enabled)
+pub fn client_ip(req: &Request) -> Option<IpAddr> {
+    req.extensions()
+        .get::<ForwardedClient>()
+        .and_then(|client| client.ip)
+        .or_else(|| req.extensions().get::<ConnectInfo<SocketAddr>>().map(|ConnectInfo(addr)| addr.ip()))
+}
+
+/// Original client according to `Forwarded` (RFC 7239) or, without it, the
+/// `X-Forwarded-For` / `-Proto` / `-Host` headers. Each proxy appends its own
+/// entry, so the client is the `trusted_hops`-th one from the right; with fewer
+/// entries, the leftmost.
+pub fn forwarded_client(headers: &HeaderMap, trusted_hops: usize) -> Option<ForwardedClient> {
+    let elements = joined(headers, "forwarded").map(|v| parse_forwarded(&v)).unwrap_or_default();
+    if !elements.is_empty() {
+        let element = pick(&elements, trusted_hops)?;
+        let param = |name: &str| {
+            element
+                .iter()
+                .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.clone())
+        };
+        return Some(ForwardedClient {
+            ip: param("for").as_deref().and_then(parse_node),
+            proto: param("proto").map(|p| p.to_ascii_lowercase()),
+            host: param("host"),
+        });
+    }
+
+    let list = |name: &str| -> Vec<String> {
+        joined(headers, name)
+            .map(|value| value.split(',').map(|v| v.trim().to_string()).filter(|v| !v.is_empty()).collect())
+            .unwrap_or_default()
+    };
+    let (for_list, proto_list, host_list) = (list("x-forwarded-for"), list("x-forwarded-proto"), list("x-forwarded-host"));
+    if for_list.is_empty() && proto_list.is_empty() && host_list.is_empty() {
+        return None;
+    }
+    Some(ForwardedClient {
+        ip: pick(&for_list, trusted_hops).and_then(|node| parse_node(node)),
+        proto: pick(&proto_list, trusted_hops).map(|p| p.to_ascii_lowercase()),
+        host: pick(&host_list, trusted_hops).cloned(),
+    })
+}
+
+/// Every value of a repeated header, joined as a single list
+fn joined(headers: &HeaderMap, name: &str) -> Option<String> {
+    let values: Vec<&str> = headers.get_all(name).iter().filter_map(|v| v.to_str().ok()).collect();
+    (!values.is_empty()).then(|| values.join(","))
+}
+
+fn pick<T>(entries: &[T], trusted_hops: usize) -> Option<&T> {
+    entries.get(entries.len().saturating_sub(trusted_hops.max(1)))
+}
+
+/// Parses a `Forwarded` value into its elements (one per proxy), each a list
+/// of `name=value` pairs with quoted values unescaped. Malformed pairs are skipped.
+pub fn parse_forwarded(value: &str) -> Vec<Vec<(String, String)>> {
+    let mut elements = Vec::new();
+    let mut pairs = Vec::new();
+    let mut chars = value.chars().peekable();
+
+    loop {
+        // Nombre del parámetro, hasta `=`, `;` o `,`
+        let mut name = String::new();
+        while let Some(&c) = chars.peek() {
+            if matches!(c, '=' | ';' | ',') {
+                break;
+            }
+            name.push(c);
+            chars.next();
+        }
+
+        let mut param_value = None;
+        if chars.peek() == Some(&'=') {
+            chars.next();
+            let mut v = String::new();
+            if chars.peek() == Some(&'"') {
+                chars.next();
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => v.extend(chars.next()),
+                        c => v.push(c),
+                    }
+                }
+            }
+            while let Some(&c) = chars.peek() {
+                if matches!(c, ';' | ',') {
+                    break;
+                }
+                v.push(c);
+                chars.next();
+            }
+            param_value = Some(v.trim().to_string());
+        }
+
+        let name = name.trim();
+        if let (false, Some(v)) = (name.is_empty(), param_value) {
+            pairs.push((name.to_string(), v));
+        }
+
+        match chars.next() {
+            Some(';') => {}
+            Some(',') => elements.push(std::mem::take(&mut pairs)),
+            _ => {
+                elements.push(pairs);
+                break;
+            }
+        }
+    }
+
+    elements.retain(|pairs| !pairs.is_empty());
+    elements
+}
+
+/// IP of a node (`192.0.2.43`, `192.0.2.43:47011`, `[2001:db8::1]:4711`,
+/// `2001:db8::1`). `unknown` and obfuscated identifiers (`_hidden`) yield `None`.
+pub fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim();
+    if let Some(rest) = node.strip_prefix('[') {
+        return rest.split_once(']')?.0.parse().ok();
+    }
+    node.parse()
+        .ok()
+        .or_else(|| node.rsplit_once(':').and_then(|(ip, _port)| ip.parse::<std::net::Ipv4Addr>().ok()).map(IpAddr::V4))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.append(*name, HeaderValue::from_static(value));
+        }
+        headers
+    }
+
+    fn pairs(element: &[(&str, &str)]) -> Vec<(String, String)> {
+        element.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+    }
+
+    #[test]
+    fn parses_elements_and_pairs() {
+        assert_eq!(
+            parse_forwarded("for=192.0.2.60;proto=http;by=203.0.113.43, for=198.51.100.17"),
+            vec![
+                pairs(&[("for", "192.0.2.60"), ("proto", "http"), ("by", "203.0.113.43")]),
+                pairs(&[("for", "198.51.100.17")]),
+            ]
+        );
+    }
+
+    #[test]
+    fn unquotes_and_unescapes_values() {
+        assert_eq!(
+            parse_forwarded(r#"for="[2001:db8:cafe::17]:4711";host="example.com";x="a\"b,c""#),
+            vec![pairs(&[("for", "[2001:db8:cafe::17]:4711"), ("host", "example.com"), ("x", "a\"b,c")])]
+        );
+    }
+
+    #[test]
+    fn skips_malformed_pairs_and_empty_elements() {
+        assert_eq!(
+            parse_forwarded("garbage;for=192.0.2.1,,;=x, proto=https"),
+            vec![pairs(&[("for", "192.0.2.1")]), pairs(&[("proto", "https")])]
+        );
+        assert!(parse_forwarded("").is_empty());
+    }
+
+    #[test]
+    fn parses_node_forms() {
+        let v4: IpAddr = "192.0.2.43".parse().unwrap();
+        let v6: IpAddr = "2001:db8::1".parse().unwrap();
+        assert_eq!(parse_node("192.0.2.43"), Some(v4));
+        assert_eq!(parse_node("192.0.2.43:47011"), Some(v4));
+        assert_eq!(parse_node("[2001:db8::1]:4711"), Some(v6));
+        assert_eq!(parse_node("[2001:db8::1]"), Some(v6));
+        assert_eq!(parse_node("2001:db8::1"), Some(v6));
+    }
+
+    #[test]
+    fn hidden_nodes_have_no_ip() {
+        assert_eq!(parse_node("unknown"), None);
+        assert_eq!(parse_node("_hidden"), None);
+        assert_eq!(parse_node("\"_SEVKISEK\""), None);
+    }
+
+    #[test]
+    fn pick_counts_trusted_hops_from_the_right() {
+        let entries = ["client", "proxy1", "proxy2"];
+        assert_eq!(pick(&entries, 1), Some(&"proxy2"));
+        assert_eq!(pick(&entries, 2), Some(&"proxy1"));
+        assert_eq!(pick(&entries, 5), Some(&"client"));
+        assert_eq!(pick(&entries, 0), Some(&"proxy2"));
+        assert_eq!(pick::<&str>(&[], 1), None);
+    }
+
+    #[test]
+    fn forwarded_header_takes_precedence() {
+        let headers = headers(&[
+            ("forwarded", r#"for=192.0.2.60;proto=HTTPS;host="files.example.com""#),
+            ("x-forwarded-for", "198.51.100.17"),
+            ("x-forwarded-proto", "http"),
+        ]);
+
+        assert_eq!(
+            forwarded_client(&headers, 1),
+            Some(ForwardedClient {
+                ip: Some("192.0.2.60".parse().unwrap()),
+                proto: Some("https".to_string()),
+                host: Some("files.example.com".to_string()),
+            })
+        );
+    }
+
+    #[test]
+    fn repeated_forwarded_headers_form_one_list() {
+        let headers = headers(&[("forwarded", "for=192.0.2.60"), ("forwarded", "for=198.51.100.17")]);
+
+        assert_eq!(forwarded_client(&headers, 1).unwrap().ip, Some("198.51.100.17".parse().unwrap()));
+        assert_eq!(forwarded_client(&headers, 2).unwrap().ip, Some("192.0.2.60".parse().unwrap()));
+    }
+
+    #[test]
+    fn falls_back_to_x_forwarded_headers() {
+        let headers = headers(&[
+            ("x-forwarded-for", "203.0.113.7, 10.0.0.2"),
+            ("x-forwarded-proto", "HTTPS"),
+            ("x-forwarded-host", "files.example.com"),
+        ]);
+
+        assert_eq!(
+            forwarded_client(&headers, 2),
+            Some(ForwardedClient {
+                ip: Some("203.0.113.7".parse().unwrap()),
+                proto: Some("https".to_string()),
+                host: Some("files.example.com".to_string()),
+            })
+        );
+    }
+
+    #[test]
+    fn obfuscated_client_keeps_proto() {
+        let headers = headers(&[("forwarded", "for=_hidden;proto=https")]);
+        let client = forwarded_client(&headers, 1).unwrap();
+
+        assert_eq!(client.ip, None);
+        assert_eq!(client.proto.as_deref(), Some("https"));
+    }
+
+    #[test]
+    fn no_forwarding_headers() {
+        assert_eq!(forwarded_client(&HeaderMap::new(), 1), None);
+    }
+}
@@ -0,0 +1,400 @@
+use axum::{
+    body::Body,
+    http::{header, HeaderMap, HeaderValue, Method, Request, StatusCode},
+};
+use http_body_util::BodyExt;
+use hyper_rustls::HttpsConnectorBuilder;
+use hyper_util::{
+    client::legacy::{connect::HttpConnector, Client},
+    rt::TokioExecutor,
+};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::{
+    backends::{join_backend_url, split_url_credentials},
+    db::Backend,
+    mtls::ClientIdentity,
+    proxy::HttpsClient,
+};
+
+/// Método del protocolo estándar de health checking de gRPC
+const HEALTH_CHECK_PATH: &str = "/grpc.health.v1.Health/Check";
+
+/// Estado de `grpc.health.v1.HealthCheckResponse`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServingStatus {
+    Unknown,
+    Serving,
+    NotServing,
+    ServiceUnknown,
+}
+
+impl ServingStatus {
+    fn from_proto(value: u64) -> Self {
+        match value {
+            1 => Self::Serving,
+            2 => Self::NotServing,
+            3 => Self::ServiceUnknown,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// HTTP/2 client for the probes: ALPN over TLS, prior knowledge over cleartext
+fn h2_client(tls_config: Option<rustls::ClientConfig>, timeout: Duration) -> HttpsClient {
+    let mut http = HttpConnector::new();
+    http.enforce_http(false);
+    http.set_connect_timeout(Some(timeout));
+
+    let builder = match tls_config {
+        Some(tls_config) => HttpsConnectorBuilder::new().with_tls_config(tls_config),
+        None => HttpsConnectorBuilder::new()
+            .with_native_roots()
+            .expect("Failed to load native root certificates"),
+    };
+    let https = builder.https_or_http().enable_http2().wrap_connector(http);
+    Client::builder(TokioExecutor::new()).http2_only(true).build(https)
+}
+
+/// Cliente de `grpc.health.v1.Health/Check` para los backends gRPC
+pub struct GrpcHealthClient {
+    client: HttpsClient,
+    /// Cliente con el certificado mTLS, para los backends que lo exigen
+    mtls: Option<(HttpsClient, Arc<ClientIdentity>)>,
+    timeout: Duration,
+}
+
+impl GrpcHealthClient {
+    pub fn new(identity: Option<Arc<ClientIdentity>>, timeout: Duration) -> Self {
+        let mtls = identity.map(|identity| {
+            // La identidad ya se validó al cargarla
+            let tls_config = identity.tls_config().expect("Invalid upstream client certificate");
+            (h2_client(Some(tls_config), timeout), identity)
+        });
+        Self {
+            client: h2_client(None, timeout),
+            mtls,
+            timeout,
+        }
+    }
+
+    /// Calls the health service of `backend` for `service` (empty = the whole
+    /// server). Returns the reported status, or why the call failed.
+    pub async fn check(&self, backend: &Backend, service: &str, vk_secret: Option<&str>) -> Result<ServingStatus, String> {
+        let client = match &self.mtls {
+            Some((client, identity)) if identity.applies_to(backend) => client,
+            _ => &self.client,
+        };
+
+        let (url, authorization) = split_url_credentials(&join_backend_url(&backend.server_url, HEALTH_CHECK_PATH, None));
+        let mut builder = Request::builder()
+            .method(Method::POST)
+            .uri(url)
+            .header(header::CONTENT_TYPE, "application/grpc")
+            .header(header::TE, "trailers");
+        if let Some(authorization) = authorization {
+            builder = builder.header(header::AUTHORIZATION, authorization);
+        }
+        if let Some(secret) = vk_secret.and_then(|s| HeaderValue::from_str(s).ok()) {
+            builder = builder.header("X-KV-SECRET", secret);
+        }
+        let request = builder
+            .body(Body::from(encode_request(service)))
+            .map_err(|e| e.to_string())?;
+
+        let call = async {
+            let response = client.request(request).await.map_err(|e| e.to_string())?;
+            let (parts, body) = response.into_parts();
+            let body = body.collect().await.map_err(|e| e.to_string())?;
+            Ok::<_, String>((parts, body))
+        };
+        let (parts, body) = tokio::time::timeout(self.timeout, call)
+            .await
+            .map_err(|_| format!("timed out after {:?}", self.timeout))??;
+
+        if parts.status != StatusCode::OK {
+            return Err(format!("HTTP status {}", parts.status));
+        }
+        // Una respuesta sin mensaje lleva grpc-status en los headers ("trailers-only")
+        let trailers = body.trailers().cloned().unwrap_or_default();
+        check_grpc_status(&parts.headers, &trailers)?;
+        decode_response(&body.to_bytes())
+    }
+}
+
+/// Fails on a non-zero `grpc-status`, from the headers or the trailers
+fn check_grpc_status(headers: &HeaderMap, trailers: &HeaderMap) -> Result<(), String> {
+    let value = |name: &str| {
+        headers
+            .get(name)
+            .or_else(|| trailers.get(name))
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default()
+            .to_string()
+    };
+    match value("grpc-status").as_str() {
+        "" | "0" => Ok(()),
+        // 12 = UNIMPLEMENTED: el backend no expone el servicio de health
+        status => Err(format!("grpc-status {} {}", status, value("grpc-message")).trim_end().to_string()),
+    }
+}
+
+/// gRPC frame with a `HealthCheckRequest { string service = 1; }`
+fn encode_request(service: &str) -> Vec<u8> {
+    let mut message = Vec::new();
+    if !service.is_empty() {
+        message.push(0x0a);
+        push_varint(&mut message, service.len() as u64);
+        message.extend_from_slice(service.as_bytes());
+    }
+
+    let mut frame = Vec::with_capacity(5 + message.len());
+    frame.push(0);
+    frame.extend_from_slice(&(message.len() as u32).to_be_bytes());
+    frame.extend_from_slice(&message);
+    frame
+}
+
+/// Reads the `status` field (1, enum) of the `HealthCheckResponse` in the
+/// first gRPC frame. A missing field is the proto3 default, `UNKNOWN`.
+fn decode_response(body: &[u8]) -> Result<ServingStatus, String> {
+    let (&compressed, rest) = body.split_first().ok_or("empty response")?;
+    if compressed != 0 {
+        return Err("compressed responses are not supported".to_string());
+    }
+    let len = rest
+        .get(..4)
+        .map(|len| u32::from_be_bytes([len[0], len[1], len[2], len[3]]) as usize)
+        .ok_or("truncated frame header")?;
+    let mut message = rest.get(4..4 + len).ok_or("truncated message")?;
+
+    let mut status = ServingStatus::Unknown;
+    while !message.is_empty() {
+        let key = read_varint(&mut message)?;
+        match (key >> 3, key & 0x7) {
+            (1, 0) => status = ServingStatus::from_proto(read_varint(&mut message)?),
+            // Campos desconocidos: se saltan según su wire type
+            (_, 0) => {
+                read_varint(&mut message)?;
+            }
+            (_, 1) => message = message.get(8..).ok_or("truncated field")?,
+            (_, 2) => {
+                let len = read_varint(&mut message)? as usize;
+                message = message.get(len..).ok_or("truncated field")?;
+            }
+            (_, 5) => message = message.get(4..).ok_or("truncated field")?,
+            (_, wire_type) => return Err(format!("unsupported wire type {}", wire_type)),
+        }
+    }
+    Ok(status)
+}
+
+fn push_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn read_varint(buf: &mut &[u8]) -> Result<u64, String> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = buf.split_first().ok_or("truncated varint")?;
+        *buf = rest;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err("varint too long".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_backend;
+    use crate::health::{HealthCheckConfig, HealthChecker};
+    use axum::body::Bytes;
+    use http_body::Frame;
+    use http_body_util::StreamBody;
+    use std::convert::Infallible;
+    use std::sync::Mutex;
+
+    /// `HealthCheckResponse { status }` en un frame gRPC
+    fn serving_frame(status: u64) -> Vec<u8> {
+        let mut message = vec![0x08];
+        push_varint(&mut message, status);
+        let mut frame = vec![0];
+        frame.extend_from_slice(&(message.len() as u32).to_be_bytes());
+        frame.extend_from_slice(&message);
+        frame
+    }
+
+    /// Servicio pedido en un frame con `HealthCheckRequest`
+    fn requested_service(frame: &[u8]) -> String {
+        let message = &frame[5..];
+        match message.split_first() {
+            Some((0x0a, rest)) => String::from_utf8(rest[1..].to_vec()).unwrap(),
+            _ => String::new(),
+        }
+    }
+
+    /// Health service gRPC sobre h2c: `statuses` da el estado de cada servicio
+    /// (`None` = trailers-only con `NOT_FOUND`). Registra el path, el secreto y
+    /// el servicio de cada llamada.
+    async fn grpc_backend(
+        server_id: &str,
+        statuses: fn(&str) -> Option<u64>,
+    ) -> (Backend, Arc<Mutex<Vec<(String, Option<String>, String)>>>) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let received = calls.clone();
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                let received = received.clone();
+                let service = hyper::service::service_fn(move |req: hyper::Request<hyper::body::Incoming>| {
+                    let received = received.clone();
+                    async move {
+                        let path = req.uri().path().to_string();
+                        let secret = req.headers().get("x-kv-secret").map(|v| v.to_str().unwrap().to_string());
+                        let body = req.into_body().collect().await.unwrap().to_bytes();
+                        let service = requested_service(&body);
+                        received.lock().unwrap().push((path, secret, service.clone()));
+
+                        let response = axum::http::Response::builder().header(header::CONTENT_TYPE, "application/grpc");
+                        let frames: Vec<Result<Frame<Bytes>, Infallible>> = match statuses(&service) {
+                            Some(status) => {
+                                let mut trailers = HeaderMap::new();
+                                trailers.insert("grpc-status", HeaderValue::from_static("0"));
+                                vec![Ok(Frame::data(Bytes::from(serving_frame(status)))), Ok(Frame::trailers(trailers))]
+                            }
+                            None => {
+                                let response = response
+                                    .header("grpc-status", "5")
+                                    .header("grpc-message", "unknown service")
+                                    .body(Body::empty())
+                                    .unwrap();
+                                return Ok::<_, Infallible>(response);
+                            }
+                        };
+                        Ok(response.body(Body::new(StreamBody::new(futures::stream::iter(frames)))).unwrap())
+                    }
+                });
+                tokio::spawn(
+                    hyper::server::conn::http2::Builder::new(TokioExecutor::new())
+                        .serve_connection(hyper_util::rt::TokioIo::new(socket), service),
+                );
+            }
+        });
+        let backend = Backend {
+            server_url: format!("http://{}", addr),
+            health_check_type: Some("grpc".to_string()),
+            ..test_backend(server_id)
+        };
+        (backend, calls)
+    }
+
+    /// SERVING para el servidor entero, NOT_SERVING para `storage.Blob` y
+    /// desconocido para el resto
+    fn statuses(service: &str) -> Option<u64> {
+        match service {
+            "" => Some(1),
+            "storage.Blob" => Some(2),
+            _ => None,
+        }
+    }
+
+    fn client() -> GrpcHealthClient {
+        GrpcHealthClient::new(None, Duration::from_secs(5))
+    }
+
+    #[tokio::test]
+    async fn health_service_reports_serving_and_not_serving() {
+        let (backend, calls) = grpc_backend("grpc", statuses).await;
+
+        assert_eq!(client().check(&backend, "", Some("s3cret")).await, Ok(ServingStatus::Serving));
+        assert_eq!(client().check(&backend, "storage.Blob", None).await, Ok(ServingStatus::NotServing));
+        assert_eq!(
+            calls.lock().unwrap().clone(),
+            [
+                (HEALTH_CHECK_PATH.to_string(), Some("s3cret".to_string()), String::new()),
+                (HEALTH_CHECK_PATH.to_string(), None, "storage.Blob".to_string()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn trailers_only_error_is_a_failed_check() {
+        let (backend, _) = grpc_backend("grpc", statuses).await;
+
+        let error = client().check(&backend, "storage.Missing", None).await.unwrap_err();
+        assert_eq!(error, "grpc-status 5 unknown service");
+    }
+
+    #[tokio::test]
+    async fn only_serving_backends_are_healthy() {
+        let (backend, _) = grpc_backend("grpc", statuses).await;
+        let checker = |grpc_service: &str| {
+            let config = HealthCheckConfig {
+                grpc_service: grpc_service.to_string(),
+                ..HealthCheckConfig::default()
+            };
+            HealthChecker::new(None, config)
+        };
+
+        assert!(checker("").check_backend(&backend).await);
+        assert!(!checker("storage.Blob").check_backend(&backend).await);
+        assert!(!checker("storage.Missing").check_backend(&backend).await);
+
+        // Sin health_check_type se sondea por HTTP, que este backend no sirve
+        let http = Backend {
+            health_check_type: None,
+            ..backend
+        };
+        assert!(!checker("").check_backend(&http).await);
+    }
+
+    #[test]
+    fn health_check_type_selects_grpc() {
+        let with_type = |kind: Option<&str>| Backend {
+            health_check_type: kind.map(str::to_string),
+            ..test_backend("b")
+        };
+        assert!(with_type(Some(" gRPC ")).uses_grpc_health_check());
+        assert!(!with_type(Some("http")).uses_grpc_health_check());
+        assert!(!with_type(None).uses_grpc_health_check());
+    }
+
+    #[test]
+    fn request_carries_the_service_name() {
+        assert_eq!(encode_request(""), [0, 0, 0, 0, 0]);
+        let frame = encode_request("storage.Blob");
+        assert_eq!(&frame[..7], [0, 0, 0, 0, 14, 0x0a, 12]);
+        assert_eq!(requested_service(&frame), "storage.Blob");
+    }
+
+    #[test]
+    fn response_status_is_decoded_skipping_unknown_fields() {
+        assert_eq!(decode_response(&serving_frame(1)), Ok(ServingStatus::Serving));
+        assert_eq!(decode_response(&serving_frame(3)), Ok(ServingStatus::ServiceUnknown));
+        assert_eq!(decode_response(&[0, 0, 0, 0, 0]), Ok(ServingStatus::Unknown));
+
+        // Campo 2 (string "x") y campo 3 (varint 300) antes del estado
+        let message = [0x12, 1, b'x', 0x18, 0xac, 0x02, 0x08, 2];
+        let mut frame = vec![0, 0, 0, 0, message.len() as u8];
+        frame.extend_from_slice(&message);
+        assert_eq!(decode_response(&frame), Ok(ServingStatus::NotServing));
+    }
+
+    #[test]
+    fn malformed_responses_are_errors() {
+        assert!(decode_response(&[]).is_err());
+        assert!(decode_response(&[1, 0, 0, 0, 0]).is_err());
+        assert!(decode_response(&[0, 0, 0]).is_err());
+        assert!(decode_response(&[0, 0, 0, 0, 4, 0x08]).is_err());
+        assert!(decode_response(&[0, 0, 0, 0, 1, 0x08]).is_err());
+    }
+}
@@ -0,0 +1,336 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::Request,
+    http::{header, HeaderMap, HeaderValue, StatusCode, Version},
+    response::Response,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use http_body_util::BodyExt;
+
+/// Tamaño máximo de un mensaje gRPC-Web (igual al límite por defecto de gRPC)
+const MAX_GRPC_WEB_BODY: usize = 4 * 1024 * 1024;
+
+/// Flag del frame de trailers en gRPC-Web
+const TRAILER_FRAME_FLAG: u8 = 0x80;
+
+/// Trailers que un backend puede enviar en los headers (respuesta "trailers-only")
+const STATUS_HEADERS: [&str; 3] = ["grpc-status", "grpc-message", "grpc-status-details-bin"];
+
+/// gRPC-Web wire encoding used by the client
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GrpcWebMode {
+    /// `application/grpc-web[+proto]`: binary frames
+    Binary,
+    /// `application/grpc-web-text[+proto]`: base64-encoded frames
+    Text,
+}
+
+/// Detects a gRPC-Web request from its content type
+pub fn detect(headers: &HeaderMap) -> Option<GrpcWebMode> {
+    let content_type = headers.get(header::CONTENT_TYPE)?.to_str().ok()?;
+
+    if content_type.starts_with("application/grpc-web-text") {
+        Some(GrpcWebMode::Text)
+    } else if content_type.starts_with("application/grpc-web") {
+        Some(GrpcWebMode::Binary)
+    } else {
+        None
+    }
+}
+
+/// Translates a gRPC-Web request into a gRPC (HTTP/2) request.
+/// Only unary calls are supported, so the whole body is buffered.
+pub async fn into_grpc_request(req: Request, mode: GrpcWebMode) -> Result<Request, StatusCode> {
+    let (mut parts, body) = req.into_parts();
+
+    let body = axum::body::to_bytes(body, MAX_GRPC_WEB_BODY)
+        .await
+        .map_err(|e| {
+            tracing::warn!("Failed to read gRPC-Web request body: {}", e);
+            StatusCode::PAYLOAD_TOO_LARGE
+        })?;
+
+    let body = match mode {
+        GrpcWebMode::Binary => body,
+        GrpcWebMode::Text => {
+            let encoded: Vec<u8> = body.iter().copied().filter(|b| !b.is_ascii_whitespace()).collect();
+            Bytes::from(STANDARD.decode(encoded).map_err(|e| {
+                tracing::warn!("Invalid base64 in gRPC-Web text request: {}", e);
+                StatusCode::BAD_REQUEST
+            })?)
+        }
+    };
+
+    // application/grpc-web-text+proto -> application/grpc+proto
+    let content_type = parts
+        .headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|ct| {
+            ct.replacen("application/grpc-web-text", "application/grpc", 1)
+                .replacen("application/grpc-web", "application/grpc", 1)
+        })
+        .unwrap_or_else(|| "application/grpc".to_string());
+
+    parts.version = Version::HTTP_2;
+    parts.headers.remove(header::CONTENT_LENGTH);
+    parts.headers.remove("x-grpc-web");
+    parts.headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_str(&content_type).map_err(|_| StatusCode::BAD_REQUEST)?,
+    );
+    parts.headers.insert(header::TE, HeaderValue::from_static("trailers"));
+    // HTTP/2 no admite headers de conexión
+    parts.headers.remove(header::CONNECTION);
+    parts.headers.remove(header::HOST);
+
+    Ok(Request::from_parts(parts, Body::from(body)))
+}
+
+/// Translates a gRPC response into gRPC-Web, encoding the trailers as the
+/// final frame of the body
+pub async fn into_grpc_web_response(
+    response: hyper::Response<hyper::body::Incoming>,
+    mode: GrpcWebMode,
+) -> Result<Response, StatusCode> {
+    let (mut parts, body) = response.into_parts();
+
+    let collected = body.collect().await.map_err(|e| {
+        tracing::error!("Failed to read gRPC response body: {}", e);
+        StatusCode::BAD_GATEWAY
+    })?;
+    let mut trailers = collected.trailers().cloned().unwrap_or_default();
+    let data = collected.to_bytes();
+
+    // Trailers-only responses carry the status in the headers
+    for name in STATUS_HEADERS {
+        if let Some(value) = parts.headers.remove(name) {
+            trailers.entry(name).or_insert(value);
+        }
+    }
+
+    let mut trailer_block = Vec::new();
+    for (name, value) in &trailers {
+        trailer_block.extend_from_slice(name.as_str().as_bytes());
+        trailer_block.extend_from_slice(b":");
+        trailer_block.extend_from_slice(value.as_bytes());
+        trailer_block.extend_from_slice(b"\r\n");
+    }
+
+    let mut frames = Vec::with_capacity(data.len() + trailer_block.len() + 5);
+    frames.extend_from_slice(&data);
+    frames.push(TRAILER_FRAME_FLAG);
+    frames.extend_from_slice(&(trailer_block.len() as u32).to_be_bytes());
+    frames.extend_from_slice(&trailer_block);
+
+    let (body, content_type) = match mode {
+        GrpcWebMode::Binary => (frames, "application/grpc-web+proto"),
+        GrpcWebMode::Text => (
+            STANDARD.encode(frames).into_bytes(),
+            "application/grpc-web-text+proto",
+        ),
+    };
+
+    parts.version = Version::HTTP_11;
+    parts.headers.remove(header::CONTENT_LENGTH);
+    parts.headers.remove(header::TRAILER);
+    parts
+        .headers
+        .insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+
+    Ok(Response::from_parts(parts, Body::from(body)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body::Frame;
+    use http_body_util::StreamBody;
+    use std::convert::Infallible;
+
+    /// Mensaje gRPC con su prefijo de 5 bytes (sin compresión)
+    fn grpc_frame(message: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0];
+        frame.extend_from_slice(&(message.len() as u32).to_be_bytes());
+        frame.extend_from_slice(message);
+        frame
+    }
+
+    /// Respuesta de un backend local con `headers`, `data` y `trailers`, leída
+    /// con un cliente hyper para obtener un body `Incoming`
+    async fn backend_response(
+        headers: &'static [(&'static str, &'static str)],
+        data: Vec<u8>,
+        trailers: HeaderMap,
+    ) -> hyper::Response<hyper::body::Incoming> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let service = hyper::service::service_fn(move |_req: hyper::Request<hyper::body::Incoming>| {
+                let frames: Vec<Result<Frame<Bytes>, Infallible>> =
+                    vec![Ok(Frame::data(Bytes::from(data.clone()))), Ok(Frame::trailers(trailers.clone()))];
+                let mut response = axum::http::Response::builder().header(header::TRAILER, "grpc-status");
+                for (name, value) in headers {
+                    response = response.header(*name, *value);
+                }
+                async move { Ok::<_, Infallible>(response.body(StreamBody::new(futures::stream::iter(frames))).unwrap()) }
+            });
+            hyper::server::conn::http1::Builder::new()
+                .serve_connection(hyper_util::rt::TokioIo::new(socket), service)
+                .await
+                .unwrap();
+        });
+
+        let socket = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let (mut sender, conn) = hyper::client::conn::http1::handshake(hyper_util::rt::TokioIo::new(socket))
+            .await
+            .unwrap();
+        tokio::spawn(conn);
+        let req = hyper::Request::builder()
+            .uri("/pkg.Service/Method")
+            .header(header::HOST, "backend")
+            .header(header::TE, "trailers")
+            .body(http_body_util::Empty::<Bytes>::new())
+            .unwrap();
+        sender.send_request(req).await.unwrap()
+    }
+
+    fn status_trailers(status: &'static str) -> HeaderMap {
+        let mut trailers = HeaderMap::new();
+        trailers.insert("grpc-status", HeaderValue::from_static(status));
+        trailers
+    }
+
+    /// Splits a gRPC-Web body into its data frames and the trailer block
+    fn split_frames(body: &[u8]) -> (Vec<u8>, String) {
+        let mut data = Vec::new();
+        let mut rest = body;
+        while let Some((&flag, tail)) = rest.split_first() {
+            let len = u32::from_be_bytes(tail[..4].try_into().unwrap()) as usize;
+            let payload = &tail[4..4 + len];
+            if flag == TRAILER_FRAME_FLAG {
+                assert_eq!(4 + len, tail.len(), "trailer frame must be last");
+                return (data, String::from_utf8(payload.to_vec()).unwrap());
+            }
+            data.extend_from_slice(&rest[..5 + len]);
+            rest = &tail[4 + len..];
+        }
+        panic!("missing trailer frame");
+    }
+
+    fn grpc_web_request(content_type: &str, body: impl Into<Body>) -> Request {
+        Request::builder()
+            .method("POST")
+            .uri("/pkg.Service/Method")
+            .header(header::CONTENT_TYPE, content_type)
+            .header(header::CONTENT_LENGTH, "10")
+            .header(header::HOST, "gateway")
+            .header("x-grpc-web", "1")
+            .body(body.into())
+            .unwrap()
+    }
+
+    #[test]
+    fn content_type_selects_the_mode() {
+        let mut headers = HeaderMap::new();
+        for (content_type, mode) in [
+            ("application/grpc-web", Some(GrpcWebMode::Binary)),
+            ("application/grpc-web+proto", Some(GrpcWebMode::Binary)),
+            ("application/grpc-web-text", Some(GrpcWebMode::Text)),
+            ("application/grpc-web-text+proto", Some(GrpcWebMode::Text)),
+            ("application/grpc", None),
+            ("application/json", None),
+        ] {
+            headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+            assert_eq!(detect(&headers), mode, "{}", content_type);
+        }
+    }
+
+    #[tokio::test]
+    async fn binary_request_becomes_grpc() {
+        let message = grpc_frame(b"hello");
+        let req = grpc_web_request("application/grpc-web+proto", message.clone());
+
+        let req = into_grpc_request(req, GrpcWebMode::Binary).await.unwrap();
+        assert_eq!(req.version(), Version::HTTP_2);
+        assert_eq!(req.headers()[header::CONTENT_TYPE], "application/grpc+proto");
+        assert_eq!(req.headers()[header::TE], "trailers");
+        for removed in [header::CONTENT_LENGTH, header::HOST] {
+            assert!(!req.headers().contains_key(&removed), "{} kept", removed);
+        }
+        assert!(!req.headers().contains_key("x-grpc-web"));
+        let body = axum::body::to_bytes(req.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(body, message);
+    }
+
+    #[tokio::test]
+    async fn text_request_is_base64_decoded() {
+        let message = grpc_frame(b"hello");
+        let encoded = STANDARD.encode(&message);
+        // Los clientes pueden partir el base64 en líneas
+        let wrapped = format!("{}\r\n{}", &encoded[..4], &encoded[4..]);
+        let req = grpc_web_request("application/grpc-web-text", wrapped);
+
+        let req = into_grpc_request(req, GrpcWebMode::Text).await.unwrap();
+        assert_eq!(req.headers()[header::CONTENT_TYPE], "application/grpc");
+        let body = axum::body::to_bytes(req.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(body, message);
+    }
+
+    #[tokio::test]
+    async fn invalid_base64_is_rejected() {
+        let req = grpc_web_request("application/grpc-web-text", "not base64!");
+        assert_eq!(into_grpc_request(req, GrpcWebMode::Text).await.unwrap_err(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn binary_response_ends_with_a_trailer_frame() {
+        let message = grpc_frame(b"world");
+        let response = backend_response(&[("content-type", "application/grpc")], message.clone(), status_trailers("0")).await;
+
+        let response = into_grpc_web_response(response, GrpcWebMode::Binary).await.unwrap();
+        assert_eq!(response.version(), Version::HTTP_11);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/grpc-web+proto");
+        assert!(!response.headers().contains_key(header::TRAILER));
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let (data, trailers) = split_frames(&body);
+        assert_eq!(data, message);
+        assert_eq!(trailers, "grpc-status:0\r\n");
+    }
+
+    #[tokio::test]
+    async fn text_response_is_base64_encoded() {
+        let message = grpc_frame(b"world");
+        let response = backend_response(&[("content-type", "application/grpc")], message.clone(), status_trailers("0")).await;
+
+        let response = into_grpc_web_response(response, GrpcWebMode::Text).await.unwrap();
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/grpc-web-text+proto");
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let (data, trailers) = split_frames(&STANDARD.decode(&body).unwrap());
+        assert_eq!(data, message);
+        assert_eq!(trailers, "grpc-status:0\r\n");
+    }
+
+    #[tokio::test]
+    async fn trailers_only_status_moves_into_the_trailer_frame() {
+        let response = backend_response(
+            &[("grpc-status", "5"), ("grpc-message", "not found")],
+            Vec::new(),
+            HeaderMap::new(),
+        )
+        .await;
+
+        let response = into_grpc_web_response(response, GrpcWebMode::Binary).await.unwrap();
+        assert!(!response.headers().contains_key("grpc-status"));
+        assert!(!response.headers().contains_key("grpc-message"));
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let (data, trailers) = split_frames(&body);
+        assert!(data.is_empty());
+        assert!(trailers.contains("grpc-status:5\r\n"));
+        assert!(trailers.contains("grpc-message:not found\r\n"));
+    }
+}
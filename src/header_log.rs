@@ -0,0 +1,113 @@
+use axum::http::HeaderMap;
+
+use crate::config::REDACTED;
+
+/// Headers ocultados por defecto en los logs
+const DEFAULT_REDACTED_HEADERS: [&str; 4] =
+    ["authorization", "x-upload-token", "x-kv-secret", "x-vk-secret"];
+
+/// Logging de headers de petición/respuesta para depuración
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct HeaderLogConfig {
+    /// Fracción de peticiones cuyos headers se registran (0.0 = deshabilitado)
+    pub sample_rate: f64,
+    /// Headers cuyo valor se reemplaza por `***`, en minúsculas
+    pub redact: Vec<String>,
+}
+
+impl Default for HeaderLogConfig {
+    fn default() -> Self {
+        Self {
+            sample_rate: 0.0,
+            redact: DEFAULT_REDACTED_HEADERS.iter().map(|h| h.to_string()).collect(),
+        }
+    }
+}
+
+impl HeaderLogConfig {
+    /// Decides whether the current request is sampled
+    pub fn should_sample(&self) -> bool {
+        if self.sample_rate <= 0.0 || !tracing::enabled!(tracing::Level::DEBUG) {
+            return false;
+        }
+        if self.sample_rate >= 1.0 {
+            return true;
+        }
+
+        use std::collections::hash_map::RandomState;
+        use std::hash::BuildHasher;
+
+        let hash = RandomState::new().hash_one(std::time::SystemTime::now());
+        (hash as f64 / u64::MAX as f64) < self.sample_rate
+    }
+
+    /// Formats the headers as `name: value` pairs, hiding sensitive values
+    pub fn format_headers(&self, headers: &HeaderMap) -> String {
+        headers
+            .iter()
+            .map(|(name, value)| {
+                let value = if self.redact.iter().any(|r| r == name.as_str()) {
+                    REDACTED
+                } else {
+                    value.to_str().unwrap_or("<binary>")
+                };
+                format!("{}: {}", name, value)
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.append(*name, HeaderValue::from_static(value));
+        }
+        headers
+    }
+
+    #[test]
+    fn sensitive_headers_are_redacted_by_default() {
+        let formatted = HeaderLogConfig::default().format_headers(&headers(&[
+            ("Authorization", "Bearer s3cret"),
+            ("X-KV-Secret", "kv-s3cret"),
+            ("x-vk-secret", "vk-s3cret"),
+            ("x-upload-token", "upload-s3cret"),
+            ("content-type", "application/json"),
+        ]));
+
+        assert!(!formatted.contains("s3cret"), "{}", formatted);
+        assert!(formatted.contains("authorization: ***"));
+        assert!(formatted.contains("x-kv-secret: ***"));
+        assert!(formatted.contains("content-type: application/json"));
+    }
+
+    #[test]
+    fn configured_headers_are_redacted_too() {
+        let mut config = HeaderLogConfig::default();
+        config.redact.push("cookie".to_string());
+
+        let formatted = config.format_headers(&headers(&[("Cookie", "session=s3cret"), ("accept", "*/*")]));
+        assert_eq!(formatted, "cookie: ***, accept: */*");
+    }
+
+    #[test]
+    fn repeated_and_binary_values_are_listed() {
+        let mut map = headers(&[("x-tag", "a"), ("x-tag", "b")]);
+        map.insert("x-raw", HeaderValue::from_bytes(&[0xff, 0xfe]).unwrap());
+
+        let formatted = HeaderLogConfig::default().format_headers(&map);
+        assert!(formatted.contains("x-tag: a, x-tag: b"));
+        assert!(formatted.contains("x-raw: <binary>"));
+    }
+
+    #[test]
+    fn sampling_is_off_without_a_rate() {
+        assert!(!HeaderLogConfig::default().should_sample());
+    }
+}
@@ -1,16 +1,260 @@
+use crate::backends::{join_backend_url, BackendRegistry};
 use crate::db::Backend;
+use crate::grpc_health::{GrpcHealthClient, ServingStatus};
+use crate::mtls::ClientIdentity;
+use crate::shared_health::{SharedHealth, SharedHealthStore};
 use reqwest::Client;
 use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::sync::RwLock;
-use tokio::time::interval;
+use tokio::sync::{broadcast, RwLock};
+use tokio::time::Instant;
+
+/// Timeout de cada health check
+pub const HEALTH_CHECK_TIMEOUT_SECS: u64 = 5;
+
+/// Cómo se sondea cada backend
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct HealthCheckConfig {
+    /// Método HTTP del health check (GET por defecto)
+    pub method: String,
+    /// Body opcional, enviado como JSON (p. ej. para health checks por POST)
+    pub body: Option<String>,
+    /// Status que cuentan como saludable (`HEALTH_OK_STATUSES`); vacío = cualquier 2xx.
+    /// Un 3xx listado se evalúa sin seguir la redirección
+    pub ok_statuses: Vec<u16>,
+    /// Texto que debe aparecer en el body de la respuesta
+    pub body_contains: Option<String>,
+    /// Campo JSON que debe existir en el body, como ruta con puntos
+    /// (`status`, `checks.db`) y opcionalmente con valor (`status=ok`)
+    pub json_field: Option<String>,
+    /// Header que debe traer la respuesta, como `nombre` u opcionalmente con
+    /// valor (`X-Health: ok`)
+    pub header: Option<String>,
+    /// Servicio consultado en los backends con health check gRPC; vacío = el servidor entero
+    pub grpc_service: String,
+    /// Chequeos exitosos seguidos que necesita un backend no saludable para volver
+    pub healthy_threshold: usize,
+}
+
+impl Default for HealthCheckConfig {
+    fn default() -> Self {
+        Self {
+            method: "GET".to_string(),
+            body: None,
+            ok_statuses: Vec::new(),
+            body_contains: None,
+            json_field: None,
+            header: None,
+            grpc_service: String::new(),
+            healthy_threshold: 1,
+        }
+    }
+}
+
+impl HealthCheckConfig {
+    /// Whether a probe response status counts as healthy
+    pub fn is_ok_status(&self, status: reqwest::StatusCode) -> bool {
+        if self.ok_statuses.is_empty() {
+            status.is_success()
+        } else {
+            self.ok_statuses.contains(&status.as_u16())
+        }
+    }
+
+    /// Checks the probe response headers against the configured header.
+    /// Returns the reason when it is missing or has another value.
+    pub fn validate_headers(&self, headers: &reqwest::header::HeaderMap) -> Result<(), String> {
+        let Some(ref required) = self.header else {
+            return Ok(());
+        };
+        let (name, expected) = match required.split_once(':') {
+            Some((name, value)) => (name.trim(), Some(value.trim())),
+            None => (required.trim(), None),
+        };
+
+        let values: Vec<&str> = headers.get_all(name).iter().filter_map(|v| v.to_str().ok()).collect();
+        if values.is_empty() {
+            return Err(format!("header {} is missing", name));
+        }
+        if let Some(expected) = expected {
+            if !values.iter().any(|v| v.trim() == expected) {
+                return Err(format!("header {} is {} (expected {})", name, values.join(", "), expected));
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the probe needs to read the response body
+    fn validates_body(&self) -> bool {
+        self.body_contains.is_some() || self.json_field.is_some()
+    }
+
+    /// Checks the (truncated) probe response body against the configured
+    /// substring and JSON field. Returns the reason when it does not match.
+    pub fn validate_body(&self, body: &[u8]) -> Result<(), String> {
+        let text = String::from_utf8_lossy(body);
+
+        if let Some(ref needle) = self.body_contains {
+            if !text.contains(needle.as_str()) {
+                return Err(format!("body does not contain {:?}", needle));
+            }
+        }
+
+        if let Some(ref field) = self.json_field {
+            let json: serde_json::Value = serde_json::from_slice(body)
+                .map_err(|e| format!("body is not valid JSON: {}", e))?;
+
+            let (path, expected) = match field.split_once('=') {
+                Some((path, value)) => (path, Some(value)),
+                None => (field.as_str(), None),
+            };
+            let value = path
+                .split('.')
+                .try_fold(&json, |node, key| node.get(key))
+                .ok_or_else(|| format!("JSON field {} is missing", path))?;
+
+            if let Some(expected) = expected {
+                // Compara strings sin comillas y cualquier otro valor por su JSON
+                let actual = match value {
+                    serde_json::Value::String(s) => s.clone(),
+                    other => other.to_string(),
+                };
+                if actual != expected {
+                    return Err(format!("JSON field {} is {} (expected {})", path, actual, expected));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Resultados de los últimos health checks como bits (1 = éxito), el más reciente en el bit 0
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProbeHistory {
+    bits: u64,
+    len: u8,
+}
+
+impl ProbeHistory {
+    /// Records a probe result, keeping at most `window` (up to 64) of them
+    pub fn push(&mut self, success: bool, window: u8) {
+        self.bits = (self.bits << 1) | u64::from(success);
+        self.len = self.len.saturating_add(1).min(window.min(64));
+    }
+
+    pub fn len(&self) -> usize {
+        usize::from(self.len)
+    }
+
+    /// Fraction of successful probes in the window, `None` before the first one
+    pub fn score(&self) -> Option<f64> {
+        if self.len == 0 {
+            return None;
+        }
+        let mask = u64::MAX >> (64 - u32::from(self.len));
+        Some((self.bits & mask).count_ones() as f64 / f64::from(self.len))
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct HealthStatus {
     pub is_healthy: bool,
+    /// Backend recién descubierto que aún no completó su primer health check
+    pub probing: bool,
     pub last_check: std::time::Instant,
     pub consecutive_failures: usize,
+    pub consecutive_successes: usize,
+    /// Health checks recientes de esta instancia, para el score de salud
+    pub history: ProbeHistory,
+}
+
+impl HealthStatus {
+    /// Estado legible para las estadísticas
+    pub fn state(&self) -> &'static str {
+        if self.probing {
+            "probing"
+        } else if self.is_healthy {
+            "healthy"
+        } else {
+            "unhealthy"
+        }
+    }
+}
+
+/// Estado forzado manualmente por un operador, con precedencia sobre los health checks
+#[derive(Debug, Clone)]
+pub struct HealthOverride {
+    pub healthy: bool,
+    pub expires_at: Option<std::time::Instant>,
+}
+
+impl HealthOverride {
+    pub fn is_active(&self) -> bool {
+        self.expires_at
+            .map(|at| std::time::Instant::now() < at)
+            .unwrap_or(true)
+    }
+}
+
+/// Bytes del body leídos como máximo al validar un health check
+const MAX_PROBE_BODY_BYTES: usize = 64 * 1024;
+
+/// Espera máxima del scheduler de health checks entre revisiones del registro
+const SCHEDULER_MAX_SLEEP: Duration = Duration::from_secs(1);
+
+/// Capacidad del canal de eventos; los suscriptores lentos pierden los más antiguos
+const HEALTH_EVENTS_CAPACITY: usize = 256;
+
+/// Cambio de estado de un backend, publicado a los suscriptores de eventos
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct HealthEvent {
+    pub server_id: String,
+    pub previous_state: Option<&'static str>,
+    pub state: &'static str,
+    pub consecutive_failures: usize,
+    /// Unix timestamp (segundos) del cambio
+    pub timestamp: u64,
+}
+
+/// Reads at most `limit` bytes of a response body; a read error ends it early
+async fn read_bounded(mut response: reqwest::Response, limit: usize) -> Vec<u8> {
+    let mut body = Vec::new();
+    while body.len() < limit {
+        match response.chunk().await {
+            Ok(Some(chunk)) => {
+                let take = chunk.len().min(limit - body.len());
+                body.extend_from_slice(&chunk[..take]);
+            }
+            _ => break,
+        }
+    }
+    body
+}
+
+fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// HTTP client for the probes, presenting `identity` when given
+fn probe_client(probe_config: &HealthCheckConfig, identity: Option<reqwest::Identity>) -> Client {
+    // Si un 3xx cuenta como saludable, la redirección no debe seguirse: se evalúa su status
+    let redirect_policy = if probe_config.ok_statuses.iter().any(|s| (300..400).contains(s)) {
+        reqwest::redirect::Policy::none()
+    } else {
+        reqwest::redirect::Policy::default()
+    };
+    let mut builder = Client::builder()
+        .timeout(Duration::from_secs(HEALTH_CHECK_TIMEOUT_SECS))
+        .redirect(redirect_policy);
+    if let Some(identity) = identity {
+        builder = builder.identity(identity);
+    }
+    builder.build().expect("Failed to create HTTP client")
 }
 
 /// Servicio que monitorea la salud de los backends
@@ -18,60 +262,313 @@ pub struct HealthChecker {
     client: Client,
     health_status: Arc<RwLock<HashMap<String, HealthStatus>>>,
     vk_secret: Option<String>,
+    method: reqwest::Method,
+    probe_config: HealthCheckConfig,
+    events: broadcast::Sender<HealthEvent>,
+    overrides: RwLock<HashMap<String, HealthOverride>>,
+    /// Estado compartido con otras instancias vía Redis (`SHARED_HEALTH`)
+    shared: Option<SharedHealthStore>,
+    /// Si un backend aún no chequeado se considera saludable
+    assume_healthy_until_probed: bool,
+    /// Health checks recientes que entran en el score
+    score_window: u8,
+    /// Score mínimo para estar saludable (`None` = solo fallos consecutivos)
+    score_threshold: Option<f64>,
+    /// Cliente con el certificado mTLS, para los backends que lo exigen
+    mtls: Option<(Client, Arc<ClientIdentity>)>,
+    /// Health checks gRPC (`grpc.health.v1`) de los backends con `health_check_type = grpc`
+    grpc: GrpcHealthClient,
 }
 
 impl HealthChecker {
-    pub fn new(vk_secret: Option<String>) -> Self {
-        let client = Client::builder()
-            .timeout(Duration::from_secs(5))
-            .build()
-            .expect("Failed to create HTTP client");
+    pub fn new(vk_secret: Option<String>, probe_config: HealthCheckConfig) -> Self {
+        let client = probe_client(&probe_config, None);
+
+        let (events, _) = broadcast::channel(HEALTH_EVENTS_CAPACITY);
+
+        // El método se valida al cargar la configuración
+        let method = reqwest::Method::from_bytes(probe_config.method.as_bytes())
+            .unwrap_or(reqwest::Method::GET);
 
         Self {
             client,
             health_status: Arc::new(RwLock::new(HashMap::new())),
             vk_secret,
+            method,
+            probe_config,
+            events,
+            overrides: RwLock::new(HashMap::new()),
+            shared: None,
+            assume_healthy_until_probed: true,
+            score_window: 10,
+            score_threshold: None,
+            mtls: None,
+            grpc: GrpcHealthClient::new(None, Duration::from_secs(HEALTH_CHECK_TIMEOUT_SECS)),
         }
     }
 
-    /// Inicia el chequeo periódico de salud de los backends
+    /// Whether backends that were never probed receive traffic (the default)
+    /// or wait for a first successful probe
+    pub fn with_assume_healthy_until_probed(mut self, assume_healthy: bool) -> Self {
+        self.assume_healthy_until_probed = assume_healthy;
+        self
+    }
+
+    /// Scores each backend over its last `window` probes. With a `threshold`,
+    /// a backend whose score drops below it is unhealthy even without three
+    /// consecutive failures, once the window is full.
+    pub fn with_health_score(mut self, window: u8, threshold: Option<f64>) -> Self {
+        self.score_window = window.clamp(1, 64);
+        self.score_threshold = threshold;
+        self
+    }
+
+    /// Presents the upstream client certificate when probing the backends it
+    /// applies to, so backends requiring mTLS are not reported as down
+    pub fn with_client_identity(mut self, identity: Arc<ClientIdentity>) -> Self {
+        // La identidad ya se validó al cargarla
+        let reqwest_identity = identity.reqwest_identity().expect("Invalid upstream client certificate");
+        let client = probe_client(&self.probe_config, Some(reqwest_identity));
+        self.grpc = GrpcHealthClient::new(Some(identity.clone()), Duration::from_secs(HEALTH_CHECK_TIMEOUT_SECS));
+        self.mtls = Some((client, identity));
+        self
+    }
+
+    /// Shares probe results with other gateway instances through Redis, so
+    /// each backend is probed by a single instance per interval
+    pub fn with_shared_state(mut self, store: SharedHealthStore) -> Self {
+        self.shared = Some(store);
+        self
+    }
+
+    /// Suscribe a los cambios de estado de los backends
+    pub fn subscribe(&self) -> broadcast::Receiver<HealthEvent> {
+        self.events.subscribe()
+    }
+
+    /// Publica un cambio de estado; sin suscriptores el evento simplemente se descarta
+    fn emit(&self, server_id: &str, previous: Option<&'static str>, status: Option<&HealthStatus>) {
+        let event = HealthEvent {
+            server_id: server_id.to_string(),
+            previous_state: previous,
+            state: status.map(|s| s.state()).unwrap_or("removed"),
+            consecutive_failures: status.map(|s| s.consecutive_failures).unwrap_or(0),
+            timestamp: unix_now(),
+        };
+        let _ = self.events.send(event);
+    }
+
+    /// Inicia el chequeo periódico de salud de los backends. Cada backend se
+    /// programa con su propio intervalo (`health_check_interval`), o con
+    /// `interval_secs` si no tiene uno.
     pub async fn start_health_checks(
         self: Arc<Self>,
-        backends: Vec<Backend>,
+        backends: BackendRegistry,
         interval_secs: u64,
     ) {
-        let mut interval = interval(Duration::from_secs(interval_secs));
-
         tokio::spawn(async move {
+            let mut next_probe: HashMap<String, Instant> = HashMap::new();
+            let mut first_pass = true;
+
             loop {
-                interval.tick().await;
+                let now = Instant::now();
+                let current = backends.all();
+                next_probe.retain(|id, _| current.iter().any(|b| &b.server_id == id));
 
-                for backend in &backends {
-                    let checker = self.clone();
-                    let backend = backend.clone();
+                for backend in current {
+                    let interval = backend.health_check_interval(interval_secs);
+                    // Los backends agregados por el refresco ya se chequean al descubrirse
+                    let due = next_probe
+                        .entry(backend.server_id.clone())
+                        .or_insert(if first_pass { now } else { now + interval });
+                    if *due > now {
+                        continue;
+                    }
+                    *due = now + interval;
 
+                    let checker = self.clone();
                     tokio::spawn(async move {
-                        checker.check_backend(&backend).await;
+                        checker.scheduled_check(&backend, interval).await;
                     });
                 }
+                first_pass = false;
+
+                // Despierta con el próximo chequeo pendiente, o antes para notar backends nuevos
+                let wake = next_probe
+                    .values()
+                    .min()
+                    .copied()
+                    .unwrap_or(now + Duration::from_secs(interval_secs))
+                    .min(now + SCHEDULER_MAX_SLEEP);
+                tokio::time::sleep_until(wake).await;
             }
         });
     }
 
-    /// Verifica la salud de un backend específico
-    async fn check_backend(&self, backend: &Backend) {
-        let health_url = format!("{}/api/v1/health", backend.server_url.trim_end_matches('/'));
+    /// Marca un backend recién descubierto como "probing": queda excluido de la
+    /// selección hasta que un health check termine
+    pub async fn mark_probing(&self, server_id: &str) {
+        let status = HealthStatus {
+            is_healthy: false,
+            probing: true,
+            last_check: std::time::Instant::now(),
+            consecutive_failures: 0,
+            consecutive_successes: 0,
+            history: ProbeHistory::default(),
+        };
+        let previous = self
+            .health_status
+            .write()
+            .await
+            .insert(server_id.to_string(), status.clone());
+        self.emit(server_id, previous.map(|p| p.state()), Some(&status));
+    }
 
-        let mut request = self.client.get(&health_url);
+    /// Ends the "probing" state of a backend whose first health check never
+    /// finished, marking it unhealthy until a later probe succeeds
+    pub async fn expire_probing(&self, server_id: &str) {
+        let still_probing = self
+            .health_status
+            .read()
+            .await
+            .get(server_id)
+            .is_some_and(|status| status.probing);
+        if still_probing {
+            self.record_probe_result(server_id, false).await;
+        }
+    }
+
+    /// Elimina el estado de un backend que ya no existe
+    pub async fn forget_backend(&self, server_id: &str) {
+        if let Some(previous) = self.health_status.write().await.remove(server_id) {
+            self.emit(server_id, Some(previous.state()), None);
+        }
+    }
+
+    /// Verifica la salud de un backend específico y actualiza su estado.
+    /// Retorna el resultado de este chequeo.
+    pub async fn check_backend(&self, backend: &Backend) -> bool {
+        let is_healthy = self.probe(backend).await;
+        self.record_probe_result(&backend.server_id, is_healthy).await;
+
+        if let Some(ref shared) = self.shared {
+            let status = self.health_status.read().await.get(&backend.server_id).cloned();
+            if let Some(status) = status {
+                if let Err(e) = shared.publish(backend, &status).await {
+                    tracing::warn!("Failed to publish shared health for {}: {}", backend.server_id, e);
+                }
+            }
+        }
+        is_healthy
+    }
+
+    /// Chequeo programado. Con estado compartido solo la instancia que reclama
+    /// el chequeo sondea el backend; las demás adoptan el resultado publicado.
+    /// Si Redis no responde, cada instancia vuelve a sondear por su cuenta.
+    async fn scheduled_check(&self, backend: &Backend, interval: Duration) {
+        if let Some(ref shared) = self.shared {
+            match shared.try_claim_probe(&backend.server_id, interval).await {
+                Ok(true) => {}
+                Ok(false) => {
+                    match shared.fetch(&backend.server_id).await {
+                        Ok(Some(state)) => self.adopt_shared_status(&backend.server_id, state).await,
+                        // La instancia que reclamó el chequeo aún no publicó el resultado
+                        Ok(None) => {}
+                        Err(e) => {
+                            tracing::warn!("Failed to read shared health for {}: {}", backend.server_id, e);
+                        }
+                    }
+                    return;
+                }
+                Err(e) => {
+                    tracing::warn!(
+                        "Shared health unavailable, probing {} locally: {}",
+                        backend.server_id,
+                        e
+                    );
+                }
+            }
+        }
+
+        self.check_backend(backend).await;
+    }
+
+    /// Reemplaza el estado local con el publicado por otra instancia
+    async fn adopt_shared_status(&self, server_id: &str, shared: SharedHealth) {
+        let mut health_map = self.health_status.write().await;
+        // El historial no se comparte: se conserva el de los chequeos propios
+        let history = health_map.get(server_id).map(|s| s.history).unwrap_or_default();
+        let status = HealthStatus {
+            is_healthy: shared.is_healthy,
+            probing: shared.probing,
+            last_check: std::time::Instant::now(),
+            consecutive_failures: shared.consecutive_failures,
+            consecutive_successes: shared.consecutive_successes,
+            history,
+        };
+
+        let previous = health_map.insert(server_id.to_string(), status.clone());
+        drop(health_map);
+
+        let previous_state = previous.map(|p| p.state());
+        if previous_state != Some(status.state()) {
+            tracing::info!(
+                "Backend {} is {} according to gateway instance {}",
+                server_id,
+                status.state(),
+                shared.instance
+            );
+            self.emit(server_id, previous_state, Some(&status));
+        }
+    }
+
+    /// Ejecuta el health check HTTP contra un backend
+    async fn probe(&self, backend: &Backend) -> bool {
+        if backend.uses_grpc_health_check() {
+            return self.probe_grpc(backend).await;
+        }
+
+        let health_url = join_backend_url(&backend.server_url, "/api/v1/health", None);
+
+        let client = match &self.mtls {
+            Some((client, identity)) if identity.applies_to(backend) => client,
+            _ => &self.client,
+        };
+        let mut request = client.request(self.method.clone(), &health_url);
+
+        if let Some(ref body) = self.probe_config.body {
+            request = request
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(body.clone());
+        }
 
         // Agrega el header X-KV-SECRET si está configurado
         if let Some(ref secret) = self.vk_secret {
             request = request.header("X-KV-SECRET", secret);
         }
 
-        let is_healthy = match request.send().await {
+        match request.send().await {
             Ok(response) => {
-                if response.status().is_success() {
+                if self.probe_config.is_ok_status(response.status()) {
+                    if let Err(reason) = self.probe_config.validate_headers(response.headers()) {
+                        tracing::warn!(
+                            "Backend {} health check response is invalid: {}",
+                            backend.server_id,
+                            reason
+                        );
+                        return false;
+                    }
+                    if self.probe_config.validates_body() {
+                        let body = read_bounded(response, MAX_PROBE_BODY_BYTES).await;
+                        if let Err(reason) = self.probe_config.validate_body(&body) {
+                            tracing::warn!(
+                                "Backend {} health check body is invalid: {}",
+                                backend.server_id,
+                                reason
+                            );
+                            return false;
+                        }
+                    }
                     tracing::debug!("Backend {} is healthy", backend.server_id);
                     true
                 } else {
@@ -87,23 +584,73 @@ impl HealthChecker {
                 tracing::warn!("Backend {} health check failed: {}", backend.server_id, e);
                 false
             }
-        };
+        }
+    }
+
+    /// gRPC health check: only `SERVING` counts as healthy
+    async fn probe_grpc(&self, backend: &Backend) -> bool {
+        let service = &self.probe_config.grpc_service;
+        match self.grpc.check(backend, service, self.vk_secret.as_deref()).await {
+            Ok(ServingStatus::Serving) => {
+                tracing::debug!("Backend {} is serving (gRPC health check)", backend.server_id);
+                true
+            }
+            Ok(status) => {
+                tracing::warn!("Backend {} gRPC health check returned {:?}", backend.server_id, status);
+                false
+            }
+            Err(e) => {
+                tracing::warn!("Backend {} gRPC health check failed: {}", backend.server_id, e);
+                false
+            }
+        }
+    }
 
-        // Actualiza el estado de salud
+    /// Actualiza el estado de salud con el resultado de un health check
+    async fn record_probe_result(&self, server_id: &str, is_healthy: bool) {
         let mut health_map = self.health_status.write().await;
         let status = health_map
-            .entry(backend.server_id.clone())
+            .entry(server_id.to_string())
+            // Un backend sin chequear parte del mismo estado que `effective_health` le daba
             .or_insert(HealthStatus {
-                is_healthy: true,
+                is_healthy: self.assume_healthy_until_probed,
+                probing: !self.assume_healthy_until_probed,
                 last_check: std::time::Instant::now(),
                 consecutive_failures: 0,
+                consecutive_successes: 0,
+                history: ProbeHistory::default(),
             });
 
+        let previous_state = status.state();
         status.last_check = std::time::Instant::now();
+        status.history.push(is_healthy, self.score_window);
 
         if is_healthy {
-            status.is_healthy = true;
+            status.consecutive_successes += 1;
+        } else {
+            status.consecutive_successes = 0;
+        }
+
+        if status.probing {
+            // El primer resultado de un backend en probing decide su estado directamente
+            status.probing = false;
+            status.is_healthy = is_healthy;
+            status.consecutive_failures = usize::from(!is_healthy);
+            if is_healthy {
+                tracing::info!("Backend {} passed its warmup probe", server_id);
+            } else {
+                tracing::warn!("Backend {} failed its warmup probe", server_id);
+            }
+        } else if is_healthy {
             status.consecutive_failures = 0;
+            if !status.is_healthy && status.consecutive_successes >= self.probe_config.healthy_threshold {
+                status.is_healthy = true;
+                tracing::info!(
+                    "Backend {} marked as healthy after {} consecutive successful probes",
+                    server_id,
+                    status.consecutive_successes
+                );
+            }
         } else {
             status.consecutive_failures += 1;
 
@@ -112,25 +659,85 @@ impl HealthChecker {
                 status.is_healthy = false;
                 tracing::error!(
                     "Backend {} marked as unhealthy after {} consecutive failures",
-                    backend.server_id,
+                    server_id,
                     status.consecutive_failures
                 );
             }
         }
+
+        // Con score, los fallos intermitentes también sacan al backend del balanceo
+        if status.is_healthy && !status.probing && self.is_degraded(&status.history) {
+            status.is_healthy = false;
+            if previous_state == "healthy" {
+                tracing::error!(
+                    "Backend {} marked as unhealthy, health score {:.2} is below {:.2}",
+                    server_id,
+                    status.history.score().unwrap_or(0.0),
+                    self.score_threshold.unwrap_or(0.0)
+                );
+            }
+        }
+
+        if status.state() != previous_state {
+            let status = status.clone();
+            drop(health_map);
+            self.emit(server_id, Some(previous_state), Some(&status));
+        }
+    }
+
+    /// Whether a full probe window scores below the configured threshold
+    fn is_degraded(&self, history: &ProbeHistory) -> bool {
+        match (self.score_threshold, history.score()) {
+            (Some(threshold), Some(score)) => history.len() >= usize::from(self.score_window) && score < threshold,
+            _ => false,
+        }
+    }
+
+    /// Resuelve la salud efectiva: un override activo tiene precedencia sobre los health checks
+    pub fn effective_health(
+        &self,
+        health_map: &HashMap<String, HealthStatus>,
+        overrides: &HashMap<String, HealthOverride>,
+        server_id: &str,
+    ) -> bool {
+        if let Some(forced) = overrides.get(server_id).filter(|o| o.is_active()) {
+            return forced.healthy;
+        }
+
+        health_map
+            .get(server_id)
+            .map(|status| status.is_healthy)
+            // Si no se ha chequeado, depende de ASSUME_HEALTHY_UNTIL_PROBED
+            .unwrap_or(self.assume_healthy_until_probed)
+    }
+
+    /// State shown for a backend, consistent with `effective_health`: an active
+    /// override wins, then the last probe, then `ASSUME_HEALTHY_UNTIL_PROBED`
+    pub fn effective_state(
+        &self,
+        health_map: &HashMap<String, HealthStatus>,
+        overrides: &HashMap<String, HealthOverride>,
+        server_id: &str,
+    ) -> &'static str {
+        if let Some(forced) = overrides.get(server_id).filter(|o| o.is_active()) {
+            return if forced.healthy { "healthy" } else { "unhealthy" };
+        }
+
+        match health_map.get(server_id) {
+            Some(status) => status.state(),
+            None if self.assume_healthy_until_probed => "healthy",
+            None => "probing",
+        }
     }
 
     /// Retorna solo los backends saludables
     pub async fn get_healthy_backends(&self, backends: &[Backend]) -> Vec<Backend> {
         let health_map = self.health_status.read().await;
+        let overrides = self.overrides.read().await;
 
         backends
             .iter()
-            .filter(|backend| {
-                health_map
-                    .get(&backend.server_id)
-                    .map(|status| status.is_healthy)
-                    .unwrap_or(true) // Si no se ha chequeado, asume que está saludable
-            })
+            .filter(|backend| self.effective_health(&health_map, &overrides, &backend.server_id))
             .cloned()
             .collect()
     }
@@ -138,14 +745,555 @@ impl HealthChecker {
     /// Verifica si un backend específico está saludable
     pub async fn is_backend_healthy(&self, server_id: &str) -> bool {
         let health_map = self.health_status.read().await;
-        health_map
-            .get(server_id)
-            .map(|status| status.is_healthy)
-            .unwrap_or(true)
+        let overrides = self.overrides.read().await;
+        self.effective_health(&health_map, &overrides, server_id)
+    }
+
+    /// Fuerza el estado de un backend hasta que se limpie o venza `ttl`
+    pub async fn set_override(&self, server_id: &str, healthy: bool, ttl: Option<Duration>) {
+        let forced = HealthOverride {
+            healthy,
+            expires_at: ttl.map(|ttl| std::time::Instant::now() + ttl),
+        };
+        self.overrides
+            .write()
+            .await
+            .insert(server_id.to_string(), forced);
+
+        tracing::warn!(
+            "Backend {} forced {} by operator (ttl: {:?})",
+            server_id,
+            if healthy { "healthy" } else { "unhealthy" },
+            ttl
+        );
+        self.emit_override(server_id, if healthy { "forced_healthy" } else { "forced_unhealthy" })
+            .await;
+    }
+
+    /// Elimina el override de un backend. Retorna false si no tenía uno activo.
+    pub async fn clear_override(&self, server_id: &str) -> bool {
+        let removed = self.overrides.write().await.remove(server_id);
+        let was_active = removed.is_some_and(|o| o.is_active());
+
+        if was_active {
+            tracing::info!("Health override cleared for backend {}", server_id);
+            self.emit_override(server_id, "override_cleared").await;
+        }
+        was_active
+    }
+
+    async fn emit_override(&self, server_id: &str, state: &'static str) {
+        let status = self.health_status.read().await.get(server_id).cloned();
+        let _ = self.events.send(HealthEvent {
+            server_id: server_id.to_string(),
+            previous_state: status.as_ref().map(|s| s.state()),
+            state,
+            consecutive_failures: status.map(|s| s.consecutive_failures).unwrap_or(0),
+            timestamp: unix_now(),
+        });
+    }
+
+    /// Runs `f` over the health map and the overrides without cloning them,
+    /// holding both read locks for the duration of the call. Overrides may
+    /// include expired entries; check `HealthOverride::is_active`.
+    pub async fn with_health_status<R>(
+        &self,
+        f: impl FnOnce(&HashMap<String, HealthStatus>, &HashMap<String, HealthOverride>) -> R,
+    ) -> R {
+        let health_map = self.health_status.read().await;
+        let overrides = self.overrides.read().await;
+        f(&health_map, &overrides)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_backend;
+    use http_body_util::BodyExt;
+
+    /// Backend que responde `status` a su health check
+    async fn backend_returning(status: u16) -> Backend {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                let service = hyper::service::service_fn(move |_req: hyper::Request<hyper::body::Incoming>| async move {
+                    Ok::<_, std::convert::Infallible>(
+                        axum::http::Response::builder()
+                            .status(status)
+                            .header("location", "/status")
+                            .body(http_body_util::Empty::<axum::body::Bytes>::new())
+                            .unwrap(),
+                    )
+                });
+                tokio::spawn(
+                    hyper::server::conn::http1::Builder::new()
+                        .serve_connection(hyper_util::rt::TokioIo::new(socket), service),
+                );
+            }
+        });
+        Backend {
+            server_url: format!("http://{}", addr),
+            ..test_backend("probed")
+        }
+    }
+
+    fn checker(ok_statuses: &[u16]) -> HealthChecker {
+        let config = HealthCheckConfig {
+            ok_statuses: ok_statuses.to_vec(),
+            ..HealthCheckConfig::default()
+        };
+        HealthChecker::new(None, config)
+    }
+
+    #[test]
+    fn any_2xx_is_ok_by_default() {
+        let config = HealthCheckConfig::default();
+        assert!(config.is_ok_status(reqwest::StatusCode::OK));
+        assert!(config.is_ok_status(reqwest::StatusCode::NO_CONTENT));
+        assert!(!config.is_ok_status(reqwest::StatusCode::FOUND));
+        assert!(!config.is_ok_status(reqwest::StatusCode::SERVICE_UNAVAILABLE));
+    }
+
+    #[test]
+    fn configured_statuses_replace_the_2xx_default() {
+        let config = HealthCheckConfig {
+            ok_statuses: vec![200, 302],
+            ..HealthCheckConfig::default()
+        };
+        assert!(config.is_ok_status(reqwest::StatusCode::OK));
+        assert!(config.is_ok_status(reqwest::StatusCode::FOUND));
+        assert!(!config.is_ok_status(reqwest::StatusCode::NO_CONTENT));
+    }
+
+    #[tokio::test]
+    async fn backend_returning_204_is_healthy_only_when_allowed() {
+        let backend = backend_returning(204).await;
+
+        assert!(checker(&[]).check_backend(&backend).await);
+        assert!(checker(&[200, 204]).check_backend(&backend).await);
+        assert!(!checker(&[200]).check_backend(&backend).await);
+    }
+
+    #[tokio::test]
+    async fn listed_redirect_is_evaluated_without_following_it() {
+        let backend = backend_returning(302).await;
+
+        assert!(checker(&[200, 302]).check_backend(&backend).await);
+        assert!(!checker(&[200]).check_backend(&backend).await);
+    }
+
+    async fn status_of(checker: &HealthChecker, server_id: &str) -> HealthStatus {
+        checker.health_status.read().await.get(server_id).cloned().expect("backend was probed")
+    }
+
+    #[tokio::test]
+    async fn unhealthy_backend_needs_consecutive_successes_to_recover() {
+        let checker = HealthChecker::new(
+            None,
+            HealthCheckConfig {
+                healthy_threshold: 3,
+                ..HealthCheckConfig::default()
+            },
+        );
+        for _ in 0..3 {
+            checker.record_probe_result("flappy", false).await;
+        }
+        assert!(!checker.is_backend_healthy("flappy").await);
+
+        checker.record_probe_result("flappy", true).await;
+        checker.record_probe_result("flappy", true).await;
+        assert!(!checker.is_backend_healthy("flappy").await);
+        assert_eq!(status_of(&checker, "flappy").await.consecutive_successes, 2);
+        assert_eq!(status_of(&checker, "flappy").await.consecutive_failures, 0);
+
+        // Un fallo reinicia la cuenta
+        checker.record_probe_result("flappy", false).await;
+        assert_eq!(status_of(&checker, "flappy").await.consecutive_successes, 0);
+        checker.record_probe_result("flappy", true).await;
+        checker.record_probe_result("flappy", true).await;
+        assert!(!checker.is_backend_healthy("flappy").await);
+        checker.record_probe_result("flappy", true).await;
+        assert!(checker.is_backend_healthy("flappy").await);
+        assert_eq!(status_of(&checker, "flappy").await.consecutive_successes, 3);
+    }
+
+    #[tokio::test]
+    async fn single_success_recovers_by_default() {
+        let checker = checker(&[]);
+        for _ in 0..3 {
+            checker.record_probe_result("backend", false).await;
+        }
+        assert!(!checker.is_backend_healthy("backend").await);
+        checker.record_probe_result("backend", true).await;
+        assert!(checker.is_backend_healthy("backend").await);
+    }
+
+    #[tokio::test]
+    async fn healthy_backend_stays_healthy_through_isolated_failures() {
+        let checker = HealthChecker::new(
+            None,
+            HealthCheckConfig {
+                healthy_threshold: 3,
+                ..HealthCheckConfig::default()
+            },
+        );
+        checker.record_probe_result("backend", true).await;
+        checker.record_probe_result("backend", false).await;
+        checker.record_probe_result("backend", true).await;
+        assert!(checker.is_backend_healthy("backend").await);
+        assert_eq!(status_of(&checker, "backend").await.consecutive_successes, 1);
+    }
+
+    #[tokio::test]
+    async fn override_wins_over_probe_results_until_cleared() {
+        let checker = checker(&[]);
+        checker.record_probe_result("backend", true).await;
+
+        checker.set_override("backend", false, None).await;
+        checker.record_probe_result("backend", true).await;
+        assert!(!checker.is_backend_healthy("backend").await);
+        assert!(checker.get_healthy_backends(&[test_backend("backend")]).await.is_empty());
+
+        assert!(checker.clear_override("backend").await);
+        assert!(checker.is_backend_healthy("backend").await);
+        assert!(!checker.clear_override("backend").await);
+
+        for _ in 0..3 {
+            checker.record_probe_result("backend", false).await;
+        }
+        checker.set_override("backend", true, None).await;
+        assert!(checker.is_backend_healthy("backend").await);
+        checker.clear_override("backend").await;
+        assert!(!checker.is_backend_healthy("backend").await);
+    }
+
+    #[tokio::test]
+    async fn override_expires_after_its_ttl() {
+        let checker = checker(&[]);
+        checker.record_probe_result("backend", true).await;
+
+        checker.set_override("backend", false, Some(Duration::from_millis(50))).await;
+        assert!(!checker.is_backend_healthy("backend").await);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(checker.is_backend_healthy("backend").await);
+        // Un override vencido no cuenta como activo al limpiarlo
+        assert!(!checker.clear_override("backend").await);
+    }
+
+    /// Petición de health check recibida: método, content type y body
+    type ProbeLog = Arc<std::sync::Mutex<Vec<(String, Option<String>, String)>>>;
+
+    /// Backend cuyo health check responde `status` con `body` y registra cada petición
+    async fn health_endpoint(status: u16, body: &'static str) -> (Backend, ProbeLog) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let log = ProbeLog::default();
+        let received = log.clone();
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                let received = received.clone();
+                let service = hyper::service::service_fn(move |req: hyper::Request<hyper::body::Incoming>| {
+                    let received = received.clone();
+                    async move {
+                        let method = req.method().to_string();
+                        let content_type = req
+                            .headers()
+                            .get("content-type")
+                            .map(|v| v.to_str().unwrap().to_string());
+                        let request_body = req.into_body().collect().await.unwrap().to_bytes();
+                        received.lock().unwrap().push((
+                            method,
+                            content_type,
+                            String::from_utf8(request_body.to_vec()).unwrap(),
+                        ));
+                        Ok::<_, std::convert::Infallible>(
+                            axum::http::Response::builder()
+                                .status(status)
+                                .body(axum::body::Body::from(body))
+                                .unwrap(),
+                        )
+                    }
+                });
+                tokio::spawn(
+                    hyper::server::conn::http1::Builder::new()
+                        .serve_connection(hyper_util::rt::TokioIo::new(socket), service),
+                );
+            }
+        });
+        let backend = Backend {
+            server_url: format!("http://{}", addr),
+            ..test_backend("probed")
+        };
+        (backend, log)
+    }
+
+    #[tokio::test]
+    async fn probe_uses_the_configured_method_and_body() {
+        let (backend, log) = health_endpoint(204, "").await;
+        let checker = HealthChecker::new(
+            None,
+            HealthCheckConfig {
+                method: "POST".to_string(),
+                body: Some(r#"{"probe":true}"#.to_string()),
+                ok_statuses: vec![200, 204],
+                ..HealthCheckConfig::default()
+            },
+        );
+
+        assert!(checker.check_backend(&backend).await);
+        let probes = log.lock().unwrap().clone();
+        assert_eq!(
+            probes,
+            [("POST".to_string(), Some("application/json".to_string()), r#"{"probe":true}"#.to_string())]
+        );
+    }
+
+    #[tokio::test]
+    async fn default_probe_is_a_get_without_body() {
+        let (backend, log) = health_endpoint(200, "").await;
+        assert!(checker(&[]).check_backend(&backend).await);
+        assert_eq!(log.lock().unwrap().clone(), [("GET".to_string(), None, String::new())]);
+    }
+
+    #[tokio::test]
+    async fn unlisted_status_fails_the_probe() {
+        let (backend, _) = health_endpoint(503, "").await;
+        assert!(!checker(&[]).check_backend(&backend).await);
+        assert!(!checker(&[200, 204]).check_backend(&backend).await);
+        assert!(checker(&[503]).check_backend(&backend).await);
+    }
+
+    fn body_config(body_contains: Option<&str>, json_field: Option<&str>) -> HealthCheckConfig {
+        HealthCheckConfig {
+            body_contains: body_contains.map(str::to_string),
+            json_field: json_field.map(str::to_string),
+            ..HealthCheckConfig::default()
+        }
+    }
+
+    #[test]
+    fn body_must_contain_the_configured_text() {
+        let config = body_config(Some(r#""status":"ok""#), None);
+        assert!(config.validates_body());
+        assert_eq!(config.validate_body(br#"{"status":"ok"}"#), Ok(()));
+        assert!(config.validate_body(br#"{"status":"degraded"}"#).is_err());
+        assert!(!HealthCheckConfig::default().validates_body());
+        assert_eq!(HealthCheckConfig::default().validate_body(b"anything"), Ok(()));
+    }
+
+    #[test]
+    fn json_field_must_exist_and_match() {
+        let body = br#"{"status":"ok","checks":{"db":true,"queue":3}}"#;
+
+        assert_eq!(body_config(None, Some("status")).validate_body(body), Ok(()));
+        assert_eq!(body_config(None, Some("status=ok")).validate_body(body), Ok(()));
+        assert_eq!(body_config(None, Some("checks.db=true")).validate_body(body), Ok(()));
+        assert_eq!(body_config(None, Some("checks.queue=3")).validate_body(body), Ok(()));
+
+        assert!(body_config(None, Some("status=down")).validate_body(body).is_err());
+        assert!(body_config(None, Some("checks.cache")).validate_body(body).is_err());
+        assert!(body_config(None, Some("checks.db=false")).validate_body(body).is_err());
+        assert!(body_config(None, Some("status")).validate_body(b"OK").is_err());
+    }
+
+    #[test]
+    fn text_and_json_checks_combine() {
+        let config = body_config(Some("ready"), Some("status=ok"));
+        assert_eq!(config.validate_body(br#"{"status":"ok","message":"ready"}"#), Ok(()));
+        assert!(config.validate_body(br#"{"status":"ok"}"#).is_err());
+        assert!(config.validate_body(br#"{"status":"down","message":"ready"}"#).is_err());
+    }
+
+    #[tokio::test]
+    async fn ok_status_with_a_failing_body_is_unhealthy() {
+        let config = body_config(None, Some("status=ok"));
+        let (passing, _) = health_endpoint(200, r#"{"status":"ok"}"#).await;
+        let (failing, _) = health_endpoint(200, r#"{"status":"error","message":"db down"}"#).await;
+
+        assert!(HealthChecker::new(None, config.clone()).check_backend(&passing).await);
+        assert!(!HealthChecker::new(None, config).check_backend(&failing).await);
+    }
+
+    fn header_config(header: &str) -> HealthCheckConfig {
+        HealthCheckConfig {
+            header: Some(header.to_string()),
+            ..HealthCheckConfig::default()
+        }
+    }
+
+    #[test]
+    fn required_header_must_exist_and_match() {
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.append("x-health", "degraded".parse().unwrap());
+        headers.append("x-health", "ok".parse().unwrap());
+
+        assert_eq!(HealthCheckConfig::default().validate_headers(&headers), Ok(()));
+        assert_eq!(header_config("X-Health").validate_headers(&headers), Ok(()));
+        assert_eq!(header_config("X-Health: ok").validate_headers(&headers), Ok(()));
+        assert_eq!(
+            header_config("X-Health: up").validate_headers(&headers),
+            Err("header X-Health is degraded, ok (expected up)".to_string())
+        );
+        assert_eq!(
+            header_config("X-Ready").validate_headers(&headers),
+            Err("header X-Ready is missing".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn ok_status_without_the_required_header_is_unhealthy() {
+        // `backend_returning` responde con `Location: /status`
+        let backend = backend_returning(200).await;
+
+        assert!(HealthChecker::new(None, header_config("Location: /status")).check_backend(&backend).await);
+        assert!(HealthChecker::new(None, header_config("location")).check_backend(&backend).await);
+        assert!(!HealthChecker::new(None, header_config("Location: /ready")).check_backend(&backend).await);
+        assert!(!HealthChecker::new(None, header_config("X-Health: ok")).check_backend(&backend).await);
     }
 
-    /// Retorna el estado de salud de todos los backends
-    pub async fn get_all_health_status(&self) -> HashMap<String, HealthStatus> {
-        self.health_status.read().await.clone()
+    #[tokio::test]
+    async fn required_header_combines_with_status_and_body_checks() {
+        let failing_status = backend_returning(503).await;
+        assert!(!HealthChecker::new(None, header_config("Location")).check_backend(&failing_status).await);
+
+        let (backend, _) = health_endpoint(200, r#"{"status":"ok"}"#).await;
+        let config = |json_field: &str| HealthCheckConfig {
+            header: Some("Content-Length".to_string()),
+            ..body_config(None, Some(json_field))
+        };
+        assert!(HealthChecker::new(None, config("status=ok")).check_backend(&backend).await);
+        assert!(!HealthChecker::new(None, config("status=down")).check_backend(&backend).await);
+    }
+
+    async fn shared_instances(redis: &crate::fake_redis::FakeRedis) -> [HealthChecker; 2] {
+        [
+            checker(&[]).with_shared_state(SharedHealthStore::new(redis.client().await, 30)),
+            checker(&[]).with_shared_state(SharedHealthStore::new(redis.client().await, 30)),
+        ]
+    }
+
+    #[tokio::test]
+    async fn one_instance_probes_and_the_other_adopts_its_result() {
+        let redis = crate::fake_redis::FakeRedis::start().await;
+        let [first, second] = shared_instances(&redis).await;
+        let (backend, probes) = health_endpoint(200, "ok").await;
+        for instance in [&first, &second] {
+            for _ in 0..3 {
+                instance.record_probe_result(&backend.server_id, false).await;
+            }
+            assert!(!instance.is_backend_healthy(&backend.server_id).await);
+        }
+
+        let interval = Duration::from_secs(30);
+        first.scheduled_check(&backend, interval).await;
+        second.scheduled_check(&backend, interval).await;
+
+        // Un solo sondeo para las dos instancias, que acaban con la misma vista
+        assert_eq!(probes.lock().unwrap().len(), 1);
+        assert!(first.is_backend_healthy(&backend.server_id).await);
+        assert!(second.is_backend_healthy(&backend.server_id).await);
+        assert_eq!(status_of(&second, &backend.server_id).await.consecutive_successes, 1);
+    }
+
+    #[tokio::test]
+    async fn instances_probe_locally_when_redis_fails() {
+        let redis = crate::fake_redis::FakeRedis::start().await;
+        let [first, second] = shared_instances(&redis).await;
+        let (backend, probes) = health_endpoint(200, "ok").await;
+
+        let interval = Duration::from_secs(30);
+        first.scheduled_check(&backend, interval).await;
+        redis.fail_next(crate::fake_redis::Fault::Error("ERR unavailable"));
+        second.scheduled_check(&backend, interval).await;
+
+        assert_eq!(probes.lock().unwrap().len(), 2);
+        assert!(second.is_backend_healthy(&backend.server_id).await);
+    }
+
+    /// Records `results` for a never-probed backend and returns its effective health
+    async fn health_after(assume_healthy: bool, results: &[bool]) -> bool {
+        let checker = checker(&[]).with_assume_healthy_until_probed(assume_healthy);
+        for &result in results {
+            checker.record_probe_result("fresh", result).await;
+        }
+        !checker.get_healthy_backends(&[test_backend("fresh")]).await.is_empty()
+    }
+
+    #[tokio::test]
+    async fn assumed_healthy_backends_need_three_failures() {
+        assert!(health_after(true, &[]).await);
+        assert!(health_after(true, &[false]).await);
+        assert!(health_after(true, &[false, false]).await);
+        assert!(!health_after(true, &[false, false, false]).await);
+    }
+
+    #[tokio::test]
+    async fn unprobed_backends_are_decided_by_their_first_probe() {
+        assert!(!health_after(false, &[]).await);
+        assert!(!health_after(false, &[false]).await);
+        assert!(health_after(false, &[true]).await);
+        // Tras el primer resultado se aplican los umbrales habituales
+        assert!(health_after(false, &[true, false, false]).await);
+        assert!(!health_after(false, &[true, false, false, false]).await);
+    }
+
+    #[test]
+    fn probe_history_scores_the_last_window() {
+        let mut history = ProbeHistory::default();
+        assert_eq!(history.score(), None);
+
+        for success in [false, false, true, true, true, false] {
+            history.push(success, 4);
+        }
+        // Solo cuentan los 4 últimos: true, true, true, false
+        assert_eq!(history.len(), 4);
+        assert_eq!(history.score(), Some(0.75));
+    }
+
+    #[test]
+    fn probe_history_holds_up_to_64_results() {
+        let mut history = ProbeHistory::default();
+        history.push(false, 64);
+        for _ in 0..63 {
+            history.push(true, 64);
+        }
+        assert_eq!(history.len(), 64);
+        assert_eq!(history.score(), Some(63.0 / 64.0));
+
+        history.push(true, 200);
+        assert_eq!(history.len(), 64);
+        assert_eq!(history.score(), Some(1.0));
+    }
+
+    #[tokio::test]
+    async fn intermittent_failures_below_the_score_mark_the_backend_unhealthy() {
+        let checker = checker(&[]).with_health_score(4, Some(0.75));
+
+        // Nunca hay tres fallos seguidos, pero el score de la ventana cae a 0.5
+        for success in [true, false, true] {
+            checker.record_probe_result("flaky", success).await;
+            assert!(checker.is_backend_healthy("flaky").await);
+        }
+        checker.record_probe_result("flaky", false).await;
+        assert!(!checker.is_backend_healthy("flaky").await);
+
+        // Vuelve cuando el score de la ventana alcanza el umbral
+        checker.record_probe_result("flaky", true).await;
+        assert!(!checker.is_backend_healthy("flaky").await);
+        checker.record_probe_result("flaky", true).await;
+        assert!(checker.is_backend_healthy("flaky").await);
+    }
+
+    #[tokio::test]
+    async fn score_is_ignored_without_a_threshold() {
+        let checker = checker(&[]).with_health_score(4, None);
+
+        for success in [true, false, true, false, true, false] {
+            checker.record_probe_result("flaky", success).await;
+        }
+        assert!(checker.is_backend_healthy("flaky").await);
+        assert_eq!(status_of(&checker, "flaky").await.history.score(), Some(0.5));
     }
 }
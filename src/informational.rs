@@ -0,0 +1,87 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::Request,
+    http::{header, HeaderMap, StatusCode},
+};
+use http_body::{Frame, SizeHint};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+use tokio::sync::Notify;
+
+/// Tiempo máximo de espera del `100 Continue` del backend antes de enviar el body
+/// igualmente, como hacen los clientes HTTP (RFC 9110 §10.1.1)
+const CONTINUE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Whether the client asked to confirm the request before sending its body
+fn expects_continue(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::EXPECT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.trim().eq_ignore_ascii_case("100-continue"))
+}
+
+/// Relays `Expect: 100-continue` to an HTTP/1.1 backend.
+///
+/// hyper answers the client with `100 Continue` as soon as the request body is
+/// first read, so the body is held back until the backend sends its own
+/// `100 Continue` (or `CONTINUE_TIMEOUT` elapses). If the backend answers with a
+/// final status instead, the body is never read and the client never uploads it.
+/// Other 1xx responses, such as `103 Early Hints`, are only logged: hyper cannot
+/// send informational responses to the client.
+pub fn relay_continue(req: &mut Request) {
+    let notify = Arc::new(Notify::new());
+    let on_continue = notify.clone();
+
+    hyper::ext::on_informational(req, move |res| {
+        if res.status() == StatusCode::CONTINUE {
+            on_continue.notify_one();
+        } else {
+            tracing::debug!("Dropping informational response {} from backend", res.status());
+        }
+    });
+
+    if !expects_continue(req.headers()) {
+        return;
+    }
+
+    let body = std::mem::take(req.body_mut());
+    let wait = Box::pin(async move {
+        if tokio::time::timeout(CONTINUE_TIMEOUT, notify.notified()).await.is_err() {
+            tracing::debug!("No 100 Continue from backend after {:?}, sending body", CONTINUE_TIMEOUT);
+        }
+    });
+    *req.body_mut() = Body::new(ContinueGate { wait: Some(wait), inner: body });
+}
+
+/// Body que no empieza a leerse hasta que el backend confirma con `100 Continue`
+struct ContinueGate {
+    wait: Option<Pin<Box<dyn Future<Output = ()> + Send>>>,
+    inner: Body,
+}
+
+impl http_body::Body for ContinueGate {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        if let Some(wait) = self.wait.as_mut() {
+            ready!(wait.as_mut().poll(cx));
+            self.wait = None;
+        }
+        Pin::new(&mut self.inner).poll_frame(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.wait.is_none() && self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
@@ -0,0 +1,310 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::Request,
+    http::StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+
+use crate::routing::BackendGroup;
+
+/// Métodos distintos con contador propio; el resto se agrupa en `other` para
+/// que un cliente no pueda hacer crecer las estadísticas sin límite
+const MAX_TRACKED_METHODS: usize = 256;
+
+const OTHER_METHOD: &str = "other";
+
+/// Regla que envía las llamadas JSON-RPC a un método concreto, en un endpoint
+/// concreto, a un grupo de backends. Solo se parsea el body de los paths que
+/// aparecen en alguna regla.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JsonRpcRoute {
+    /// Path exacto del endpoint JSON-RPC (`/rpc`)
+    pub path: String,
+    /// Método a comparar; un `*` final acepta cualquier sufijo (`eth_*`)
+    pub method: String,
+    #[serde(flatten)]
+    pub group: BackendGroup,
+}
+
+impl JsonRpcRoute {
+    pub fn matches_method(&self, method: &str) -> bool {
+        match self.method.strip_suffix('*') {
+            Some(prefix) => method.starts_with(prefix),
+            None => method == self.method,
+        }
+    }
+}
+
+/// Llamadas de una petición JSON-RPC, individual o batch
+#[derive(Debug, Clone)]
+pub struct JsonRpcRequest {
+    pub batch: bool,
+    /// Método de cada llamada, en el orden del body
+    pub methods: Vec<String>,
+    /// `id` de cada llamada (`null` en las notificaciones), para los logs
+    pub ids: Vec<serde_json::Value>,
+}
+
+impl JsonRpcRequest {
+    /// Parses a single call or a batch. Returns `None` when the body is not
+    /// JSON-RPC, so the request is routed as any other.
+    pub fn parse(body: &[u8]) -> Option<Self> {
+        #[derive(Deserialize)]
+        struct Call {
+            method: String,
+            #[serde(default)]
+            id: serde_json::Value,
+        }
+
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Payload {
+            Batch(Vec<Call>),
+            Single(Call),
+        }
+
+        let (batch, calls) = match serde_json::from_slice(body).ok()? {
+            Payload::Batch(calls) if calls.is_empty() => return None,
+            Payload::Batch(calls) => (true, calls),
+            Payload::Single(call) => (false, vec![call]),
+        };
+        let (methods, ids) = calls.into_iter().map(|c| (c.method, c.id)).unzip();
+        Some(Self { batch, methods, ids })
+    }
+
+    /// First rule of `routes` that matches every call, so a batch can be
+    /// forwarded intact to a single backend. A batch mixing methods of
+    /// different rules matches none.
+    pub fn route<'a>(&self, routes: &'a [JsonRpcRoute], path: &str) -> Option<&'a JsonRpcRoute> {
+        routes
+            .iter()
+            .filter(|route| route.path == path)
+            .find(|route| self.methods.iter().all(|m| route.matches_method(m)))
+    }
+}
+
+/// Whether requests to `path` have their body inspected
+pub fn is_json_rpc_path(routes: &[JsonRpcRoute], path: &str) -> bool {
+    routes.iter().any(|route| route.path == path)
+}
+
+/// Reads the body of a request to a JSON-RPC path and puts it back untouched.
+/// Bodies without a known length or above `max_body_bytes` are not read, so
+/// they keep streaming to the backend and are routed by the usual rules.
+pub async fn inspect(req: &mut Request, max_body_bytes: usize) -> Result<Option<JsonRpcRequest>, StatusCode> {
+    let len = http_body::Body::size_hint(req.body()).exact();
+    if !len.is_some_and(|len| len > 0 && len <= max_body_bytes as u64) {
+        tracing::debug!("Not inspecting JSON-RPC body of length {:?}", len);
+        return Ok(None);
+    }
+
+    let body = std::mem::take(req.body_mut());
+    let bytes: Bytes = axum::body::to_bytes(body, max_body_bytes).await.map_err(|e| {
+        tracing::warn!("Failed to read JSON-RPC request body: {}", e);
+        StatusCode::BAD_REQUEST
+    })?;
+    let parsed = JsonRpcRequest::parse(&bytes);
+    *req.body_mut() = Body::from(bytes);
+    Ok(parsed)
+}
+
+/// Llamadas JSON-RPC por método, desde el arranque
+#[derive(Debug, Default)]
+pub struct JsonRpcMetrics {
+    methods: RwLock<HashMap<String, AtomicU64>>,
+    requests: AtomicU64,
+    batches: AtomicU64,
+}
+
+impl JsonRpcMetrics {
+    pub fn record(&self, request: &JsonRpcRequest) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        if request.batch {
+            self.batches.fetch_add(1, Ordering::Relaxed);
+        }
+
+        for method in &request.methods {
+            if let Some(counter) = self.methods.read().unwrap().get(method) {
+                counter.fetch_add(1, Ordering::Relaxed);
+                continue;
+            }
+
+            let mut methods = self.methods.write().unwrap();
+            let key = if methods.len() < MAX_TRACKED_METHODS || methods.contains_key(method) {
+                method.as_str()
+            } else {
+                OTHER_METHOD
+            };
+            methods
+                .entry(key.to_string())
+                .or_insert_with(|| AtomicU64::new(0))
+                .fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Current counters for the stats endpoint
+    pub fn snapshot(&self) -> serde_json::Value {
+        let methods: BTreeMap<_, _> = self
+            .methods
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(method, calls)| (method.clone(), calls.load(Ordering::Relaxed)))
+            .collect();
+        serde_json::json!({
+            "requests": self.requests.load(Ordering::Relaxed),
+            "batches": self.batches.load(Ordering::Relaxed),
+            "calls_by_method": methods,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn route(path: &str, method: &str, provider: &str) -> JsonRpcRoute {
+        JsonRpcRoute {
+            path: path.to_string(),
+            method: method.to_string(),
+            group: BackendGroup {
+                provider: Some(provider.to_string()),
+                backends: Vec::new(),
+            },
+        }
+    }
+
+    fn sized_request(body: &'static str) -> Request {
+        Request::builder()
+            .method("POST")
+            .uri("/rpc")
+            .header("content-length", body.len())
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    #[test]
+    fn single_call_is_parsed() {
+        let call = JsonRpcRequest::parse(br#"{"jsonrpc":"2.0","method":"eth_call","params":[],"id":7}"#).unwrap();
+        assert!(!call.batch);
+        assert_eq!(call.methods, ["eth_call"]);
+        assert_eq!(call.ids, [serde_json::json!(7)]);
+    }
+
+    #[test]
+    fn batch_keeps_every_call_in_order() {
+        let call = JsonRpcRequest::parse(
+            br#"[{"jsonrpc":"2.0","method":"eth_call","id":"a"},{"jsonrpc":"2.0","method":"eth_notify"}]"#,
+        )
+        .unwrap();
+        assert!(call.batch);
+        assert_eq!(call.methods, ["eth_call", "eth_notify"]);
+        // Las notificaciones no llevan id
+        assert_eq!(call.ids, [serde_json::json!("a"), serde_json::Value::Null]);
+    }
+
+    #[test]
+    fn non_json_rpc_bodies_are_not_parsed() {
+        for body in [&b"not json"[..], b"[]", b"{\"id\":1}", b"[1,2]", b"{\"method\":42}"] {
+            assert!(JsonRpcRequest::parse(body).is_none(), "{}", String::from_utf8_lossy(body));
+        }
+    }
+
+    #[test]
+    fn method_patterns_match_exactly_or_by_prefix() {
+        let exact = route("/rpc", "eth_call", "a");
+        assert!(exact.matches_method("eth_call"));
+        assert!(!exact.matches_method("eth_callMany"));
+
+        let prefix = route("/rpc", "eth_*", "a");
+        assert!(prefix.matches_method("eth_call"));
+        assert!(prefix.matches_method("eth_"));
+        assert!(!prefix.matches_method("net_version"));
+    }
+
+    #[test]
+    fn calls_are_routed_by_path_and_method() {
+        let routes = [
+            route("/rpc", "debug_*", "archive"),
+            route("/rpc", "eth_*", "full"),
+            route("/other", "net_*", "other"),
+        ];
+        let provider = |body: &[u8], path: &str| {
+            JsonRpcRequest::parse(body)
+                .unwrap()
+                .route(&routes, path)
+                .and_then(|r| r.group.provider.clone())
+        };
+
+        assert_eq!(provider(br#"{"method":"debug_trace"}"#, "/rpc").as_deref(), Some("archive"));
+        assert_eq!(provider(br#"{"method":"eth_call"}"#, "/rpc").as_deref(), Some("full"));
+        // Las reglas de otro path no aplican
+        assert_eq!(provider(br#"{"method":"net_version"}"#, "/rpc"), None);
+        assert_eq!(provider(br#"{"method":"net_version"}"#, "/other").as_deref(), Some("other"));
+
+        // Un batch va entero a la regla que cubre todas sus llamadas, o a ninguna
+        assert_eq!(
+            provider(br#"[{"method":"eth_call"},{"method":"eth_getLogs"}]"#, "/rpc").as_deref(),
+            Some("full")
+        );
+        assert_eq!(provider(br#"[{"method":"eth_call"},{"method":"debug_trace"}]"#, "/rpc"), None);
+
+        assert!(is_json_rpc_path(&routes, "/rpc"));
+        assert!(!is_json_rpc_path(&routes, "/rpc/"));
+    }
+
+    #[tokio::test]
+    async fn inspected_body_is_put_back_untouched() {
+        let body = r#"[{"method":"eth_call","id":1},{"method":"eth_chainId","id":2}]"#;
+        let mut req = sized_request(body);
+
+        let call = inspect(&mut req, 1024).await.unwrap().unwrap();
+        assert_eq!(call.methods, ["eth_call", "eth_chainId"]);
+        let forwarded = axum::body::to_bytes(req.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(forwarded, body);
+    }
+
+    #[tokio::test]
+    async fn oversized_or_streaming_bodies_are_not_read() {
+        let body = r#"{"method":"eth_call","id":1}"#;
+        let mut oversized = sized_request(body);
+        assert!(inspect(&mut oversized, 8).await.unwrap().is_none());
+        assert_eq!(axum::body::to_bytes(oversized.into_body(), usize::MAX).await.unwrap(), body);
+
+        let stream = futures::stream::iter([Ok::<_, std::io::Error>(Bytes::from(body))]);
+        let mut streaming = Request::builder()
+            .method("POST")
+            .uri("/rpc")
+            .body(Body::from_stream(stream))
+            .unwrap();
+        assert!(inspect(&mut streaming, 1024).await.unwrap().is_none());
+        assert_eq!(axum::body::to_bytes(streaming.into_body(), usize::MAX).await.unwrap(), body);
+    }
+
+    #[test]
+    fn metrics_count_calls_per_method_and_cap_the_method_names() {
+        let metrics = JsonRpcMetrics::default();
+        metrics.record(&JsonRpcRequest::parse(br#"{"method":"eth_call"}"#).unwrap());
+        metrics.record(&JsonRpcRequest::parse(br#"[{"method":"eth_call"},{"method":"net_version"}]"#).unwrap());
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot["requests"], 2);
+        assert_eq!(snapshot["batches"], 1);
+        assert_eq!(snapshot["calls_by_method"], serde_json::json!({"eth_call": 2, "net_version": 1}));
+
+        for i in 0..MAX_TRACKED_METHODS {
+            let body = format!(r#"{{"method":"m{}"}}"#, i);
+            metrics.record(&JsonRpcRequest::parse(body.as_bytes()).unwrap());
+        }
+        let snapshot = metrics.snapshot();
+        let methods = snapshot["calls_by_method"].as_object().unwrap();
+        assert_eq!(methods.len(), MAX_TRACKED_METHODS + 1);
+        assert_eq!(methods[OTHER_METHOD], 2);
+        // Los métodos ya registrados siguen contando aparte
+        metrics.record(&JsonRpcRequest::parse(br#"{"method":"eth_call"}"#).unwrap());
+        assert_eq!(metrics.snapshot()["calls_by_method"]["eth_call"], 3);
+    }
+}
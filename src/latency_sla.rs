@@ -0,0 +1,347 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::{backends::BackendRegistry, config::redact_webhook_url, db::Backend};
+
+/// Muestras necesarias en la ventana para calcular un p95 fiable
+const MIN_SAMPLES: usize = 20;
+
+/// Muestras conservadas por backend aunque la ventana admita más
+const MAX_SAMPLES: usize = 2000;
+
+/// Cada cuánto se compara el p95 de cada backend con el SLA
+const CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Tiempo máximo de una llamada al webhook
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Default)]
+struct BackendLatency {
+    /// Instante y latencia en ms de las respuestas dentro de la ventana
+    samples: VecDeque<(Instant, u64)>,
+    /// Estado notificado por última vez
+    breached: bool,
+    last_alert: Option<Instant>,
+}
+
+impl BackendLatency {
+    fn prune(&mut self, window: Duration) {
+        while self.samples.front().is_some_and(|(at, _)| at.elapsed() > window) {
+            self.samples.pop_front();
+        }
+    }
+
+    /// p95 of the samples in the window, `None` with too few of them
+    fn p95(&self) -> Option<u64> {
+        if self.samples.len() < MIN_SAMPLES {
+            return None;
+        }
+        let mut latencies: Vec<u64> = self.samples.iter().map(|(_, ms)| *ms).collect();
+        latencies.sort_unstable();
+        let rank = (latencies.len() * 95).div_ceil(100);
+        Some(latencies[rank.saturating_sub(1)])
+    }
+}
+
+/// Alerta por webhook cuando el p95 de la latencia de un backend supera el SLA
+/// (`SLA_P95_MS`), con otra alerta cuando vuelve a estar por debajo
+pub struct LatencySla {
+    sla_p95_ms: u64,
+    window: Duration,
+    /// Tiempo mínimo entre dos alertas del mismo backend
+    debounce: Duration,
+    webhook_url: Option<String>,
+    client: reqwest::Client,
+    backends: Mutex<HashMap<String, BackendLatency>>,
+}
+
+impl LatencySla {
+    pub fn new(sla_p95_ms: u64, window: Duration, debounce: Duration, webhook_url: Option<String>) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(WEBHOOK_TIMEOUT)
+            .build()
+            .expect("Failed to create webhook HTTP client");
+        Self {
+            sla_p95_ms,
+            window,
+            debounce,
+            webhook_url,
+            client,
+            backends: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.sla_p95_ms > 0
+    }
+
+    /// Records how long `backend` took to respond
+    pub fn record(&self, backend: &Backend, latency: Duration) {
+        if !self.is_enabled() {
+            return;
+        }
+        let mut backends = self.backends.lock().unwrap();
+        let entry = backends.entry(backend.server_id.clone()).or_default();
+        if entry.samples.len() >= MAX_SAMPLES {
+            entry.samples.pop_front();
+        }
+        entry.samples.push_back((Instant::now(), latency.as_millis() as u64));
+    }
+
+    /// Compares every backend's p95 with the SLA and fires the webhook for the
+    /// backends that crossed it, in either direction, outside their debounce period
+    fn check(&self, registry: &BackendRegistry) {
+        let mut alerts = Vec::new();
+        {
+            let mut backends = self.backends.lock().unwrap();
+            // Backends retirados del registro
+            backends.retain(|server_id, _| registry.find(server_id).is_some());
+
+            for (server_id, latency) in backends.iter_mut() {
+                latency.prune(self.window);
+                // Sin tráfico suficiente se mantiene el último estado notificado
+                let Some(p95) = latency.p95() else {
+                    continue;
+                };
+                let breached = p95 > self.sla_p95_ms;
+                if breached == latency.breached
+                    || latency.last_alert.is_some_and(|at| at.elapsed() < self.debounce)
+                {
+                    continue;
+                }
+
+                latency.breached = breached;
+                latency.last_alert = Some(Instant::now());
+                if let Some(backend) = registry.find(server_id) {
+                    alerts.push((backend, breached, p95, latency.samples.len()));
+                }
+            }
+        }
+
+        for (backend, breached, p95, samples) in alerts {
+            if breached {
+                tracing::warn!(
+                    "Backend {} p95 latency {}ms exceeds the {}ms SLA ({} samples)",
+                    backend.server_id,
+                    p95,
+                    self.sla_p95_ms,
+                    samples
+                );
+            } else {
+                tracing::info!(
+                    "Backend {} p95 latency {}ms is back under the {}ms SLA",
+                    backend.server_id,
+                    p95,
+                    self.sla_p95_ms
+                );
+            }
+            self.notify(&backend, breached, p95, samples);
+        }
+    }
+
+    /// Posts the alert to the webhook without waiting for it
+    fn notify(&self, backend: &Backend, breached: bool, p95: u64, samples: usize) {
+        let Some(url) = self.webhook_url.clone() else {
+            return;
+        };
+        let payload = serde_json::json!({
+            "event": if breached { "sla_breached" } else { "sla_recovered" },
+            "server_id": backend.server_id,
+            "server_name": backend.server_name,
+            "provider": backend.provider,
+            "p95_ms": p95,
+            "sla_p95_ms": self.sla_p95_ms,
+            "samples": samples,
+            "window_secs": self.window.as_secs(),
+            "timestamp": SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+        });
+
+        let client = self.client.clone();
+        tokio::spawn(async move {
+            match client.post(&url).json(&payload).send().await {
+                Ok(response) if response.status().is_success() => {}
+                Ok(response) => {
+                    tracing::warn!("SLA webhook {} responded with status {}", redact_webhook_url(&url), response.status())
+                }
+                // El error de reqwest incluye la URL completa, con el token
+                Err(e) => tracing::warn!("Failed to call SLA webhook {}: {}", redact_webhook_url(&url), e.without_url()),
+            }
+        });
+    }
+
+    /// p95 and alert state per backend for the stats endpoint, `null` when disabled
+    pub fn snapshot(&self) -> serde_json::Value {
+        if !self.is_enabled() {
+            return serde_json::Value::Null;
+        }
+        let mut backends = self.backends.lock().unwrap();
+        let by_backend: serde_json::Map<_, _> = backends
+            .iter_mut()
+            .map(|(server_id, latency)| {
+                latency.prune(self.window);
+                let status = serde_json::json!({
+                    "p95_ms": latency.p95(),
+                    "samples": latency.samples.len(),
+                    "breached": latency.breached,
+                });
+                (server_id.clone(), status)
+            })
+            .collect();
+        serde_json::json!({
+            "sla_p95_ms": self.sla_p95_ms,
+            "window_secs": self.window.as_secs(),
+            "backends": by_backend,
+        })
+    }
+}
+
+/// Checks the SLA of every backend periodically
+pub fn start_sla_checks(sla: Arc<LatencySla>, registry: BackendRegistry) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(CHECK_INTERVAL);
+        loop {
+            ticker.tick().await;
+            sla.check(&registry);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_backend;
+
+    type Alerts = Arc<Mutex<Vec<serde_json::Value>>>;
+
+    /// Webhook de prueba que guarda cada alerta recibida
+    async fn webhook() -> (String, Alerts) {
+        let alerts = Alerts::default();
+        let received = alerts.clone();
+        let app = axum::Router::new().route(
+            "/hook",
+            axum::routing::post(move |axum::Json(alert): axum::Json<serde_json::Value>| {
+                let received = received.clone();
+                async move { received.lock().unwrap().push(alert) }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        (url, alerts)
+    }
+
+    /// Waits up to a second for `count` alerts, then a little longer for any extra one
+    async fn alerts_after(alerts: &Alerts, count: usize) -> Vec<serde_json::Value> {
+        for _ in 0..100 {
+            if alerts.lock().unwrap().len() >= count {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        alerts.lock().unwrap().clone()
+    }
+
+    fn record_many(sla: &LatencySla, backend: &Backend, ms: u64, count: usize) {
+        for _ in 0..count {
+            sla.record(backend, Duration::from_millis(ms));
+        }
+    }
+
+    #[test]
+    fn p95_needs_enough_samples() {
+        let mut latency = BackendLatency::default();
+        for ms in 1..MIN_SAMPLES as u64 {
+            latency.samples.push_back((Instant::now(), ms));
+        }
+        assert_eq!(latency.p95(), None);
+
+        // 100 muestras de 1 a 100 ms: el p95 es la 95ª
+        latency.samples = (1..=100).map(|ms| (Instant::now(), ms)).collect();
+        assert_eq!(latency.p95(), Some(95));
+    }
+
+    #[tokio::test]
+    async fn crossing_the_sla_alerts_once_in_each_direction() {
+        let (url, alerts) = webhook().await;
+        let backend = test_backend("slow");
+        let registry = BackendRegistry::new(vec![backend.clone()]);
+        let sla = LatencySla::new(100, Duration::from_millis(200), Duration::ZERO, Some(url));
+
+        record_many(&sla, &backend, 50, MIN_SAMPLES);
+        sla.check(&registry);
+        record_many(&sla, &backend, 400, MIN_SAMPLES);
+        sla.check(&registry);
+        sla.check(&registry);
+
+        let received = alerts_after(&alerts, 1).await;
+        assert_eq!(received.len(), 1, "{:?}", received);
+        assert_eq!(received[0]["event"], "sla_breached");
+        assert_eq!(received[0]["server_id"], "slow");
+        assert_eq!(received[0]["p95_ms"], 400);
+        assert_eq!(received[0]["sla_p95_ms"], 100);
+        assert_eq!(sla.snapshot()["backends"]["slow"]["breached"], true);
+
+        // Las muestras lentas salen de la ventana y las nuevas están bajo el SLA
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        record_many(&sla, &backend, 30, MIN_SAMPLES);
+        sla.check(&registry);
+
+        let received = alerts_after(&alerts, 2).await;
+        assert_eq!(received.len(), 2, "{:?}", received);
+        assert_eq!(received[1]["event"], "sla_recovered");
+        assert_eq!(received[1]["p95_ms"], 30);
+        assert_eq!(sla.snapshot()["backends"]["slow"]["breached"], false);
+    }
+
+    #[tokio::test]
+    async fn alerts_within_the_debounce_period_are_held_back() {
+        let (url, alerts) = webhook().await;
+        let backend = test_backend("flappy");
+        let registry = BackendRegistry::new(vec![backend.clone()]);
+        let sla = LatencySla::new(100, Duration::from_millis(100), Duration::from_secs(3600), Some(url));
+
+        record_many(&sla, &backend, 400, MIN_SAMPLES);
+        sla.check(&registry);
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        record_many(&sla, &backend, 30, MIN_SAMPLES);
+        sla.check(&registry);
+
+        let received = alerts_after(&alerts, 1).await;
+        assert_eq!(received.len(), 1, "{:?}", received);
+        assert_eq!(received[0]["event"], "sla_breached");
+        // La recuperación se notificará cuando acabe el debounce
+        assert_eq!(sla.snapshot()["backends"]["flappy"]["breached"], true);
+    }
+
+    #[tokio::test]
+    async fn too_few_samples_or_removed_backends_do_not_alert() {
+        let (url, alerts) = webhook().await;
+        let kept = test_backend("kept");
+        let removed = test_backend("removed");
+        let registry = BackendRegistry::new(vec![kept.clone()]);
+        let sla = LatencySla::new(100, Duration::from_secs(60), Duration::ZERO, Some(url));
+
+        record_many(&sla, &kept, 400, MIN_SAMPLES - 1);
+        record_many(&sla, &removed, 400, MIN_SAMPLES);
+        sla.check(&registry);
+
+        assert!(alerts_after(&alerts, 1).await.is_empty());
+        let snapshot = sla.snapshot();
+        assert_eq!(snapshot["backends"]["kept"]["p95_ms"], serde_json::Value::Null);
+        assert!(snapshot["backends"].get("removed").is_none());
+    }
+
+    #[test]
+    fn disabled_sla_records_nothing() {
+        let sla = LatencySla::new(0, Duration::from_secs(60), Duration::ZERO, None);
+        assert!(!sla.is_enabled());
+        sla.record(&test_backend("b"), Duration::from_millis(500));
+        assert!(sla.backends.lock().unwrap().is_empty());
+        assert!(sla.snapshot().is_null());
+    }
+}
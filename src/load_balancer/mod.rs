@@ -1,8 +1,11 @@
 pub mod strategies;
 
 use crate::db::Backend;
+use crate::health::HealthChecker;
 use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
 use std::sync::Arc;
+use tokio::sync::broadcast::error::RecvError;
 
 /// Trait que define el comportamiento de un balanceador de carga.
 /// Implementa este trait para crear nuevos algoritmos de balanceo.
@@ -23,14 +26,93 @@ pub trait LoadBalancer: Send + Sync {
 
     /// Retorna el nombre del algoritmo de balanceo
     fn name(&self) -> &str;
+
+    /// Fija (`Some`) o elimina (`None`) un peso temporal para un backend, con
+    /// precedencia sobre su peso base. Retorna false si el algoritmo no usa pesos.
+    fn set_weight_override(&self, _server_id: &str, _weight: Option<usize>) -> bool {
+        false
+    }
+
+    /// Peso efectivo de un backend, o None si el algoritmo no usa pesos
+    fn effective_weight(&self, _backend: &Backend) -> Option<usize> {
+        None
+    }
+
+    /// Notifica que un backend volvió a estar saludable tras un periodo no saludable.
+    /// Útil para algoritmos con estado por backend que quedó obsoleto mientras estaba fuera.
+    async fn backend_recovered(&self, _server_id: &str) {}
+}
+
+/// Lowest failover tier among `healthy`, the one that receives traffic
+pub fn active_tier(healthy: &[Backend]) -> Option<i32> {
+    healthy.iter().map(Backend::priority).min()
+}
+
+/// Keeps only the backends of the active tier, so backup tiers receive traffic
+/// only while every backend of the tiers above them is unhealthy
+pub fn retain_active_tier(healthy: &mut Vec<Backend>) {
+    if let Some(tier) = active_tier(healthy) {
+        healthy.retain(|b| b.priority() == tier);
+    }
+}
+
+/// Algoritmo de balanceo propio de un prefijo de ruta (p. ej. `/upload` con least-connections)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoadBalancerRoute {
+    /// Prefijo por segmentos completos: `/upload` cubre `/upload/x` pero no `/uploads`
+    pub prefix: String,
+    /// Estrategia, con los mismos nombres que `LOAD_BALANCER_STRATEGY`
+    pub strategy: String,
+}
+
+impl LoadBalancerRoute {
+    pub fn matches(&self, path: &str) -> bool {
+        let prefix = self.prefix.trim_end_matches('/');
+        path.strip_prefix(prefix)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    }
+}
+
+/// Whether `strategy` names a balancer known to `create_load_balancer`
+pub fn is_known_strategy(strategy: &str) -> bool {
+    matches!(
+        strategy.to_lowercase().as_str(),
+        "round-robin" | "roundrobin" | "least-connections" | "leastconnections" | "random" | "weighted-round-robin"
+            | "weightedroundrobin"
+    )
+}
+
+/// Forwards unhealthy -> healthy transitions to `balancers`, so their
+/// per-backend state starts fresh when a backend comes back
+pub fn start_recovery_reset(health_checker: &HealthChecker, balancers: Vec<Arc<dyn LoadBalancer>>) {
+    let mut receiver = health_checker.subscribe();
+
+    tokio::spawn(async move {
+        loop {
+            match receiver.recv().await {
+                Ok(event) if event.previous_state == Some("unhealthy") && event.state == "healthy" => {
+                    tracing::debug!("Backend {} recovered, resetting its load balancer state", event.server_id);
+                    for balancer in &balancers {
+                        balancer.backend_recovered(&event.server_id).await;
+                    }
+                }
+                Ok(_) => {}
+                Err(RecvError::Lagged(skipped)) => {
+                    tracing::warn!("Load balancer missed {} health events", skipped);
+                }
+                Err(RecvError::Closed) => return,
+            }
+        }
+    });
 }
 
-/// Factory para crear diferentes tipos de balanceadores
-pub fn create_load_balancer(strategy: &str) -> Arc<dyn LoadBalancer> {
+/// Factory para crear diferentes tipos de balanceadores.
+/// `seed` hace deterministas las estrategias aleatorias (`LB_RANDOM_SEED`).
+pub fn create_load_balancer(strategy: &str, seed: Option<u64>) -> Arc<dyn LoadBalancer> {
     match strategy.to_lowercase().as_str() {
         "round-robin" | "roundrobin" => Arc::new(strategies::RoundRobinBalancer::new()),
         "least-connections" | "leastconnections" => Arc::new(strategies::LeastConnectionsBalancer::new()),
-        "random" => Arc::new(strategies::RandomBalancer::new()),
+        "random" => Arc::new(strategies::RandomBalancer::new(seed)),
         "weighted-round-robin" | "weightedroundrobin" => Arc::new(strategies::WeightedRoundRobinBalancer::new()),
         _ => {
             tracing::warn!("Unknown load balancer strategy '{}', defaulting to round-robin", strategy);
@@ -38,3 +120,146 @@ pub fn create_load_balancer(strategy: &str) -> Arc<dyn LoadBalancer> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_backend;
+    use crate::health::HealthCheckConfig;
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    fn with_priority(server_id: &str, priority: Option<i32>) -> Backend {
+        Backend {
+            priority,
+            ..test_backend(server_id)
+        }
+    }
+
+    fn ids(backends: &[Backend]) -> Vec<&str> {
+        backends.iter().map(|b| b.server_id.as_str()).collect()
+    }
+
+    #[test]
+    fn only_the_lowest_tier_is_kept() {
+        let mut healthy = vec![
+            with_priority("backup", Some(1)),
+            with_priority("primary-a", None),
+            with_priority("cold", Some(2)),
+            with_priority("primary-b", Some(0)),
+        ];
+
+        assert_eq!(active_tier(&healthy), Some(0));
+        retain_active_tier(&mut healthy);
+        assert_eq!(ids(&healthy), ["primary-a", "primary-b"]);
+    }
+
+    #[test]
+    fn backup_tier_takes_over_when_the_primary_is_gone() {
+        let mut healthy = vec![with_priority("cold", Some(2)), with_priority("backup", Some(1))];
+
+        retain_active_tier(&mut healthy);
+        assert_eq!(ids(&healthy), ["backup"]);
+
+        // Prioridades negativas van por delante del tier por defecto
+        let mut healthy = vec![with_priority("default", None), with_priority("preferred", Some(-1))];
+        retain_active_tier(&mut healthy);
+        assert_eq!(ids(&healthy), ["preferred"]);
+    }
+
+    #[test]
+    fn no_healthy_backends_has_no_active_tier() {
+        let mut healthy = Vec::new();
+
+        assert_eq!(active_tier(&healthy), None);
+        retain_active_tier(&mut healthy);
+        assert!(healthy.is_empty());
+    }
+
+    #[test]
+    fn route_prefix_matches_whole_segments() {
+        for prefix in ["/upload", "/upload/"] {
+            let route = LoadBalancerRoute {
+                prefix: prefix.to_string(),
+                strategy: "least-connections".to_string(),
+            };
+            assert!(route.matches("/upload"), "{}", prefix);
+            assert!(route.matches("/upload/abc"), "{}", prefix);
+            assert!(!route.matches("/uploads"), "{}", prefix);
+            assert!(!route.matches("/api/upload"), "{}", prefix);
+        }
+    }
+
+    #[test]
+    fn strategy_names_are_case_insensitive() {
+        for strategy in ["round-robin", "LeastConnections", "RANDOM", "weighted-round-robin"] {
+            assert!(is_known_strategy(strategy), "{}", strategy);
+        }
+        assert!(!is_known_strategy("fastest"));
+    }
+
+    /// Balanceador que solo anota los backends recuperados
+    #[derive(Default)]
+    struct RecoveryLog(Mutex<Vec<String>>);
+
+    #[async_trait]
+    impl LoadBalancer for RecoveryLog {
+        async fn select_backend(&self, backends: &[Backend]) -> Option<Backend> {
+            backends.first().cloned()
+        }
+
+        async fn release_backend(&self, _backend: &Backend) {}
+
+        fn name(&self) -> &str {
+            "RecoveryLog"
+        }
+
+        async fn backend_recovered(&self, server_id: &str) {
+            self.0.lock().unwrap().push(server_id.to_string());
+        }
+    }
+
+    /// Backend whose `/api/v1/health` always answers 200
+    async fn healthy_backend(server_id: &str) -> Backend {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = axum::Router::new().route("/api/v1/health", axum::routing::get(|| async { "ok" }));
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        Backend {
+            server_url: format!("http://{}", addr),
+            ..test_backend(server_id)
+        }
+    }
+
+    #[tokio::test]
+    async fn only_unhealthy_to_healthy_transitions_reach_the_balancers() {
+        let checker = HealthChecker::new(None, HealthCheckConfig::default());
+        let first = Arc::new(RecoveryLog::default());
+        let second = Arc::new(RecoveryLog::default());
+        start_recovery_reset(&checker, vec![first.clone(), second.clone()]);
+
+        // "down" cae tras no terminar su primer chequeo y luego se recupera
+        let down = healthy_backend("down").await;
+        checker.mark_probing("down").await;
+        checker.expire_probing("down").await;
+        assert!(checker.check_backend(&down).await);
+
+        // "new" pasa de probing a healthy sin haber estado caído
+        let new = healthy_backend("new").await;
+        checker.mark_probing("new").await;
+        assert!(checker.check_backend(&new).await);
+
+        // Un override manual tampoco cuenta como recuperación
+        checker.set_override("down", true, None).await;
+
+        for _ in 0..100 {
+            if !second.0.lock().unwrap().is_empty() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(*first.0.lock().unwrap(), ["down"]);
+        assert_eq!(*second.0.lock().unwrap(), ["down"]);
+    }
+}
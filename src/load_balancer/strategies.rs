@@ -2,7 +2,7 @@ use super::LoadBalancer;
 use crate::db::Backend;
 use async_trait::async_trait;
 use std::collections::HashMap;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
@@ -90,14 +90,49 @@ impl LoadBalancer for LeastConnectionsBalancer {
     fn name(&self) -> &str {
         "LeastConnections"
     }
+
+    /// El contador de un backend recuperado vuelve a cero: lo que quedaba de antes
+    /// de caer no refleja su carga real. Las peticiones que aún terminen después
+    /// no lo dejan negativo, porque `release_backend` satura en cero.
+    async fn backend_recovered(&self, server_id: &str) {
+        if let Some(count) = self.connections.write().await.get_mut(server_id) {
+            *count = 0;
+        }
+    }
 }
 
-/// Balanceador Random - selecciona un backend aleatoriamente
-pub struct RandomBalancer;
+/// Balanceador Random - selecciona un backend aleatoriamente.
+/// Con una semilla la secuencia de selecciones es reproducible.
+pub struct RandomBalancer {
+    /// Estado del generador SplitMix64
+    state: AtomicU64,
+}
 
 impl RandomBalancer {
-    pub fn new() -> Self {
-        Self
+    pub fn new(seed: Option<u64>) -> Self {
+        let seed = seed.unwrap_or_else(|| {
+            use std::collections::hash_map::RandomState;
+            use std::hash::BuildHasher;
+
+            // Semilla aleatoria basada en el timestamp
+            RandomState::new().hash_one(std::time::SystemTime::now())
+        });
+
+        Self {
+            state: AtomicU64::new(seed),
+        }
+    }
+
+    /// Next value of the SplitMix64 sequence; lock-free since each call
+    /// only advances the state by a fixed increment
+    pub fn next_u64(&self) -> u64 {
+        let mut z = self
+            .state
+            .fetch_add(0x9E37_79B9_7F4A_7C15, Ordering::Relaxed)
+            .wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
     }
 }
 
@@ -108,16 +143,7 @@ impl LoadBalancer for RandomBalancer {
             return None;
         }
 
-        use std::collections::hash_map::RandomState;
-        use std::hash::{BuildHasher, Hash, Hasher};
-
-        // Usa un hash aleatorio basado en el timestamp
-        let s = RandomState::new();
-        let mut hasher = s.build_hasher();
-        std::time::SystemTime::now().hash(&mut hasher);
-        let hash = hasher.finish();
-
-        let index = (hash as usize) % backends.len();
+        let index = (self.next_u64() % backends.len() as u64) as usize;
         Some(backends[index].clone())
     }
 
@@ -134,15 +160,28 @@ impl LoadBalancer for RandomBalancer {
 /// Los pesos se basan en el provider (puedes ajustar según necesites)
 pub struct WeightedRoundRobinBalancer {
     counter: AtomicUsize,
+    /// Pesos fijados por un operador en tiempo de ejecución, por server_id.
+    /// Se guardan aparte del peso base para que un refresco no los pise.
+    weight_overrides: std::sync::RwLock<HashMap<String, usize>>,
 }
 
 impl WeightedRoundRobinBalancer {
     pub fn new() -> Self {
         Self {
             counter: AtomicUsize::new(0),
+            weight_overrides: std::sync::RwLock::new(HashMap::new()),
         }
     }
 
+    fn weight_for(&self, backend: &Backend) -> usize {
+        self.weight_overrides
+            .read()
+            .unwrap()
+            .get(&backend.server_id)
+            .copied()
+            .unwrap_or_else(|| Self::get_weight(&backend.provider))
+    }
+
     fn get_weight(provider: &str) -> usize {
         match provider {
             "supabase" => 3, // Supabase recibe 3x más tráfico
@@ -162,7 +201,7 @@ impl LoadBalancer for WeightedRoundRobinBalancer {
         // Construye una lista ponderada de backends
         let mut weighted_backends = Vec::new();
         for backend in backends {
-            let weight = Self::get_weight(&backend.provider);
+            let weight = self.weight_for(backend);
             for _ in 0..weight {
                 weighted_backends.push(backend.clone());
             }
@@ -183,4 +222,106 @@ impl LoadBalancer for WeightedRoundRobinBalancer {
     fn name(&self) -> &str {
         "WeightedRoundRobin"
     }
+
+    fn set_weight_override(&self, server_id: &str, weight: Option<usize>) -> bool {
+        let mut overrides = self.weight_overrides.write().unwrap();
+        match weight {
+            Some(weight) => overrides.insert(server_id.to_string(), weight),
+            None => overrides.remove(server_id),
+        };
+        true
+    }
+
+    fn effective_weight(&self, backend: &Backend) -> Option<usize> {
+        Some(self.weight_for(backend))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_backend;
+
+    async fn selections(balancer: &RandomBalancer, backends: &[Backend], count: usize) -> Vec<String> {
+        let mut selected = Vec::with_capacity(count);
+        for _ in 0..count {
+            selected.push(balancer.select_backend(backends).await.unwrap().server_id);
+        }
+        selected
+    }
+
+    #[tokio::test]
+    async fn same_seed_produces_same_sequence() {
+        let backends: Vec<_> = ["a", "b", "c", "d"].into_iter().map(test_backend).collect();
+        let first = RandomBalancer::new(Some(42));
+        let second = RandomBalancer::new(Some(42));
+
+        assert_eq!(selections(&first, &backends, 64).await, selections(&second, &backends, 64).await);
+    }
+
+    #[tokio::test]
+    async fn different_seeds_produce_different_sequences() {
+        let backends: Vec<_> = ["a", "b", "c", "d"].into_iter().map(test_backend).collect();
+        let first = RandomBalancer::new(Some(1));
+        let second = RandomBalancer::new(Some(2));
+
+        assert_ne!(selections(&first, &backends, 64).await, selections(&second, &backends, 64).await);
+    }
+
+    #[tokio::test]
+    async fn seeded_selection_reaches_every_backend() {
+        let backends: Vec<_> = ["a", "b", "c"].into_iter().map(test_backend).collect();
+        let balancer = RandomBalancer::new(Some(7));
+        let selected = selections(&balancer, &backends, 300).await;
+
+        for backend in &backends {
+            assert!(selected.contains(&backend.server_id));
+        }
+    }
+
+    #[tokio::test]
+    async fn create_load_balancer_passes_the_seed() {
+        let backends: Vec<_> = ["a", "b", "c", "d"].into_iter().map(test_backend).collect();
+        let first = crate::load_balancer::create_load_balancer("random", Some(9));
+        let second = crate::load_balancer::create_load_balancer("random", Some(9));
+
+        for _ in 0..64 {
+            let a = first.select_backend(&backends).await.unwrap();
+            let b = second.select_backend(&backends).await.unwrap();
+            assert_eq!(a.server_id, b.server_id);
+        }
+    }
+
+    #[tokio::test]
+    async fn no_backends_selects_nothing() {
+        assert!(RandomBalancer::new(Some(42)).select_backend(&[]).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn recovered_backend_starts_with_no_connections() {
+        let balancer = LeastConnectionsBalancer::new();
+        let a = test_backend("a");
+        let both = [test_backend("a"), test_backend("b")];
+        for _ in 0..3 {
+            balancer.select_backend(std::slice::from_ref(&a)).await;
+        }
+        assert_eq!(balancer.select_backend(&both).await.unwrap().server_id, "b");
+
+        balancer.backend_recovered("a").await;
+        assert_eq!(balancer.select_backend(&both).await.unwrap().server_id, "a");
+
+        // Las peticiones de antes de caer que terminan ahora no lo dejan en negativo
+        for _ in 0..3 {
+            balancer.release_backend(&a).await;
+        }
+        assert_eq!(balancer.connections.read().await["a"], 0);
+        assert_eq!(balancer.connections.read().await["b"], 1);
+    }
+
+    #[tokio::test]
+    async fn recovery_of_an_unknown_backend_is_ignored() {
+        let balancer = LeastConnectionsBalancer::new();
+        balancer.backend_recovered("missing").await;
+        assert!(balancer.connections.read().await.is_empty());
+    }
 }
@@ -1,30 +1,96 @@
+mod admin;
+mod backends;
+mod bandwidth;
 mod cache;
+mod canary;
+mod capture;
+mod checksum;
+mod compression;
 mod config;
 mod db;
+mod db_circuit;
+mod discovery;
+mod error_pages;
+#[cfg(test)]
+mod fake_redis;
+mod file_cache;
+mod file_lock;
+mod forwarded;
+mod grpc_health;
+mod grpc_web;
+mod header_log;
 mod health;
+mod informational;
+mod json_rpc;
+mod latency_sla;
 mod load_balancer;
+mod method_override;
+mod mirror;
+mod mtls;
+mod panic_guard;
+mod preflight;
+mod prewarm;
+mod provider_switch;
 mod proxy;
+mod proxy_protocol;
+mod queue_time;
 mod rate_limiter;
+mod redirect;
+mod request_guard;
+mod routing;
+mod server;
+mod shared_health;
+mod stale_cache;
+mod stats_log;
+mod sticky;
+mod throttle;
+mod token_validator;
+mod trace_sampling;
+mod upload_metrics;
 
 use anyhow::Result;
 use axum::{middleware, routing::get, Router};
+use std::net::SocketAddr;
 use std::sync::Arc;
-use tower_http::{cors::CorsLayer, trace::TraceLayer};
+use tower_http::{catch_panic::CatchPanicLayer, cors::CorsLayer, trace::TraceLayer};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 use crate::{
-    config::Config,
+    admin::effective_settings,
+    backends::{filter_valid_backends, start_backend_refresh, BackendRegistry},
+    config::{Config, RuntimeConfig},
+    discovery::create_backend_source,
+    error_pages::error_page_middleware,
+    forwarded::resolve_client,
     health::HealthChecker,
-    load_balancer::create_load_balancer,
+    latency_sla::start_sla_checks,
+    load_balancer::{create_load_balancer, start_recovery_reset},
+    method_override::method_override_middleware,
+    mtls::ClientIdentity,
+    panic_guard::{install_panic_hook, panic_response, with_request_id},
+    preflight::{check_requested, run_check},
+    prewarm::start_connection_prewarm,
     proxy::{
-        delete_expired_files, gateway_health, gateway_stats, proxy_handler,
-        proxy_to_specific_backend, ProxyState,
+        admin_routes, builtin_asset_routes, gateway_health, proxy_handler, public_admin_routes,
+        specific_backend_route, ProxyState,
     },
-    rate_limiter::{rate_limit_middleware, RateLimiterConfig},
+    rate_limiter::{rate_limit_middleware, RateLimitPolicy},
+    request_guard::request_guard_middleware,
+    server::ConnectionTimeouts,
+    shared_health::SharedHealthStore,
+    stats_log::{count_requests, start_stats_log},
+    token_validator::create_token_validator,
+    trace_sampling::sample_request,
 };
 
-#[tokio::main]
-async fn main() -> Result<()> {
+fn main() -> Result<()> {
+    // El runtime se dimensiona antes de cargar la configuración, así que .env se lee aquí
+    dotenvy::dotenv().ok();
+    let runtime_config = RuntimeConfig::from_env();
+    runtime_config.build()?.block_on(run(runtime_config))
+}
+
+async fn run(runtime_config: RuntimeConfig) -> Result<()> {
     // Inicializa el sistema de logging
     tracing_subscriber::registry()
         .with(
@@ -33,50 +99,108 @@ async fn main() -> Result<()> {
         )
         .with(tracing_subscriber::fmt::layer())
         .init();
+    install_panic_hook();
 
     tracing::info!("Starting VK Gateway...");
 
+    for name in &runtime_config.invalid {
+        tracing::warn!("Ignoring invalid {}: must be a positive number", name);
+    }
+
     // Carga la configuración
-    let config = Config::from_env()?;
+    let mut config = Config::from_env()?;
     tracing::info!("Configuration loaded");
 
-    // Conecta a PostgreSQL
-    let db_pool = db::create_pool(&config.database_url).await?;
-    tracing::info!("Connected to PostgreSQL");
+    // Con --check (o GATEWAY_MODE=check) solo se valida el despliegue y se sale
+    if check_requested() {
+        if !run_check(&config).await {
+            return Err(anyhow::anyhow!("Deployment check failed"));
+        }
+        return Ok(());
+    }
+
+    // Conecta a PostgreSQL. Sin DATABASE_URL (fuentes de backends file, dns o env)
+    // se arranca sin base de datos y sin las funciones que dependen de ella
+    let db_pool = match config.database_url {
+        Some(ref database_url) => {
+            let pool = db::create_pool(database_url).await?;
+            tracing::info!("Connected to PostgreSQL");
+            Some(pool)
+        }
+        None => {
+            tracing::warn!("DATABASE_URL is not set: file routing and expired file cleanup are disabled");
+            config.file_routing = false;
+            None
+        }
+    };
+
+    // El enrutamiento de archivos depende de application.metadata: si falta, cada
+    // petición de archivo fallaría y caería al balanceo sin que nadie lo note
+    match db_pool {
+        Some(ref pool) if config.file_routing => match db::check_metadata_schema(pool).await {
+            Ok(()) => tracing::info!("File routing enabled (application.metadata is available)"),
+            Err(e) if db::is_schema_error(&e) => {
+                tracing::error!(
+                    "application.metadata is missing or lacks file_id/server_id columns, file routing will not work: {}",
+                    e
+                );
+                if config.file_routing_auto_disable {
+                    tracing::warn!("File routing disabled, file requests will be load balanced");
+                    config.file_routing = false;
+                }
+            }
+            Err(e) => tracing::error!("Could not verify application.metadata for file routing: {}", e),
+        },
+        Some(_) => tracing::info!("File routing disabled by FILE_ROUTING"),
+        None => {}
+    }
+
+    // Los borrados fallidos de archivos caducados se registran para reintentarlos
+    if let (true, Some(pool)) = (config.expiry_max_attempts > 0, db_pool.as_ref()) {
+        if let Err(e) = db::ensure_expiry_dead_letter_table(pool).await {
+            tracing::error!(
+                "Could not create application.expiry_dead_letter, expired file cleanup will fail until it exists: {}",
+                e
+            );
+        }
+    }
+    let config = Arc::new(config);
 
     // Conecta a Redis
     let redis_client = cache::create_redis_client(&config.redis_url).await?;
     tracing::info!("Connected to Redis");
 
     // Configura rate limiter
-    let rate_limiter_config = RateLimiterConfig {
-        max_requests: std::env::var("RATE_LIMIT_MAX_REQUESTS")
-            .ok()
-            .and_then(|s| s.parse().ok())
-            .unwrap_or(10),
-        window_secs: std::env::var("RATE_LIMIT_WINDOW_SECS")
-            .ok()
-            .and_then(|s| s.parse().ok())
-            .unwrap_or(60),
-        block_duration_secs: std::env::var("RATE_LIMIT_BLOCK_DURATION_SECS")
-            .ok()
-            .and_then(|s| s.parse().ok())
-            .unwrap_or(300),
-    };
+    let rate_limiter_config = config.rate_limit;
     tracing::info!(
         "Rate limiter configured: max {} requests per {} seconds, block for {} seconds",
         rate_limiter_config.max_requests,
         rate_limiter_config.window_secs,
         rate_limiter_config.block_duration_secs
     );
+    if let Some(ip_limits) = config.rate_limit_ip {
+        tracing::info!(
+            "Per-IP rate limit for requests without token: max {} requests per {} seconds, block for {} seconds",
+            ip_limits.max_requests,
+            ip_limits.window_secs,
+            ip_limits.block_duration_secs
+        );
+    }
 
-    // Obtiene la lista de backends desde la base de datos
-    let backends = db::get_all_backends(&db_pool).await?;
-    tracing::info!("Loaded {} backends from database", backends.len());
+    // Obtiene la lista de backends desde la fuente configurada
+    let backend_source = create_backend_source(&config, db_pool.as_ref())?;
+    let backends = backend_source.list_backends().await?;
+    let backends = filter_valid_backends(backends, &config.backend_host_allowlist);
+    tracing::info!(
+        "Loaded {} backends from {}",
+        backends.len(),
+        backend_source.name()
+    );
 
     if backends.is_empty() {
         tracing::warn!(
-            "No backends found in database. The gateway will not be able to proxy requests."
+            "No backends found in {}. Requests and /api/v1/health will get 503 until backends are configured.",
+            backend_source.name()
         );
     }
 
@@ -85,27 +209,56 @@ async fn main() -> Result<()> {
             "  - {} ({}) - {} [{}]",
             backend.server_name,
             backend.server_id,
-            backend.server_url,
+            config::redact_url(&backend.server_url),
             backend.provider
         );
     }
 
     // Crea el load balancer
-    // Puedes cambiar la estrategia aquí: "round-robin", "least-connections", "random", "weighted-round-robin"
-    let load_balancer_strategy =
-        std::env::var("LOAD_BALANCER_STRATEGY").unwrap_or_else(|_| "round-robin".to_string());
-
-    let load_balancer = create_load_balancer(&load_balancer_strategy);
+    // Puedes cambiar la estrategia con LOAD_BALANCER_STRATEGY: "round-robin", "least-connections", "random", "weighted-round-robin"
+    let load_balancer = create_load_balancer(&config.load_balancer_strategy, config.lb_random_seed);
     tracing::info!("Using load balancer: {}", load_balancer.name());
 
+    let token_validator = create_token_validator(&config.token_validator, config.vk_secret.as_deref())?;
+    tracing::info!("Using upload token validator: {}", token_validator.name());
+
+    // Certificado de cliente para los backends con mTLS; un archivo inválido impide arrancar
+    let client_identity = ClientIdentity::from_config(&config)?.map(Arc::new);
+    if client_identity.is_some() {
+        let targets = if config.upstream_mtls_backends.is_empty() {
+            "all backends".to_string()
+        } else {
+            config.upstream_mtls_backends.join(", ")
+        };
+        tracing::info!("Presenting upstream client certificate to {}", targets);
+    }
+
     // Crea el health checker
-    let health_checker = Arc::new(HealthChecker::new(config.vk_secret.clone()));
+    let mut health_checker = HealthChecker::new(config.vk_secret.clone(), config.health_check.clone())
+        .with_assume_healthy_until_probed(config.assume_healthy_until_probed)
+        .with_health_score(
+            config.health_score_window,
+            Some(config.health_score_threshold).filter(|t| *t > 0.0),
+        );
 
-    // Inicia los health checks periódicos (cada 30 segundos)
-    let health_check_interval = std::env::var("HEALTH_CHECK_INTERVAL")
-        .ok()
-        .and_then(|s| s.parse().ok())
-        .unwrap_or(30);
+    // Con SHARED_HEALTH las instancias se reparten los health checks vía Redis
+    if config.shared_health {
+        let store = SharedHealthStore::new(redis_client.clone(), config.health_check_interval);
+        tracing::info!("Sharing health state through Redis as instance {}", store.instance_id());
+        health_checker = health_checker.with_shared_state(store);
+    }
+    if let Some(ref identity) = client_identity {
+        health_checker = health_checker.with_client_identity(identity.clone());
+    }
+    let health_checker = Arc::new(health_checker);
+
+    // Inicia los health checks periódicos (cada 30 segundos por defecto)
+    let health_check_interval = config.health_check_interval;
+
+    let backends = BackendRegistry::new(backends);
+
+    // Suscrito antes de los primeros health checks para no perder sus eventos
+    let prewarm_events = (config.prewarm_connections > 0).then(|| health_checker.subscribe());
 
     health_checker
         .clone()
@@ -116,15 +269,55 @@ async fn main() -> Result<()> {
         health_check_interval
     );
 
+    // Refresca periódicamente la lista de backends desde la fuente (0 = deshabilitado)
+    if config.backend_refresh_interval > 0 {
+        start_backend_refresh(
+            backends.clone(),
+            backend_source.clone(),
+            health_checker.clone(),
+            config.clone(),
+        );
+        tracing::info!(
+            "Backend refresh started (interval: {}s)",
+            config.backend_refresh_interval
+        );
+    }
+
     // Crea el estado del proxy
     let proxy_state = ProxyState::new(
+        config.clone(),
         backends,
         load_balancer,
         health_checker,
         db_pool.clone(),
-        config.vk_secret.clone(),
+        redis_client.clone(),
+        client_identity,
     );
 
+    // Un backend recuperado no debe arrastrar el estado que tenía al caer
+    if config.lb_reset_on_recovery {
+        let balancers = proxy_state.balancers().cloned().collect();
+        start_recovery_reset(&proxy_state.health_checker, balancers);
+    }
+
+    // Conexiones abiertas de antemano a cada backend saludable (PREWARM_CONNECTIONS=0 lo deshabilita)
+    if let Some(receiver) = prewarm_events {
+        start_connection_prewarm(proxy_state.clone(), receiver);
+    }
+
+    // Alertas de latencia por backend (SLA_P95_MS=0 las deshabilita)
+    if proxy_state.latency_sla.is_enabled() {
+        start_sla_checks(proxy_state.latency_sla.clone(), proxy_state.backends.clone());
+    }
+
+    // Resumen periódico en los logs para despliegues sin Prometheus (0 = deshabilitado)
+    if config.stats_log_interval_secs > 0 {
+        start_stats_log(
+            proxy_state.clone(),
+            std::time::Duration::from_secs(config.stats_log_interval_secs),
+        );
+    }
+
     // Configura CORS basado en variables de entorno
     let cors_layer = if let Some(allowed_origins) = &config.cors_allowed_origins {
         tracing::info!(
@@ -154,9 +347,21 @@ async fn main() -> Result<()> {
                 axum::http::header::CONTENT_TYPE,
                 axum::http::header::ACCEPT,
                 axum::http::header::ORIGIN,
+                // Peticiones condicionales y descargas parciales
+                axum::http::header::IF_NONE_MATCH,
+                axum::http::header::IF_MODIFIED_SINCE,
+                axum::http::header::IF_RANGE,
+                axum::http::header::RANGE,
                 // Custom headers
                 axum::http::header::HeaderName::from_static("x-vk-secret"),
                 axum::http::header::HeaderName::from_static("x-upload-token"),
+                axum::http::header::HeaderName::from_static(config::CLIENT_TIMEOUT_HEADER),
+                axum::http::header::HeaderName::from_static("x-http-method-override"),
+            ])
+            // Validadores que el cliente necesita leer para sus peticiones condicionales
+            .expose_headers([
+                axum::http::header::ETAG,
+                axum::http::header::LAST_MODIFIED,
             ])
             .allow_credentials(true)
     } else {
@@ -164,39 +369,152 @@ async fn main() -> Result<()> {
         CorsLayer::permissive()
     };
 
+    // Un único evento con toda la configuración efectiva (sin secretos)
+    let mut summary = effective_settings(&proxy_state);
+    summary["derived"]["backend_source"] = backend_source.name().into();
+    summary["derived"]["runtime"] = serde_json::json!(runtime_config);
+    tracing::info!(summary = %summary, "Startup summary");
+
+    let request_guard_config = config.request_guard;
+    let method_override_allowed: Option<Arc<[String]>> =
+        config.method_override.then(|| config.method_override_allowed.clone().into());
+    let forwarded_config = config.forwarded;
+    let rate_limit_metrics = proxy_state.rate_limit_metrics.clone();
+    let connection_limiter = proxy_state.connection_limiter.clone();
+    let rate_limit_policy = RateLimitPolicy {
+        config: rate_limiter_config,
+        routes: config.rate_limit_routes.clone().into(),
+        ip_limits: config.rate_limit_ip,
+        require_token_routes: config.require_token_routes.clone().into(),
+        validator: token_validator,
+        response: Arc::new(config.rate_limit_response.clone()),
+    };
+
+    // Endpoints de administración, estadísticas y métricas. Con ADMIN_PORT se sirven
+    // en su propio listener y el puerto público solo atiende el tráfico proxy
+    let admin_routes = admin_routes();
+
     // Configura las rutas de Axum
     let app = Router::new()
         // Rutas del gateway
+        .merge(public_admin_routes(&config))
         .route("/api/v1/health", get(gateway_health))
-        .route("/api/v1/stats", get(gateway_stats))
-        .route(
-            "/api/v1/files/delete-expired",
-            axum::routing::delete(delete_expired_files),
-        )
         // Ruta para acceder a un backend específico por ID
         .route(
             "/api/v1/backend/:server_id/*path",
-            get(proxy_to_specific_backend)
-                .post(proxy_to_specific_backend)
-                .put(proxy_to_specific_backend)
-                .patch(proxy_to_specific_backend)
-                .delete(proxy_to_specific_backend),
+            specific_backend_route(),
         )
+        .merge(builtin_asset_routes(&config))
         // Ruta catch-all para proxy transparente
         .fallback(proxy_handler)
-        .with_state(proxy_state)
-        // Middlewares
-        .layer(middleware::from_fn(move |req, next| {
-            rate_limit_middleware(redis_client.clone(), rate_limiter_config.clone(), req, next)
-        }))
-        .layer(cors_layer)
-        .layer(TraceLayer::new_for_http());
+        .with_state(proxy_state.clone());
+
+    // Mismos middlewares en ambos listeners
+    let trace_sample_rate = config.trace_sample_rate;
+    let with_middlewares = |router: Router| {
+        let method_override_allowed = method_override_allowed.clone();
+        let redis_client = redis_client.clone();
+        let rate_limit_policy = rate_limit_policy.clone();
+        let rate_limit_metrics = rate_limit_metrics.clone();
+        router
+            .layer(middleware::from_fn(move |req, next| {
+                rate_limit_middleware(
+                    redis_client.clone(),
+                    rate_limit_policy.clone(),
+                    rate_limit_metrics.clone(),
+                    req,
+                    next,
+                )
+            }))
+            // El rate limiter ya ve al cliente original detrás de los proxies de confianza
+            .layer(middleware::from_fn(move |req, next| resolve_client(forwarded_config, req, next)))
+            // Rechaza headers excesivos o peticiones malformadas antes del rate limiter
+            .layer(middleware::from_fn(move |req, next| {
+                request_guard_middleware(request_guard_config, req, next)
+            }))
+            // Antes de validar la petición: el rate limiter y los handlers ven el método real
+            .layer(middleware::from_fn(move |req, next| {
+                method_override_middleware(method_override_allowed.clone(), req, next)
+            }))
+            .layer(cors_layer.clone())
+            .layer(TraceLayer::new_for_http())
+            // Un panic en un handler o middleware responde 500 en vez de cortar la conexión
+            .layer(CatchPanicLayer::custom(panic_response))
+            // Decide por petición si sus logs de proxy salen a nivel info
+            .layer(middleware::from_fn(move |req, next| sample_request(trace_sample_rate, req, next)))
+            .layer(middleware::from_fn(with_request_id))
+            // El más externo, para que el tiempo en cola cubra todos los middlewares
+            .layer(middleware::from_fn(queue_time::mark_arrival))
+    };
+    // Solo se cuenta el tráfico del puerto público, incluidos los rechazos de los middlewares
+    let app = with_middlewares(app).layer(middleware::from_fn_with_state(
+        proxy_state.request_metrics.clone(),
+        count_requests,
+    ));
+    // Sustituye el body de los errores generados por el gateway por la página configurada
+    let app = if config.error_pages.is_empty() {
+        app
+    } else {
+        app.layer(middleware::from_fn_with_state(
+            Arc::new(config.error_pages.clone()),
+            error_page_middleware,
+        ))
+    };
+
+    // Listener de administración, con las rutas de admin y el health del gateway
+    let admin_server = match config.admin_port {
+        Some(admin_port) if admin_port == config.port => {
+            return Err(anyhow::anyhow!("ADMIN_PORT must differ from PORT"));
+        }
+        Some(admin_port) => {
+            let admin_addr = format!("{}:{}", config.admin_bind_address, admin_port);
+            let admin_listener = tokio::net::TcpListener::bind(&admin_addr).await?;
+            tracing::info!("Serving admin endpoints on {}", admin_addr);
+            let admin_app = admin_routes
+                .route("/api/v1/health", get(gateway_health))
+                .with_state(proxy_state);
+            let admin_app = with_middlewares(admin_app);
+            Some(axum::serve(
+                admin_listener,
+                admin_app.into_make_service_with_connect_info::<SocketAddr>(),
+            ))
+        }
+        None => None,
+    };
+    let admin_server = async move {
+        match admin_server {
+            Some(server) => server.await,
+            None => std::future::pending().await,
+        }
+    };
 
     // Inicia el servidor
     let addr = format!("0.0.0.0:{}", config.port);
     let listener = tokio::net::TcpListener::bind(&addr).await?;
 
-    axum::serve(listener, app).await?;
+    // Detrás de un balanceador L4 la dirección real del cliente llega en el header PROXY,
+    // y el límite de conexiones por IP y los timeouts de conexión se aplican al aceptarlas:
+    // todos requieren un accept propio
+    let timeouts = ConnectionTimeouts {
+        header_read: Some(config.client_header_timeout_secs)
+            .filter(|secs| *secs > 0)
+            .map(std::time::Duration::from_secs),
+        idle: Some(config.client_idle_timeout_secs)
+            .filter(|secs| *secs > 0)
+            .map(std::time::Duration::from_secs),
+    };
+    let public_server = async move {
+        if config.proxy_protocol || connection_limiter.is_enabled() || timeouts.is_enabled() {
+            if config.proxy_protocol {
+                tracing::info!("Expecting PROXY protocol headers on every connection");
+            }
+            server::serve(listener, app, config.proxy_protocol, connection_limiter, timeouts).await
+        } else {
+            axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await
+        }
+    };
+
+    tokio::try_join!(public_server, admin_server)?;
 
     Ok(())
 }
@@ -0,0 +1,112 @@
+use axum::{
+    extract::Request,
+    http::{Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::sync::Arc;
+
+/// Header con el que clientes limitados a GET/POST indican el método real
+pub const METHOD_OVERRIDE_HEADER: &str = "x-http-method-override";
+
+/// Rewrites a POST to the method in `X-HTTP-Method-Override`, so the backend
+/// sees the real method. Only the `allowed` methods are accepted; any other
+/// value is rejected with `400`. The header is removed before forwarding.
+pub fn apply_method_override(allowed: &[String], req: &mut Request) -> Result<(), StatusCode> {
+    if req.method() != Method::POST {
+        return Ok(());
+    }
+    let Some(value) = req.headers_mut().remove(METHOD_OVERRIDE_HEADER) else {
+        return Ok(());
+    };
+
+    let requested = value.to_str().unwrap_or("").trim().to_uppercase();
+    if !allowed.contains(&requested) {
+        tracing::warn!("Rejected method override to {:?} for {}", requested, req.uri().path());
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let method = Method::from_bytes(requested.as_bytes()).map_err(|_| StatusCode::BAD_REQUEST)?;
+    tracing::debug!("Overriding POST {} as {}", req.uri().path(), method);
+    *req.method_mut() = method;
+    Ok(())
+}
+
+/// Middleware that applies the override (`METHOD_OVERRIDE`, with the methods
+/// in `allowed`) at ingress, so the request guard, the rate limiter and the
+/// handlers all see the real method. `None` leaves requests untouched.
+pub async fn method_override_middleware(allowed: Option<Arc<[String]>>, mut req: Request, next: Next) -> Response {
+    if let Some(allowed) = allowed {
+        if let Err(status) = apply_method_override(&allowed, &mut req) {
+            return (status, "Method override not allowed").into_response();
+        }
+    }
+    next.run(req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+
+    fn allowed() -> Vec<String> {
+        ["PUT", "PATCH", "DELETE"].map(String::from).to_vec()
+    }
+
+    fn with_override(method: Method, value: &str) -> Request {
+        Request::builder()
+            .method(method)
+            .uri("/files/a")
+            .header(METHOD_OVERRIDE_HEADER, value)
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[test]
+    fn method_override_accepts_only_the_allowed_methods() {
+        for (value, method) in [("DELETE", Method::DELETE), (" patch ", Method::PATCH), ("put", Method::PUT)] {
+            let mut req = with_override(Method::POST, value);
+            apply_method_override(&allowed(), &mut req).unwrap();
+            assert_eq!(req.method(), method);
+            assert!(req.headers().get(METHOD_OVERRIDE_HEADER).is_none());
+        }
+
+        for value in ["GET", "TRACE", "", "NOT A METHOD"] {
+            let mut req = with_override(Method::POST, value);
+            assert_eq!(apply_method_override(&allowed(), &mut req).unwrap_err(), StatusCode::BAD_REQUEST, "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn method_override_only_applies_to_post() {
+        let mut req = with_override(Method::GET, "DELETE");
+        apply_method_override(&allowed(), &mut req).unwrap();
+        assert_eq!(req.method(), Method::GET);
+    }
+
+    #[tokio::test]
+    async fn middleware_rewrites_only_when_enabled() {
+        use tower::ServiceExt;
+
+        let app = |allowed: Option<Arc<[String]>>| {
+            axum::Router::new()
+                .fallback(|req: Request| async move { req.method().to_string() })
+                .layer(axum::middleware::from_fn(move |req, next| {
+                    method_override_middleware(allowed.clone(), req, next)
+                }))
+        };
+        let method = |response: Response| async move {
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            String::from_utf8(body.to_vec()).unwrap()
+        };
+
+        let enabled = app(Some(allowed().into()));
+        let response = enabled.clone().oneshot(with_override(Method::POST, "DELETE")).await.unwrap();
+        assert_eq!(method(response).await, "DELETE");
+        let response = enabled.oneshot(with_override(Method::POST, "CONNECT")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let response = app(None).oneshot(with_override(Method::POST, "DELETE")).await.unwrap();
+        assert_eq!(method(response).await, "POST");
+    }
+}
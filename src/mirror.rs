@@ -0,0 +1,109 @@
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, Request},
+    http::{HeaderMap, Method, StatusCode, Uri},
+};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use crate::{
+    backends::join_backend_url,
+    db::Backend,
+    proxy::{forward_request, ProxyState},
+};
+
+/// Solo se duplican métodos idempotentes, sin body
+fn is_mirrorable(method: &Method) -> bool {
+    matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+}
+
+/// Copia de una petición del cliente para reenviarla al backend mirror
+pub struct MirroredRequest {
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+    client: Option<ConnectInfo<SocketAddr>>,
+}
+
+impl MirroredRequest {
+    /// Captures a copy of `req` when a mirror is configured and the request is
+    /// eligible. Requests already routed to the mirror itself are not duplicated.
+    pub fn capture(state: &ProxyState, req: &Request, primary: &Backend) -> Option<Self> {
+        let mirror_id = state.config.mirror_backend.as_deref()?;
+        if !is_mirrorable(req.method()) || primary.server_id == mirror_id {
+            return None;
+        }
+
+        Some(Self {
+            method: req.method().clone(),
+            uri: req.uri().clone(),
+            headers: req.headers().clone(),
+            client: req.extensions().get().copied(),
+        })
+    }
+
+    /// Sends the copy to the mirror in the background, discarding its response.
+    /// Only the status and latency are compared with the primary's in the logs.
+    /// Skipped when the mirror is unavailable or already at its concurrency cap.
+    pub async fn send(self, state: &ProxyState, primary_status: StatusCode, primary_latency: Duration) {
+        let Some(mirror_id) = state.config.mirror_backend.as_deref() else {
+            return;
+        };
+        let Some(mirror) = state.backends.find(mirror_id) else {
+            tracing::debug!("Mirror backend {} not found, skipping", mirror_id);
+            return;
+        };
+        if !state.health_checker.is_backend_healthy(mirror_id).await {
+            tracing::debug!("Mirror backend {} is not healthy, skipping", mirror_id);
+            return;
+        }
+        let Ok(permit) = state.mirror_permits.clone().try_acquire_owned() else {
+            tracing::debug!("Mirror concurrency limit reached, skipping request");
+            return;
+        };
+
+        let state = state.clone();
+        tokio::spawn(async move {
+            let _permit = permit;
+
+            let mut req = Request::new(Body::empty());
+            *req.method_mut() = self.method;
+            *req.headers_mut() = self.headers;
+            if let Some(client) = self.client {
+                req.extensions_mut().insert(client);
+            }
+            let url = join_backend_url(&mirror.server_url, self.uri.path(), self.uri.query());
+
+            let start = Instant::now();
+            let result = forward_request(&state, &mirror, req, &url).await;
+            let latency = start.elapsed();
+
+            // El body se descarta sin leerlo
+            let mirror_status = match result {
+                Ok(response) => response.status(),
+                Err(status) => status,
+            };
+
+            if mirror_status == primary_status {
+                tracing::info!(
+                    "Mirror {} {}: status {} (latency {:?} vs primary {:?})",
+                    mirror.server_id,
+                    self.uri.path(),
+                    mirror_status,
+                    latency,
+                    primary_latency
+                );
+            } else {
+                tracing::warn!(
+                    "Mirror {} {}: status {} differs from primary {} (latency {:?} vs primary {:?})",
+                    mirror.server_id,
+                    self.uri.path(),
+                    mirror_status,
+                    primary_status,
+                    latency,
+                    primary_latency
+                );
+            }
+        });
+    }
+}
@@ -0,0 +1,226 @@
+use hyper_rustls::ConfigBuilderExt;
+use rustls::pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer};
+
+use crate::{config::Config, db::Backend};
+
+/// Certificado de cliente presentado a los backends que exigen mTLS
+/// (`UPSTREAM_CLIENT_CERT` / `UPSTREAM_CLIENT_KEY`)
+pub struct ClientIdentity {
+    certs: Vec<CertificateDer<'static>>,
+    key: PrivateKeyDer<'static>,
+    /// PEM tal cual, para el cliente de los health checks
+    cert_pem: Vec<u8>,
+    key_pem: Vec<u8>,
+    /// Backends (server_id) que lo reciben; vacío = todos
+    backends: Vec<String>,
+}
+
+impl ClientIdentity {
+    /// Loads the client certificate configured in `config`, if any. A missing
+    /// or malformed file is a startup error rather than a failed handshake later.
+    pub fn from_config(config: &Config) -> Result<Option<Self>, anyhow::Error> {
+        let (cert_path, key_path) = match (&config.upstream_client_cert, &config.upstream_client_key) {
+            (Some(cert), Some(key)) => (cert, key),
+            _ => return Ok(None),
+        };
+
+        let cert_pem = std::fs::read(cert_path)
+            .map_err(|e| anyhow::anyhow!("Failed to read UPSTREAM_CLIENT_CERT {}: {}", cert_path, e))?;
+        let key_pem = std::fs::read(key_path)
+            .map_err(|e| anyhow::anyhow!("Failed to read UPSTREAM_CLIENT_KEY {}: {}", key_path, e))?;
+
+        let certs = CertificateDer::pem_slice_iter(&cert_pem)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| anyhow::anyhow!("Invalid certificate in UPSTREAM_CLIENT_CERT {}: {}", cert_path, e))?;
+        if certs.is_empty() {
+            return Err(anyhow::anyhow!("UPSTREAM_CLIENT_CERT {} contains no PEM certificate", cert_path));
+        }
+
+        // El cliente de los health checks (native-tls) solo acepta claves PKCS#8
+        let key = match PrivateKeyDer::from_pem_slice(&key_pem) {
+            Ok(key @ PrivateKeyDer::Pkcs8(_)) => key,
+            Ok(_) => {
+                return Err(anyhow::anyhow!(
+                    "UPSTREAM_CLIENT_KEY {} must be a PKCS#8 key (BEGIN PRIVATE KEY); convert it with `openssl pkcs8 -topk8 -nocrypt`",
+                    key_path
+                ))
+            }
+            Err(e) => return Err(anyhow::anyhow!("Invalid private key in UPSTREAM_CLIENT_KEY {}: {}", key_path, e)),
+        };
+
+        let identity = Self {
+            certs,
+            key,
+            cert_pem,
+            key_pem,
+            backends: config.upstream_mtls_backends.clone(),
+        };
+
+        // Un certificado que no corresponde a la clave se detecta al arrancar
+        identity.tls_config()?;
+        identity.reqwest_identity()?;
+        Ok(Some(identity))
+    }
+
+    /// Whether the certificate is presented to `backend`
+    pub fn applies_to(&self, backend: &Backend) -> bool {
+        self.backends.is_empty() || self.backends.contains(&backend.server_id)
+    }
+
+    /// rustls configuration with the native roots and the client certificate
+    pub fn tls_config(&self) -> Result<rustls::ClientConfig, anyhow::Error> {
+        rustls::ClientConfig::builder()
+            .with_native_roots()
+            .map_err(|e| anyhow::anyhow!("Failed to load native root certificates: {}", e))?
+            .with_client_auth_cert(self.certs.clone(), self.key.clone_key())
+            .map_err(|e| anyhow::anyhow!("Invalid upstream client certificate or key: {}", e))
+    }
+
+    /// The same certificate for the health check client
+    pub fn reqwest_identity(&self) -> Result<reqwest::Identity, anyhow::Error> {
+        reqwest::Identity::from_pkcs8_pem(&self.cert_pem, &self.key_pem)
+            .map_err(|e| anyhow::anyhow!("Invalid upstream client certificate for health checks: {}", e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_backend;
+    use crate::proxy::test_config;
+    use axum::body::Body;
+    use http_body_util::BodyExt;
+    use hyper_util::{client::legacy::Client, rt::TokioExecutor};
+    use rustls::client::WebPkiServerVerifier;
+    use rustls::server::WebPkiClientVerifier;
+    use rustls::RootCertStore;
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+
+    fn fixture(name: &str) -> String {
+        format!("{}/tests/fixtures/mtls/{}", env!("CARGO_MANIFEST_DIR"), name)
+    }
+
+    fn test_ca() -> Arc<RootCertStore> {
+        let mut roots = RootCertStore::empty();
+        for cert in CertificateDer::pem_file_iter(fixture("ca.pem")).unwrap() {
+            roots.add(cert.unwrap()).unwrap();
+        }
+        Arc::new(roots)
+    }
+
+    fn identity_config(cert: &str, key: &str) -> Config {
+        let mut config = test_config();
+        config.upstream_client_cert = Some(fixture(cert));
+        config.upstream_client_key = Some(fixture(key));
+        config
+    }
+
+    /// TLS server that only completes the handshake with a client certificate
+    /// signed by the test CA, and answers every request with 200
+    async fn mtls_server() -> SocketAddr {
+        let verifier = WebPkiClientVerifier::builder(test_ca()).build().unwrap();
+        let certs = CertificateDer::pem_file_iter(fixture("server.pem"))
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let key = PrivateKeyDer::from_pem_file(fixture("server.key")).unwrap();
+        let tls = rustls::ServerConfig::builder()
+            .with_client_cert_verifier(verifier)
+            .with_single_cert(certs, key)
+            .unwrap();
+        let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(tls));
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                let acceptor = acceptor.clone();
+                tokio::spawn(async move {
+                    let Ok(stream) = acceptor.accept(socket).await else {
+                        return;
+                    };
+                    let service = hyper::service::service_fn(|_req| async {
+                        Ok::<_, std::convert::Infallible>(axum::http::Response::new(Body::from("authenticated")))
+                    });
+                    let _ = hyper::server::conn::http1::Builder::new()
+                        .serve_connection(hyper_util::rt::TokioIo::new(stream), service)
+                        .await;
+                });
+            }
+        });
+        addr
+    }
+
+    async fn get(mut tls: rustls::ClientConfig, addr: SocketAddr) -> Result<String, String> {
+        // El servidor de prueba usa una CA propia en lugar de las raíces del sistema
+        tls.dangerous()
+            .set_certificate_verifier(WebPkiServerVerifier::builder(test_ca()).build().unwrap());
+        let https = hyper_rustls::HttpsConnectorBuilder::new()
+            .with_tls_config(tls)
+            .https_only()
+            .enable_http1()
+            .build();
+        let client: Client<_, Body> = Client::builder(TokioExecutor::new()).build(https);
+        let uri = format!("https://localhost:{}/", addr.port());
+        let response = client.get(uri.parse().unwrap()).await.map_err(|e| format!("{:?}", e))?;
+        let body = response.into_body().collect().await.map_err(|e| e.to_string())?.to_bytes();
+        Ok(String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn client_certificate_is_presented_to_the_backend() {
+        let addr = mtls_server().await;
+        let identity = ClientIdentity::from_config(&identity_config("client.pem", "client.key"))
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(get(identity.tls_config().unwrap(), addr).await.unwrap(), "authenticated");
+        assert!(identity.reqwest_identity().is_ok());
+
+        // Sin certificado el servidor corta el handshake
+        let anonymous = rustls::ClientConfig::builder()
+            .with_root_certificates(test_ca())
+            .with_no_client_auth();
+        assert!(get(anonymous, addr).await.is_err());
+    }
+
+    #[test]
+    fn identity_applies_to_the_configured_backends_only() {
+        let identity = ClientIdentity::from_config(&identity_config("client.pem", "client.key"))
+            .unwrap()
+            .unwrap();
+        assert!(identity.applies_to(&test_backend("any")));
+
+        let mut config = identity_config("client.pem", "client.key");
+        config.upstream_mtls_backends = vec!["secure".to_string()];
+        let identity = ClientIdentity::from_config(&config).unwrap().unwrap();
+        assert!(identity.applies_to(&test_backend("secure")));
+        assert!(!identity.applies_to(&test_backend("public")));
+    }
+
+    #[test]
+    fn no_certificate_configured_means_no_identity() {
+        let mut config = test_config();
+        config.upstream_client_cert = None;
+        config.upstream_client_key = None;
+        assert!(ClientIdentity::from_config(&config).unwrap().is_none());
+    }
+
+    #[test]
+    fn unusable_certificates_are_startup_errors() {
+        let error = |cert: &str, key: &str| {
+            ClientIdentity::from_config(&identity_config(cert, key))
+                .err()
+                .unwrap()
+                .to_string()
+        };
+
+        assert!(error("missing.pem", "client.key").contains("Failed to read UPSTREAM_CLIENT_CERT"));
+        assert!(error("client.pem", "missing.key").contains("Failed to read UPSTREAM_CLIENT_KEY"));
+        assert!(error("client.key", "client.key").contains("contains no PEM certificate"));
+        assert!(error("client.pem", "client-rsa.key").contains("must be a PKCS#8 key"));
+        // Un certificado que no corresponde a la clave
+        assert!(error("client.pem", "server.key").contains("Invalid upstream client certificate"));
+    }
+}
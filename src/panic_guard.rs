@@ -0,0 +1,142 @@
+use axum::{
+    extract::Request,
+    http::{HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::any::Any;
+use std::backtrace::Backtrace;
+
+/// Header con el identificador de la petición, aceptado del cliente o generado
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Longitud máxima de un `X-Request-Id` enviado por el cliente
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+tokio::task_local! {
+    /// Identificador de la petición en curso, para el 500 de un handler que falló
+    static REQUEST_ID: String;
+}
+
+/// Middleware that makes the request ID (the client's `X-Request-Id`, or a new
+/// UUID) available to the panic handler of the layers inside it
+pub async fn with_request_id(req: Request, next: Next) -> Response {
+    let request_id = req
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(str::trim)
+        .filter(|id| !id.is_empty() && id.len() <= MAX_REQUEST_ID_LEN)
+        .map(str::to_string)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+
+    REQUEST_ID.scope(request_id, next.run(req)).await
+}
+
+/// Response for a request whose handler or middleware panicked (`CatchPanicLayer`):
+/// a 500 with the request ID, instead of a reset connection
+pub fn panic_response(panic: Box<dyn Any + Send + 'static>) -> Response {
+    let message = panic
+        .downcast_ref::<String>()
+        .map(String::as_str)
+        .or_else(|| panic.downcast_ref::<&str>().copied())
+        .unwrap_or("unknown panic");
+    let request_id = REQUEST_ID.try_with(String::clone).ok();
+
+    tracing::error!(
+        request_id = request_id.as_deref().unwrap_or("-"),
+        "Request handler panicked, responding 500: {}",
+        message
+    );
+
+    let body = serde_json::json!({
+        "error": "internal_error",
+        "message": "The gateway failed to handle the request",
+        "request_id": request_id,
+    });
+    let mut response = (StatusCode::INTERNAL_SERVER_ERROR, axum::Json(body)).into_response();
+    if let Some(value) = request_id.and_then(|id| HeaderValue::from_str(&id).ok()) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
+
+/// Logs every panic through `tracing` with its backtrace, including those of
+/// background tasks, which are not caught and still end their task
+pub fn install_panic_hook() {
+    std::panic::set_hook(Box::new(|info| {
+        // `info` incluye el archivo y la línea
+        tracing::error!(backtrace = %Backtrace::force_capture(), "{}", info);
+    }));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, middleware, routing::get, Router};
+    use tower::ServiceExt;
+    use tower_http::catch_panic::CatchPanicLayer;
+
+    /// Router con las mismas capas que el gateway y rutas que fallan a propósito
+    fn app() -> Router {
+        Router::new()
+            .route("/boom", get(|| async { panic!("deliberate failure") as &str }))
+            .route(
+                "/background",
+                get(|| async {
+                    let task = tokio::spawn(async { panic!("background failure") as () });
+                    // El panic termina la tarea, no la petición que la lanzó
+                    assert!(task.await.unwrap_err().is_panic());
+                    "still here"
+                }),
+            )
+            .route("/ok", get(|| async { "ok" }))
+            .layer(CatchPanicLayer::custom(panic_response))
+            .layer(middleware::from_fn(with_request_id))
+    }
+
+    async fn send(uri: &str, request_id: Option<&str>) -> Response {
+        let mut req = Request::builder().uri(uri);
+        if let Some(id) = request_id {
+            req = req.header(REQUEST_ID_HEADER, id);
+        }
+        app().oneshot(req.body(Body::empty()).unwrap()).await.unwrap()
+    }
+
+    async fn json_body(response: Response) -> serde_json::Value {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    #[tokio::test]
+    async fn handler_panic_becomes_a_500_with_the_request_id() {
+        let response = send("/boom", Some("req-42")).await;
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(response.headers()[REQUEST_ID_HEADER], "req-42");
+        let body = json_body(response).await;
+        assert_eq!(body["error"], "internal_error");
+        assert_eq!(body["request_id"], "req-42");
+        // El mensaje del panic solo va al log
+        assert!(!body.to_string().contains("deliberate"));
+    }
+
+    #[tokio::test]
+    async fn missing_or_oversized_request_ids_are_replaced() {
+        for request_id in [None, Some("   "), Some(&*"x".repeat(MAX_REQUEST_ID_LEN + 1))] {
+            let response = send("/boom", request_id).await;
+            assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+            let header = response.headers()[REQUEST_ID_HEADER].to_str().unwrap().to_string();
+            assert!(uuid::Uuid::parse_str(&header).is_ok(), "{}", header);
+            assert_eq!(json_body(response).await["request_id"], header);
+        }
+    }
+
+    #[tokio::test]
+    async fn other_requests_and_background_tasks_are_unaffected() {
+        let response = send("/ok", None).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers().get(REQUEST_ID_HEADER).is_none());
+
+        assert_eq!(send("/background", None).await.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,250 @@
+use std::future::Future;
+use std::time::Duration;
+
+use crate::{
+    backends::{filter_valid_backends, validate_backend_url},
+    cache,
+    config::{redact_url, Config},
+    db,
+    discovery::create_backend_source,
+    health::HealthChecker,
+    mtls::ClientIdentity,
+};
+
+/// Tiempo máximo para conectar con cada dependencia
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Whether the gateway runs the deployment check (`--check` or
+/// `GATEWAY_MODE=check`) instead of serving
+pub fn check_requested() -> bool {
+    std::env::args().skip(1).any(|arg| arg == "--check")
+        || std::env::var("GATEWAY_MODE").is_ok_and(|mode| mode.trim().eq_ignore_ascii_case("check"))
+}
+
+/// Report lines of the deployment check, printed to stdout
+#[derive(Default)]
+struct Report {
+    failed: bool,
+}
+
+impl Report {
+    fn ok(&self, name: &str, detail: impl std::fmt::Display) {
+        println!("[ OK ] {:<16} {}", name, detail);
+    }
+
+    fn warn(&self, name: &str, detail: impl std::fmt::Display) {
+        println!("[WARN] {:<16} {}", name, detail);
+    }
+
+    fn fail(&mut self, name: &str, detail: impl std::fmt::Display) {
+        println!("[FAIL] {:<16} {}", name, detail);
+        self.failed = true;
+    }
+}
+
+async fn connect<T, E: std::fmt::Display>(future: impl Future<Output = Result<T, E>>) -> Result<T, String> {
+    match tokio::time::timeout(CONNECT_TIMEOUT, future).await {
+        Ok(result) => result.map_err(|e| e.to_string()),
+        Err(_) => Err(format!("timed out after {}s", CONNECT_TIMEOUT.as_secs())),
+    }
+}
+
+/// Validates a deployment without serving traffic: connects to PostgreSQL and
+/// Redis, loads the backends and probes each of them once. Returns whether
+/// every critical dependency is available; unhealthy backends are only fatal
+/// when none is healthy.
+pub async fn run_check(config: &Config) -> bool {
+    let mut report = Report::default();
+    println!("VK Gateway deployment check");
+
+    let db_pool = match config.database_url {
+        Some(ref database_url) => match connect(db::create_pool(database_url)).await {
+            Ok(pool) => {
+                report.ok("postgres", redact_url(database_url));
+                Some(pool)
+            }
+            Err(e) => {
+                report.fail("postgres", e);
+                None
+            }
+        },
+        None => {
+            report.warn("postgres", "DATABASE_URL not set, file routing disabled");
+            None
+        }
+    };
+
+    let redis = connect(cache::create_redis_client(&config.redis_url)).await;
+    let ping = match redis {
+        Ok(redis) => {
+            let ping = redis.run(|mut conn| async move { redis::cmd("PING").query_async::<_, String>(&mut conn).await });
+            connect(ping).await.map(|_| ())
+        }
+        Err(e) => Err(e),
+    };
+    match ping {
+        Ok(()) => report.ok("redis", redact_url(&config.redis_url)),
+        Err(e) => report.fail("redis", e),
+    }
+
+    let backends = match create_backend_source(config, db_pool.as_ref()) {
+        Ok(source) => match source.list_backends().await {
+            Ok(backends) => {
+                report.ok("backend source", format!("{} backends from {}", backends.len(), source.name()));
+                for backend in &backends {
+                    if let Err(reason) = validate_backend_url(&backend.server_url, &config.backend_host_allowlist) {
+                        report.warn(&format!("backend {}", backend.server_id), format!("excluded: {}", reason));
+                    }
+                }
+                filter_valid_backends(backends, &config.backend_host_allowlist)
+            }
+            Err(e) => {
+                report.fail("backend source", format!("{}: {}", source.name(), e));
+                Vec::new()
+            }
+        },
+        Err(e) => {
+            report.fail("backend source", e);
+            Vec::new()
+        }
+    };
+
+    let mut health_checker = HealthChecker::new(config.vk_secret.clone(), config.health_check.clone());
+    match ClientIdentity::from_config(config) {
+        Ok(Some(identity)) => health_checker = health_checker.with_client_identity(std::sync::Arc::new(identity)),
+        Ok(None) => {}
+        Err(e) => report.fail("client cert", e),
+    }
+
+    let probes = backends.iter().map(|backend| health_checker.check_backend(backend));
+    let results = futures::future::join_all(probes).await;
+    for (backend, healthy) in backends.iter().zip(&results) {
+        let name = format!("backend {}", backend.server_id);
+        let detail = format!("{} ({})", redact_url(&backend.server_url), backend.provider);
+        if *healthy {
+            report.ok(&name, detail);
+        } else {
+            report.warn(&name, format!("{} failed its health check", detail));
+        }
+    }
+    let healthy = results.iter().filter(|healthy| **healthy).count();
+    match (backends.len(), healthy) {
+        (0, _) => report.fail("backends", "no backends configured"),
+        (_, 0) => report.fail("backends", "no backend passed its health check"),
+        (total, healthy) => report.ok("backends", format!("{}/{} healthy", healthy, total)),
+    }
+
+    println!("{}", if report.failed { "Deployment check FAILED" } else { "Deployment check passed" });
+    !report.failed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fake_redis::FakeRedis;
+    use crate::proxy::test_config;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// Health endpoint que responde `status` y cuenta los sondeos recibidos
+    async fn health_endpoint(status: u16) -> (String, Arc<AtomicUsize>) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let probes = Arc::new(AtomicUsize::new(0));
+        let received = probes.clone();
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                let received = received.clone();
+                let service = hyper::service::service_fn(move |_req: hyper::Request<hyper::body::Incoming>| {
+                    received.fetch_add(1, Ordering::SeqCst);
+                    async move {
+                        Ok::<_, std::convert::Infallible>(
+                            axum::http::Response::builder()
+                                .status(status)
+                                .body(axum::body::Body::empty())
+                                .unwrap(),
+                        )
+                    }
+                });
+                tokio::spawn(
+                    hyper::server::conn::http1::Builder::new()
+                        .serve_connection(hyper_util::rt::TokioIo::new(socket), service),
+                );
+            }
+        });
+        (format!("http://{}", addr), probes)
+    }
+
+    /// Configuración sin base de datos, con `BACKENDS` como lista `server_id=url`
+    fn check_config(redis_url: &str, backends: &[(&str, &str)]) -> Config {
+        let mut config = test_config();
+        config.database_url = None;
+        config.redis_url = redis_url.to_string();
+        config.backend_source = "env".to_string();
+        config.backend_host_allowlist = vec!["127.0.0.1".to_string()];
+        let spec: Vec<String> = backends.iter().map(|(id, url)| format!("{}={}", id, url)).collect();
+        config.backends_env = Some(spec.join(","));
+        config
+    }
+
+    #[tokio::test]
+    async fn passes_when_dependencies_are_up_and_a_backend_is_healthy() {
+        let redis = FakeRedis::start().await;
+        let (up, up_probes) = health_endpoint(200).await;
+        let (down, down_probes) = health_endpoint(503).await;
+
+        assert!(run_check(&check_config(&redis.url(), &[("up", &up), ("down", &down)])).await);
+        assert_eq!(up_probes.load(Ordering::SeqCst), 1);
+        assert_eq!(down_probes.load(Ordering::SeqCst), 1);
+        assert!(redis.command_names().contains(&"PING".to_string()));
+    }
+
+    #[tokio::test]
+    async fn fails_when_no_backend_is_healthy() {
+        let redis = FakeRedis::start().await;
+        let (down, _) = health_endpoint(503).await;
+
+        assert!(!run_check(&check_config(&redis.url(), &[("down", &down)])).await);
+        assert!(!run_check(&check_config(&redis.url(), &[("gone", "http://127.0.0.1:1")])).await);
+    }
+
+    #[tokio::test]
+    async fn fails_when_redis_is_unreachable() {
+        let (up, _) = health_endpoint(200).await;
+        assert!(!run_check(&check_config("redis://127.0.0.1:1", &[("up", &up)])).await);
+    }
+
+    #[tokio::test]
+    async fn excluded_backends_are_not_probed() {
+        let redis = FakeRedis::start().await;
+        let (up, probes) = health_endpoint(200).await;
+        let mut config = check_config(&redis.url(), &[("up", &up)]);
+        config.backend_host_allowlist.clear();
+
+        assert!(!run_check(&config).await);
+        assert_eq!(probes.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn fails_without_a_usable_backend_source() {
+        let redis = FakeRedis::start().await;
+        let mut config = check_config(&redis.url(), &[]);
+        config.backends_env = None;
+        assert!(!run_check(&config).await);
+
+        // Sin DATABASE_URL la fuente Postgres no puede cargarse
+        config.backend_source = "postgres".to_string();
+        assert!(!run_check(&config).await);
+    }
+
+    #[test]
+    fn check_mode_is_selected_by_gateway_mode() {
+        let _env = crate::config::TEST_ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        assert!(!check_requested());
+        for (mode, requested) in [(" Check ", true), ("serve", false)] {
+            std::env::set_var("GATEWAY_MODE", mode);
+            assert_eq!(check_requested(), requested, "{}", mode);
+        }
+        std::env::remove_var("GATEWAY_MODE");
+    }
+}
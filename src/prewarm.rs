@@ -0,0 +1,236 @@
+use axum::{
+    body::Body,
+    http::{header, HeaderValue, Method, Request},
+};
+use http_body_util::BodyExt;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::{
+    backends::{join_backend_url, split_url_credentials},
+    db::Backend,
+    health::HealthEvent,
+    proxy::ProxyState,
+};
+
+/// Conexiones precalentadas por backend como máximo
+pub const MAX_PREWARM_CONNECTIONS: usize = 32;
+
+/// Tiempo máximo de cada petición de precalentamiento
+const PREWARM_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Opens `PREWARM_CONNECTIONS` idle connections to every backend whose health
+/// check succeeds (first probe or recovery), so its first real requests reuse
+/// them instead of paying for the TCP and TLS handshakes. `receiver` must be
+/// subscribed before the health checks start, not to miss the first probes.
+pub fn start_connection_prewarm(state: ProxyState, mut receiver: broadcast::Receiver<HealthEvent>) {
+    tokio::spawn(async move {
+        loop {
+            match receiver.recv().await {
+                Ok(event) if event.state == "healthy" && event.previous_state != Some("healthy") => {
+                    let Some(backend) = state.backends.find(&event.server_id) else {
+                        continue;
+                    };
+                    let state = state.clone();
+                    tokio::spawn(async move { prewarm_backend(&state, &backend).await });
+                }
+                Ok(_) => {}
+                Err(RecvError::Lagged(skipped)) => {
+                    tracing::warn!("Connection prewarm missed {} health events", skipped);
+                }
+                Err(RecvError::Closed) => return,
+            }
+        }
+    });
+}
+
+/// Sends concurrent requests to the backend's health endpoint, one per
+/// connection to open. Once their bodies are read, the connections stay idle
+/// in the client pool until they expire.
+async fn prewarm_backend(state: &ProxyState, backend: &Backend) {
+    let connections = state.config.prewarm_connections;
+    let started = Instant::now();
+
+    let warmed = futures::future::join_all((0..connections).map(|_| warm_connection(state, backend)))
+        .await
+        .into_iter()
+        .filter(|ok| *ok)
+        .count();
+
+    tracing::info!(
+        "Pre-established {}/{} connections to backend {} in {}ms",
+        warmed,
+        connections,
+        backend.server_id,
+        started.elapsed().as_millis()
+    );
+}
+
+async fn warm_connection(state: &ProxyState, backend: &Backend) -> bool {
+    let (url, authorization) = split_url_credentials(&join_backend_url(&backend.server_url, "/api/v1/health", None));
+    let mut builder = Request::builder().method(Method::GET).uri(url);
+    if let Some(authorization) = authorization {
+        builder = builder.header(header::AUTHORIZATION, authorization);
+    }
+    if let Some(secret) = state.config.vk_secret.as_deref().and_then(|s| HeaderValue::from_str(s).ok()) {
+        builder = builder.header("X-KV-SECRET", secret);
+    }
+    let Ok(request) = builder.body(Body::empty()) else {
+        return false;
+    };
+
+    let client = state.client_for(backend);
+    let warm = async {
+        let response = client.request(request).await.ok()?;
+        // La conexión vuelve al pool cuando el body termina
+        response.into_body().collect().await.ok()
+    };
+    match tokio::time::timeout(PREWARM_TIMEOUT, warm).await {
+        Ok(Some(_)) => true,
+        _ => {
+            tracing::debug!("Failed to pre-establish a connection to backend {}", backend.server_id);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_backend;
+    use crate::proxy::{forward_request, test_config, test_state};
+    use axum::http::HeaderMap;
+    use std::convert::Infallible;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    /// Backend que cuenta las conexiones aceptadas y guarda la ruta y los
+    /// headers de cada petición
+    struct CountingBackend {
+        backend: Backend,
+        connections: Arc<AtomicUsize>,
+        requests: Arc<Mutex<Vec<(String, HeaderMap)>>>,
+    }
+
+    async fn counting_backend(server_id: &str) -> CountingBackend {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let connections = Arc::new(AtomicUsize::new(0));
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let (accepted, received) = (connections.clone(), requests.clone());
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                accepted.fetch_add(1, Ordering::SeqCst);
+                let received = received.clone();
+                let service = hyper::service::service_fn(move |req: hyper::Request<hyper::body::Incoming>| {
+                    received.lock().unwrap().push((req.uri().path().to_string(), req.headers().clone()));
+                    async move {
+                        // Las peticiones simultáneas no pueden compartir conexión
+                        tokio::time::sleep(Duration::from_millis(50)).await;
+                        Ok::<_, Infallible>(axum::http::Response::new(Body::from("ok")))
+                    }
+                });
+                tokio::spawn(
+                    hyper::server::conn::http1::Builder::new()
+                        .serve_connection(hyper_util::rt::TokioIo::new(socket), service),
+                );
+            }
+        });
+        CountingBackend {
+            backend: Backend {
+                server_url: format!("http://{}", addr),
+                ..test_backend(server_id)
+            },
+            connections,
+            requests,
+        }
+    }
+
+    async fn prewarm_state(connections: usize, backends: &[Backend]) -> ProxyState {
+        let mut config = test_config();
+        config.prewarm_connections = connections;
+        let mut state = test_state(config, backends).await;
+        let mut config = (*state.config).clone();
+        config.vk_secret = Some("s3cret".to_string());
+        state.config = Arc::new(config);
+        state
+    }
+
+    fn event(server_id: &str, previous_state: Option<&'static str>, state: &'static str) -> HealthEvent {
+        HealthEvent {
+            server_id: server_id.to_string(),
+            previous_state,
+            state,
+            consecutive_failures: 0,
+            timestamp: 0,
+        }
+    }
+
+    /// Espera hasta un segundo a que `counter` llegue a `expected`
+    async fn wait_for(counter: &AtomicUsize, expected: usize) {
+        for _ in 0..100 {
+            if counter.load(Ordering::SeqCst) >= expected {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn first_requests_reuse_the_prewarmed_connections() {
+        let mock = counting_backend("warm").await;
+        let state = prewarm_state(3, std::slice::from_ref(&mock.backend)).await;
+
+        prewarm_backend(&state, &mock.backend).await;
+        assert_eq!(mock.connections.load(Ordering::SeqCst), 3);
+        {
+            let requests = mock.requests.lock().unwrap();
+            assert_eq!(requests.len(), 3);
+            assert!(requests.iter().all(|(path, _)| path == "/api/v1/health"));
+            assert!(requests.iter().all(|(_, headers)| headers["x-kv-secret"] == "s3cret"));
+        }
+
+        let url = format!("{}/report", mock.backend.server_url);
+        for _ in 0..3 {
+            let req = Request::builder().uri("/report").body(Body::empty()).unwrap();
+            let response = forward_request(&state, &mock.backend, req, &url).await.unwrap();
+            response.into_body().collect().await.unwrap();
+        }
+        assert_eq!(mock.connections.load(Ordering::SeqCst), 3, "requests opened new connections");
+    }
+
+    #[tokio::test]
+    async fn only_backends_becoming_healthy_are_prewarmed() {
+        let first = counting_backend("first").await;
+        let recovered = counting_backend("recovered").await;
+        let steady = counting_backend("steady").await;
+        let backends = [first.backend.clone(), recovered.backend.clone(), steady.backend.clone()];
+        let state = prewarm_state(2, &backends).await;
+        let (sender, receiver) = broadcast::channel(16);
+        start_connection_prewarm(state, receiver);
+
+        sender.send(event("first", None, "healthy")).unwrap();
+        sender.send(event("recovered", Some("unhealthy"), "healthy")).unwrap();
+        sender.send(event("steady", Some("healthy"), "healthy")).unwrap();
+        sender.send(event("steady", Some("healthy"), "unhealthy")).unwrap();
+        sender.send(event("unknown", None, "healthy")).unwrap();
+
+        wait_for(&first.connections, 2).await;
+        wait_for(&recovered.connections, 2).await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(first.connections.load(Ordering::SeqCst), 2);
+        assert_eq!(recovered.connections.load(Ordering::SeqCst), 2);
+        assert_eq!(steady.connections.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn unreachable_backends_are_skipped() {
+        let backend = Backend {
+            server_url: "http://127.0.0.1:1".to_string(),
+            ..test_backend("down")
+        };
+        let state = prewarm_state(2, std::slice::from_ref(&backend)).await;
+        assert!(!warm_connection(&state, &backend).await);
+        prewarm_backend(&state, &backend).await;
+    }
+}
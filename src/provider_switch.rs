@@ -0,0 +1,70 @@
+use std::collections::BTreeSet;
+use std::sync::RwLock;
+
+use crate::db::Backend;
+
+/// Providers deshabilitados por un operador: sus backends dejan de recibir tráfico
+/// balanceado y las peticiones de archivos que les pertenecen se rechazan
+#[derive(Debug, Default)]
+pub struct ProviderSwitch {
+    /// Nombres en minúsculas; el provider se compara sin distinguir mayúsculas
+    disabled: RwLock<BTreeSet<String>>,
+}
+
+impl ProviderSwitch {
+    /// Disables `provider`. Returns false if it was already disabled.
+    pub fn disable(&self, provider: &str) -> bool {
+        self.disabled.write().unwrap().insert(provider.to_lowercase())
+    }
+
+    /// Re-enables `provider`. Returns false if it was not disabled.
+    pub fn enable(&self, provider: &str) -> bool {
+        self.disabled.write().unwrap().remove(&provider.to_lowercase())
+    }
+
+    pub fn is_disabled(&self, provider: &str) -> bool {
+        let disabled = self.disabled.read().unwrap();
+        !disabled.is_empty() && disabled.contains(&provider.to_lowercase())
+    }
+
+    /// Whether `backend` belongs to a disabled provider
+    pub fn excludes(&self, backend: &Backend) -> bool {
+        self.is_disabled(&backend.provider)
+    }
+
+    /// Disabled providers, sorted, for the stats endpoint
+    pub fn snapshot(&self) -> Vec<String> {
+        self.disabled.read().unwrap().iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_backend;
+
+    #[test]
+    fn disabling_a_provider_excludes_its_backends() {
+        let switch = ProviderSwitch::default();
+        let backend = test_backend("b1");
+
+        assert!(!switch.excludes(&backend));
+        assert!(switch.disable("Test"));
+        assert!(!switch.disable("TEST"), "already disabled");
+        assert!(switch.excludes(&backend));
+        assert!(!switch.is_disabled("supabase"));
+        assert_eq!(switch.snapshot(), vec!["test".to_string()]);
+    }
+
+    #[test]
+    fn enabling_a_provider_restores_its_backends() {
+        let switch = ProviderSwitch::default();
+        let backend = test_backend("b1");
+        switch.disable("test");
+
+        assert!(switch.enable("TEST"));
+        assert!(!switch.enable("test"), "not disabled");
+        assert!(!switch.excludes(&backend));
+        assert!(switch.snapshot().is_empty());
+    }
+}
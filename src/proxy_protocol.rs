@@ -0,0 +1,240 @@
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use tokio::io::AsyncReadExt;
+use tokio::net::TcpStream;
+
+/// Longitud máxima de un header v1, incluido el CRLF final
+const V1_MAX_LENGTH: usize = 107;
+
+/// Firma con la que empieza un header v2
+const V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Parses a v1 (text) header line without its CRLF, e.g.
+/// `PROXY TCP4 203.0.113.7 10.0.0.1 51234 443`.
+/// Returns `None` for `PROXY UNKNOWN`, sent for connections the load balancer
+/// cannot describe (its own health checks).
+pub fn parse_v1(line: &str) -> io::Result<Option<SocketAddr>> {
+    let mut parts = line.split(' ');
+    if parts.next() != Some("PROXY") {
+        return Err(invalid("missing PROXY prefix"));
+    }
+
+    match parts.next() {
+        Some("TCP4") | Some("TCP6") => {}
+        Some("UNKNOWN") => return Ok(None),
+        _ => return Err(invalid("unsupported v1 protocol")),
+    }
+
+    let (Some(src_ip), Some(_dst_ip), Some(src_port), Some(_dst_port), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err(invalid("malformed v1 header"));
+    };
+
+    let ip: IpAddr = src_ip.parse().map_err(|_| invalid("invalid v1 source address"))?;
+    let port: u16 = src_port.parse().map_err(|_| invalid("invalid v1 source port"))?;
+    Ok(Some(SocketAddr::new(ip, port)))
+}
+
+/// Parses a v2 (binary) header: the 16-byte fixed part and its address block.
+/// Returns `None` for the LOCAL command and for non-IP address families.
+pub fn parse_v2(header: &[u8; 16], addresses: &[u8]) -> io::Result<Option<SocketAddr>> {
+    if header[..12] != V2_SIGNATURE {
+        return Err(invalid("invalid v2 signature"));
+    }
+    if header[12] >> 4 != 2 {
+        return Err(invalid("unsupported v2 version"));
+    }
+
+    match header[12] & 0x0f {
+        0 => return Ok(None),
+        1 => {}
+        _ => return Err(invalid("unsupported v2 command")),
+    }
+
+    // Familia en el nibble alto (1 = IPv4, 2 = IPv6), transporte en el bajo
+    match header[13] >> 4 {
+        1 if addresses.len() >= 12 => {
+            let ip = Ipv4Addr::new(addresses[0], addresses[1], addresses[2], addresses[3]);
+            let port = u16::from_be_bytes([addresses[8], addresses[9]]);
+            Ok(Some(SocketAddr::new(ip.into(), port)))
+        }
+        2 if addresses.len() >= 36 => {
+            let mut octets = [0u8; 16];
+            octets.copy_from_slice(&addresses[..16]);
+            let port = u16::from_be_bytes([addresses[32], addresses[33]]);
+            Ok(Some(SocketAddr::new(Ipv6Addr::from(octets).into(), port)))
+        }
+        1 | 2 => Err(invalid("truncated v2 address block")),
+        _ => Ok(None),
+    }
+}
+
+/// Reads the PROXY header (v1 or v2) at the start of a connection, consuming
+/// exactly its bytes so the HTTP request that follows is left untouched
+pub async fn read_header(stream: &mut TcpStream) -> io::Result<Option<SocketAddr>> {
+    let mut start = [0u8; 5];
+    stream.read_exact(&mut start).await?;
+
+    if &start == b"PROXY" {
+        // v1 no indica su longitud: se lee byte a byte hasta el CRLF
+        let mut line = start.to_vec();
+        while !line.ends_with(b"\r\n") {
+            if line.len() >= V1_MAX_LENGTH {
+                return Err(invalid("v1 header too long"));
+            }
+            line.push(stream.read_u8().await?);
+        }
+        let line = std::str::from_utf8(&line[..line.len() - 2]).map_err(|_| invalid("v1 header is not ASCII"))?;
+        return parse_v1(line);
+    }
+
+    if start[..] != V2_SIGNATURE[..5] {
+        return Err(invalid("connection did not start with a PROXY header"));
+    }
+
+    let mut header = [0u8; 16];
+    header[..5].copy_from_slice(&start);
+    stream.read_exact(&mut header[5..]).await?;
+
+    let length = u16::from_be_bytes([header[14], header[15]]) as usize;
+    let mut addresses = vec![0u8; length];
+    stream.read_exact(&mut addresses).await?;
+
+    parse_v2(&header, &addresses)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::TcpListener;
+
+    fn v2_header(command: u8, family: u8, length: u16) -> [u8; 16] {
+        let mut header = [0u8; 16];
+        header[..12].copy_from_slice(&V2_SIGNATURE);
+        header[12] = 0x20 | command;
+        header[13] = family << 4 | 0x1;
+        header[14..].copy_from_slice(&length.to_be_bytes());
+        header
+    }
+
+    #[test]
+    fn parses_v1_tcp4_and_tcp6() {
+        assert_eq!(
+            parse_v1("PROXY TCP4 203.0.113.7 10.0.0.1 51234 443").unwrap(),
+            Some("203.0.113.7:51234".parse().unwrap())
+        );
+        assert_eq!(
+            parse_v1("PROXY TCP6 2001:db8::7 2001:db8::1 51234 443").unwrap(),
+            Some("[2001:db8::7]:51234".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn v1_unknown_has_no_address() {
+        assert_eq!(parse_v1("PROXY UNKNOWN").unwrap(), None);
+    }
+
+    #[test]
+    fn rejects_malformed_v1() {
+        assert!(parse_v1("GET / HTTP/1.1").is_err());
+        assert!(parse_v1("PROXY UDP4 203.0.113.7 10.0.0.1 51234 443").is_err());
+        assert!(parse_v1("PROXY TCP4 203.0.113.7 10.0.0.1 51234").is_err());
+        assert!(parse_v1("PROXY TCP4 203.0.113.7 10.0.0.1 51234 443 extra").is_err());
+        assert!(parse_v1("PROXY TCP4 not-an-ip 10.0.0.1 51234 443").is_err());
+        assert!(parse_v1("PROXY TCP4 203.0.113.7 10.0.0.1 99999 443").is_err());
+    }
+
+    #[test]
+    fn parses_v2_ipv4() {
+        let addresses = [203, 0, 113, 7, 10, 0, 0, 1, 0xc8, 0x22, 0x01, 0xbb];
+        assert_eq!(
+            parse_v2(&v2_header(1, 1, 12), &addresses).unwrap(),
+            Some("203.0.113.7:51234".parse().unwrap())
+        );
+    }
+
+    #[test]
+    fn parses_v2_ipv6() {
+        let source: Ipv6Addr = "2001:db8::7".parse().unwrap();
+        let mut addresses = Vec::new();
+        addresses.extend_from_slice(&source.octets());
+        addresses.extend_from_slice(&Ipv6Addr::LOCALHOST.octets());
+        addresses.extend_from_slice(&51234u16.to_be_bytes());
+        addresses.extend_from_slice(&443u16.to_be_bytes());
+
+        assert_eq!(
+            parse_v2(&v2_header(1, 2, 36), &addresses).unwrap(),
+            Some(SocketAddr::new(source.into(), 51234))
+        );
+    }
+
+    #[test]
+    fn v2_local_and_unix_have_no_address() {
+        assert_eq!(parse_v2(&v2_header(0, 0, 0), &[]).unwrap(), None);
+        assert_eq!(parse_v2(&v2_header(1, 3, 216), &[0u8; 216]).unwrap(), None);
+    }
+
+    #[test]
+    fn rejects_invalid_v2() {
+        let mut bad_signature = v2_header(1, 1, 12);
+        bad_signature[0] = b'X';
+        assert!(parse_v2(&bad_signature, &[0u8; 12]).is_err());
+
+        let mut bad_version = v2_header(1, 1, 12);
+        bad_version[12] = 0x11;
+        assert!(parse_v2(&bad_version, &[0u8; 12]).is_err());
+
+        assert!(parse_v2(&v2_header(2, 1, 12), &[0u8; 12]).is_err());
+        assert!(parse_v2(&v2_header(1, 1, 8), &[0u8; 8]).is_err());
+    }
+
+    /// Sends `bytes` over a real connection and reads the header on the other end,
+    /// returning the parsed address and what is left in the stream
+    async fn read_from_connection(bytes: Vec<u8>) -> (io::Result<Option<SocketAddr>>, Vec<u8>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let client = tokio::spawn(async move {
+            let mut stream = TcpStream::connect(address).await.unwrap();
+            stream.write_all(&bytes).await.unwrap();
+        });
+
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let result = read_header(&mut stream).await;
+        client.await.unwrap();
+        let mut rest = Vec::new();
+        stream.read_to_end(&mut rest).await.unwrap();
+        (result, rest)
+    }
+
+    #[tokio::test]
+    async fn read_header_consumes_only_the_v1_header() {
+        let (address, rest) =
+            read_from_connection(b"PROXY TCP4 203.0.113.7 10.0.0.1 51234 443\r\nGET / HTTP/1.1\r\n\r\n".to_vec()).await;
+
+        assert_eq!(address.unwrap(), Some("203.0.113.7:51234".parse().unwrap()));
+        assert_eq!(rest, b"GET / HTTP/1.1\r\n\r\n");
+    }
+
+    #[tokio::test]
+    async fn read_header_consumes_only_the_v2_header() {
+        let mut bytes = v2_header(1, 1, 12).to_vec();
+        bytes.extend_from_slice(&[203, 0, 113, 7, 10, 0, 0, 1, 0xc8, 0x22, 0x01, 0xbb]);
+        bytes.extend_from_slice(b"GET / HTTP/1.1\r\n\r\n");
+        let (address, rest) = read_from_connection(bytes).await;
+
+        assert_eq!(address.unwrap(), Some("203.0.113.7:51234".parse().unwrap()));
+        assert_eq!(rest, b"GET / HTTP/1.1\r\n\r\n");
+    }
+
+    #[tokio::test]
+    async fn read_header_rejects_connections_without_header() {
+        let (address, _) = read_from_connection(b"GET / HTTP/1.1\r\n\r\n".to_vec()).await;
+        assert!(address.is_err());
+    }
+}
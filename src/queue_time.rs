@@ -0,0 +1,135 @@
+use axum::{extract::Request, middleware::Next, response::Response};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Instante en que la petición llegó al gateway
+#[derive(Debug, Clone, Copy)]
+pub struct ArrivedAt(pub Instant);
+
+/// Middleware that stamps every request with its arrival time. Installed as
+/// the outermost layer so the queue time covers every other middleware.
+pub async fn mark_arrival(mut req: Request, next: Next) -> Response {
+    req.extensions_mut().insert(ArrivedAt(Instant::now()));
+    next.run(req).await
+}
+
+/// Tiempo que pasan las peticiones en el gateway antes de reenviarse
+#[derive(Debug, Default)]
+pub struct QueueMetrics {
+    pub forwarded: AtomicU64,
+    pub total_ms: AtomicU64,
+    pub max_ms: AtomicU64,
+    /// Peticiones descartadas por superar `MAX_QUEUE_TIME_MS`
+    pub shed: AtomicU64,
+}
+
+impl QueueMetrics {
+    fn record(&self, queued: Duration) {
+        let ms = queued.as_millis() as u64;
+        self.forwarded.fetch_add(1, Ordering::Relaxed);
+        self.total_ms.fetch_add(ms, Ordering::Relaxed);
+        self.max_ms.fetch_max(ms, Ordering::Relaxed);
+    }
+
+    /// Current counter values for the stats endpoint
+    pub fn snapshot(&self) -> serde_json::Value {
+        let forwarded = self.forwarded.load(Ordering::Relaxed);
+        let total_ms = self.total_ms.load(Ordering::Relaxed);
+        serde_json::json!({
+            "forwarded": forwarded,
+            "avg_ms": total_ms.checked_div(forwarded).unwrap_or(0),
+            "max_ms": self.max_ms.load(Ordering::Relaxed),
+            "shed": self.shed.load(Ordering::Relaxed),
+        })
+    }
+}
+
+/// Records how long a request waited before being forwarded and decides
+/// whether it is still worth sending. Only the first forward of a client
+/// request is measured: the arrival stamp is consumed here, so retries and
+/// mirrored copies pass through. Returns `false` when the request should be shed.
+pub fn admit(req: &mut Request, metrics: &QueueMetrics, max_queue_time_ms: u64) -> bool {
+    let Some(ArrivedAt(arrived)) = req.extensions_mut().remove::<ArrivedAt>() else {
+        return true;
+    };
+    let queued = arrived.elapsed();
+
+    if max_queue_time_ms > 0 && queued > Duration::from_millis(max_queue_time_ms) {
+        metrics.shed.fetch_add(1, Ordering::Relaxed);
+        tracing::warn!(
+            "Shedding {} {} after {:?} in the gateway (MAX_QUEUE_TIME_MS={})",
+            req.method(),
+            req.uri().path(),
+            queued,
+            max_queue_time_ms
+        );
+        return false;
+    }
+
+    metrics.record(queued);
+    tracing::debug!("{} {} queued for {:?} before forwarding", req.method(), req.uri().path(), queued);
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+
+    /// Request that arrived at the gateway `age` ago
+    fn arrived(age: Duration) -> Request {
+        let mut req = Request::builder().uri("/files").body(Body::empty()).unwrap();
+        req.extensions_mut().insert(ArrivedAt(Instant::now() - age));
+        req
+    }
+
+    #[test]
+    fn requests_queued_too_long_are_shed() {
+        let metrics = QueueMetrics::default();
+
+        assert!(!admit(&mut arrived(Duration::from_millis(500)), &metrics, 100));
+        assert_eq!(metrics.shed.load(Ordering::Relaxed), 1);
+        assert_eq!(metrics.forwarded.load(Ordering::Relaxed), 0);
+
+        assert!(admit(&mut arrived(Duration::from_millis(20)), &metrics, 100));
+        assert_eq!(metrics.shed.load(Ordering::Relaxed), 1);
+        assert_eq!(metrics.forwarded.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn zero_limit_never_sheds() {
+        let metrics = QueueMetrics::default();
+
+        assert!(admit(&mut arrived(Duration::from_secs(60)), &metrics, 0));
+        assert_eq!(metrics.shed.load(Ordering::Relaxed), 0);
+        assert!(metrics.max_ms.load(Ordering::Relaxed) >= 60_000);
+    }
+
+    #[test]
+    fn retries_pass_once_the_stamp_is_consumed() {
+        let metrics = QueueMetrics::default();
+        let mut req = arrived(Duration::from_millis(20));
+
+        assert!(admit(&mut req, &metrics, 100));
+        assert!(req.extensions().get::<ArrivedAt>().is_none());
+        // El reintento ya no se mide ni se descarta
+        std::thread::sleep(Duration::from_millis(120));
+        assert!(admit(&mut req, &metrics, 100));
+        assert_eq!(metrics.forwarded.load(Ordering::Relaxed), 1);
+        assert_eq!(metrics.shed.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn snapshot_averages_forwarded_requests() {
+        let metrics = QueueMetrics::default();
+        assert_eq!(metrics.snapshot()["avg_ms"], 0);
+
+        metrics.record(Duration::from_millis(10));
+        metrics.record(Duration::from_millis(30));
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot["forwarded"], 2);
+        assert_eq!(snapshot["avg_ms"], 20);
+        assert_eq!(snapshot["max_ms"], 30);
+        assert_eq!(snapshot["shed"], 0);
+    }
+}
@@ -1,13 +1,17 @@
 use axum::{
     extract::Request,
-    http::StatusCode,
+    http::{header, StatusCode},
     middleware::Next,
     response::{IntoResponse, Response},
 };
 use redis::AsyncCommands;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crate::{cache::RedisClient, error_pages::PreserveBody, forwarded, token_validator::TokenValidator};
 
 /// Rate limiter configuration
-#[derive(Clone, Copy)]
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
 pub struct RateLimiterConfig {
     pub max_requests: u32,
     pub window_secs: u64,
@@ -24,33 +28,211 @@ impl Default for RateLimiterConfig {
     }
 }
 
-/// Check if a token is rate limited using Redis
+/// Placeholder del body de la respuesta 429 sustituido por los segundos de bloqueo restantes
+pub const RETRY_AFTER_PLACEHOLDER: &str = "{retry_after}";
+
+/// Respuesta a las peticiones rechazadas por el rate limiter, además del `Retry-After`
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct RateLimitResponse {
+    pub content_type: String,
+    /// Body con `{retry_after}` opcional
+    pub body: String,
+    /// Configurado por el operador: no se sustituye por `ERROR_PAGE_429`
+    #[serde(skip)]
+    pub custom: bool,
+}
+
+impl Default for RateLimitResponse {
+    fn default() -> Self {
+        Self {
+            content_type: "text/plain; charset=utf-8".to_string(),
+            body: "Rate limit exceeded. Token is temporarily blocked.".to_string(),
+            custom: false,
+        }
+    }
+}
+
+impl RateLimitResponse {
+    /// 429 telling the client to retry after `retry_after_secs`
+    fn render(&self, retry_after_secs: u64) -> Response {
+        let retry_after = retry_after_secs.to_string();
+        let mut response = (
+            StatusCode::TOO_MANY_REQUESTS,
+            [
+                (header::CONTENT_TYPE, self.content_type.clone()),
+                (header::RETRY_AFTER, retry_after.clone()),
+            ],
+            self.body.replace(RETRY_AFTER_PLACEHOLDER, &retry_after),
+        )
+            .into_response();
+        if self.custom {
+            response.extensions_mut().insert(PreserveBody);
+        }
+        response
+    }
+}
+
+/// Límites propios de un grupo de rutas (p. ej. subidas más estrictas que descargas)
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct RateLimitRoute {
+    /// Nombre del grupo, usado como espacio de claves en Redis
+    pub name: String,
+    /// Patrón de ruta por segmentos; `*` o `{param}` aceptan cualquier segmento
+    /// (`/api/v1/files/{id}`). Cubre también las rutas que cuelgan de él.
+    pub pattern: String,
+    /// Métodos a los que se aplica (vacío = todos)
+    #[serde(default)]
+    pub methods: Vec<String>,
+    #[serde(flatten)]
+    pub limits: RateLimiterConfig,
+}
+
+impl RateLimitRoute {
+    /// Whether the rule applies to a request
+    pub fn matches(&self, method: &str, path: &str) -> bool {
+        if !self.methods.is_empty() && !self.methods.iter().any(|m| m.eq_ignore_ascii_case(method)) {
+            return false;
+        }
+
+        let mut segments = path.split('/').filter(|s| !s.is_empty());
+        self.pattern
+            .split('/')
+            .filter(|s| !s.is_empty())
+            .all(|expected| match segments.next() {
+                Some(segment) => {
+                    expected == "*"
+                        || (expected.starts_with('{') && expected.ends_with('}'))
+                        || expected == segment
+                }
+                None => false,
+            })
+    }
+}
+
+/// First route group matching the request, in configuration order
+pub fn match_route<'a>(routes: &'a [RateLimitRoute], method: &str, path: &str) -> Option<&'a RateLimitRoute> {
+    routes.iter().find(|route| route.matches(method, path))
+}
+
+/// Espacio de claves del límite por IP (`rate_limit:ip:count:{ip}`)
+const IP_GROUP: &str = "ip";
+
+/// Redis key for a token's counter or block flag. The default limits keep the
+/// original `rate_limit:{kind}:{token}` keys; route groups get their own namespace.
+fn rate_limit_key(kind: &str, group: Option<&str>, token: &str) -> String {
+    match group {
+        Some(group) => format!("rate_limit:{}:{}:{}", group, kind, token),
+        None => format!("rate_limit:{}:{}", kind, token),
+    }
+}
+
+/// Contadores de decisiones de un nivel del rate limiter
+#[derive(Debug, Default)]
+pub struct TierCounters {
+    pub allowed: AtomicU64,
+    pub blocked: AtomicU64,
+    pub redis_errors: AtomicU64,
+}
+
+impl TierCounters {
+    fn snapshot(&self) -> serde_json::Value {
+        serde_json::json!({
+            "allowed": self.allowed.load(Ordering::Relaxed),
+            "blocked": self.blocked.load(Ordering::Relaxed),
+            "redis_errors": self.redis_errors.load(Ordering::Relaxed),
+        })
+    }
+}
+
+/// Decisiones del rate limiter, por nivel: por token y, para las peticiones
+/// sin token, por IP del cliente. Las que no se limitan se cuentan aparte.
+#[derive(Debug, Default)]
+pub struct RateLimitMetrics {
+    pub token: TierCounters,
+    pub ip: TierCounters,
+    pub unlimited: AtomicU64,
+    /// Peticiones rechazadas por no traer token en una ruta que lo exige
+    pub missing_token: AtomicU64,
+    /// Peticiones rechazadas porque el validador no aceptó su token
+    pub invalid_token: AtomicU64,
+}
+
+impl RateLimitMetrics {
+    /// Current counter values for the stats endpoint
+    pub fn snapshot(&self) -> serde_json::Value {
+        serde_json::json!({
+            "token": self.token.snapshot(),
+            "ip": self.ip.snapshot(),
+            "unlimited": self.unlimited.load(Ordering::Relaxed),
+            "missing_token": self.missing_token.load(Ordering::Relaxed),
+            "invalid_token": self.invalid_token.load(Ordering::Relaxed),
+        })
+    }
+}
+
+/// Decisión del rate limiter para una petición
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimitDecision {
+    Allowed,
+    /// Token bloqueado; segundos hasta que expire el bloqueo
+    Blocked { retry_after_secs: u64 },
+}
+
+/// Cuenta la petición y bloquea el token al superar el límite, todo en un paso
+/// atómico. KEYS: contador, bloqueo. ARGV: ventana, máximo, duración del bloqueo.
+/// Devuelve el contador tras la petición.
+const COUNT_SCRIPT: &str = r#"
+local count = redis.call("INCR", KEYS[1])
+if count == 1 then
+    redis.call("EXPIRE", KEYS[1], ARGV[1])
+end
+if count > tonumber(ARGV[2]) then
+    redis.call("SET", KEYS[2], "blocked", "EX", ARGV[3])
+    redis.call("DEL", KEYS[1])
+end
+return count
+"#;
+
+/// Check if a token is rate limited using Redis. `group` is the route group
+/// whose counters apply (`None` for the default limits). Only the block lookup
+/// is retried on transient errors: the counter step runs once, since a retry
+/// after a lost reply would count the request twice.
 pub async fn check_rate_limit(
-    redis_client: &mut redis::aio::ConnectionManager,
+    redis: &RedisClient,
     token: &str,
+    group: Option<&str>,
     config: &RateLimiterConfig,
-) -> Result<bool, redis::RedisError> {
-    let conn = redis_client;
-
-    // Check if token is blocked
-    let block_key = format!("rate_limit:blocked:{}", token);
-    let is_blocked: bool = conn.exists(&block_key).await?;
+) -> Result<RateLimitDecision, redis::RedisError> {
+    // Check if token is blocked (-2: no existe, -1: sin expiración)
+    let block_key = rate_limit_key("blocked", group, token);
+    let block_ttl: i64 = redis
+        .run(|mut conn| {
+            let block_key = &block_key;
+            async move { conn.ttl(block_key).await }
+        })
+        .await?;
 
-    if is_blocked {
+    if block_ttl != -2 {
         tracing::warn!("Token {} is blocked", token);
-        return Ok(false);
+        let retry_after_secs = if block_ttl > 0 { block_ttl as u64 } else { config.block_duration_secs };
+        return Ok(RateLimitDecision::Blocked { retry_after_secs });
     }
 
-    // Increment request count
-    let count_key = format!("rate_limit:count:{}", token);
-    let count: u32 = conn.incr(&count_key, 1).await?;
-
-    // Set expiration on first request
-    if count == 1 {
-        let _: () = conn.expire(&count_key, config.window_secs as i64).await?;
-    }
+    // Increment request count, blocking the token when it exceeds the limit
+    let count_key = rate_limit_key("count", group, token);
+    let count: u32 = redis
+        .run_once(|mut conn| async move {
+            redis::Script::new(COUNT_SCRIPT)
+                .key(&count_key)
+                .key(&block_key)
+                .arg(config.window_secs)
+                .arg(config.max_requests)
+                .arg(config.block_duration_secs)
+                .invoke_async(&mut conn)
+                .await
+        })
+        .await?;
 
-    // Check if limit exceeded
     if count > config.max_requests {
         tracing::warn!(
             "Token {} exceeded rate limit: {} requests in {} seconds",
@@ -58,23 +240,13 @@ pub async fn check_rate_limit(
             count,
             config.window_secs
         );
-
-        // Block the token
-        let _: () = conn.set_ex(
-            &block_key,
-            "blocked",
-            config.block_duration_secs as u64,
-        )
-        .await?;
-
-        // Delete the counter
-        let _: () = conn.del(&count_key).await?;
-
-        return Ok(false);
+        return Ok(RateLimitDecision::Blocked {
+            retry_after_secs: config.block_duration_secs,
+        });
     }
 
     tracing::debug!("Token {} request count: {}/{}", token, count, config.max_requests);
-    Ok(true)
+    Ok(RateLimitDecision::Allowed)
 }
 
 /// Extract upload token from Authorization or X-Upload-Token headers
@@ -98,73 +270,164 @@ fn extract_upload_token(req: &Request) -> Option<String> {
     None
 }
 
+/// Client address for logs, as resolved by `forwarded::client_ip`
+fn client_ip(req: &Request) -> String {
+    forwarded::client_ip(req)
+        .map(|ip| ip.to_string())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// Whether `path` falls under one of the route prefixes that require a token.
+/// Prefixes match whole segments: `/api/v1/upload` covers `/api/v1/upload/x`
+/// but not `/api/v1/uploads`.
+pub fn requires_token(path: &str, routes: &[String]) -> bool {
+    routes.iter().any(|prefix| {
+        let prefix = prefix.trim_end_matches('/');
+        match path.strip_prefix(prefix) {
+            Some(rest) => rest.is_empty() || rest.starts_with('/') || prefix.is_empty(),
+            None => false,
+        }
+    })
+}
+
+/// Reglas que aplica el middleware del rate limiter
+#[derive(Clone)]
+pub struct RateLimitPolicy {
+    /// Límites por defecto
+    pub config: RateLimiterConfig,
+    /// Límites por grupo de rutas
+    pub routes: Arc<[RateLimitRoute]>,
+    /// Límites por IP de las peticiones sin token (`None` = sin límite)
+    pub ip_limits: Option<RateLimiterConfig>,
+    /// Prefijos de ruta que exigen token
+    pub require_token_routes: Arc<[String]>,
+    pub validator: Arc<dyn TokenValidator>,
+    /// Respuesta a los tokens bloqueados
+    pub response: Arc<RateLimitResponse>,
+}
+
 /// Middleware to rate limit requests based on upload token
-/// Supports both Authorization: Bearer <token> and X-Upload-Token headers
+/// Supports both Authorization: Bearer <token> and X-Upload-Token headers.
+/// The limits come from the first matching route group, or `policy.config` otherwise.
+/// Requests without a token are limited per client IP when `policy.ip_limits` is set.
 pub async fn rate_limit_middleware(
-    mut redis_client: redis::aio::ConnectionManager,
-    config: RateLimiterConfig,
+    redis_client: RedisClient,
+    policy: RateLimitPolicy,
+    metrics: Arc<RateLimitMetrics>,
     req: Request,
     next: Next,
 ) -> Response {
-    // Extract upload token from headers
-    let token = match extract_upload_token(&req) {
-        Some(t) => t,
-        None => {
-            // No token header, allow request to proceed
-            return next.run(req).await;
+    // Extract upload token from headers (an empty token counts as missing)
+    let token = match extract_upload_token(&req).filter(|t| !t.trim().is_empty()) {
+        Some(t) => Some(t),
+        None if requires_token(req.uri().path(), &policy.require_token_routes) => {
+            metrics.missing_token.fetch_add(1, Ordering::Relaxed);
+            tracing::warn!(
+                "Rejected request to {} without upload token (client {})",
+                req.uri().path(),
+                client_ip(&req)
+            );
+            return (StatusCode::UNAUTHORIZED, "Upload token required").into_response();
+        }
+        None => None,
+    };
+
+    let (tier, subject, group, config) = match token {
+        Some(token) => {
+            // Un token inválido no debe crear contadores en Redis
+            if !policy.validator.validate(&token).await {
+                metrics.invalid_token.fetch_add(1, Ordering::Relaxed);
+                tracing::warn!(
+                    "Rejected request to {} with an invalid upload token (validator: {}, client {})",
+                    req.uri().path(),
+                    policy.validator.name(),
+                    client_ip(&req)
+                );
+                return (StatusCode::UNAUTHORIZED, "Invalid upload token").into_response();
+            }
+
+            match match_route(&policy.routes, req.method().as_str(), req.uri().path()) {
+                Some(route) => (&metrics.token, token, Some(route.name.as_str()), route.limits),
+                None => (&metrics.token, token, None, policy.config),
+            }
         }
+        None => match policy.ip_limits.zip(forwarded::client_ip(&req)) {
+            Some((config, ip)) => (&metrics.ip, ip.to_string(), Some(IP_GROUP), config),
+            None => {
+                // Sin token ni límite por IP, la petición pasa sin contar
+                metrics.unlimited.fetch_add(1, Ordering::Relaxed);
+                return next.run(req).await;
+            }
+        },
     };
 
     // Check rate limit
-    match check_rate_limit(&mut redis_client, &token, &config).await {
-        Ok(true) => {
+    match check_rate_limit(&redis_client, &subject, group, &config).await {
+        Ok(RateLimitDecision::Allowed) => {
             // Rate limit OK, proceed
+            tier.allowed.fetch_add(1, Ordering::Relaxed);
             next.run(req).await
         }
-        Ok(false) => {
+        Ok(RateLimitDecision::Blocked { retry_after_secs }) => {
             // Rate limit exceeded
-            tracing::warn!("Rate limit exceeded for token: {}", token);
-            (
-                StatusCode::TOO_MANY_REQUESTS,
-                "Rate limit exceeded. Token is temporarily blocked.",
-            )
-                .into_response()
+            tier.blocked.fetch_add(1, Ordering::Relaxed);
+            tracing::warn!(
+                "Rate limit exceeded for {}: {} (group: {}, client {}, retry after {}s)",
+                if group == Some(IP_GROUP) { "IP" } else { "token" },
+                subject,
+                group.unwrap_or("default"),
+                client_ip(&req),
+                retry_after_secs
+            );
+            policy.response.render(retry_after_secs)
         }
         Err(e) => {
-            // Redis error, log but allow request to proceed
-            tracing::error!("Redis error in rate limiter: {}", e);
+            // Redis error, log but allow request to proceed (fail open)
+            tier.redis_errors.fetch_add(1, Ordering::Relaxed);
+            tracing::error!(
+                "Redis error in rate limiter, allowing request (redis healthy: {}): {}",
+                redis_client.is_healthy(),
+                e
+            );
             next.run(req).await
         }
     }
 }
 
 /// Get rate limit info for a token
+#[allow(dead_code)]
 pub async fn get_rate_limit_info(
-    redis_client: &mut redis::aio::ConnectionManager,
+    redis: &RedisClient,
     token: &str,
 ) -> Result<RateLimitInfo, redis::RedisError> {
-    let conn = redis_client;
-
     let block_key = format!("rate_limit:blocked:{}", token);
     let count_key = format!("rate_limit:count:{}", token);
 
-    let is_blocked: bool = conn.exists(&block_key).await?;
-    let request_count: Option<u32> = conn.get(&count_key).await?;
-    let ttl: i64 = if is_blocked {
-        conn.ttl(&block_key).await?
-    } else if request_count.is_some() {
-        conn.ttl(&count_key).await?
-    } else {
-        -1
-    };
+    redis
+        .run(|mut conn| {
+            let (block_key, count_key) = (&block_key, &count_key);
+            async move {
+                let is_blocked: bool = conn.exists(block_key).await?;
+                let request_count: Option<u32> = conn.get(count_key).await?;
+                let ttl: i64 = if is_blocked {
+                    conn.ttl(block_key).await?
+                } else if request_count.is_some() {
+                    conn.ttl(count_key).await?
+                } else {
+                    -1
+                };
 
-    Ok(RateLimitInfo {
-        is_blocked,
-        request_count: request_count.unwrap_or(0),
-        ttl_seconds: if ttl > 0 { Some(ttl as u64) } else { None },
-    })
+                Ok(RateLimitInfo {
+                    is_blocked,
+                    request_count: request_count.unwrap_or(0),
+                    ttl_seconds: if ttl > 0 { Some(ttl as u64) } else { None },
+                })
+            }
+        })
+        .await
 }
 
+#[allow(dead_code)]
 #[derive(Debug, Clone, serde::Serialize)]
 pub struct RateLimitInfo {
     pub is_blocked: bool,
@@ -172,18 +435,564 @@ pub struct RateLimitInfo {
     pub ttl_seconds: Option<u64>,
 }
 
-/// Clear rate limit for a token (admin function)
-pub async fn clear_rate_limit(
-    redis_client: &mut redis::aio::ConnectionManager,
-    token: &str,
-) -> Result<(), redis::RedisError> {
-    let conn = redis_client;
+/// Token bloqueado, tal como lo devuelve el listado de administración
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct BlockedToken {
+    pub token: String,
+    /// Grupo de rutas en el que está bloqueado (`None` = límites por defecto,
+    /// `ip` = límite por IP, con la dirección como token)
+    pub group: Option<String>,
+    pub ttl_seconds: Option<u64>,
+}
 
+/// Parses a block key back into its group and token. Only the default
+/// namespace, the per-IP one and the configured `groups` are recognised.
+fn parse_block_key(key: &str, groups: &[String]) -> Option<(Option<String>, String)> {
+    let rest = key.strip_prefix("rate_limit:")?;
+    if let Some(token) = rest.strip_prefix("blocked:") {
+        return Some((None, token.to_string()));
+    }
+
+    let (group, rest) = rest.split_once(':')?;
+    let token = rest.strip_prefix("blocked:")?;
+    (group == IP_GROUP || groups.iter().any(|g| g == group)).then(|| (Some(group.to_string()), token.to_string()))
+}
+
+/// Lists blocked tokens with `SCAN`, starting at `cursor`, until at least
+/// `limit` tokens are collected or the keyspace is exhausted. A page may hold
+/// slightly more than `limit` entries since SCAN returns keys in batches.
+/// Returns the tokens and the cursor for the next page (0 when done).
+pub async fn list_blocked_tokens(
+    redis: &RedisClient,
+    groups: &[String],
+    mut cursor: u64,
+    limit: usize,
+) -> Result<(Vec<BlockedToken>, u64), redis::RedisError> {
+    let mut blocked = Vec::new();
+
+    loop {
+        let (next, keys): (u64, Vec<String>) = redis
+            .run(|mut conn| async move {
+                redis::cmd("SCAN")
+                    .arg(cursor)
+                    .arg("MATCH")
+                    .arg("rate_limit:*blocked:*")
+                    .arg("COUNT")
+                    .arg(limit.max(10))
+                    .query_async(&mut conn)
+                    .await
+            })
+            .await?;
+
+        let entries: Vec<_> = keys
+            .iter()
+            .filter_map(|key| Some((key, parse_block_key(key, groups)?)))
+            .collect();
+
+        if !entries.is_empty() {
+            let mut pipe = redis::pipe();
+            for (key, _) in &entries {
+                pipe.ttl(*key);
+            }
+            let pipe = &pipe;
+            let ttls: Vec<i64> = redis
+                .run(|mut conn| async move { pipe.query_async(&mut conn).await })
+                .await?;
+
+            for ((_, (group, token)), ttl) in entries.into_iter().zip(ttls) {
+                // -2: la clave expiró entre SCAN y TTL
+                if ttl == -2 {
+                    continue;
+                }
+                blocked.push(BlockedToken {
+                    token,
+                    group,
+                    ttl_seconds: (ttl > 0).then_some(ttl as u64),
+                });
+            }
+        }
+
+        cursor = next;
+        if cursor == 0 || blocked.len() >= limit {
+            return Ok((blocked, cursor));
+        }
+    }
+}
+
+/// Clear rate limit for a token (admin function)
+#[allow(dead_code)]
+pub async fn clear_rate_limit(redis: &RedisClient, token: &str) -> Result<(), redis::RedisError> {
     let block_key = format!("rate_limit:blocked:{}", token);
     let count_key = format!("rate_limit:count:{}", token);
+    let keys = [&block_key, &count_key];
 
-    let _: () = conn.del(&[&block_key, &count_key]).await?;
+    redis
+        .run(|mut conn| async move { conn.del::<_, ()>(&keys).await })
+        .await?;
 
     tracing::info!("Cleared rate limit for token: {}", token);
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fake_redis::{FakeRedis, Fault, ScriptContext};
+    use crate::token_validator::AcceptAllValidator;
+    use axum::{body::Body, extract::ConnectInfo, middleware, routing::get, Router};
+    use std::net::SocketAddr;
+    use tower::ServiceExt;
+
+    fn route(name: &str, pattern: &str, methods: &[&str], max_requests: u32) -> RateLimitRoute {
+        RateLimitRoute {
+            name: name.to_string(),
+            pattern: pattern.to_string(),
+            methods: methods.iter().map(|m| m.to_string()).collect(),
+            limits: RateLimiterConfig {
+                max_requests,
+                ..RateLimiterConfig::default()
+            },
+        }
+    }
+
+    #[test]
+    fn pattern_matches_segments_and_placeholders() {
+        let downloads = route("downloads", "/files/{id}", &[], 100);
+
+        assert!(downloads.matches("GET", "/files/abc"));
+        assert!(downloads.matches("GET", "/files/abc/thumbnail"));
+        assert!(!downloads.matches("GET", "/files"));
+        assert!(!downloads.matches("GET", "/filesystem/abc"));
+    }
+
+    #[test]
+    fn methods_filter_is_case_insensitive() {
+        let uploads = route("uploads", "/api/v1/files", &["post", "PUT"], 5);
+
+        assert!(uploads.matches("POST", "/api/v1/files"));
+        assert!(uploads.matches("put", "/api/v1/files/abc"));
+        assert!(!uploads.matches("GET", "/api/v1/files"));
+    }
+
+    #[test]
+    fn two_routes_get_distinct_limits_and_keys() {
+        let routes = [
+            route("uploads", "/api/v1/files", &["POST"], 5),
+            route("downloads", "/files/*", &[], 100),
+        ];
+
+        let upload = match_route(&routes, "POST", "/api/v1/files").unwrap();
+        let download = match_route(&routes, "GET", "/files/abc").unwrap();
+        assert_eq!(upload.limits.max_requests, 5);
+        assert_eq!(download.limits.max_requests, 100);
+        assert_ne!(
+            rate_limit_key("count", Some(&upload.name), "token"),
+            rate_limit_key("count", Some(&download.name), "token")
+        );
+    }
+
+    #[test]
+    fn unmatched_requests_fall_back_to_default_keys() {
+        let routes = [route("uploads", "/api/v1/files", &["POST"], 5)];
+
+        assert!(match_route(&routes, "GET", "/api/v1/files").is_none());
+        assert!(match_route(&routes, "POST", "/api/v1/health").is_none());
+        assert_eq!(rate_limit_key("count", None, "token"), "rate_limit:count:token");
+    }
+
+    #[test]
+    fn first_matching_route_wins() {
+        let routes = [route("specific", "/api/v1/files/{id}", &[], 1), route("general", "/api/v1", &[], 50)];
+
+        assert_eq!(match_route(&routes, "GET", "/api/v1/files/abc").unwrap().name, "specific");
+        assert_eq!(match_route(&routes, "GET", "/api/v1/health").unwrap().name, "general");
+    }
+
+    /// `COUNT_SCRIPT` emulado para el Redis falso
+    fn count_script(redis: &mut ScriptContext<'_>, keys: &[String], argv: &[String]) -> i64 {
+        let count = redis.call(&["INCR", &keys[0]]);
+        if count == 1 {
+            redis.call(&["EXPIRE", &keys[0], &argv[0]]);
+        }
+        if count > argv[1].parse().unwrap() {
+            redis.call(&["SET", &keys[1], "blocked", "EX", &argv[2]]);
+            redis.call(&["DEL", &keys[0]]);
+        }
+        count
+    }
+
+    /// Router behind the rate limiter, allowing 2 requests per token
+    async fn limited_app(configure: impl FnOnce(&mut RateLimitPolicy)) -> (Router, Arc<RateLimitMetrics>, FakeRedis) {
+        let redis = FakeRedis::start().await;
+        redis.register_script(&redis::Script::new(COUNT_SCRIPT), count_script);
+        let client = redis.client().await;
+        let metrics = Arc::new(RateLimitMetrics::default());
+        let mut policy = RateLimitPolicy {
+            config: RateLimiterConfig {
+                max_requests: 2,
+                ..RateLimiterConfig::default()
+            },
+            routes: Vec::new().into(),
+            ip_limits: None,
+            require_token_routes: Vec::new().into(),
+            validator: Arc::new(AcceptAllValidator),
+            response: Arc::new(RateLimitResponse::default()),
+        };
+        configure(&mut policy);
+
+        let layer_metrics = metrics.clone();
+        let app = Router::new()
+            .route("/", get(|| async { "ok" }))
+            .route("/api/v1/files/upload", get(|| async { "ok" }))
+            .route("/api/v1/files/uploads", get(|| async { "ok" }))
+            .route("/files/:id", axum::routing::any(|| async { "ok" }))
+            // Como el proxy catch-all, para las rutas que el router no reconoce sin normalizar
+            .fallback(|| async { "proxied" })
+            .layer(middleware::from_fn(move |req, next| {
+                rate_limit_middleware(client.clone(), policy.clone(), layer_metrics.clone(), req, next)
+            }))
+            .layer(middleware::from_fn(|req, next| {
+                crate::request_guard::request_guard_middleware(Default::default(), req, next)
+            }))
+            .layer(middleware::from_fn(|req, next| {
+                let allowed: Arc<[String]> = Arc::new(["DELETE".to_string()]);
+                crate::method_override::method_override_middleware(Some(allowed), req, next)
+            }));
+        (app, metrics, redis)
+    }
+
+    async fn send(app: &Router, token: Option<&str>, ip: [u8; 4]) -> StatusCode {
+        send_to(app, "/", token, ip).await
+    }
+
+    async fn send_to(app: &Router, uri: &str, token: Option<&str>, ip: [u8; 4]) -> StatusCode {
+        send_with(app, Request::builder().uri(uri), token, ip).await
+    }
+
+    async fn send_with(
+        app: &Router,
+        mut req: axum::http::request::Builder,
+        token: Option<&str>,
+        ip: [u8; 4],
+    ) -> StatusCode {
+        if let Some(token) = token {
+            req = req.header("x-upload-token", token);
+        }
+        let mut req = req.body(Body::empty()).unwrap();
+        req.extensions_mut().insert(ConnectInfo(SocketAddr::from((ip, 40000))));
+        app.clone().oneshot(req).await.unwrap().status()
+    }
+
+    /// (allowed, blocked, redis_errors)
+    fn counts(tier: &TierCounters) -> (u64, u64, u64) {
+        (
+            tier.allowed.load(Ordering::Relaxed),
+            tier.blocked.load(Ordering::Relaxed),
+            tier.redis_errors.load(Ordering::Relaxed),
+        )
+    }
+
+    #[tokio::test]
+    async fn token_over_its_limit_counts_as_blocked() {
+        let (app, metrics, _redis) = limited_app(|_| {}).await;
+
+        for _ in 0..2 {
+            assert_eq!(send(&app, Some("abc"), [10, 0, 0, 1]).await, StatusCode::OK);
+        }
+        // La tercera supera el límite y las siguientes encuentran el bloqueo
+        for _ in 0..2 {
+            assert_eq!(send(&app, Some("abc"), [10, 0, 0, 1]).await, StatusCode::TOO_MANY_REQUESTS);
+        }
+        assert_eq!(send(&app, Some("other"), [10, 0, 0, 1]).await, StatusCode::OK);
+
+        assert_eq!(counts(&metrics.token), (3, 2, 0));
+        assert_eq!(counts(&metrics.ip), (0, 0, 0));
+    }
+
+    #[tokio::test]
+    async fn requests_without_token_are_unlimited_by_default() {
+        let (app, metrics, redis) = limited_app(|_| {}).await;
+
+        for _ in 0..5 {
+            assert_eq!(send(&app, None, [10, 0, 0, 1]).await, StatusCode::OK);
+        }
+
+        assert_eq!(metrics.unlimited.load(Ordering::Relaxed), 5);
+        assert_eq!(counts(&metrics.ip), (0, 0, 0));
+        assert!(redis.command_names().is_empty());
+    }
+
+    #[tokio::test]
+    async fn requests_without_token_are_limited_per_ip() {
+        let ip_limits = RateLimiterConfig {
+            max_requests: 1,
+            ..RateLimiterConfig::default()
+        };
+        let (app, metrics, _redis) = limited_app(|policy| policy.ip_limits = Some(ip_limits)).await;
+
+        assert_eq!(send(&app, None, [10, 0, 0, 1]).await, StatusCode::OK);
+        assert_eq!(send(&app, None, [10, 0, 0, 1]).await, StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(send(&app, None, [10, 0, 0, 2]).await, StatusCode::OK);
+        // Con token se aplica el límite del token, no el de la IP bloqueada
+        assert_eq!(send(&app, Some("abc"), [10, 0, 0, 1]).await, StatusCode::OK);
+
+        assert_eq!(counts(&metrics.ip), (2, 1, 0));
+        assert_eq!(counts(&metrics.token), (1, 0, 0));
+        assert_eq!(metrics.unlimited.load(Ordering::Relaxed), 0);
+    }
+
+    #[tokio::test]
+    async fn redis_errors_fail_open_and_are_counted() {
+        let (app, metrics, redis) = limited_app(|_| {}).await;
+
+        redis.fail_next(Fault::Error("ERR boom"));
+        assert_eq!(send(&app, Some("abc"), [10, 0, 0, 1]).await, StatusCode::OK);
+
+        assert_eq!(counts(&metrics.token), (0, 0, 1));
+    }
+
+    #[test]
+    fn token_routes_match_whole_segments() {
+        let routes = ["/api/v1/files/upload/".to_string()];
+
+        assert!(requires_token("/api/v1/files/upload", &routes));
+        assert!(requires_token("/api/v1/files/upload/abc", &routes));
+        assert!(!requires_token("/api/v1/files/uploads", &routes));
+        assert!(!requires_token("/api/v1/files", &routes));
+        assert!(requires_token("/anything", &["/".to_string()]));
+        assert!(!requires_token("/anything", &[]));
+    }
+
+    #[tokio::test]
+    async fn missing_token_is_401_only_on_required_routes() {
+        let (app, metrics, redis) = limited_app(|policy| {
+            policy.require_token_routes = vec!["/api/v1/files/upload".to_string()].into();
+        })
+        .await;
+        let ip = [10, 0, 0, 1];
+
+        assert_eq!(send_to(&app, "/api/v1/files/upload", None, ip).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(send_to(&app, "/api/v1/files/upload", Some(" "), ip).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(send_to(&app, "/api/v1/files/uploads", None, ip).await, StatusCode::OK);
+        assert_eq!(send_to(&app, "/", None, ip).await, StatusCode::OK);
+        assert_eq!(send_to(&app, "/api/v1/files/upload", Some("abc"), ip).await, StatusCode::OK);
+
+        assert_eq!(metrics.missing_token.load(Ordering::Relaxed), 2);
+        assert_eq!(metrics.unlimited.load(Ordering::Relaxed), 2);
+        assert_eq!(counts(&metrics.token), (1, 0, 0));
+        // Los rechazos no llegan a Redis
+        assert_eq!(redis.command_names(), ["TTL", "EVALSHA"]);
+    }
+
+    #[tokio::test]
+    async fn route_groups_apply_distinct_limits_and_keys() {
+        let (app, metrics, redis) = limited_app(|policy| {
+            policy.routes = vec![
+                route("uploads", "/api/v1/files/upload", &[], 1),
+                route("downloads", "/files/{id}", &["GET"], 3),
+            ]
+            .into();
+        })
+        .await;
+        let ip = [10, 0, 0, 1];
+
+        assert_eq!(send_to(&app, "/api/v1/files/upload", Some("abc"), ip).await, StatusCode::OK);
+        assert_eq!(send_to(&app, "/api/v1/files/upload", Some("abc"), ip).await, StatusCode::TOO_MANY_REQUESTS);
+        // Bloqueado para subidas, el token sigue descargando hasta su propio límite
+        for _ in 0..3 {
+            assert_eq!(send_to(&app, "/files/report", Some("abc"), ip).await, StatusCode::OK);
+        }
+        assert_eq!(send_to(&app, "/files/report", Some("abc"), ip).await, StatusCode::TOO_MANY_REQUESTS);
+        // Las rutas sin grupo usan los límites por defecto (2)
+        assert_eq!(send_to(&app, "/", Some("abc"), ip).await, StatusCode::OK);
+
+        assert_eq!(counts(&metrics.token), (5, 2, 0));
+        let (blocked, _) = list_blocked_tokens(&redis.client().await, &["uploads".to_string(), "downloads".to_string()], 0, 10)
+            .await
+            .unwrap();
+        let mut groups: Vec<_> = blocked.iter().map(|b| (b.group.clone().unwrap(), b.token.clone())).collect();
+        groups.sort();
+        assert_eq!(
+            groups,
+            [("downloads".to_string(), "abc".to_string()), ("uploads".to_string(), "abc".to_string())]
+        );
+    }
+
+    #[tokio::test]
+    async fn unnormalized_paths_are_still_token_and_route_limited() {
+        let (app, metrics, _redis) = limited_app(|policy| {
+            policy.require_token_routes = vec!["/api/v1/files/upload".to_string()].into();
+            policy.routes = vec![route("uploads", "/api/v1/files/upload", &[], 1)].into();
+        })
+        .await;
+        let ip = [10, 0, 0, 1];
+
+        for uri in ["/api/v1//files/upload", "/x/../api/v1/files/upload", "/api/v1/files/./upload/abc", "/api/v1/%2e%2e/v1/files/upload"] {
+            assert_eq!(send_to(&app, uri, None, ip).await, StatusCode::UNAUTHORIZED, "{}", uri);
+        }
+        assert_eq!(metrics.missing_token.load(Ordering::Relaxed), 4);
+
+        assert_eq!(send_to(&app, "/api/v1/files/upload", Some("abc"), ip).await, StatusCode::OK);
+        assert_eq!(send_to(&app, "/api/v1//files/upload", Some("abc"), ip).await, StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(send_to(&app, "/api/v1/tmp/../files/upload", Some("abc"), ip).await, StatusCode::TOO_MANY_REQUESTS);
+    }
+
+    #[tokio::test]
+    async fn route_limits_see_the_overridden_method() {
+        let (app, metrics, _redis) = limited_app(|policy| {
+            policy.routes = vec![route("deletes", "/files/{id}", &["DELETE"], 1)].into();
+        })
+        .await;
+        let ip = [10, 0, 0, 1];
+        let delete = || Request::builder().method("DELETE").uri("/files/report");
+        let overridden = || {
+            Request::builder()
+                .method("POST")
+                .uri("/files/report")
+                .header(crate::method_override::METHOD_OVERRIDE_HEADER, "DELETE")
+        };
+
+        assert_eq!(send_with(&app, overridden(), Some("abc"), ip).await, StatusCode::OK);
+        assert_eq!(send_with(&app, delete(), Some("abc"), ip).await, StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(send_with(&app, overridden(), Some("abc"), ip).await, StatusCode::TOO_MANY_REQUESTS);
+        // Un POST sin override no es un DELETE y usa los límites por defecto
+        let post = Request::builder().method("POST").uri("/files/report");
+        assert_eq!(send_with(&app, post, Some("abc"), ip).await, StatusCode::OK);
+        assert_eq!(counts(&metrics.token), (2, 2, 0));
+    }
+
+    /// Blocks `token` in `group` by exceeding a zero-request limit
+    async fn block(redis: &RedisClient, token: &str, group: Option<&str>) {
+        let config = RateLimiterConfig {
+            max_requests: 0,
+            ..RateLimiterConfig::default()
+        };
+        let decision = check_rate_limit(redis, token, group, &config).await.unwrap();
+        assert!(matches!(decision, RateLimitDecision::Blocked { .. }));
+    }
+
+    async fn fake_redis_client() -> (FakeRedis, RedisClient) {
+        let redis = FakeRedis::start().await;
+        redis.register_script(&redis::Script::new(COUNT_SCRIPT), count_script);
+        let client = redis.client().await;
+        (redis, client)
+    }
+
+    #[tokio::test]
+    async fn blocked_token_is_listed_until_cleared() {
+        let (_redis, client) = fake_redis_client().await;
+        block(&client, "abc", None).await;
+        block(&client, "def", Some("uploads")).await;
+        block(&client, "10.0.0.1", Some(IP_GROUP)).await;
+        block(&client, "ghi", Some("removed-group")).await;
+        let groups = ["uploads".to_string()];
+
+        let (mut blocked, cursor) = list_blocked_tokens(&client, &groups, 0, 10).await.unwrap();
+        assert_eq!(cursor, 0);
+        blocked.sort_by(|a, b| a.token.cmp(&b.token));
+        let listed: Vec<_> = blocked.iter().map(|b| (b.token.as_str(), b.group.as_deref(), b.ttl_seconds)).collect();
+        assert_eq!(
+            listed,
+            [
+                ("10.0.0.1", Some("ip"), Some(300)),
+                ("abc", None, Some(300)),
+                ("def", Some("uploads"), Some(300)),
+            ]
+        );
+
+        clear_rate_limit(&client, "abc").await.unwrap();
+        let (blocked, _) = list_blocked_tokens(&client, &groups, 0, 10).await.unwrap();
+        assert!(blocked.iter().all(|b| b.token != "abc"));
+        assert_eq!(blocked.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn blocked_tokens_are_paginated_with_the_scan_cursor() {
+        let (_redis, client) = fake_redis_client().await;
+        for i in 0..25 {
+            block(&client, &format!("token-{:02}", i), None).await;
+        }
+
+        let (mut tokens, mut cursor, mut pages) = (Vec::new(), 0, 0);
+        loop {
+            let (blocked, next) = list_blocked_tokens(&client, &[], cursor, 10).await.unwrap();
+            assert!(!blocked.is_empty());
+            tokens.extend(blocked.into_iter().map(|b| b.token));
+            pages += 1;
+            cursor = next;
+            if cursor == 0 {
+                break;
+            }
+        }
+
+        assert!(pages > 1);
+        tokens.sort();
+        tokens.dedup();
+        assert_eq!(tokens.len(), 25);
+    }
+
+    #[tokio::test]
+    async fn invalid_tokens_are_401_before_touching_redis() {
+        let validator = Arc::new(crate::token_validator::HmacValidator::new("s3cret"));
+        let (app, metrics, redis) = limited_app(|policy| policy.validator = validator).await;
+
+        for token in ["forged.00ff", "no-signature"] {
+            assert_eq!(send(&app, Some(token), [10, 0, 0, 1]).await, StatusCode::UNAUTHORIZED);
+        }
+        assert!(redis.command_names().is_empty());
+        assert_eq!(counts(&metrics.token), (0, 0, 0));
+
+        // user-1 firmado con "s3cret"
+        let valid = "user-1.8e6d6d439f86ee1bfe2a4d9ba3081c530a9dda2161c0ac814a747cb06c11f100";
+        assert_eq!(send(&app, Some(valid), [10, 0, 0, 1]).await, StatusCode::OK);
+        assert_eq!(counts(&metrics.token), (1, 0, 0));
+    }
+
+    async fn rejected(app: &Router) -> Response {
+        let mut response = None;
+        for _ in 0..3 {
+            let mut req = Request::builder().uri("/").header("x-upload-token", "abc").body(Body::empty()).unwrap();
+            req.extensions_mut().insert(ConnectInfo(SocketAddr::from(([10, 0, 0, 1], 40000))));
+            response = Some(app.clone().oneshot(req).await.unwrap());
+        }
+        response.unwrap()
+    }
+
+    #[tokio::test]
+    async fn rate_limited_response_uses_the_configured_body_and_retry_after() {
+        let (app, _, _redis) = limited_app(|policy| {
+            policy.config.block_duration_secs = 120;
+            policy.response = Arc::new(RateLimitResponse {
+                content_type: "application/json".to_string(),
+                body: r#"{"error":"rate_limited","retry_after":{retry_after}}"#.to_string(),
+                custom: true,
+            });
+        })
+        .await;
+
+        let response = rejected(&app).await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/json");
+        assert_eq!(response.headers()[header::RETRY_AFTER], "120");
+        // Las páginas de error no sustituyen una respuesta configurada
+        assert!(response.extensions().get::<PreserveBody>().is_some());
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap(),
+            serde_json::json!({"error": "rate_limited", "retry_after": 120})
+        );
+    }
+
+    #[tokio::test]
+    async fn default_rate_limited_response_is_plain_text() {
+        let (app, _, _redis) = limited_app(|_| {}).await;
+
+        let response = rejected(&app).await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "text/plain; charset=utf-8");
+        assert_eq!(response.headers()[header::RETRY_AFTER], "300");
+        assert!(response.extensions().get::<PreserveBody>().is_none());
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(body, "Rate limit exceeded. Token is temporarily blocked.");
+    }
+}
@@ -0,0 +1,456 @@
+use axum::{
+    body::Body,
+    http::{header, HeaderMap, HeaderName, HeaderValue, Method, Request, Response, StatusCode, Uri},
+    response::IntoResponse,
+};
+use hyper::body::Incoming;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::time::Duration;
+use url::Url;
+
+use crate::{
+    backends::{split_url_credentials, BackendRegistry},
+    db::Backend,
+    proxy::{HttpsClient, SPECIFIC_BACKEND_PREFIX},
+};
+
+/// Qué hacer con las redirecciones (3xx con `Location`) de los backends
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RedirectMode {
+    /// Se reenvían al cliente sin cambios
+    Passthrough,
+    /// El `Location` que apunta al backend se reescribe para volver por el gateway
+    Rewrite,
+    /// El gateway sigue la redirección y devuelve el contenido final
+    Follow,
+}
+
+impl RedirectMode {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "passthrough" | "" => Some(Self::Passthrough),
+            "rewrite" => Some(Self::Rewrite),
+            "follow" => Some(Self::Follow),
+            _ => None,
+        }
+    }
+}
+
+/// Headers del cliente que se conservan al seguir una redirección. El resto
+/// (Authorization, cookies, el secreto del gateway) no debe llegar a otro host.
+const FOLLOW_HEADERS: [HeaderName; 6] = [
+    header::ACCEPT,
+    header::ACCEPT_ENCODING,
+    header::RANGE,
+    header::IF_RANGE,
+    header::IF_NONE_MATCH,
+    header::IF_MODIFIED_SINCE,
+];
+
+pub fn is_redirect(status: StatusCode) -> bool {
+    matches!(status.as_u16(), 301 | 302 | 303 | 307 | 308)
+}
+
+/// What each hop repeats from the client request when following redirects
+#[derive(Debug, Clone)]
+pub struct FollowRequest {
+    pub method: Method,
+    /// Only the `FOLLOW_HEADERS`
+    pub headers: HeaderMap,
+    /// Sent again only to the origin of the first request
+    pub authorization: Option<HeaderValue>,
+}
+
+impl FollowRequest {
+    pub fn new(method: Method, headers: &HeaderMap) -> Self {
+        let mut kept = HeaderMap::new();
+        for name in FOLLOW_HEADERS {
+            for value in headers.get_all(&name) {
+                kept.append(name.clone(), value.clone());
+            }
+        }
+        Self {
+            method,
+            headers: kept,
+            authorization: headers.get(header::AUTHORIZATION).cloned(),
+        }
+    }
+}
+
+/// Absolute target of the response's `Location`, resolved against the URL
+/// that was requested. Only http(s) targets are accepted.
+fn resolve_location(requested: &Url, headers: &HeaderMap) -> Option<Url> {
+    let location = headers.get(header::LOCATION)?.to_str().ok()?;
+    requested
+        .join(location)
+        .ok()
+        .filter(|url| matches!(url.scheme(), "http" | "https"))
+}
+
+/// Rewrites a `Location` pointing at a known backend to the gateway path that
+/// proxies to that same backend, so the client never sees internal hosts. A
+/// relative `Location` refers to the responding `backend`; absolute ones may
+/// also point at any other backend in `registry`. Locations on other hosts,
+/// such as signed URLs of a storage provider, are left untouched.
+pub fn rewrite_location(headers: &mut HeaderMap, requested_url: &str, backend: &Backend, registry: &BackendRegistry) {
+    let Ok(requested) = Url::parse(requested_url) else {
+        return;
+    };
+    let Some(target) = resolve_location(&requested, headers) else {
+        return;
+    };
+
+    // El backend que respondió primero; si no, el de path base más largo que contenga el destino
+    let matched = gateway_path(&target, backend).map(|path| (backend.server_id.clone(), path)).or_else(|| {
+        registry
+            .all()
+            .iter()
+            .filter(|other| other.server_id != backend.server_id)
+            .filter_map(|other| {
+                let (server_url, _) = split_url_credentials(&other.server_url);
+                let base_len = Url::parse(&server_url).ok()?.path().trim_end_matches('/').len();
+                gateway_path(&target, other).map(|path| (base_len, other.server_id.clone(), path))
+            })
+            .max_by_key(|(base_len, _, _)| *base_len)
+            .map(|(_, server_id, path)| (server_id, path))
+    });
+    let Some((server_id, path)) = matched else {
+        tracing::debug!("Redirect of backend {} to {} is not for a known backend", backend.server_id, target);
+        return;
+    };
+
+    let mut rewritten = format!("{}{}{}", SPECIFIC_BACKEND_PREFIX, server_id, path);
+    if let Some(query) = target.query() {
+        rewritten.push('?');
+        rewritten.push_str(query);
+    }
+
+    if let Ok(value) = HeaderValue::from_str(&rewritten) {
+        tracing::debug!("Rewriting redirect of backend {} to {}", backend.server_id, rewritten);
+        headers.insert(header::LOCATION, value);
+    }
+}
+
+/// Path of `target` relative to the `server_url` of `backend`, which may have
+/// a base path. `None` when it is on another origin or outside that base path.
+fn gateway_path(target: &Url, backend: &Backend) -> Option<String> {
+    let (server_url, _) = split_url_credentials(&backend.server_url);
+    let base = Url::parse(&server_url).ok()?;
+    if target.origin() != base.origin() {
+        return None;
+    }
+
+    // El path del gateway se une al server_url, que puede tener un path base
+    let base_path = base.path().trim_end_matches('/');
+    target
+        .path()
+        .strip_prefix(base_path)
+        .filter(|rest| rest.is_empty() || rest.starts_with('/'))
+        .map(str::to_string)
+}
+
+/// Why following the redirects of a backend response failed
+#[derive(Debug)]
+pub enum RedirectError {
+    /// The chain came back to a URL it had already visited
+    Loop(Url),
+    /// The chain was still redirecting after `max_hops` hops
+    TooManyRedirects(usize),
+    /// A hop failed or timed out
+    Status(StatusCode),
+}
+
+impl IntoResponse for RedirectError {
+    fn into_response(self) -> axum::response::Response {
+        let (error, message) = match self {
+            Self::Loop(url) => ("redirect_loop", format!("The backend redirected back to {}", url)),
+            Self::TooManyRedirects(max_hops) => (
+                "too_many_redirects",
+                format!("The backend redirected more than {} times", max_hops),
+            ),
+            Self::Status(status) => return status.into_response(),
+        };
+        let body = serde_json::json!({ "error": error, "message": message });
+        (StatusCode::BAD_GATEWAY, axum::Json(body)).into_response()
+    }
+}
+
+/// Follows the redirects of `response` server-side, up to `max_hops`, and
+/// returns the first non-redirect response. Each hop is a fresh request
+/// built from `request` and has its own `timeout`. Its `Authorization` is only
+/// sent to the origin of `requested_url`, never to other hosts.
+pub async fn follow(
+    client: &HttpsClient,
+    mut response: Response<Incoming>,
+    requested_url: &str,
+    request: FollowRequest,
+    max_hops: usize,
+    timeout: Duration,
+) -> Result<Response<Incoming>, RedirectError> {
+    let mut current = Url::parse(requested_url).map_err(|_| RedirectError::Status(StatusCode::BAD_GATEWAY))?;
+    let origin = current.origin();
+    let mut visited = HashSet::from([current.to_string()]);
+
+    for _ in 0..max_hops {
+        if !is_redirect(response.status()) {
+            return Ok(response);
+        }
+        let Some(next) = resolve_location(&current, response.headers()) else {
+            // Sin un Location utilizable la redirección se entrega tal cual
+            return Ok(response);
+        };
+        if !visited.insert(next.to_string()) {
+            tracing::error!("Redirect loop from {} back to {}", requested_url, next);
+            return Err(RedirectError::Loop(next));
+        }
+
+        tracing::debug!("Following {} redirect to {}", response.status(), next);
+        let uri: Uri = next
+            .as_str()
+            .parse()
+            .map_err(|_| RedirectError::Status(StatusCode::BAD_GATEWAY))?;
+        let mut req = Request::builder()
+            .method(request.method.clone())
+            .uri(uri)
+            .body(Body::empty())
+            .map_err(|_| RedirectError::Status(StatusCode::BAD_GATEWAY))?;
+        *req.headers_mut() = request.headers.clone();
+        // Las credenciales solo vuelven al mismo origen, nunca a otro host
+        if let Some(ref authorization) = request.authorization {
+            if next.origin() == origin {
+                req.headers_mut().insert(header::AUTHORIZATION, authorization.clone());
+            }
+        }
+
+        response = match tokio::time::timeout(timeout, client.request(req)).await {
+            Ok(Ok(res)) => res,
+            Ok(Err(e)) => {
+                tracing::error!("Failed to follow redirect to {}: {}", next, e);
+                return Err(RedirectError::Status(StatusCode::BAD_GATEWAY));
+            }
+            Err(_) => {
+                tracing::error!("Following redirect to {} timed out after {:?}", next, timeout);
+                return Err(RedirectError::Status(StatusCode::GATEWAY_TIMEOUT));
+            }
+        };
+        current = next;
+    }
+
+    if is_redirect(response.status()) {
+        tracing::error!("Too many redirects (limit {}) from {}", max_hops, requested_url);
+        return Err(RedirectError::TooManyRedirects(max_hops));
+    }
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_backend;
+    use crate::proxy::{test_config, test_state};
+    use http_body_util::BodyExt;
+    use std::convert::Infallible;
+    use std::sync::{Arc, Mutex};
+
+    fn backend(server_id: &str, server_url: &str) -> Backend {
+        Backend {
+            server_url: server_url.to_string(),
+            ..test_backend(server_id)
+        }
+    }
+
+    /// `Location` after rewriting a redirect of `files` to `location`
+    fn rewritten(location: &str, requested_url: &str) -> String {
+        let files = backend("files", "http://files.internal:9000/storage");
+        let registry = BackendRegistry::new(vec![
+            files.clone(),
+            backend("thumbs", "http://media.internal"),
+            backend("thumbs-large", "http://media.internal/large"),
+        ]);
+        let mut headers = HeaderMap::new();
+        headers.insert(header::LOCATION, HeaderValue::from_str(location).unwrap());
+        rewrite_location(&mut headers, requested_url, &files, &registry);
+        headers[header::LOCATION].to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn relative_location_goes_back_through_the_gateway() {
+        let requested = "http://files.internal:9000/storage/a/old.txt";
+
+        assert_eq!(rewritten("new.txt", requested), "/api/v1/backend/files/a/new.txt");
+        assert_eq!(rewritten("/storage/b?v=2", requested), "/api/v1/backend/files/b?v=2");
+    }
+
+    #[test]
+    fn absolute_location_keeps_the_backend_base_path() {
+        let requested = "http://files.internal:9000/storage/a";
+
+        assert_eq!(rewritten("http://files.internal:9000/storage/b", requested), "/api/v1/backend/files/b");
+        // Fuera del path base no es una ruta que el gateway pueda proxyar a ese backend
+        assert_eq!(rewritten("http://files.internal:9000/other", requested), "http://files.internal:9000/other");
+        assert_eq!(rewritten("http://files.internal:9000/storagex", requested), "http://files.internal:9000/storagex");
+    }
+
+    #[test]
+    fn location_on_another_backend_uses_its_longest_base_path() {
+        let requested = "http://files.internal:9000/storage/a";
+
+        assert_eq!(rewritten("http://media.internal/thumb.png", requested), "/api/v1/backend/thumbs/thumb.png");
+        assert_eq!(rewritten("http://media.internal/large/x.png", requested), "/api/v1/backend/thumbs-large/x.png");
+    }
+
+    #[test]
+    fn foreign_hosts_and_schemes_are_left_untouched() {
+        let requested = "http://files.internal:9000/storage/a";
+
+        for location in [
+            "https://bucket.s3.amazonaws.com/a?X-Amz-Signature=abc",
+            "https://files.internal:9000/storage/a",
+            "ftp://files.internal:9000/storage/a",
+        ] {
+            assert_eq!(rewritten(location, requested), location);
+        }
+    }
+
+    /// Path and `Authorization` of each request a redirect server received
+    type HopLog = Arc<Mutex<Vec<(String, Option<String>)>>>;
+
+    /// Server that answers each path with `route(path)`: a `Location` to
+    /// redirect to with a 302, or `None` for a 200 with the path as body
+    async fn redirect_server(route: fn(&str) -> Option<String>) -> (String, HopLog) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let log = HopLog::default();
+        let received = log.clone();
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                let received = received.clone();
+                let service = hyper::service::service_fn(move |req: hyper::Request<Incoming>| {
+                    let path = req.uri().path().to_string();
+                    let authorization = req
+                        .headers()
+                        .get(header::AUTHORIZATION)
+                        .map(|v| v.to_str().unwrap().to_string());
+                    received.lock().unwrap().push((path.clone(), authorization));
+                    let response = match route(&path) {
+                        Some(location) => Response::builder()
+                            .status(StatusCode::FOUND)
+                            .header(header::LOCATION, location)
+                            .body(Body::empty()),
+                        None => Response::builder().body(Body::from(path)),
+                    };
+                    async move { Ok::<_, Infallible>(response.unwrap()) }
+                });
+                tokio::spawn(
+                    hyper::server::conn::http1::Builder::new()
+                        .serve_connection(hyper_util::rt::TokioIo::new(socket), service),
+                );
+            }
+        });
+        (format!("http://{}", addr), log)
+    }
+
+    /// Requests `url` with `authorization` and follows its redirects up to `max_hops`
+    async fn follow_from(
+        url: &str,
+        authorization: Option<&str>,
+        max_hops: usize,
+    ) -> Result<Response<Incoming>, RedirectError> {
+        let state = test_state(test_config(), &[]).await;
+        let client = state.client_for(&test_backend("files"));
+        let mut builder = Request::builder().uri(url);
+        let mut headers = HeaderMap::new();
+        if let Some(authorization) = authorization {
+            builder = builder.header(header::AUTHORIZATION, authorization);
+            headers.insert(header::AUTHORIZATION, HeaderValue::from_str(authorization).unwrap());
+        }
+        let response = client.request(builder.body(Body::empty()).unwrap()).await.unwrap();
+        let request = FollowRequest::new(Method::GET, &headers);
+        follow(client, response, url, request, max_hops, Duration::from_secs(5)).await
+    }
+
+    async fn body_of(response: Response<Incoming>) -> String {
+        String::from_utf8(response.into_body().collect().await.unwrap().to_bytes().to_vec()).unwrap()
+    }
+
+    fn paths(log: &HopLog) -> Vec<String> {
+        log.lock().unwrap().iter().map(|(path, _)| path.clone()).collect()
+    }
+
+    #[tokio::test]
+    async fn short_chain_is_followed_to_the_final_response() {
+        let (origin, log) = redirect_server(|path| match path {
+            "/a" => Some("/b".to_string()),
+            "/b" => Some("c".to_string()),
+            _ => None,
+        })
+        .await;
+
+        let response = follow_from(&format!("{}/a", origin), None, 5).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body_of(response).await, "/c");
+        assert_eq!(paths(&log), ["/a", "/b", "/c"]);
+    }
+
+    #[tokio::test]
+    async fn revisiting_a_url_is_a_loop() {
+        let (origin, log) = redirect_server(|path| match path {
+            "/a" => Some("/b".to_string()),
+            _ => Some("/a".to_string()),
+        })
+        .await;
+
+        let error = follow_from(&format!("{}/a", origin), None, 5).await.unwrap_err();
+        assert!(matches!(error, RedirectError::Loop(ref url) if url.path() == "/a"), "{:?}", error);
+        assert_eq!(paths(&log), ["/a", "/b"]);
+
+        let response = error.into_response();
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(serde_json::from_slice::<serde_json::Value>(&body).unwrap()["error"], "redirect_loop");
+    }
+
+    #[tokio::test]
+    async fn chain_longer_than_max_hops_is_rejected() {
+        let (origin, log) = redirect_server(|path| {
+            let n: u32 = path.trim_start_matches("/n").parse().unwrap();
+            Some(format!("/n{}", n + 1))
+        })
+        .await;
+
+        let error = follow_from(&format!("{}/n0", origin), None, 3).await.unwrap_err();
+        assert!(matches!(error, RedirectError::TooManyRedirects(3)), "{:?}", error);
+        assert_eq!(paths(&log), ["/n0", "/n1", "/n2", "/n3"]);
+
+        let response = error.into_response();
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(serde_json::from_slice::<serde_json::Value>(&body).unwrap()["error"], "too_many_redirects");
+    }
+
+    #[tokio::test]
+    async fn authorization_is_only_sent_back_to_the_same_origin() {
+        let (other, other_log) = redirect_server(|_| None).await;
+        // La función de rutas no captura estado, así que el destino va en el path
+        let (origin, log) = redirect_server(|path| match path.strip_prefix("/to/") {
+            Some(port) => Some(format!("http://127.0.0.1:{}/final", port)),
+            None if path == "/a" => Some("/b".to_string()),
+            None => None,
+        })
+        .await;
+        let other_port = other.rsplit(':').next().unwrap();
+
+        let response = follow_from(&format!("{}/a", origin), Some("Bearer secret"), 5).await.unwrap();
+        assert_eq!(body_of(response).await, "/b");
+        let response = follow_from(&format!("{}/to/{}", origin, other_port), Some("Bearer secret"), 5)
+            .await
+            .unwrap();
+        assert_eq!(body_of(response).await, "/final");
+
+        let same_origin = log.lock().unwrap().clone();
+        assert!(same_origin.iter().all(|(_, authorization)| authorization.as_deref() == Some("Bearer secret")));
+        assert_eq!(other_log.lock().unwrap().clone(), [("/final".to_string(), None)]);
+    }
+}
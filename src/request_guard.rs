@@ -0,0 +1,292 @@
+use axum::{
+    extract::Request,
+    http::{header, uri::{Authority, PathAndQuery}, HeaderMap, StatusCode, Uri, Version},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+/// Límites aplicados a las peticiones entrantes antes de seleccionar un backend
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize)]
+pub struct RequestGuardConfig {
+    /// Tamaño total máximo de los headers (nombres + valores) en bytes
+    pub max_header_bytes: usize,
+    /// Número máximo de headers
+    pub max_header_count: usize,
+    /// Longitud máxima de la URI (ruta + query) en bytes
+    pub max_url_length: usize,
+}
+
+impl Default for RequestGuardConfig {
+    fn default() -> Self {
+        Self {
+            max_header_bytes: 32 * 1024,
+            max_header_count: 100,
+            max_url_length: 8 * 1024,
+        }
+    }
+}
+
+/// Total size of the headers (names + values) in bytes
+pub fn header_bytes(headers: &HeaderMap) -> usize {
+    headers
+        .iter()
+        .map(|(name, value)| name.as_str().len() + value.len())
+        .sum()
+}
+
+/// Normalizes a request path at ingress, before anything matches on it:
+/// duplicate slashes are collapsed and `.`/`..` segments resolved (also percent-encoded ones,
+/// such as `%2e%2e`), never climbing above the root.
+pub fn normalize_path(path: &str) -> String {
+    let mut segments: Vec<&str> = Vec::new();
+    let mut trailing_slash = path.ends_with('/');
+
+    for segment in path.split('/').filter(|s| !s.is_empty()) {
+        let decoded = segment.to_ascii_lowercase().replace("%2e", ".");
+        match decoded.as_str() {
+            "." => trailing_slash = true,
+            ".." => {
+                segments.pop();
+                trailing_slash = true;
+            }
+            _ => {
+                segments.push(segment);
+                trailing_slash = false;
+            }
+        }
+    }
+    trailing_slash |= path.ends_with('/');
+
+    let mut normalized = format!("/{}", segments.join("/"));
+    if trailing_slash && !segments.is_empty() {
+        normalized.push('/');
+    }
+    normalized
+}
+
+/// Rejects requests whose headers exceed the configured limits (431) and
+/// requests with a malformed `Host` or an absolute-form target (400), which
+/// could otherwise confuse URL construction towards the backends. Overly long
+/// URIs are rejected with 414.
+pub fn check_request(config: &RequestGuardConfig, req: &Request) -> Result<(), (StatusCode, &'static str)> {
+    let headers = req.headers();
+
+    if headers.len() > config.max_header_count {
+        return Err((
+            StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE,
+            "Too many request headers",
+        ));
+    }
+
+    if header_bytes(headers) > config.max_header_bytes {
+        return Err((
+            StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE,
+            "Request headers too large",
+        ));
+    }
+
+    let url_length = req.uri().path_and_query().map(|pq| pq.as_str().len()).unwrap_or(0);
+    if url_length > config.max_url_length {
+        return Err((StatusCode::URI_TOO_LONG, "Request URI too long"));
+    }
+
+    // Un reverse proxy solo recibe peticiones en origin-form (`/path?query`).
+    // En HTTP/2 la URI siempre incluye scheme y authority (pseudo-headers).
+    let absolute_form = req.uri().scheme().is_some() || req.uri().authority().is_some();
+    if absolute_form && req.version() <= Version::HTTP_11 {
+        return Err((StatusCode::BAD_REQUEST, "Absolute-form request targets are not allowed"));
+    }
+
+    let mut hosts = headers.get_all(header::HOST).iter();
+    if let Some(host) = hosts.next() {
+        if hosts.next().is_some() {
+            return Err((StatusCode::BAD_REQUEST, "Multiple Host headers"));
+        }
+
+        let valid = host
+            .to_str()
+            .ok()
+            .filter(|h| !h.is_empty() && !h.contains('@'))
+            .and_then(|h| h.parse::<Authority>().ok())
+            // `Authority` acepta cualquier texto tras `:`; el puerto debe ser numérico
+            .is_some_and(|authority| authority.as_str() == authority.host() || authority.port_u16().is_some());
+        if !valid {
+            return Err((StatusCode::BAD_REQUEST, "Malformed Host header"));
+        }
+    }
+
+    Ok(())
+}
+
+/// `uri` with its path replaced by `normalize_path`, keeping the query and,
+/// in HTTP/2, the scheme and authority. `None` when it is already normalized.
+fn normalized_uri(uri: &Uri) -> Option<Uri> {
+    let path = normalize_path(uri.path());
+    if path == uri.path() {
+        return None;
+    }
+
+    let path_and_query = match uri.query() {
+        Some(query) => format!("{}?{}", path, query),
+        None => path,
+    };
+    let mut parts = uri.clone().into_parts();
+    parts.path_and_query = Some(PathAndQuery::try_from(path_and_query).ok()?);
+    Uri::from_parts(parts).ok()
+}
+
+/// Middleware that applies `check_request` to every incoming request and
+/// normalizes its path, so the rate limiter, the token and route checks and
+/// the handlers all see the path that reaches the backend
+pub async fn request_guard_middleware(
+    config: RequestGuardConfig,
+    mut req: Request,
+    next: Next,
+) -> Response {
+    if let Err((status, reason)) = check_request(&config, &req) {
+        tracing::warn!("Rejected request {} {}: {}", req.method(), req.uri().path(), reason);
+        return (status, reason).into_response();
+    }
+
+    if let Some(uri) = normalized_uri(req.uri()) {
+        tracing::debug!("Normalized request path {} to {}", req.uri().path(), uri.path());
+        *req.uri_mut() = uri;
+    }
+
+    next.run(req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+
+    fn config() -> RequestGuardConfig {
+        RequestGuardConfig {
+            max_header_bytes: 64,
+            max_header_count: 3,
+            max_url_length: 32,
+        }
+    }
+
+    fn request(uri: &str, headers: &[(&str, &str)]) -> Request {
+        let mut builder = Request::builder().uri(uri);
+        for (name, value) in headers {
+            builder = builder.header(*name, *value);
+        }
+        builder.body(Body::empty()).unwrap()
+    }
+
+    fn status(req: &Request) -> Option<StatusCode> {
+        check_request(&config(), req).err().map(|(status, _)| status)
+    }
+
+    #[test]
+    fn well_formed_request_passes() {
+        assert_eq!(status(&request("/api/v1/files?id=1", &[("host", "gateway.example.com:8080")])), None);
+        assert_eq!(status(&request("/", &[])), None);
+    }
+
+    #[test]
+    fn too_many_headers_is_431() {
+        let req = request("/", &[("a", "1"), ("b", "2"), ("c", "3"), ("d", "4")]);
+        assert_eq!(status(&req), Some(StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE));
+    }
+
+    #[test]
+    fn oversized_headers_are_431() {
+        let big = "x".repeat(64);
+        assert_eq!(status(&request("/", &[("x-big", &big)])), Some(StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE));
+        assert_eq!(status(&request("/", &[("x-big", &big[..59])])), None);
+    }
+
+    #[test]
+    fn long_uri_is_414() {
+        let path = format!("/{}", "a".repeat(31));
+        assert_eq!(status(&request(&path, &[])), None);
+        assert_eq!(status(&request(&format!("{}?q", path), &[])), Some(StatusCode::URI_TOO_LONG));
+    }
+
+    #[test]
+    fn absolute_form_is_400_only_before_http2() {
+        assert_eq!(status(&request("http://internal.example/admin", &[])), Some(StatusCode::BAD_REQUEST));
+
+        let mut req = request("https://gateway.example.com/files", &[]);
+        *req.version_mut() = Version::HTTP_2;
+        assert_eq!(status(&req), None);
+    }
+
+    #[test]
+    fn duplicate_or_malformed_host_is_400() {
+        let mut req = request("/", &[("host", "a.example.com")]);
+        req.headers_mut().append(header::HOST, "b.example.com".parse().unwrap());
+        assert_eq!(status(&req), Some(StatusCode::BAD_REQUEST));
+
+        for host in ["[::1]", "127.0.0.1:8080", "gateway.example.com"] {
+            assert_eq!(status(&request("/", &[("host", host)])), None, "{:?}", host);
+        }
+        for host in ["", "user@evil.example", "bad host", "host:port"] {
+            assert_eq!(status(&request("/", &[("host", host)])), Some(StatusCode::BAD_REQUEST), "{:?}", host);
+        }
+    }
+
+    #[test]
+    fn duplicate_slashes_and_dot_segments_are_resolved() {
+        assert_eq!(normalize_path("/api//v1///files"), "/api/v1/files");
+        assert_eq!(normalize_path("/api/./v1/files"), "/api/v1/files");
+        assert_eq!(normalize_path("/api/v1/tmp/../files"), "/api/v1/files");
+        assert_eq!(normalize_path(""), "/");
+        assert_eq!(normalize_path("/"), "/");
+    }
+
+    #[test]
+    fn percent_encoded_dot_segments_are_resolved() {
+        assert_eq!(normalize_path("/files/%2e%2e/admin"), "/admin");
+        assert_eq!(normalize_path("/files/%2E%2e/admin"), "/admin");
+        assert_eq!(normalize_path("/files/.%2e/admin"), "/admin");
+        assert_eq!(normalize_path("/files/%2e/a"), "/files/a");
+        // Solo se decodifican los puntos: `%2f` no separa segmentos
+        assert_eq!(normalize_path("/files/a%2f..%2fb"), "/files/a%2f..%2fb");
+    }
+
+    #[test]
+    fn dot_segments_never_climb_above_the_root() {
+        assert_eq!(normalize_path("/../../etc/passwd"), "/etc/passwd");
+        assert_eq!(normalize_path("/a/../../../b"), "/b");
+        assert_eq!(normalize_path("/.."), "/");
+        assert_eq!(normalize_path("/%2e%2e/%2e%2e"), "/");
+    }
+
+    #[test]
+    fn trailing_slash_is_kept() {
+        assert_eq!(normalize_path("/files/"), "/files/");
+        assert_eq!(normalize_path("/files//"), "/files/");
+        assert_eq!(normalize_path("/files/a/.."), "/files/");
+        assert_eq!(normalize_path("/files/."), "/files/");
+        assert_eq!(normalize_path("/files/a"), "/files/a");
+    }
+
+    #[test]
+    fn normalized_uri_keeps_the_query_and_authority() {
+        let normalized = |uri: &str| normalized_uri(&uri.parse().unwrap()).map(|uri| uri.to_string());
+
+        assert_eq!(normalized("/api//v1/../files?id=a/../b"), Some("/api/files?id=a/../b".to_string()));
+        assert_eq!(normalized("https://gateway.example.com//files/./a"), Some("https://gateway.example.com/files/a".to_string()));
+        assert_eq!(normalized("/api/v1/files?id=1"), None);
+    }
+
+    #[tokio::test]
+    async fn middleware_hands_on_the_normalized_path() {
+        use tower::ServiceExt;
+
+        let app = axum::Router::new()
+            .fallback(|req: Request| async move { req.uri().to_string() })
+            .layer(axum::middleware::from_fn(|req, next| {
+                request_guard_middleware(RequestGuardConfig::default(), req, next)
+            }));
+
+        let response = app.oneshot(request("/api/v1//files/%2e%2e/upload?x=1", &[])).await.unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(body, "/api/v1/upload?x=1");
+    }
+}
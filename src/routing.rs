@@ -0,0 +1,220 @@
+use axum::http::{header, HeaderMap};
+use serde::{Deserialize, Serialize};
+
+use crate::db::Backend;
+
+/// Grupo de backends elegibles para una regla, por provider y/o por server_id
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackendGroup {
+    /// Provider de los backends del grupo
+    #[serde(default)]
+    pub provider: Option<String>,
+    /// Backends (server_id) del grupo
+    #[serde(default)]
+    pub backends: Vec<String>,
+}
+
+impl BackendGroup {
+    /// Whether a backend belongs to the group
+    pub fn includes(&self, backend: &Backend) -> bool {
+        self.backends.contains(&backend.server_id)
+            || self
+                .provider
+                .as_ref()
+                .is_some_and(|p| p.eq_ignore_ascii_case(&backend.provider))
+    }
+}
+
+/// Regla que envía las peticiones con cierto Content-Type a un grupo de backends
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContentTypeRoute {
+    /// Media type a comparar, sin parámetros (`multipart/form-data`).
+    /// Un `*` como subtipo acepta cualquiera (`image/*`).
+    pub content_type: String,
+    #[serde(flatten)]
+    pub group: BackendGroup,
+}
+
+impl ContentTypeRoute {
+    /// Whether the request's Content-Type (ignoring parameters such as the
+    /// multipart boundary) matches this rule
+    pub fn matches(&self, headers: &HeaderMap) -> bool {
+        let Some(content_type) = headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+        else {
+            return false;
+        };
+        let essence = content_type.split(';').next().unwrap_or("").trim();
+
+        match self.content_type.strip_suffix("/*") {
+            Some(main_type) => essence
+                .split_once('/')
+                .is_some_and(|(t, _)| t.eq_ignore_ascii_case(main_type)),
+            None => essence.eq_ignore_ascii_case(&self.content_type),
+        }
+    }
+}
+
+/// Regla que envía las peticiones con cierto valor de un header
+/// (p. ej. `X-API-Version: v2`) a un grupo de backends
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HeaderRoute {
+    pub header: String,
+    /// Valor a comparar, sin distinguir mayúsculas
+    pub value: String,
+    #[serde(flatten)]
+    pub group: BackendGroup,
+}
+
+impl HeaderRoute {
+    pub fn matches(&self, headers: &HeaderMap) -> bool {
+        headers
+            .get_all(self.header.as_str())
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .any(|v| v.trim().eq_ignore_ascii_case(&self.value))
+    }
+}
+
+/// First content-type rule matching the request, in configuration order
+pub fn match_content_type<'a>(routes: &'a [ContentTypeRoute], headers: &HeaderMap) -> Option<&'a ContentTypeRoute> {
+    routes.iter().find(|route| route.matches(headers))
+}
+
+/// First header rule matching the request, in configuration order
+pub fn match_header<'a>(routes: &'a [HeaderRoute], headers: &HeaderMap) -> Option<&'a HeaderRoute> {
+    routes.iter().find(|route| route.matches(headers))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_backend;
+
+    fn content_type_route(content_type: &str, provider: &str) -> ContentTypeRoute {
+        ContentTypeRoute {
+            content_type: content_type.to_string(),
+            group: BackendGroup {
+                provider: Some(provider.to_string()),
+                backends: Vec::new(),
+            },
+        }
+    }
+
+    fn with_content_type(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CONTENT_TYPE, value.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn content_type_parameters_and_case_are_ignored() {
+        let route = content_type_route("multipart/form-data", "bulk");
+
+        assert!(route.matches(&with_content_type("multipart/form-data; boundary=----abc")));
+        assert!(route.matches(&with_content_type("Multipart/Form-Data")));
+        assert!(!route.matches(&with_content_type("multipart/mixed")));
+        assert!(!route.matches(&HeaderMap::new()));
+    }
+
+    #[test]
+    fn wildcard_subtype_matches_the_whole_type() {
+        let route = content_type_route("image/*", "media");
+
+        assert!(route.matches(&with_content_type("image/png")));
+        assert!(route.matches(&with_content_type("IMAGE/jpeg; q=1")));
+        assert!(!route.matches(&with_content_type("application/image")));
+        assert!(!route.matches(&with_content_type("image")));
+    }
+
+    #[test]
+    fn first_matching_content_type_rule_wins() {
+        let routes = [
+            content_type_route("multipart/form-data", "bulk"),
+            content_type_route("multipart/*", "other"),
+            content_type_route("application/json", "small"),
+        ];
+
+        let route = |value: &str| match_content_type(&routes, &with_content_type(value)).map(|r| r.group.provider.clone().unwrap());
+        assert_eq!(route("multipart/form-data; boundary=x").as_deref(), Some("bulk"));
+        assert_eq!(route("multipart/mixed").as_deref(), Some("other"));
+        assert_eq!(route("application/json").as_deref(), Some("small"));
+        assert_eq!(route("text/plain"), None);
+    }
+
+    #[test]
+    fn group_includes_backends_by_provider_or_id() {
+        let group = BackendGroup {
+            provider: Some("Supabase".to_string()),
+            backends: vec!["pinned".to_string()],
+        };
+        let backend = |id: &str, provider: &str| Backend {
+            provider: provider.to_string(),
+            ..test_backend(id)
+        };
+
+        assert!(group.includes(&backend("a", "supabase")));
+        assert!(group.includes(&backend("pinned", "gdrive")));
+        assert!(!group.includes(&backend("b", "gdrive")));
+    }
+
+    fn header_route(header: &str, value: &str, backend: &str) -> HeaderRoute {
+        HeaderRoute {
+            header: header.to_string(),
+            value: value.to_string(),
+            group: BackendGroup {
+                provider: None,
+                backends: vec![backend.to_string()],
+            },
+        }
+    }
+
+    fn with_headers(pairs: &[(&str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.append(
+                axum::http::HeaderName::from_bytes(name.as_bytes()).unwrap(),
+                value.parse().unwrap(),
+            );
+        }
+        headers
+    }
+
+    #[test]
+    fn header_rule_ignores_name_and_value_case() {
+        let route = header_route("X-API-Version", "v2", "api-v2");
+
+        assert!(route.matches(&with_headers(&[("x-api-version", "V2")])));
+        assert!(route.matches(&with_headers(&[("x-api-version", " v2 ")])));
+        assert!(!route.matches(&with_headers(&[("x-api-version", "v1")])));
+        assert!(!route.matches(&with_headers(&[("x-api-versions", "v2")])));
+        assert!(!route.matches(&HeaderMap::new()));
+    }
+
+    #[test]
+    fn header_rule_matches_any_repeated_value() {
+        let route = header_route("x-api-version", "v2", "api-v2");
+
+        assert!(route.matches(&with_headers(&[("x-api-version", "v1"), ("x-api-version", "v2")])));
+        // Un valor no-ASCII no impide comparar los demás
+        let mut headers = with_headers(&[("x-api-version", "v1")]);
+        headers.append("x-api-version", axum::http::HeaderValue::from_bytes(b"\xff").unwrap());
+        assert!(!route.matches(&headers));
+    }
+
+    #[test]
+    fn first_matching_header_rule_wins() {
+        let routes = [
+            header_route("x-api-version", "v2", "api-v2"),
+            header_route("x-tenant", "beta", "beta"),
+            header_route("x-api-version", "v2", "unreachable"),
+        ];
+
+        let route = |pairs: &[(&str, &str)]| match_header(&routes, &with_headers(pairs)).map(|r| r.group.backends[0].clone());
+        assert_eq!(route(&[("x-api-version", "v2"), ("x-tenant", "beta")]).as_deref(), Some("api-v2"));
+        assert_eq!(route(&[("x-tenant", "beta")]).as_deref(), Some("beta"));
+        assert_eq!(route(&[("x-api-version", "v3")]), None);
+        assert_eq!(route(&[]), None);
+    }
+}
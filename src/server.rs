@@ -0,0 +1,490 @@
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, Request},
+    Router,
+};
+use hyper::body::Incoming;
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo, TokioTimer},
+    server::conn::auto::Builder,
+    service::TowerToHyperService,
+};
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt, ReadBuf};
+use tokio::net::{TcpListener, TcpStream};
+use tower::ServiceExt;
+
+use crate::proxy_protocol;
+
+/// Tiempo máximo para recibir el header PROXY tras aceptar la conexión
+const PROXY_HEADER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Respuesta a una conexión que supera el límite por IP, antes de cerrarla
+const TOO_MANY_CONNECTIONS: &[u8] =
+    b"HTTP/1.1 429 Too Many Requests\r\nConnection: close\r\nContent-Length: 0\r\n\r\n";
+
+/// Conexiones abiertas por IP de cliente, con un máximo por IP
+#[derive(Debug)]
+pub struct ConnectionLimiter {
+    /// Máximo de conexiones simultáneas por IP (0 = sin límite)
+    max_per_ip: usize,
+    active: Mutex<HashMap<IpAddr, usize>>,
+    /// Conexiones rechazadas por superar el límite
+    rejected: AtomicU64,
+}
+
+impl ConnectionLimiter {
+    pub fn new(max_per_ip: usize) -> Self {
+        Self {
+            max_per_ip,
+            active: Mutex::new(HashMap::new()),
+            rejected: AtomicU64::new(0),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.max_per_ip > 0
+    }
+
+    /// Registers a new connection from `ip`, or returns `None` when the IP
+    /// already has `max_per_ip` open. The slot is released when the guard drops.
+    pub fn try_acquire(self: &Arc<Self>, ip: IpAddr) -> Option<ConnectionGuard> {
+        let mut active = self.active.lock().unwrap();
+        let count = active.entry(ip).or_insert(0);
+        if *count >= self.max_per_ip {
+            self.rejected.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+        *count += 1;
+
+        Some(ConnectionGuard {
+            limiter: self.clone(),
+            ip,
+        })
+    }
+
+    /// Current counter values for the stats endpoint
+    pub fn snapshot(&self) -> serde_json::Value {
+        let active = self.active.lock().unwrap();
+        serde_json::json!({
+            "max_per_ip": self.max_per_ip,
+            "clients": active.len(),
+            "active": active.values().sum::<usize>(),
+            "rejected": self.rejected.load(Ordering::Relaxed),
+        })
+    }
+}
+
+/// Plaza ocupada por una conexión; se libera al cerrarse
+pub struct ConnectionGuard {
+    limiter: Arc<ConnectionLimiter>,
+    ip: IpAddr,
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        let mut active = self.limiter.active.lock().unwrap();
+        if let Some(count) = active.get_mut(&self.ip) {
+            *count -= 1;
+            if *count == 0 {
+                active.remove(&self.ip);
+            }
+        }
+    }
+}
+
+/// Timeouts de las conexiones de cliente contra slowloris y clientes abandonados
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConnectionTimeouts {
+    /// Tiempo máximo para recibir los headers de cada petición HTTP/1
+    pub header_read: Option<Duration>,
+    /// Tiempo máximo sin bytes leídos ni escritos en la conexión, sin
+    /// peticiones en curso
+    pub idle: Option<Duration>,
+}
+
+impl ConnectionTimeouts {
+    pub fn is_enabled(&self) -> bool {
+        self.header_read.is_some() || self.idle.is_some()
+    }
+}
+
+/// Cuenta una petición en curso en la conexión hasta que se suelta: mientras
+/// haya alguna, la conexión no está inactiva aunque no pasen bytes
+struct InFlightGuard(Arc<AtomicUsize>);
+
+impl InFlightGuard {
+    fn new(in_flight: &Arc<AtomicUsize>) -> Self {
+        in_flight.fetch_add(1, Ordering::SeqCst);
+        Self(in_flight.clone())
+    }
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Stream que anota el instante del último byte leído o escrito, para cerrar
+/// las conexiones inactivas
+struct IdleStream {
+    inner: TcpStream,
+    started: Instant,
+    /// Milisegundos desde `started` hasta la última actividad
+    last_activity: Arc<AtomicU64>,
+}
+
+impl IdleStream {
+    fn touch(&self) {
+        let elapsed = self.started.elapsed().as_millis() as u64;
+        self.last_activity.store(elapsed, Ordering::Relaxed);
+    }
+}
+
+impl AsyncRead for IdleStream {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let filled = buf.filled().len();
+        let result = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if matches!(result, Poll::Ready(Ok(()))) && buf.filled().len() > filled {
+            self.touch();
+        }
+        result
+    }
+}
+
+impl AsyncWrite for IdleStream {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        let result = Pin::new(&mut self.inner).poll_write(cx, buf);
+        if matches!(result, Poll::Ready(Ok(n)) if n > 0) {
+            self.touch();
+        }
+        result
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let result = Pin::new(&mut self.inner).poll_write_vectored(cx, bufs);
+        if matches!(result, Poll::Ready(Ok(n)) if n > 0) {
+            self.touch();
+        }
+        result
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+/// Client address of a new connection: the one in the PROXY header when
+/// `proxy_protocol` is set, otherwise the TCP peer. `None` closes the connection.
+async fn client_addr(stream: &mut TcpStream, peer: SocketAddr, proxy_protocol: bool) -> Option<SocketAddr> {
+    if !proxy_protocol {
+        return Some(peer);
+    }
+
+    match tokio::time::timeout(PROXY_HEADER_TIMEOUT, proxy_protocol::read_header(stream)).await {
+        Ok(Ok(client)) => Some(client.unwrap_or(peer)),
+        Ok(Err(e)) => {
+            tracing::warn!("Rejected connection from {} without a valid PROXY header: {}", peer, e);
+            None
+        }
+        Err(_) => {
+            tracing::warn!("Rejected connection from {}: no PROXY header after {:?}", peer, PROXY_HEADER_TIMEOUT);
+            None
+        }
+    }
+}
+
+/// Serves `app` with our own accept loop, used instead of `axum::serve` when
+/// connections need handling before HTTP: reading a PROXY protocol header,
+/// capping concurrent connections per client IP and/or applying connection
+/// timeouts. The client address is exposed to handlers as
+/// `ConnectInfo<SocketAddr>`, like `into_make_service_with_connect_info` does.
+pub async fn serve(
+    listener: TcpListener,
+    app: Router,
+    proxy_protocol: bool,
+    limiter: Arc<ConnectionLimiter>,
+    timeouts: ConnectionTimeouts,
+) -> io::Result<()> {
+    loop {
+        let (mut stream, peer) = match listener.accept().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                tracing::error!("Failed to accept connection: {}", e);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                continue;
+            }
+        };
+        let app = app.clone();
+        let limiter = limiter.clone();
+
+        tokio::spawn(async move {
+            let Some(client) = client_addr(&mut stream, peer, proxy_protocol).await else {
+                return;
+            };
+
+            // Se cuenta por la IP real del cliente, también detrás de un balanceador L4
+            let _guard = if limiter.is_enabled() {
+                match limiter.try_acquire(client.ip()) {
+                    Some(guard) => Some(guard),
+                    None => {
+                        tracing::debug!("Rejected connection from {}: too many open connections", client.ip());
+                        let _ = tokio::time::timeout(Duration::from_secs(1), stream.write_all(TOO_MANY_CONNECTIONS)).await;
+                        return;
+                    }
+                }
+            } else {
+                None
+            };
+
+            let in_flight = Arc::new(AtomicUsize::new(0));
+            let counter = in_flight.clone();
+            let service = app
+                .map_request(move |req: Request<Incoming>| {
+                    let mut req = req.map(Body::new);
+                    req.extensions_mut().insert(ConnectInfo(client));
+                    req
+                })
+                .map_future(move |response| {
+                    let guard = InFlightGuard::new(&counter);
+                    async move {
+                        let response = response.await;
+                        drop(guard);
+                        response
+                    }
+                });
+
+            let mut builder = Builder::new(TokioExecutor::new());
+            if let Some(header_read) = timeouts.header_read {
+                builder.http1().timer(TokioTimer::new()).header_read_timeout(header_read);
+            }
+
+            let stream = IdleStream {
+                inner: stream,
+                started: Instant::now(),
+                last_activity: Arc::new(AtomicU64::new(0)),
+            };
+            let last_activity = stream.last_activity.clone();
+            let started = stream.started;
+
+            // Con upgrades, como axum::serve, para websockets
+            let connection =
+                builder.serve_connection_with_upgrades(TokioIo::new(stream), TowerToHyperService::new(service));
+            let Some(idle) = timeouts.idle else {
+                let _ = connection.await;
+                return;
+            };
+
+            // Se comprueba la inactividad al vencer el plazo desde el último byte;
+            // soltar la conexión la cierra. Con una petición en curso (un backend
+            // lento sin enviar nada) el reloj se reinicia en vez de cerrar
+            tokio::pin!(connection);
+            loop {
+                let last = Duration::from_millis(last_activity.load(Ordering::Relaxed));
+                let deadline = tokio::time::Instant::from_std(started + last + idle);
+                tokio::select! {
+                    _ = connection.as_mut() => return,
+                    _ = tokio::time::sleep_until(deadline) => {
+                        if in_flight.load(Ordering::SeqCst) > 0 {
+                            last_activity.store(started.elapsed().as_millis() as u64, Ordering::Relaxed);
+                            continue;
+                        }
+                        if started.elapsed() >= Duration::from_millis(last_activity.load(Ordering::Relaxed)) + idle {
+                            tracing::debug!("Closing connection from {}: idle for {:?}", client, idle);
+                            return;
+                        }
+                    }
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::routing::get;
+    use tokio::io::AsyncReadExt;
+
+    #[test]
+    fn connections_are_limited_per_ip() {
+        let limiter = Arc::new(ConnectionLimiter::new(2));
+        let client: IpAddr = "10.0.0.1".parse().unwrap();
+        let other: IpAddr = "10.0.0.2".parse().unwrap();
+
+        let first = limiter.try_acquire(client).unwrap();
+        let _second = limiter.try_acquire(client).unwrap();
+        assert!(limiter.try_acquire(client).is_none());
+        // Otra IP tiene su propio cupo
+        let _other = limiter.try_acquire(other).unwrap();
+
+        let snapshot = limiter.snapshot();
+        assert_eq!(snapshot["clients"], 2);
+        assert_eq!(snapshot["active"], 3);
+        assert_eq!(snapshot["rejected"], 1);
+
+        // Cerrar una conexión libera su plaza
+        drop(first);
+        assert!(limiter.try_acquire(client).is_some());
+    }
+
+    #[test]
+    fn released_ips_are_forgotten() {
+        let limiter = Arc::new(ConnectionLimiter::new(1));
+        let guard = limiter.try_acquire("10.0.0.1".parse().unwrap()).unwrap();
+
+        drop(guard);
+        assert_eq!(limiter.snapshot()["clients"], 0);
+        assert_eq!(limiter.snapshot()["active"], 0);
+    }
+
+    /// Serves a router that answers `ok` on `/` with the given settings
+    async fn start(limiter: Arc<ConnectionLimiter>, timeouts: ConnectionTimeouts) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = Router::new().route("/", get(|| async { "ok" })).route(
+            "/slow",
+            get(|| async {
+                tokio::time::sleep(Duration::from_millis(800)).await;
+                "slow"
+            }),
+        );
+        tokio::spawn(serve(listener, app, false, limiter, timeouts));
+        addr
+    }
+
+    async fn get_root(stream: &mut TcpStream) -> String {
+        stream.write_all(b"GET / HTTP/1.1\r\nHost: gateway\r\n\r\n").await.unwrap();
+        let mut buf = [0u8; 1024];
+        let n = stream.read(&mut buf).await.unwrap();
+        String::from_utf8_lossy(&buf[..n]).into_owned()
+    }
+
+    #[tokio::test]
+    async fn connections_over_the_limit_get_a_429() {
+        let limiter = Arc::new(ConnectionLimiter::new(1));
+        let addr = start(limiter.clone(), ConnectionTimeouts::default()).await;
+
+        let mut first = TcpStream::connect(addr).await.unwrap();
+        assert!(get_root(&mut first).await.starts_with("HTTP/1.1 200 OK"));
+
+        let mut second = TcpStream::connect(addr).await.unwrap();
+        let mut response = String::new();
+        second.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 429 Too Many Requests"), "{}", response);
+        assert_eq!(limiter.snapshot()["rejected"], 1);
+
+        // Al cerrar la primera, la IP vuelve a tener plaza
+        drop(first);
+        for _ in 0..50 {
+            if limiter.snapshot()["active"] == 0 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let mut third = TcpStream::connect(addr).await.unwrap();
+        assert!(get_root(&mut third).await.starts_with("HTTP/1.1 200 OK"));
+    }
+
+    /// Waits up to `limit` for the server to close `stream`, discarding what it sends
+    async fn closed_within(stream: &mut TcpStream, limit: Duration) -> bool {
+        let mut buf = [0u8; 1024];
+        let read_until_eof = async {
+            loop {
+                match stream.read(&mut buf).await {
+                    Ok(0) | Err(_) => return,
+                    Ok(_) => {}
+                }
+            }
+        };
+        tokio::time::timeout(limit, read_until_eof).await.is_ok()
+    }
+
+    #[tokio::test]
+    async fn client_stalling_mid_headers_is_dropped() {
+        let timeouts = ConnectionTimeouts {
+            header_read: Some(Duration::from_millis(200)),
+            idle: None,
+        };
+        let addr = start(Arc::new(ConnectionLimiter::new(0)), timeouts).await;
+
+        let mut stalled = TcpStream::connect(addr).await.unwrap();
+        stalled.write_all(b"GET / HTTP/1.1\r\nHost: gat").await.unwrap();
+        assert!(!closed_within(&mut stalled, Duration::from_millis(100)).await);
+        assert!(closed_within(&mut stalled, Duration::from_secs(2)).await);
+
+        // Una petición completa a tiempo se atiende con normalidad
+        let mut prompt = TcpStream::connect(addr).await.unwrap();
+        assert!(get_root(&mut prompt).await.starts_with("HTTP/1.1 200 OK"));
+    }
+
+    #[tokio::test]
+    async fn idle_connections_are_closed_and_active_ones_kept() {
+        let timeouts = ConnectionTimeouts {
+            header_read: None,
+            idle: Some(Duration::from_millis(300)),
+        };
+        assert!(timeouts.is_enabled());
+        let addr = start(Arc::new(ConnectionLimiter::new(0)), timeouts).await;
+
+        // Una conexión keep-alive que sigue enviando peticiones no se cierra
+        let mut active = TcpStream::connect(addr).await.unwrap();
+        for _ in 0..4 {
+            assert!(get_root(&mut active).await.starts_with("HTTP/1.1 200 OK"));
+            tokio::time::sleep(Duration::from_millis(150)).await;
+        }
+
+        // Sin más bytes, se cierra al cumplirse el plazo
+        assert!(!closed_within(&mut active, Duration::from_millis(50)).await);
+        assert!(closed_within(&mut active, Duration::from_secs(2)).await);
+
+        // También la que se abre y nunca envía nada
+        let mut silent = TcpStream::connect(addr).await.unwrap();
+        assert!(closed_within(&mut silent, Duration::from_secs(2)).await);
+    }
+
+    #[tokio::test]
+    async fn requests_slower_than_the_idle_timeout_are_not_cut() {
+        let timeouts = ConnectionTimeouts {
+            header_read: None,
+            idle: Some(Duration::from_millis(300)),
+        };
+        let addr = start(Arc::new(ConnectionLimiter::new(0)), timeouts).await;
+
+        // El handler tarda más que el plazo de inactividad sin enviar nada
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream.write_all(b"GET /slow HTTP/1.1\r\nHost: gateway\r\n\r\n").await.unwrap();
+        let mut buf = [0u8; 1024];
+        let n = stream.read(&mut buf).await.unwrap();
+        let response = String::from_utf8_lossy(&buf[..n]);
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+
+        // Terminada la petición, el reloj de inactividad vuelve a correr
+        assert!(closed_within(&mut stream, Duration::from_secs(2)).await);
+    }
+
+    #[test]
+    fn timeouts_are_disabled_by_default() {
+        assert!(!ConnectionTimeouts::default().is_enabled());
+    }
+}
@@ -0,0 +1,151 @@
+use redis::RedisError;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use crate::cache::{cache_get, cache_set, RedisClient};
+use crate::db::Backend;
+use crate::health::HealthStatus;
+
+/// Estado de salud de un backend publicado en Redis para las demás instancias
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SharedHealth {
+    pub is_healthy: bool,
+    pub probing: bool,
+    pub consecutive_failures: usize,
+    pub consecutive_successes: usize,
+    /// Instancia del gateway que ejecutó el health check
+    pub instance: String,
+}
+
+/// Health state shared between gateway instances through Redis.
+///
+/// For every scheduled check the instances race to claim a short-lived probe
+/// lock; the winner probes the backend and publishes the result, the others
+/// adopt it. Published results expire, so a stale view never outlives the
+/// instance that wrote it by more than a few intervals.
+pub struct SharedHealthStore {
+    redis: RedisClient,
+    instance_id: String,
+    /// Intervalo global de health checks, base del TTL de los resultados
+    default_interval: Duration,
+}
+
+impl SharedHealthStore {
+    pub fn new(redis: RedisClient, default_interval_secs: u64) -> Self {
+        Self {
+            redis,
+            instance_id: uuid::Uuid::new_v4().to_string(),
+            default_interval: Duration::from_secs(default_interval_secs.max(1)),
+        }
+    }
+
+    pub fn instance_id(&self) -> &str {
+        &self.instance_id
+    }
+
+    /// Tries to become the instance that probes `server_id` for this interval
+    pub async fn try_claim_probe(&self, server_id: &str, interval: Duration) -> Result<bool, RedisError> {
+        let key = format!("health:probe_lock:{}", server_id);
+        let (key, instance_id) = (&key, &self.instance_id);
+
+        // Una sola vez: un reintento tras perder la respuesta encontraría nuestro
+        // propio claim y nadie sondearía el backend en este intervalo
+        let claimed: Option<String> = self
+            .redis
+            .run_once(|mut conn| async move {
+                redis::cmd("SET")
+                    .arg(key)
+                    .arg(instance_id)
+                    .arg("NX")
+                    .arg("EX")
+                    .arg(interval.as_secs().max(1))
+                    .query_async(&mut conn)
+                    .await
+            })
+            .await?;
+
+        Ok(claimed.is_some())
+    }
+
+    /// Publishes a probe result, kept for three health check intervals of the backend
+    pub async fn publish(&self, backend: &Backend, status: &HealthStatus) -> Result<(), RedisError> {
+        let shared = SharedHealth {
+            is_healthy: status.is_healthy,
+            probing: status.probing,
+            consecutive_failures: status.consecutive_failures,
+            consecutive_successes: status.consecutive_successes,
+            instance: self.instance_id.clone(),
+        };
+        let value = serde_json::to_string(&shared).unwrap_or_default();
+        let ttl = backend.health_check_interval(self.default_interval.as_secs()) * 3;
+
+        cache_set(&self.redis, &format!("health:shared:{}", backend.server_id), &value, ttl).await
+    }
+
+    /// Reads the last result published by any instance
+    pub async fn fetch(&self, server_id: &str) -> Result<Option<SharedHealth>, RedisError> {
+        let value = cache_get(&self.redis, &format!("health:shared:{}", server_id)).await?;
+        Ok(value.and_then(|v| serde_json::from_str(&v).ok()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_backend;
+    use crate::fake_redis::{FakeRedis, Fault};
+
+    #[tokio::test]
+    async fn only_one_instance_claims_each_probe() {
+        let redis = FakeRedis::start().await;
+        let first = SharedHealthStore::new(redis.client().await, 30);
+        let second = SharedHealthStore::new(redis.client().await, 30);
+        let interval = Duration::from_secs(30);
+
+        assert!(first.try_claim_probe("backend", interval).await.unwrap());
+        assert!(!second.try_claim_probe("backend", interval).await.unwrap());
+        assert!(!first.try_claim_probe("backend", interval).await.unwrap());
+        // Cada backend tiene su propio lock
+        assert!(second.try_claim_probe("other", interval).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn claim_whose_reply_was_lost_is_not_retried() {
+        let redis = FakeRedis::start().await;
+        let store = SharedHealthStore::new(redis.client().await, 30);
+        let other = SharedHealthStore::new(redis.client().await, 30);
+        let interval = Duration::from_secs(30);
+
+        // El SET se aplica pero la respuesta se pierde: error (se sondea localmente), no `false`
+        redis.fail_next(Fault::Lost);
+        assert!(store.try_claim_probe("backend", interval).await.is_err());
+        assert_eq!(redis.command_names(), ["SET"]);
+        assert!(!other.try_claim_probe("backend", interval).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn published_status_is_visible_to_other_instances() {
+        let redis = FakeRedis::start().await;
+        let first = SharedHealthStore::new(redis.client().await, 30);
+        let second = SharedHealthStore::new(redis.client().await, 30);
+        let backend = test_backend("backend");
+
+        assert!(second.fetch("backend").await.unwrap().is_none());
+
+        let status = HealthStatus {
+            is_healthy: false,
+            probing: false,
+            last_check: std::time::Instant::now(),
+            consecutive_failures: 3,
+            consecutive_successes: 0,
+            history: Default::default(),
+        };
+        first.publish(&backend, &status).await.unwrap();
+
+        let shared = second.fetch("backend").await.unwrap().unwrap();
+        assert!(!shared.is_healthy);
+        assert_eq!(shared.consecutive_failures, 3);
+        assert_eq!(shared.instance, first.instance_id());
+        assert_ne!(first.instance_id(), second.instance_id());
+    }
+}
@@ -0,0 +1,473 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::Request,
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode},
+    response::Response,
+};
+use http_body::{Frame, SizeHint};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+use tokio::time::Instant;
+
+use crate::{config::redact_url, db::Backend, error_pages::PreserveBody};
+
+/// Headers de la petición que distinguen entradas: una copia solo se sirve a
+/// quien envió las mismas credenciales y acepta la misma representación
+const VARY_HEADERS: [header::HeaderName; 5] = [
+    header::AUTHORIZATION,
+    header::COOKIE,
+    header::ACCEPT,
+    header::ACCEPT_ENCODING,
+    header::HeaderName::from_static("x-upload-token"),
+];
+
+/// Respuestas stale ante errores de los backends (stale-if-error)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StaleConfig {
+    pub enabled: bool,
+    /// Antigüedad máxima de una copia servida
+    pub max_age_secs: u64,
+    /// Respuestas guardadas como máximo (por instancia)
+    pub max_entries: usize,
+    /// Tamaño máximo del body de una respuesta guardada
+    pub max_body_bytes: usize,
+}
+
+impl Default for StaleConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_age_secs: 300,
+            max_entries: 1000,
+            max_body_bytes: 256 * 1024,
+        }
+    }
+}
+
+/// Entrada del caché: backend, URL pedida y hash de los headers de `VARY_HEADERS`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct StaleKey {
+    server_id: String,
+    url: String,
+    vary: u64,
+}
+
+struct CachedResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+    stored_at: Instant,
+}
+
+#[derive(Default)]
+struct StaleCounters {
+    stored: AtomicU64,
+    served: AtomicU64,
+}
+
+/// In-memory copy of the last successful GET response of each backend URL,
+/// served with `Warning: 110` when the backend fails (`SERVE_STALE_ON_ERROR`)
+pub struct StaleCache {
+    config: StaleConfig,
+    entries: Arc<Mutex<HashMap<StaleKey, CachedResponse>>>,
+    hasher: std::collections::hash_map::RandomState,
+    counters: Arc<StaleCounters>,
+}
+
+impl StaleCache {
+    pub fn new(config: StaleConfig) -> Self {
+        Self {
+            config,
+            entries: Arc::default(),
+            hasher: Default::default(),
+            counters: Arc::default(),
+        }
+    }
+
+    fn max_age(&self) -> Duration {
+        Duration::from_secs(self.config.max_age_secs)
+    }
+
+    /// Cache key of a request to `backend_url`, `None` when the request cannot
+    /// use the cache: disabled, not a GET, a range or a `no-store` request
+    pub fn key(&self, backend: &Backend, req: &Request, backend_url: &str) -> Option<StaleKey> {
+        if !self.config.enabled || req.method() != Method::GET || req.headers().contains_key(header::RANGE) {
+            return None;
+        }
+        if has_directive(req.headers(), "no-store") {
+            return None;
+        }
+
+        let mut hasher = self.hasher.build_hasher();
+        for name in &VARY_HEADERS {
+            for value in req.headers().get_all(name) {
+                name.hash(&mut hasher);
+                value.as_bytes().hash(&mut hasher);
+            }
+        }
+        Some(StaleKey {
+            server_id: backend.server_id.clone(),
+            url: backend_url.to_string(),
+            vary: hasher.finish(),
+        })
+    }
+
+    /// Copies the body of a successful response as it streams to the client,
+    /// and stores it once complete. Responses marked `no-store`, setting
+    /// cookies or larger than `max_body_bytes` are not stored.
+    pub fn record(&self, key: Option<StaleKey>, response: Response) -> Response {
+        let Some(key) = key else {
+            return response;
+        };
+        let headers = response.headers();
+        if response.status() != StatusCode::OK
+            || headers.contains_key(header::SET_COOKIE)
+            || has_directive(headers, "no-store")
+        {
+            return response;
+        }
+        let length = headers
+            .get(header::CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<usize>().ok());
+        if length.is_some_and(|length| length > self.config.max_body_bytes) {
+            return response;
+        }
+
+        let (parts, body) = response.into_parts();
+        let recording = RecordingBody {
+            inner: body,
+            key: Some(key),
+            status: parts.status,
+            headers: parts.headers.clone(),
+            body: Vec::with_capacity(length.unwrap_or(0)),
+            max_body_bytes: self.config.max_body_bytes,
+            max_entries: self.config.max_entries,
+            max_age: self.max_age(),
+            entries: self.entries.clone(),
+            counters: self.counters.clone(),
+        };
+        Response::from_parts(parts, Body::new(recording))
+    }
+
+    /// Replaces a backend failure (a 5xx response, or a 502/503/504 from the
+    /// gateway) with the stored copy when it is younger than `max_age_secs`
+    pub fn serve_on_error(
+        &self,
+        key: Option<&StaleKey>,
+        result: Result<Response, StatusCode>,
+    ) -> Result<Response, StatusCode> {
+        let Some(key) = key else {
+            return result;
+        };
+        let failed = match &result {
+            Ok(response) => response.status().is_server_error(),
+            Err(status) => matches!(
+                *status,
+                StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT
+            ),
+        };
+        if !failed {
+            return result;
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        let Some(cached) = entries.get(key) else {
+            return result;
+        };
+        let age = cached.stored_at.elapsed();
+        if age > self.max_age() {
+            entries.remove(key);
+            return result;
+        }
+
+        let mut response = Response::new(Body::from(cached.body.clone()));
+        *response.status_mut() = cached.status;
+        *response.headers_mut() = cached.headers.clone();
+        drop(entries);
+
+        let headers = response.headers_mut();
+        headers.insert(header::AGE, HeaderValue::from(age.as_secs()));
+        headers.append(header::WARNING, HeaderValue::from_static("110 - \"Response is Stale\""));
+        response.extensions_mut().insert(PreserveBody);
+
+        self.counters.served.fetch_add(1, Ordering::Relaxed);
+        tracing::warn!(
+            "Backend {} failed, serving a stale response {}s old for {}",
+            key.server_id,
+            age.as_secs(),
+            redact_url(&key.url)
+        );
+        Ok(response)
+    }
+
+    /// Counters for the stats endpoint
+    pub fn snapshot(&self) -> serde_json::Value {
+        serde_json::json!({
+            "enabled": self.config.enabled,
+            "entries": self.entries.lock().unwrap().len(),
+            "stored": self.counters.stored.load(Ordering::Relaxed),
+            "served": self.counters.served.load(Ordering::Relaxed),
+        })
+    }
+}
+
+/// Whether a `Cache-Control` header carries `directive`
+fn has_directive(headers: &HeaderMap, directive: &str) -> bool {
+    headers
+        .get_all(header::CACHE_CONTROL)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|d| d.trim().eq_ignore_ascii_case(directive))
+}
+
+/// Body que copia la respuesta mientras se transmite y la guarda al completarse.
+/// Si supera `max_body_bytes` o se corta antes del final, no se guarda.
+struct RecordingBody {
+    inner: Body,
+    key: Option<StaleKey>,
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Vec<u8>,
+    max_body_bytes: usize,
+    max_entries: usize,
+    max_age: Duration,
+    entries: Arc<Mutex<HashMap<StaleKey, CachedResponse>>>,
+    counters: Arc<StaleCounters>,
+}
+
+impl RecordingBody {
+    fn store(&mut self) {
+        let Some(key) = self.key.take().filter(|_| self.max_entries > 0) else {
+            return;
+        };
+
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= self.max_entries && !entries.contains_key(&key) {
+            // Primero las caducadas; si no hay, la más antigua
+            let max_age = self.max_age;
+            entries.retain(|_, cached| cached.stored_at.elapsed() <= max_age);
+            if entries.len() >= self.max_entries {
+                let oldest = entries.iter().min_by_key(|(_, cached)| cached.stored_at).map(|(k, _)| k.clone());
+                if let Some(oldest) = oldest {
+                    entries.remove(&oldest);
+                }
+            }
+        }
+
+        entries.insert(
+            key,
+            CachedResponse {
+                status: self.status,
+                headers: std::mem::take(&mut self.headers),
+                body: Bytes::from(std::mem::take(&mut self.body)),
+                stored_at: Instant::now(),
+            },
+        );
+        self.counters.stored.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl http_body::Body for RecordingBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let frame = ready!(Pin::new(&mut self.inner).poll_frame(cx));
+        match &frame {
+            Some(Ok(frame)) => {
+                if let Some(data) = frame.data_ref() {
+                    if self.body.len() + data.len() > self.max_body_bytes {
+                        self.key = None;
+                        self.body = Vec::new();
+                    } else if self.key.is_some() {
+                        self.body.extend_from_slice(data);
+                    }
+                }
+            }
+            // Un error del backend deja la copia incompleta
+            Some(Err(_)) => self.key = None,
+            None => self.store(),
+        }
+        Poll::Ready(frame)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_backend;
+    use http_body_util::BodyExt;
+
+    fn cache() -> StaleCache {
+        StaleCache::new(StaleConfig {
+            enabled: true,
+            ..StaleConfig::default()
+        })
+    }
+
+    fn get(headers: &[(&'static str, &'static str)]) -> Request {
+        let mut req = Request::new(Body::empty());
+        for (name, value) in headers {
+            req.headers_mut().append(*name, HeaderValue::from_static(value));
+        }
+        req
+    }
+
+    fn ok(body: &'static str) -> Response {
+        let mut response = Response::new(Body::from(body));
+        response.headers_mut().insert(header::CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        response
+    }
+
+    /// Sends `response` through the cache and reads it like a client would
+    async fn record(cache: &StaleCache, key: &StaleKey, response: Response) -> Bytes {
+        let response = cache.record(Some(key.clone()), response);
+        response.into_body().collect().await.unwrap().to_bytes()
+    }
+
+    async fn body_of(response: Response) -> Bytes {
+        response.into_body().collect().await.unwrap().to_bytes()
+    }
+
+    #[tokio::test]
+    async fn backend_error_serves_the_stale_copy() {
+        let cache = cache();
+        let backend = test_backend("a");
+        let key = cache.key(&backend, &get(&[]), "http://a.internal/files/1").unwrap();
+        assert_eq!(record(&cache, &key, ok(r#"{"id":1}"#)).await, r#"{"id":1}"#);
+
+        let response = cache.serve_on_error(Some(&key), Err(StatusCode::BAD_GATEWAY)).unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::WARNING], "110 - \"Response is Stale\"");
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/json");
+        assert!(response.headers().contains_key(header::AGE));
+        assert!(response.extensions().get::<PreserveBody>().is_some());
+        assert_eq!(body_of(response).await, r#"{"id":1}"#);
+    }
+
+    #[tokio::test]
+    async fn backend_5xx_response_is_replaced() {
+        let cache = cache();
+        let key = cache.key(&test_backend("a"), &get(&[]), "http://a.internal/files/1").unwrap();
+        record(&cache, &key, ok("fresh")).await;
+
+        let mut failure = Response::new(Body::from("boom"));
+        *failure.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+        let response = cache.serve_on_error(Some(&key), Ok(failure)).unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body_of(response).await, "fresh");
+    }
+
+    #[tokio::test]
+    async fn successful_and_client_error_responses_pass_through() {
+        let cache = cache();
+        let key = cache.key(&test_backend("a"), &get(&[]), "http://a.internal/files/1").unwrap();
+        record(&cache, &key, ok("old")).await;
+
+        let response = cache.serve_on_error(Some(&key), Ok(ok("new"))).unwrap();
+        assert_eq!(body_of(response).await, "new");
+        assert_eq!(cache.serve_on_error(Some(&key), Err(StatusCode::NOT_FOUND)).unwrap_err(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn copies_older_than_max_age_are_not_served() {
+        let cache = cache();
+        let key = cache.key(&test_backend("a"), &get(&[]), "http://a.internal/files/1").unwrap();
+        record(&cache, &key, ok("old")).await;
+
+        tokio::time::advance(Duration::from_secs(301)).await;
+        assert_eq!(
+            cache.serve_on_error(Some(&key), Err(StatusCode::GATEWAY_TIMEOUT)).unwrap_err(),
+            StatusCode::GATEWAY_TIMEOUT
+        );
+    }
+
+    #[tokio::test]
+    async fn copies_are_not_shared_across_credentials_or_backends() {
+        let cache = cache();
+        let alice = cache.key(&test_backend("a"), &get(&[("authorization", "Bearer alice")]), "http://a.internal/f").unwrap();
+        let bob = cache.key(&test_backend("a"), &get(&[("authorization", "Bearer bob")]), "http://a.internal/f").unwrap();
+        let other = cache.key(&test_backend("b"), &get(&[("authorization", "Bearer alice")]), "http://a.internal/f").unwrap();
+        record(&cache, &alice, ok("alice")).await;
+
+        assert!(cache.serve_on_error(Some(&bob), Err(StatusCode::BAD_GATEWAY)).is_err());
+        assert!(cache.serve_on_error(Some(&other), Err(StatusCode::BAD_GATEWAY)).is_err());
+        assert!(cache.serve_on_error(Some(&alice), Err(StatusCode::BAD_GATEWAY)).is_ok());
+    }
+
+    #[tokio::test]
+    async fn uncacheable_requests_and_responses_are_skipped() {
+        let cache = cache();
+        let backend = test_backend("a");
+        let mut post = get(&[]);
+        *post.method_mut() = Method::POST;
+        assert!(cache.key(&backend, &post, "http://a.internal/f").is_none());
+        assert!(cache.key(&backend, &get(&[("range", "bytes=0-10")]), "http://a.internal/f").is_none());
+        assert!(cache.key(&backend, &get(&[("cache-control", "no-store")]), "http://a.internal/f").is_none());
+        assert!(StaleCache::new(StaleConfig::default()).key(&backend, &get(&[]), "http://a.internal/f").is_none());
+
+        let key = cache.key(&backend, &get(&[]), "http://a.internal/f").unwrap();
+        let mut no_store = ok("secret");
+        no_store.headers_mut().insert(header::CACHE_CONTROL, HeaderValue::from_static("private, no-store"));
+        record(&cache, &key, no_store).await;
+        let mut cookie = ok("session");
+        cookie.headers_mut().insert(header::SET_COOKIE, HeaderValue::from_static("id=1"));
+        record(&cache, &key, cookie).await;
+
+        assert!(cache.serve_on_error(Some(&key), Err(StatusCode::BAD_GATEWAY)).is_err());
+    }
+
+    #[tokio::test]
+    async fn large_bodies_are_streamed_but_not_stored() {
+        let cache = StaleCache::new(StaleConfig {
+            enabled: true,
+            max_body_bytes: 4,
+            ..StaleConfig::default()
+        });
+        let key = cache.key(&test_backend("a"), &get(&[]), "http://a.internal/f").unwrap();
+
+        assert_eq!(record(&cache, &key, ok("too large")).await, "too large");
+        assert!(cache.serve_on_error(Some(&key), Err(StatusCode::BAD_GATEWAY)).is_err());
+    }
+
+    #[tokio::test]
+    async fn oldest_entry_is_evicted_when_full() {
+        let cache = StaleCache::new(StaleConfig {
+            enabled: true,
+            max_entries: 2,
+            ..StaleConfig::default()
+        });
+        let backend = test_backend("a");
+        let keys: Vec<_> = ["/1", "/2", "/3"]
+            .iter()
+            .map(|path| cache.key(&backend, &get(&[]), &format!("http://a.internal{}", path)).unwrap())
+            .collect();
+        for key in &keys {
+            record(&cache, key, ok("body")).await;
+            tokio::time::sleep(Duration::from_millis(2)).await;
+        }
+
+        assert!(cache.serve_on_error(Some(&keys[0]), Err(StatusCode::BAD_GATEWAY)).is_err());
+        assert!(cache.serve_on_error(Some(&keys[1]), Err(StatusCode::BAD_GATEWAY)).is_ok());
+        assert!(cache.serve_on_error(Some(&keys[2]), Err(StatusCode::BAD_GATEWAY)).is_ok());
+    }
+}
@@ -0,0 +1,178 @@
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::proxy::ProxyState;
+
+/// Peticiones atendidas por el puerto público desde el arranque
+#[derive(Debug, Default)]
+pub struct RequestMetrics {
+    pub requests: AtomicU64,
+    /// Respuestas 5xx, incluidas las generadas por el gateway
+    pub server_errors: AtomicU64,
+}
+
+impl RequestMetrics {
+    /// Current counter values for the stats endpoint
+    pub fn snapshot(&self) -> serde_json::Value {
+        serde_json::json!({
+            "total": self.requests.load(Ordering::Relaxed),
+            "server_errors": self.server_errors.load(Ordering::Relaxed),
+        })
+    }
+}
+
+/// Middleware that counts every request and its 5xx responses
+pub async fn count_requests(State(metrics): State<Arc<RequestMetrics>>, req: Request, next: Next) -> Response {
+    metrics.requests.fetch_add(1, Ordering::Relaxed);
+    let response = next.run(req).await;
+    if response.status().is_server_error() {
+        metrics.server_errors.fetch_add(1, Ordering::Relaxed);
+    }
+    response
+}
+
+/// Logs a summary of the gateway state every `interval`, so deployments
+/// without a metrics stack still get passive observability from their logs
+pub fn start_stats_log(state: ProxyState, interval: Duration) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        // El primer tick es inmediato: no hay nada que resumir todavía
+        ticker.tick().await;
+
+        let mut last_requests = state.request_metrics.requests.load(Ordering::Relaxed);
+        let mut last_errors = state.request_metrics.server_errors.load(Ordering::Relaxed);
+
+        loop {
+            ticker.tick().await;
+
+            let requests_total = state.request_metrics.requests.load(Ordering::Relaxed);
+            let errors_total = state.request_metrics.server_errors.load(Ordering::Relaxed);
+            let requests = requests_total.saturating_sub(last_requests);
+            let errors = errors_total.saturating_sub(last_errors);
+            (last_requests, last_errors) = (requests_total, errors_total);
+
+            let backends = state.backends.all();
+            let healthy = state.health_checker.get_healthy_backends(&backends).await.len();
+
+            // Circuitos abiertos o probando su recuperación
+            let mut tripped = Vec::new();
+            let db_circuit = state.db_circuit.snapshot();
+            if matches!(db_circuit["state"].as_str(), Some("open" | "half_open")) {
+                tripped.push("db");
+            }
+
+            tracing::info!(
+                backends_total = backends.len(),
+                backends_healthy = healthy,
+                requests,
+                errors,
+                error_rate = if requests > 0 { errors as f64 / requests as f64 } else { 0.0 },
+                tripped_circuits = ?tripped,
+                disabled_providers = ?state.provider_switch.snapshot(),
+                "Gateway stats for the last {:?}",
+                interval
+            );
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_backend;
+    use crate::proxy::{test_config, test_state};
+    use axum::{body::Body, http::StatusCode, routing::get, Router};
+    use std::sync::Mutex;
+    use tower::ServiceExt;
+
+    /// Destino de los logs de un test, para inspeccionar lo que se emitió
+    #[derive(Clone, Default)]
+    struct LogBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for LogBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl LogBuffer {
+        fn lines(&self) -> Vec<String> {
+            String::from_utf8_lossy(&self.0.lock().unwrap())
+                .lines()
+                .filter(|line| line.contains("Gateway stats"))
+                .map(str::to_string)
+                .collect()
+        }
+    }
+
+    #[tokio::test]
+    async fn snapshot_is_logged_after_each_interval() {
+        let logs = LogBuffer::default();
+        let writer = logs.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(move || writer.clone())
+            .with_ansi(false)
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let state = test_state(test_config(), &[test_backend("a"), test_backend("b")]).await;
+        state.health_checker.set_override("a", false, None).await;
+        // Lo anterior al arranque de la tarea no cuenta en la primera ventana
+        state.request_metrics.requests.store(10, Ordering::Relaxed);
+
+        start_stats_log(state.clone(), Duration::from_millis(400));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(logs.lines().is_empty());
+
+        state.request_metrics.requests.fetch_add(4, Ordering::Relaxed);
+        state.request_metrics.server_errors.fetch_add(1, Ordering::Relaxed);
+        tokio::time::sleep(Duration::from_millis(550)).await;
+
+        let lines = logs.lines();
+        assert_eq!(lines.len(), 1, "{:?}", lines);
+        for field in [
+            "backends_total=2",
+            "backends_healthy=1",
+            "requests=4",
+            "errors=1",
+            "error_rate=0.25",
+            "tripped_circuits=[]",
+        ] {
+            assert!(lines[0].contains(field), "{} missing in {}", field, lines[0]);
+        }
+
+        // La siguiente ventana solo cuenta lo nuevo
+        tokio::time::sleep(Duration::from_millis(400)).await;
+        let lines = logs.lines();
+        assert_eq!(lines.len(), 2, "{:?}", lines);
+        assert!(lines[1].contains("requests=0"), "{}", lines[1]);
+    }
+
+    #[tokio::test]
+    async fn requests_and_server_errors_are_counted() {
+        let metrics = Arc::new(RequestMetrics::default());
+        let app = Router::new()
+            .route("/ok", get(|| async { StatusCode::OK }))
+            .route("/fail", get(|| async { StatusCode::BAD_GATEWAY }))
+            .route("/missing", get(|| async { StatusCode::NOT_FOUND }))
+            .layer(axum::middleware::from_fn_with_state(metrics.clone(), count_requests));
+
+        for uri in ["/ok", "/fail", "/missing", "/fail"] {
+            let req = Request::builder().uri(uri).body(Body::empty()).unwrap();
+            app.clone().oneshot(req).await.unwrap();
+        }
+
+        assert_eq!(metrics.snapshot(), serde_json::json!({"total": 4, "server_errors": 2}));
+    }
+}
@@ -0,0 +1,149 @@
+use axum::http::{header, HeaderMap, HeaderValue};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Configuración de sticky sessions basadas en cookie
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct StickyConfig {
+    pub enabled: bool,
+    pub cookie_name: String,
+    pub cookie_ttl_secs: u64,
+}
+
+impl Default for StickyConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            cookie_name: "GATEWAY_ROUTE".to_string(),
+            cookie_ttl_secs: 3600,
+        }
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn sign(secret: &str, payload: &str) -> HmacSha256 {
+    let mut mac =
+        HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(payload.as_bytes());
+    mac
+}
+
+/// Encodes a route cookie value as `{server_id}.{expires_at}.{hex(hmac)}`
+pub fn encode_route(secret: &str, server_id: &str, ttl_secs: u64) -> String {
+    let payload = format!("{}.{}", server_id, now_secs() + ttl_secs);
+    let signature = hex::encode(sign(secret, &payload).finalize().into_bytes());
+    format!("{}.{}", payload, signature)
+}
+
+/// Returns the pinned server_id if the cookie value is well formed, correctly
+/// signed and not expired
+pub fn decode_route(secret: &str, value: &str) -> Option<String> {
+    let (payload, signature) = value.rsplit_once('.')?;
+    let (server_id, expires_at) = payload.rsplit_once('.')?;
+
+    let signature = hex::decode(signature).ok()?;
+    sign(secret, payload).verify_slice(&signature).ok()?;
+
+    if expires_at.parse::<u64>().ok()? < now_secs() {
+        return None;
+    }
+
+    Some(server_id.to_string())
+}
+
+/// Reads a cookie by name from the request `Cookie` headers
+pub fn read_cookie(headers: &HeaderMap, name: &str) -> Option<String> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value.to_string())
+}
+
+/// Builds the `Set-Cookie` header that pins the client to `server_id`
+pub fn route_cookie(config: &StickyConfig, secret: &str, server_id: &str) -> Option<HeaderValue> {
+    let value = encode_route(secret, server_id, config.cookie_ttl_secs);
+    HeaderValue::from_str(&format!(
+        "{}={}; Path=/; Max-Age={}; HttpOnly; SameSite=Lax",
+        config.cookie_name, value, config.cookie_ttl_secs
+    ))
+    .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "sticky-secret";
+
+    #[test]
+    fn signed_route_round_trips() {
+        let value = encode_route(SECRET, "backend-1", 60);
+        assert_eq!(decode_route(SECRET, &value).as_deref(), Some("backend-1"));
+        assert_eq!(decode_route("other-secret", &value), None);
+    }
+
+    #[test]
+    fn server_ids_with_dots_round_trip() {
+        let value = encode_route(SECRET, "node.eu-1", 60);
+        assert_eq!(decode_route(SECRET, &value).as_deref(), Some("node.eu-1"));
+    }
+
+    #[test]
+    fn tampered_cookie_is_rejected() {
+        let value = encode_route(SECRET, "backend-1", 60);
+        let (payload, signature) = value.rsplit_once('.').unwrap();
+
+        let repinned = payload.replacen("backend-1", "backend-2", 1);
+        assert_eq!(decode_route(SECRET, &format!("{}.{}", repinned, signature)), None);
+
+        let (server_id, expires_at) = payload.rsplit_once('.').unwrap();
+        let extended = format!("{}.{}", server_id, expires_at.parse::<u64>().unwrap() + 3600);
+        assert_eq!(decode_route(SECRET, &format!("{}.{}", extended, signature)), None);
+
+        assert_eq!(decode_route(SECRET, &format!("{}.not-hex", payload)), None);
+        assert_eq!(decode_route(SECRET, "garbage"), None);
+    }
+
+    #[test]
+    fn expired_cookie_is_rejected() {
+        let payload = format!("backend-1.{}", now_secs() - 1);
+        let signature = hex::encode(sign(SECRET, &payload).finalize().into_bytes());
+        assert_eq!(decode_route(SECRET, &format!("{}.{}", payload, signature)), None);
+    }
+
+    #[test]
+    fn cookie_is_read_among_others() {
+        let mut headers = HeaderMap::new();
+        headers.append(header::COOKIE, HeaderValue::from_static("theme=dark; GATEWAY_ROUTE=abc"));
+        headers.append(header::COOKIE, HeaderValue::from_static("lang=es"));
+        assert_eq!(read_cookie(&headers, "GATEWAY_ROUTE").as_deref(), Some("abc"));
+        assert_eq!(read_cookie(&headers, "lang").as_deref(), Some("es"));
+        assert_eq!(read_cookie(&headers, "missing"), None);
+    }
+
+    #[test]
+    fn route_cookie_pins_with_the_configured_name_and_ttl() {
+        let config = StickyConfig { enabled: true, cookie_name: "ROUTE".to_string(), cookie_ttl_secs: 120 };
+        let cookie = route_cookie(&config, SECRET, "backend-1").unwrap();
+        let cookie = cookie.to_str().unwrap();
+        assert!(cookie.starts_with("ROUTE="));
+        assert!(cookie.contains("Max-Age=120"));
+        assert!(cookie.contains("HttpOnly"));
+
+        let value = cookie.trim_start_matches("ROUTE=").split(';').next().unwrap();
+        assert_eq!(decode_route(SECRET, value).as_deref(), Some("backend-1"));
+    }
+}
@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::db::Backend;
+
+/// Token bucket de un backend. La capacidad es un segundo de peticiones, así
+/// que admite ráfagas cortas sin superar `max_rps` de media.
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    /// Peticiones que esperaron un token
+    delayed: u64,
+    /// Peticiones rechazadas con 503 tras agotar la espera
+    rejected: u64,
+}
+
+impl Bucket {
+    fn refill(&mut self, rate: f64) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(rate.max(1.0));
+        self.updated = now;
+    }
+}
+
+/// Limita las peticiones por segundo que el gateway envía a cada backend con
+/// `max_rps`, para no agotar la cuota de APIs de terceros (p. ej. Google Drive)
+pub struct OutboundThrottle {
+    /// Espera máxima por un token antes de rechazar la petición
+    max_wait: Duration,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl OutboundThrottle {
+    pub fn new(max_wait: Duration) -> Self {
+        Self {
+            max_wait,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Whether `backend` can take a request right now, without consuming a
+    /// token. Used to steer load-balanced requests to backends with capacity.
+    pub fn has_capacity(&self, backend: &Backend) -> bool {
+        let Some(rate) = backend.max_rps() else {
+            return true;
+        };
+        let mut buckets = self.buckets.lock().unwrap();
+        match buckets.get_mut(&backend.server_id) {
+            Some(bucket) => {
+                bucket.refill(rate);
+                bucket.tokens >= 1.0
+            }
+            None => true,
+        }
+    }
+
+    /// Takes a token for a request to `backend`, waiting up to `max_wait` for
+    /// one. Returns `false` when the backend stays saturated.
+    pub async fn acquire(&self, backend: &Backend) -> bool {
+        let Some(rate) = backend.max_rps() else {
+            return true;
+        };
+        let deadline = Instant::now() + self.max_wait;
+        let mut waited = false;
+
+        loop {
+            let wait = {
+                let mut buckets = self.buckets.lock().unwrap();
+                let bucket = buckets.entry(backend.server_id.clone()).or_insert_with(|| Bucket {
+                    tokens: rate.max(1.0),
+                    updated: Instant::now(),
+                    delayed: 0,
+                    rejected: 0,
+                });
+                bucket.refill(rate);
+                if bucket.tokens >= 1.0 {
+                    bucket.tokens -= 1.0;
+                    if waited {
+                        bucket.delayed += 1;
+                    }
+                    return true;
+                }
+
+                // Tiempo hasta el siguiente token; otra petición puede ganarlo antes
+                let wait = Duration::from_secs_f64((1.0 - bucket.tokens) / rate);
+                if Instant::now() + wait > deadline {
+                    bucket.rejected += 1;
+                    tracing::warn!(
+                        "Backend {} is at its {} requests/s limit, rejecting request",
+                        backend.server_id,
+                        rate
+                    );
+                    return false;
+                }
+                wait
+            };
+
+            waited = true;
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Counters per throttled backend for the stats endpoint
+    pub fn snapshot(&self) -> serde_json::Value {
+        let buckets = self.buckets.lock().unwrap();
+        let by_backend: serde_json::Map<_, _> = buckets
+            .iter()
+            .map(|(server_id, bucket)| {
+                let counters = serde_json::json!({
+                    "delayed": bucket.delayed,
+                    "rejected": bucket.rejected,
+                });
+                (server_id.clone(), counters)
+            })
+            .collect();
+        serde_json::json!({
+            "max_wait_ms": self.max_wait.as_millis() as u64,
+            "backends": by_backend,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_backend;
+
+    fn capped(server_id: &str, max_rps: f64) -> Backend {
+        let mut backend = test_backend(server_id);
+        backend.max_rps = Some(max_rps);
+        backend
+    }
+
+    #[tokio::test]
+    async fn outbound_rate_stays_under_the_cap() {
+        let throttle = OutboundThrottle::new(Duration::from_secs(1));
+        let backend = capped("drive", 100.0);
+
+        // Un segundo de ráfaga pasa sin esperar; el resto sale a 100 por segundo
+        let started = Instant::now();
+        for _ in 0..150 {
+            assert!(throttle.acquire(&backend).await);
+        }
+        let elapsed = started.elapsed();
+        assert!(elapsed >= Duration::from_millis(450), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_millis(1500), "{:?}", elapsed);
+
+        // Con la máquina cargada la ráfaga tarda y recarga algún token: menos esperas
+        let snapshot = throttle.snapshot();
+        let delayed = snapshot["backends"]["drive"]["delayed"].as_u64().unwrap();
+        assert!((40..=50).contains(&delayed), "{}", delayed);
+        assert_eq!(snapshot["backends"]["drive"]["rejected"], 0);
+    }
+
+    #[tokio::test]
+    async fn requests_are_rejected_once_the_wait_would_exceed_max_wait() {
+        let throttle = OutboundThrottle::new(Duration::from_millis(100));
+        let backend = capped("quota", 1.0);
+
+        assert!(throttle.acquire(&backend).await);
+        let started = Instant::now();
+        assert!(!throttle.acquire(&backend).await);
+        // El siguiente token tarda un segundo: se rechaza sin esperar
+        assert!(started.elapsed() < Duration::from_millis(50));
+
+        let snapshot = throttle.snapshot();
+        assert_eq!(snapshot["max_wait_ms"], 100);
+        assert_eq!(snapshot["backends"]["quota"]["rejected"], 1);
+    }
+
+    #[tokio::test]
+    async fn has_capacity_does_not_consume_tokens() {
+        let throttle = OutboundThrottle::new(Duration::ZERO);
+        let backend = capped("single", 1.0);
+
+        assert!(throttle.has_capacity(&backend));
+        assert!(throttle.has_capacity(&backend));
+        assert!(throttle.acquire(&backend).await);
+        assert!(!throttle.has_capacity(&backend));
+        assert!(!throttle.acquire(&backend).await);
+
+        // Cada backend tiene su propio bucket
+        assert!(throttle.has_capacity(&capped("other", 1.0)));
+    }
+
+    #[tokio::test]
+    async fn backends_without_a_cap_are_not_throttled() {
+        let throttle = OutboundThrottle::new(Duration::ZERO);
+        for backend in [test_backend("free"), capped("zero", 0.0)] {
+            for _ in 0..1000 {
+                assert!(throttle.acquire(&backend).await);
+            }
+            assert!(throttle.has_capacity(&backend));
+        }
+        assert_eq!(throttle.snapshot()["backends"], serde_json::json!({}));
+    }
+}
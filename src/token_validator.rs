@@ -0,0 +1,119 @@
+use async_trait::async_trait;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::sync::Arc;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Valida los tokens de subida antes de que el rate limiter les asigne contadores.
+/// Implementa este trait para otros esquemas (p. ej. una consulta a la base de datos).
+#[async_trait]
+pub trait TokenValidator: Send + Sync {
+    /// Whether `token` is acceptable. Invalid tokens are rejected with `401`
+    /// before any Redis work.
+    async fn validate(&self, token: &str) -> bool;
+
+    /// Retorna el nombre del validador
+    fn name(&self) -> &str;
+}
+
+/// Acepta cualquier token (comportamiento por defecto)
+pub struct AcceptAllValidator;
+
+#[async_trait]
+impl TokenValidator for AcceptAllValidator {
+    async fn validate(&self, _token: &str) -> bool {
+        true
+    }
+
+    fn name(&self) -> &str {
+        "none"
+    }
+}
+
+/// Acepta tokens `{payload}.{hex(hmac_sha256(secret, payload))}` firmados con el secreto
+pub struct HmacValidator {
+    secret: String,
+}
+
+impl HmacValidator {
+    pub fn new(secret: &str) -> Self {
+        Self {
+            secret: secret.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl TokenValidator for HmacValidator {
+    async fn validate(&self, token: &str) -> bool {
+        let Some((payload, signature)) = token.rsplit_once('.') else {
+            return false;
+        };
+        let Ok(signature) = hex::decode(signature) else {
+            return false;
+        };
+
+        let mut mac =
+            HmacSha256::new_from_slice(self.secret.as_bytes()).expect("HMAC accepts keys of any size");
+        mac.update(payload.as_bytes());
+        mac.verify_slice(&signature).is_ok()
+    }
+
+    fn name(&self) -> &str {
+        "hmac"
+    }
+}
+
+/// Factory para crear el validador configurado en `TOKEN_VALIDATOR`.
+/// `hmac` firma con `VK_SECRET`, así que lo requiere.
+pub fn create_token_validator(kind: &str, secret: Option<&str>) -> Result<Arc<dyn TokenValidator>, anyhow::Error> {
+    match kind.trim().to_lowercase().as_str() {
+        "" | "none" => Ok(Arc::new(AcceptAllValidator)),
+        "hmac" => match secret {
+            Some(secret) => Ok(Arc::new(HmacValidator::new(secret))),
+            None => Err(anyhow::anyhow!("TOKEN_VALIDATOR=hmac requires VK_SECRET")),
+        },
+        other => Err(anyhow::anyhow!("Unknown TOKEN_VALIDATOR '{}', expected none or hmac", other)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Token `{payload}.{signature}` signed with `secret`
+    fn signed(secret: &str, payload: &str) -> String {
+        let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(payload.as_bytes());
+        format!("{}.{}", payload, hex::encode(mac.finalize().into_bytes()))
+    }
+
+    #[tokio::test]
+    async fn hmac_accepts_only_tokens_signed_with_the_secret() {
+        let validator = HmacValidator::new("s3cret");
+
+        assert!(validator.validate(&signed("s3cret", "user-42")).await);
+        // El payload puede contener puntos: la firma va tras el último
+        assert!(validator.validate(&signed("s3cret", "user.42.exp")).await);
+        assert!(!validator.validate(&signed("other", "user-42")).await);
+        assert!(!validator.validate(&signed("s3cret", "user-42").replace("user-42", "user-43")).await);
+    }
+
+    #[tokio::test]
+    async fn malformed_tokens_are_rejected() {
+        let validator = HmacValidator::new("s3cret");
+
+        for token in ["", "user-42", "user-42.", "user-42.not-hex", "user-42.abcd"] {
+            assert!(!validator.validate(token).await, "{:?}", token);
+        }
+    }
+
+    #[test]
+    fn factory_requires_a_secret_for_hmac() {
+        assert_eq!(create_token_validator("", None).unwrap().name(), "none");
+        assert_eq!(create_token_validator(" HMAC ", Some("s3cret")).unwrap().name(), "hmac");
+        assert!(create_token_validator("hmac", None).is_err());
+        assert!(create_token_validator("jwt", Some("s3cret")).is_err());
+    }
+}
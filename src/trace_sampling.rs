@@ -0,0 +1,125 @@
+use axum::{extract::Request, middleware::Next, response::Response};
+
+tokio::task_local! {
+    /// Si la petición en curso registra sus logs de proxy a nivel info
+    static SAMPLED: bool;
+}
+
+/// Middleware that decides once per request, with probability `rate`
+/// (`TRACE_SAMPLE_RATE`), whether its proxy logs go out at info level
+pub async fn sample_request(rate: f64, req: Request, next: Next) -> Response {
+    SAMPLED.scope(sampled(rate), next.run(req)).await
+}
+
+/// Whether the current request was sampled. Outside a request (background
+/// tasks, spawned retries) everything is logged.
+pub fn is_sampled() -> bool {
+    SAMPLED.try_with(|sampled| *sampled).unwrap_or(true)
+}
+
+fn sampled(rate: f64) -> bool {
+    if rate >= 1.0 {
+        return true;
+    }
+    if rate <= 0.0 {
+        return false;
+    }
+
+    use std::collections::hash_map::RandomState;
+    use std::hash::BuildHasher;
+
+    let hash = RandomState::new().hash_one(std::time::SystemTime::now());
+    (hash as f64 / u64::MAX as f64) < rate
+}
+
+/// `tracing::info!` for the sampled requests and `tracing::debug!` for the
+/// rest. Only for routine per-request logs: warnings and errors always go out.
+macro_rules! request_info {
+    ($($arg:tt)+) => {
+        if $crate::trace_sampling::is_sampled() {
+            tracing::info!($($arg)+)
+        } else {
+            tracing::debug!($($arg)+)
+        }
+    };
+}
+
+pub(crate) use request_info;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, routing::get, Router};
+    use std::sync::{Arc, Mutex};
+    use tower::ServiceExt;
+
+    #[derive(Clone, Default)]
+    struct LogBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for LogBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Logs at info level and above of one request through the middleware
+    /// with `rate`
+    async fn logs_with_rate(rate: f64) -> String {
+        let logs = LogBuffer::default();
+        let writer = logs.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(move || writer.clone())
+            .with_ansi(false)
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let app = Router::new()
+            .route(
+                "/files",
+                get(|| async {
+                    request_info!("Proxying request to backend");
+                    tracing::error!("Backend request failed");
+                }),
+            )
+            .layer(axum::middleware::from_fn(move |req, next| sample_request(rate, req, next)));
+        let req = Request::builder().uri("/files").body(Body::empty()).unwrap();
+        app.oneshot(req).await.unwrap();
+
+        let output = String::from_utf8_lossy(&logs.0.lock().unwrap()).to_string();
+        output
+    }
+
+    #[tokio::test]
+    async fn sampled_requests_log_at_info_level() {
+        let logs = logs_with_rate(1.0).await;
+        assert!(logs.contains("INFO"), "{}", logs);
+        assert!(logs.contains("Proxying request to backend"), "{}", logs);
+    }
+
+    #[tokio::test]
+    async fn unsampled_requests_only_log_errors() {
+        let logs = logs_with_rate(0.0).await;
+        assert!(!logs.contains("Proxying request to backend"), "{}", logs);
+        assert!(logs.contains("Backend request failed"), "{}", logs);
+    }
+
+    #[test]
+    fn outside_a_request_everything_is_sampled() {
+        assert!(is_sampled());
+        assert!(!SAMPLED.sync_scope(false, is_sampled));
+    }
+
+    #[test]
+    fn rate_is_the_fraction_of_sampled_requests() {
+        assert!((0..100).all(|_| sampled(1.0)));
+        assert!((0..100).all(|_| !sampled(0.0)));
+
+        let hits = (0..2000).filter(|_| sampled(0.25)).count();
+        assert!((300..700).contains(&hits), "{} of 2000 sampled", hits);
+    }
+}
@@ -0,0 +1,178 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::Request,
+};
+use http_body::{Frame, SizeHint};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::task::{ready, Context, Poll};
+
+use crate::db::Backend;
+
+/// Subidas acumuladas hacia un backend
+#[derive(Debug)]
+struct BackendUploads {
+    provider: String,
+    bytes: AtomicU64,
+    requests: AtomicU64,
+}
+
+/// Bytes de body enviados a cada backend desde el arranque, para planificar
+/// capacidad en los providers de almacenamiento
+#[derive(Debug, Default)]
+pub struct UploadMetrics {
+    backends: RwLock<HashMap<String, Arc<BackendUploads>>>,
+}
+
+impl UploadMetrics {
+    fn counter(&self, backend: &Backend) -> Arc<BackendUploads> {
+        if let Some(counter) = self.backends.read().unwrap().get(&backend.server_id) {
+            return counter.clone();
+        }
+
+        self.backends
+            .write()
+            .unwrap()
+            .entry(backend.server_id.clone())
+            .or_insert_with(|| {
+                Arc::new(BackendUploads {
+                    provider: backend.provider.clone(),
+                    bytes: AtomicU64::new(0),
+                    requests: AtomicU64::new(0),
+                })
+            })
+            .clone()
+    }
+
+    /// Wraps the request body so its bytes are counted for `backend` as they
+    /// stream. Requests without a body are not counted.
+    pub fn count_body(&self, backend: &Backend, req: &mut Request) {
+        if http_body::Body::is_end_stream(req.body()) {
+            return;
+        }
+
+        let counter = self.counter(backend);
+        counter.requests.fetch_add(1, Ordering::Relaxed);
+
+        let inner = std::mem::take(req.body_mut());
+        *req.body_mut() = Body::new(CountingBody { inner, counter });
+    }
+
+    /// Current totals per backend and per provider for the stats endpoint
+    pub fn snapshot(&self) -> serde_json::Value {
+        let backends = self.backends.read().unwrap();
+        let mut by_provider: BTreeMap<&str, u64> = BTreeMap::new();
+        let mut by_backend = serde_json::Map::new();
+
+        for (server_id, counter) in backends.iter() {
+            let bytes = counter.bytes.load(Ordering::Relaxed);
+            *by_provider.entry(&counter.provider).or_default() += bytes;
+            by_backend.insert(
+                server_id.clone(),
+                serde_json::json!({
+                    "provider": counter.provider,
+                    "bytes": bytes,
+                    "requests": counter.requests.load(Ordering::Relaxed),
+                }),
+            );
+        }
+
+        serde_json::json!({
+            "by_backend": by_backend,
+            "by_provider": by_provider,
+        })
+    }
+
+    /// Counters in the Prometheus text exposition format
+    pub fn prometheus(&self) -> String {
+        let backends = self.backends.read().unwrap();
+        let mut out = String::new();
+
+        for (name, help) in [
+            ("vk_gateway_upload_bytes_total", "Request body bytes sent to each backend"),
+            ("vk_gateway_upload_requests_total", "Requests with a body sent to each backend"),
+        ] {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} counter", name);
+            for (server_id, counter) in backends.iter() {
+                let value = if name == "vk_gateway_upload_bytes_total" {
+                    counter.bytes.load(Ordering::Relaxed)
+                } else {
+                    counter.requests.load(Ordering::Relaxed)
+                };
+                let _ = writeln!(
+                    out,
+                    "{}{{server_id=\"{}\",provider=\"{}\"}} {}",
+                    name,
+                    escape_label(server_id),
+                    escape_label(&counter.provider),
+                    value
+                );
+            }
+        }
+
+        out
+    }
+}
+
+/// Escapes a Prometheus label value
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Body que suma al contador los bytes de cada frame de datos
+struct CountingBody {
+    inner: Body,
+    counter: Arc<BackendUploads>,
+}
+
+impl http_body::Body for CountingBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let frame = ready!(Pin::new(&mut self.inner).poll_frame(cx));
+        if let Some(data) = frame.as_ref().and_then(|f| f.as_ref().ok()).and_then(Frame::data_ref) {
+            self.counter.bytes.fetch_add(data.len() as u64, Ordering::Relaxed);
+        }
+        Poll::Ready(frame)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_backend;
+
+    #[test]
+    fn label_values_are_escaped() {
+        assert_eq!(escape_label(r#"a"b\c"#), r#"a\"b\\c"#);
+        assert_eq!(escape_label("line\nbreak"), "line\\nbreak");
+    }
+
+    #[test]
+    fn bodyless_requests_are_not_counted() {
+        let metrics = UploadMetrics::default();
+        let mut req = Request::new(Body::empty());
+
+        metrics.count_body(&test_backend("b"), &mut req);
+        assert_eq!(metrics.snapshot()["by_backend"], serde_json::json!({}));
+    }
+}